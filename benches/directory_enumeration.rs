@@ -0,0 +1,71 @@
+//! Benchmarks for enumerating directory trees.
+//!
+//! Enumerates a synthetic tree which is deep and wide, like a resourcepack with many nested texture folders, so the
+//! cost per directory entry dominates. The tree is generated into the system temp directory when the benchmark starts.
+//!
+//! Run with `cargo bench --bench directory_enumeration`.
+
+#![feature(test)]
+
+extern crate test;
+
+use nova_rs::fs::dir::{read_recursive, read_recursive_with_options, DirectoryManifest, IgnorePatterns};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use test::Bencher;
+
+/// Levels of directories below the root of the synthetic tree.
+const TREE_DEPTH: u32 = 5;
+
+/// Subdirectories in every directory above the deepest level.
+const DIRECTORIES_PER_DIRECTORY: usize = 4;
+
+/// Files in every directory.
+const FILES_PER_DIRECTORY: usize = 8;
+
+/// Path to the synthetic tree, generating it on first use.
+///
+/// Benchmarks run in parallel, so generation is guarded to only happen once per run.
+fn synthetic_tree_path() -> PathBuf {
+    static GENERATE: Once = Once::new();
+
+    let root = std::env::temp_dir().join("nova-rs-bench-synthetic-tree");
+    GENERATE.call_once(|| {
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+        generate_directory(&root, TREE_DEPTH);
+    });
+    root
+}
+
+fn generate_directory(path: &Path, depth: u32) {
+    fs::create_dir_all(path).unwrap();
+    for i in 0..FILES_PER_DIRECTORY {
+        fs::write(path.join(format!("texture_{}.png", i)), b"").unwrap();
+    }
+    if depth > 0 {
+        for i in 0..DIRECTORIES_PER_DIRECTORY {
+            generate_directory(&path.join(format!("folder_{}", i)), depth - 1);
+        }
+    }
+}
+
+#[bench]
+fn synthetic_read_recursive(b: &mut Bencher) {
+    let path = synthetic_tree_path();
+    b.iter(|| read_recursive(&path).unwrap());
+}
+
+#[bench]
+fn synthetic_read_recursive_following_symlinks(b: &mut Bencher) {
+    let path = synthetic_tree_path();
+    b.iter(|| read_recursive_with_options(&path, true, &IgnorePatterns::none()).unwrap());
+}
+
+#[bench]
+fn synthetic_manifest(b: &mut Bencher) {
+    let path = synthetic_tree_path();
+    b.iter(|| DirectoryManifest::from_path(&path).unwrap());
+}
@@ -0,0 +1,55 @@
+//! Benchmarks for updating the draw commands of a scene.
+//!
+//! Compares moving every object of a large scene with one call per draw command against a single batched update.
+//!
+//! Run with `cargo bench --bench scene_updates`.
+
+#![feature(test)]
+
+extern crate test;
+
+use cgmath::{Matrix4, Vector3};
+use nova_rs::mesh::{DrawCommandId, GeometryTags, GeometryType, MeshId, StaticMeshDrawCommand};
+use nova_rs::renderer::Scene;
+use test::Bencher;
+
+/// Amount of draw commands in the benchmarked scene.
+const DRAW_COMMAND_COUNT: u64 = 10_000;
+
+fn scene() -> (Scene, Vec<(DrawCommandId, Matrix4<f32>, bool)>) {
+    let mut scene = Scene::new();
+    let updates = (0..DRAW_COMMAND_COUNT)
+        .map(|mesh| {
+            let id = scene.add_draw_command(StaticMeshDrawCommand {
+                mesh: MeshId(mesh),
+                tags: GeometryTags::new(GeometryType::Block),
+                model_matrix: Matrix4::from_scale(1.0),
+                is_visible: true,
+            });
+            let moved = Matrix4::from_translation(Vector3::new(mesh as f32, 0.0, 0.0));
+            (id, moved, mesh % 2 == 0)
+        })
+        .collect();
+    scene.take_dirty_draw_commands();
+    (scene, updates)
+}
+
+#[bench]
+fn individual_updates(b: &mut Bencher) {
+    let (mut scene, updates) = scene();
+    b.iter(|| {
+        for &(id, model_matrix, is_visible) in &updates {
+            scene.update_draw_command(id, model_matrix, is_visible);
+        }
+        scene.take_dirty_draw_commands()
+    });
+}
+
+#[bench]
+fn batch_update(b: &mut Bencher) {
+    let (mut scene, updates) = scene();
+    b.iter(|| {
+        scene.update_draw_commands(&updates);
+        scene.take_dirty_draw_commands()
+    });
+}
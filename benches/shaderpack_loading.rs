@@ -0,0 +1,232 @@
+//! Benchmarks for the shaderpack loading pipeline.
+//!
+//! Measures both the full [`load_nova_shaderpack`] path and the individual phases it is built out of: file tree
+//! enumeration, JSON parsing, and shader reading. Each benchmark is run against the default shaderpack fixture and a
+//! large synthetic shaderpack which is generated into the system temp directory when the benchmark starts.
+//!
+//! Run with `cargo bench --bench shaderpack_loading`.
+
+#![feature(test)]
+
+extern crate test;
+
+use futures::executor::{block_on, ThreadPool, ThreadPoolBuilder};
+use nova_rs::loading::{DirectoryFileTree, FileTree};
+use nova_rs::shaderpack::*;
+use path_dsl::path;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use test::Bencher;
+
+/// Amount of material/pipeline/shader sets to put in the synthetic shaderpack.
+const SYNTHETIC_PIPELINE_COUNT: usize = 512;
+
+/// Path to the default shaderpack fixture used by the integration tests.
+fn default_pack_path() -> PathBuf {
+    path!("tests" | "data" | "shaderpacks" | "nova" | "DefaultShaderpack").into()
+}
+
+/// Path to the large synthetic shaderpack, generating it on first use.
+///
+/// Benchmarks run in parallel, so generation is guarded to only happen once per run. It is regenerated on every run so
+/// that changes to the generator or the fixture are always picked up.
+fn synthetic_pack_path() -> PathBuf {
+    static GENERATE: Once = Once::new();
+
+    let root = std::env::temp_dir().join("nova-rs-bench-synthetic-shaderpack");
+    GENERATE.call_once(|| generate_synthetic_pack(&root));
+    root
+}
+
+/// Generates a large shaderpack based on the default shaderpack into `root`.
+fn generate_synthetic_pack(root: &Path) {
+    let default = default_pack_path();
+    if root.exists() {
+        fs::remove_dir_all(root).unwrap();
+    }
+    fs::create_dir_all(root.join("materials")).unwrap();
+    fs::create_dir_all(root.join("shaders")).unwrap();
+
+    for file in &["passes.json", "resources.json"] {
+        fs::copy(default.join(file), root.join(file)).unwrap();
+    }
+
+    let vertex_source = fs::read_to_string(default.join("shaders").join("gbuffers_terrain.vert")).unwrap();
+    let fragment_source = fs::read_to_string(default.join("shaders").join("gbuffers_terrain.frag")).unwrap();
+
+    for i in 0..SYNTHETIC_PIPELINE_COUNT {
+        let material = format!(
+            r#"{{
+    "name": "material_{i}",
+    "filter": "geometry_type::block AND not_transparent",
+    "passes": [
+        {{
+            "name": "main",
+            "pipeline": "pipeline_{i}",
+            "bindings": {{
+                "per_model_uniforms": "NovaModelMatrixBuffer"
+            }}
+        }}
+    ]
+}}"#,
+            i = i
+        );
+        let pipeline = format!(
+            r#"{{
+    "name": "pipeline_{i}",
+    "pass": "Forward",
+    "vertexShader": "shaders/shader_{i}.vert",
+    "fragmentShader": "shaders/shader_{i}.frag",
+    "vertexFields": [
+        {{
+            "name": "position_in",
+            "field": "Position"
+        }},
+        {{
+            "name": "uv_in",
+            "field": "UV0"
+        }}
+    ]
+}}"#,
+            i = i
+        );
+
+        let materials = root.join("materials");
+        let shaders = root.join("shaders");
+        fs::write(materials.join(format!("material_{}.mat", i)), material).unwrap();
+        fs::write(materials.join(format!("pipeline_{}.pipeline", i)), pipeline).unwrap();
+        fs::write(shaders.join(format!("shader_{}.vert", i)), &vertex_source).unwrap();
+        fs::write(shaders.join(format!("shader_{}.frag", i)), &fragment_source).unwrap();
+    }
+}
+
+fn create_threadpool() -> ThreadPool {
+    ThreadPoolBuilder::new()
+        .name_prefix("shaderpack_loading_bench")
+        .create()
+        .unwrap()
+}
+
+/// Contents of every json file in a shaderpack, read ahead of time so parsing can be measured on its own.
+struct JsonSources {
+    passes: String,
+    resources: String,
+    materials: Vec<String>,
+    pipelines: Vec<String>,
+}
+
+fn read_json_sources(tree: &DirectoryFileTree) -> JsonSources {
+    let mut materials = Vec::new();
+    let mut pipelines = Vec::new();
+    for file in tree.read_dir(Path::new("materials")).unwrap() {
+        let full_path: PathBuf = path!("materials" | &file).into();
+        match file.extension().and_then(OsStr::to_str) {
+            Some("mat") => materials.push(block_on(tree.read_text(&full_path)).unwrap()),
+            Some("pipeline") => pipelines.push(block_on(tree.read_text(&full_path)).unwrap()),
+            _ => {}
+        }
+    }
+
+    JsonSources {
+        passes: block_on(tree.read_text(Path::new("passes.json"))).unwrap(),
+        resources: block_on(tree.read_text(Path::new("resources.json"))).unwrap(),
+        materials,
+        pipelines,
+    }
+}
+
+fn shader_paths(tree: &DirectoryFileTree) -> Vec<PathBuf> {
+    tree.read_dir(Path::new("shaders"))
+        .unwrap()
+        .into_iter()
+        .map(|file| path!("shaders" | file).into())
+        .collect()
+}
+
+fn bench_full_load(b: &mut Bencher, path: PathBuf) {
+    let mut threadpool = create_threadpool();
+    let executor = threadpool.clone();
+    b.iter(|| {
+        threadpool
+            .run(load_nova_shaderpack(executor.clone(), path.clone()))
+            .unwrap()
+    });
+}
+
+fn bench_enumeration(b: &mut Bencher, path: PathBuf) {
+    b.iter(|| block_on(DirectoryFileTree::from_path(&path)).unwrap());
+}
+
+fn bench_json_parse(b: &mut Bencher, path: PathBuf) {
+    let tree = block_on(DirectoryFileTree::from_path(&path)).unwrap();
+    let sources = read_json_sources(&tree);
+    b.iter(|| {
+        let passes: Vec<RenderPassCreationInfo> = serde_json::from_str(&sources.passes).unwrap();
+        let resources: ShaderpackResourceData = serde_json::from_str(&sources.resources).unwrap();
+        let materials: Vec<MaterialData> = sources
+            .materials
+            .iter()
+            .map(|s| serde_json::from_str(s).unwrap())
+            .collect();
+        let pipelines: Vec<PipelineCreationInfo> = sources
+            .pipelines
+            .iter()
+            .map(|s| serde_json::from_str(s).unwrap())
+            .collect();
+        (passes, resources, materials, pipelines)
+    });
+}
+
+fn bench_shader_read(b: &mut Bencher, path: PathBuf) {
+    let tree = block_on(DirectoryFileTree::from_path(&path)).unwrap();
+    let shaders = shader_paths(&tree);
+    b.iter(|| {
+        // Dispatch every read before awaiting any of them, the same way the loader does.
+        let futs: Vec<_> = shaders.iter().map(|p| tree.read_text(p)).collect();
+        futs.into_iter()
+            .map(|fut| block_on(fut).unwrap())
+            .collect::<Vec<String>>()
+    });
+}
+
+#[bench]
+fn default_full_load(b: &mut Bencher) {
+    bench_full_load(b, default_pack_path());
+}
+
+#[bench]
+fn default_enumeration(b: &mut Bencher) {
+    bench_enumeration(b, default_pack_path());
+}
+
+#[bench]
+fn default_json_parse(b: &mut Bencher) {
+    bench_json_parse(b, default_pack_path());
+}
+
+#[bench]
+fn default_shader_read(b: &mut Bencher) {
+    bench_shader_read(b, default_pack_path());
+}
+
+#[bench]
+fn synthetic_full_load(b: &mut Bencher) {
+    bench_full_load(b, synthetic_pack_path());
+}
+
+#[bench]
+fn synthetic_enumeration(b: &mut Bencher) {
+    bench_enumeration(b, synthetic_pack_path());
+}
+
+#[bench]
+fn synthetic_json_parse(b: &mut Bencher) {
+    bench_json_parse(b, synthetic_pack_path());
+}
+
+#[bench]
+fn synthetic_shader_read(b: &mut Bencher) {
+    bench_shader_read(b, synthetic_pack_path());
+}
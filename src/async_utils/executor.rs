@@ -0,0 +1,45 @@
+//! Executor abstraction used by Nova's asynchronous APIs.
+
+use futures::future::FutureObj;
+use futures::task::{Spawn, SpawnError};
+
+/// An executor that Nova can spawn its asynchronous tasks on.
+///
+/// This is automatically implemented for every cloneable [`Spawn`] implementation, so any executor from the `futures`
+/// crate, such as [`ThreadPool`](futures::executor::ThreadPool), can be used directly. Executors which don't implement
+/// [`Spawn`], such as the ones from tokio or async-std, can be wrapped in a [`SpawnFn`].
+pub trait NovaExecutor: Spawn + Clone + 'static {}
+
+impl<T> NovaExecutor for T where T: Spawn + Clone + 'static {}
+
+/// Adapter which turns a spawning function into a [`NovaExecutor`].
+///
+/// The function is handed every task Nova wants to run and must drive it to completion in the background.
+///
+/// # Example
+///
+/// Wrapping a tokio runtime:
+///
+/// ```ignore
+/// use nova_rs::async_utils::SpawnFn;
+///
+/// let runtime = tokio::runtime::Runtime::new().unwrap();
+/// let handle = runtime.handle().clone();
+/// let executor = SpawnFn(move |task| {
+///     handle.spawn(task);
+/// });
+///
+/// let shaderpack = runtime.block_on(load_nova_shaderpack(executor, path));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpawnFn<F>(pub F);
+
+impl<F> Spawn for SpawnFn<F>
+where
+    F: FnMut(FutureObj<'static, ()>),
+{
+    fn spawn_obj(&mut self, future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+        (self.0)(future);
+        Ok(())
+    }
+}
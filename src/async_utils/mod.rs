@@ -1,17 +1,22 @@
 //! Utility structures for making writing async code easier.
 //!
-//! Provides [`async_call`](../macro.async_invoke.html) macro.
+//! Provides [`async_call`](../macro.async_invoke.html) macro and the [`NovaExecutor`] abstraction.
 
 use futures::executor::ThreadPool;
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::sync::Arc;
 
+mod executor;
+pub use executor::*;
+
 /// Asynchronous context, provided by [`async_call`](../macro.async_invoke.html) macro. Contains an
 /// executor and a call stack.
-pub struct Context {
+///
+/// The executor is a [`ThreadPool`] unless another [`NovaExecutor`] is passed to the macro.
+pub struct Context<E = ThreadPool> {
     /// Executor in this context.
-    pub executor: ThreadPool,
+    pub executor: E,
     /// Asynchronous call stack that called this function.
     pub call_stack: Arc<StackFrame>,
 }
@@ -114,9 +119,12 @@ macro_rules! async_call_stack {
 ///   - Async Context:
 ///     - `exec` invokes the function on the provided executor using [`spawn_with_handle`].
 ///     - `inline` invokes the function directly.
+///     - `try-exec` is the same as `exec`, but returns the `Result` of [`spawn_with_handle`] instead of handling it.
 ///   - Sync Context:
 ///     - `from-sync` invokes the function on the provided executor using [`spawn_with_handle`]. It is the start of the
 ///       async call stack.
+///     - `try-from-sync` is the same as `from-sync`, but returns the `Result` of [`spawn_with_handle`] instead of
+///       handling it.
 ///     - `primary` invokes the function on the provided executor using [`run`]. It is the future that keeps the
 ///       executor running and will block until the future returns.
 /// - `<ctx>` is the [`Context`] of the current async function. If you don't have a context, you are either in a sync
@@ -132,7 +140,7 @@ macro_rules! async_call_stack {
 ///   ommitted in a sync context, will create a new callstack with this call at the top.
 /// - `<handler>` is the error handler to use. The error handler is a function that will be passed to `map_err`. This
 ///   result is then passed to the try operator `?`. This is never required. If not provided it will use a simple
-///   `unwrap`.
+///   `unwrap`. Not available in the `try-*` modes, as they leave error handling to the caller.
 ///
 /// # Examples
 ///
@@ -221,6 +229,17 @@ macro_rules! async_invoke {
         };
         $crate::async_handler!($crate::async_executor!($ctx $(, $executor)?).spawn_with_handle($func(new_context, $($($args),+)?)) $(, $handler)?)
     }};
+    // Invoke on the executor, returning the spawn result to the caller
+    (try-exec: $ctx:expr, $func:expr $(, executor: $executor:expr)? $(, stack: $call_stack:expr)? $(, args: $($args:expr),+)? ) => {{
+        use futures::task::SpawnExt;
+        let new_executor = $crate::async_executor!($ctx $(, $executor)?).clone();
+        let stack = $crate::async_call_stack!($ctx $(, $call_stack)?).clone().create_new_stack_frame(file!(), line!(), column!());
+        let new_context = $crate::async_utils::Context {
+            executor: new_executor,
+            call_stack: stack,
+        };
+        $crate::async_executor!($ctx $(, $executor)?).spawn_with_handle($func(new_context, $($($args),+)?))
+    }};
     // Invoke without calling off to the executor
     (inline: $ctx:expr, $func:expr $(, executor: $executor:expr)? $(, stack: $call_stack:expr)? $(, args: $($args:expr),+)? ) => {{
         let new_executor = $crate::async_executor!($ctx $(, $executor)?).clone();
@@ -242,6 +261,17 @@ macro_rules! async_invoke {
         };
         $crate::async_handler!($crate::async_executor!(x, $executor).spawn_with_handle($func(new_context, $($($args),+)?)) $(, $handler)?)
     }};
+    // Invoke on the executor from synchronous code, returning the spawn result to the caller
+    (try-from-sync: $func:expr, executor: $executor:expr $(, args: $($args:expr),+)?) => {{
+        use futures::task::SpawnExt;
+        let stack = $crate::async_utils::StackFrame::new(file!(), line!(), column!());
+        let new_executor = $crate::async_executor!(x, $executor).clone();
+        let new_context = $crate::async_utils::Context {
+            executor: new_executor,
+            call_stack: stack,
+        };
+        $crate::async_executor!(x, $executor).spawn_with_handle($func(new_context, $($($args),+)?))
+    }};
     // Invoke on the executor using `run` instead of `spawn_with_handle`
     (primary: $func:expr, executor: $executor:expr $(, handler: $handler:expr)? $(, args: $($args:expr),+)?) => {{
         let stack = $crate::async_utils::StackFrame::new(file!(), line!(), column!());
@@ -256,8 +286,11 @@ macro_rules! async_invoke {
 
 #[cfg(test)]
 mod test {
-    use crate::async_utils::Context;
-    use futures::executor::ThreadPoolBuilder;
+    use crate::async_utils::{Context, StackFrame};
+    use futures::executor::{block_on, ThreadPoolBuilder};
+    use futures::future::FutureObj;
+    use futures::task::{Spawn, SpawnError};
+    use matches::matches;
 
     async fn async_sub_fn(_ctx: Context, v: i32) -> i32 {
         assert_eq!(v, 2);
@@ -275,4 +308,55 @@ mod test {
         let mut exec = ThreadPoolBuilder::new().create().expect("ThreadPool failed to start.");
         async_invoke!(primary: async_fn, executor: exec);
     }
+
+    async fn try_async_fn(mut ctx: Context) -> Result<i32, SpawnError> {
+        let handle = async_invoke!(try-exec: ctx, async_sub_fn, args: 2)?;
+        Ok(handle.await)
+    }
+
+    #[test]
+    fn async_invoke_try() {
+        let mut exec = ThreadPoolBuilder::new().create().expect("ThreadPool failed to start.");
+
+        let handle = async_invoke!(try-from-sync: async_sub_fn, executor: exec, args: 2);
+        let handle = handle.expect("Failed to spawn on a running ThreadPool.");
+        assert_eq!(exec.run(handle), 3);
+
+        let result = async_invoke!(primary: try_async_fn, executor: exec);
+        assert_eq!(result.expect("Failed to spawn on a running ThreadPool."), 3);
+    }
+
+    /// Executor which has been shut down, so it refuses every task.
+    #[derive(Clone)]
+    struct ShutdownExecutor;
+
+    impl Spawn for ShutdownExecutor {
+        fn spawn_obj(&mut self, _future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+            Err(SpawnError::shutdown())
+        }
+    }
+
+    async fn shutdown_sub_fn(_ctx: Context<ShutdownExecutor>, v: i32) -> i32 {
+        v
+    }
+
+    async fn try_shutdown_fn(mut ctx: Context<ShutdownExecutor>) -> Result<i32, SpawnError> {
+        let handle = async_invoke!(try-exec: ctx, shutdown_sub_fn, args: 2)?;
+        Ok(handle.await)
+    }
+
+    #[test]
+    fn async_invoke_try_shutdown() {
+        let mut exec = ShutdownExecutor;
+
+        let result = async_invoke!(try-from-sync: shutdown_sub_fn, executor: exec, args: 2);
+        assert!(matches!(result, Err(ref err) if err.is_shutdown()));
+
+        let ctx = Context {
+            executor: ShutdownExecutor,
+            call_stack: StackFrame::new(file!(), line!(), column!()),
+        };
+        let result = block_on(try_shutdown_fn(ctx));
+        assert!(matches!(result, Err(ref err) if err.is_shutdown()));
+    }
 }
@@ -0,0 +1,291 @@
+//! Packing of many small textures into a single texture atlas.
+//!
+//! Nova's virtual textures are built out of atlases full of smaller textures. [`AtlasPacker`] decides where each of
+//! those textures go. It doesn't touch any texture data, so the actual copying is left to whoever owns the GPU
+//! resources.
+
+use cgmath::Vector2;
+use failure::Fail;
+use std::collections::{HashMap, HashSet};
+
+/// Packs named sub-textures into an atlas using a shelf packing algorithm.
+///
+/// Packing is fully deterministic: the same textures will always get the same placement, no matter the order they
+/// were added in.
+#[derive(Debug, Clone)]
+pub struct AtlasPacker {
+    max_size: u32,
+    textures: Vec<(String, Vector2<u32>)>,
+}
+
+impl AtlasPacker {
+    /// Creates a packer for atlases that are at most `max_size` pixels in either dimension.
+    pub fn new(max_size: u32) -> Self {
+        Self {
+            max_size,
+            textures: Vec::new(),
+        }
+    }
+
+    /// Adds a texture of the given size in pixels to be packed.
+    pub fn add_texture(&mut self, name: String, size: Vector2<u32>) {
+        self.textures.push((name, size));
+    }
+
+    /// Packs all the added textures into an atlas.
+    ///
+    /// The atlas dimensions are always powers of two. Its width is grown until everything fits, and its height is
+    /// then shrunk to what was actually used.
+    pub fn pack(&self) -> Result<PackedAtlas, AtlasPackingError> {
+        let mut seen = HashSet::new();
+        for (name, size) in &self.textures {
+            if size.x == 0 || size.y == 0 {
+                return Err(AtlasPackingError::EmptyTexture(name.clone()));
+            }
+            if size.x > self.max_size || size.y > self.max_size {
+                return Err(AtlasPackingError::TextureTooLarge {
+                    name: name.clone(),
+                    size: *size,
+                    max_size: self.max_size,
+                });
+            }
+            if !seen.insert(name.as_str()) {
+                return Err(AtlasPackingError::DuplicateTexture(name.clone()));
+            }
+        }
+
+        if self.textures.is_empty() {
+            return Ok(PackedAtlas {
+                size: Vector2::new(0, 0),
+                regions: HashMap::new(),
+            });
+        }
+
+        // Tallest textures first so each shelf is as tightly packed as possible. The name is used as a final tie
+        // breaker to keep the output independent of the order textures were added in.
+        let mut order: Vec<&(String, Vector2<u32>)> = self.textures.iter().collect();
+        order.sort_by(|(a_name, a_size), (b_name, b_size)| {
+            b_size
+                .y
+                .cmp(&a_size.y)
+                .then(b_size.x.cmp(&a_size.x))
+                .then(a_name.cmp(b_name))
+        });
+
+        let widest = order.iter().map(|(_, size)| size.x).max().unwrap_or(1);
+        let total_area: u64 = order
+            .iter()
+            .map(|(_, size)| u64::from(size.x) * u64::from(size.y))
+            .sum();
+        let square_side = (total_area as f64).sqrt().ceil() as u32;
+        let mut width = widest.max(square_side).next_power_of_two().min(self.max_size);
+
+        loop {
+            let (positions, used_height) = Self::place_on_shelves(&order, width);
+            if used_height <= self.max_size {
+                let height = used_height.next_power_of_two().min(self.max_size);
+                let size = Vector2::new(width, height);
+                let regions = order
+                    .iter()
+                    .zip(positions)
+                    .map(|((name, texture_size), position)| {
+                        (name.clone(), AtlasRegion::new(position, *texture_size, size))
+                    })
+                    .collect();
+                return Ok(PackedAtlas { size, regions });
+            }
+
+            if width >= self.max_size {
+                return Err(AtlasPackingError::AtlasFull {
+                    max_size: self.max_size,
+                });
+            }
+            width = (width * 2).min(self.max_size);
+        }
+    }
+
+    /// Places the textures left to right on shelves of the given width, returning each texture's position and the
+    /// total height used.
+    fn place_on_shelves(order: &[&(String, Vector2<u32>)], width: u32) -> (Vec<Vector2<u32>>, u32) {
+        let mut positions = Vec::with_capacity(order.len());
+        let mut cursor = Vector2::new(0, 0);
+        let mut shelf_height = 0;
+
+        for (_, size) in order {
+            if cursor.x + size.x > width {
+                cursor.x = 0;
+                cursor.y += shelf_height;
+                shelf_height = 0;
+            }
+            positions.push(cursor);
+            cursor.x += size.x;
+            shelf_height = shelf_height.max(size.y);
+        }
+
+        (positions, cursor.y + shelf_height)
+    }
+}
+
+/// Result of packing textures into an atlas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedAtlas {
+    /// Size of the atlas in pixels.
+    pub size: Vector2<u32>,
+
+    /// Where each texture ended up, by name.
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+/// The area of an atlas a single texture occupies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    /// Position of the top left corner in pixels.
+    pub position: Vector2<u32>,
+
+    /// Size of the region in pixels.
+    pub size: Vector2<u32>,
+
+    /// UV coordinate of the top left corner.
+    pub uv_min: Vector2<f32>,
+
+    /// UV coordinate of the bottom right corner.
+    pub uv_max: Vector2<f32>,
+}
+
+impl AtlasRegion {
+    fn new(position: Vector2<u32>, size: Vector2<u32>, atlas_size: Vector2<u32>) -> Self {
+        let to_uv = |pixel: Vector2<u32>| {
+            Vector2::new(
+                pixel.x as f32 / atlas_size.x as f32,
+                pixel.y as f32 / atlas_size.y as f32,
+            )
+        };
+
+        Self {
+            position,
+            size,
+            uv_min: to_uv(position),
+            uv_max: to_uv(position + size),
+        }
+    }
+}
+
+/// Reasons textures can fail to be packed into an atlas.
+#[derive(Fail, Debug, Clone, PartialEq)]
+pub enum AtlasPackingError {
+    /// A texture has a width or height of zero.
+    #[fail(display = "Texture {:?} has no area.", _0)]
+    EmptyTexture(String),
+
+    /// The same texture name was added more than once.
+    #[fail(display = "Texture {:?} was added to the atlas more than once.", _0)]
+    DuplicateTexture(String),
+
+    /// A single texture is larger than the maximum atlas size.
+    #[fail(
+        display = "Texture {:?} of size {:?} is larger than the maximum atlas size {}.",
+        name, size, max_size
+    )]
+    TextureTooLarge {
+        /// Name of the texture.
+        name: String,
+        /// Size of the texture.
+        size: Vector2<u32>,
+        /// Maximum size of the atlas.
+        max_size: u32,
+    },
+
+    /// All the textures together don't fit in an atlas of the maximum size.
+    #[fail(display = "Textures do not fit in an atlas of size {}.", max_size)]
+    AtlasFull {
+        /// Maximum size of the atlas.
+        max_size: u32,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn packer(max_size: u32, textures: &[(&str, u32, u32)]) -> AtlasPacker {
+        let mut packer = AtlasPacker::new(max_size);
+        for (name, width, height) in textures {
+            packer.add_texture((*name).to_owned(), Vector2::new(*width, *height));
+        }
+        packer
+    }
+
+    #[test]
+    fn shelf_layout() {
+        let atlas = packer(1024, &[("small", 16, 16), ("tall", 16, 32), ("wide", 32, 16)])
+            .pack()
+            .unwrap();
+
+        assert_eq!(atlas.size, Vector2::new(64, 32));
+        assert_eq!(atlas.regions["tall"].position, Vector2::new(0, 0));
+        assert_eq!(atlas.regions["wide"].position, Vector2::new(16, 0));
+        assert_eq!(atlas.regions["small"].position, Vector2::new(48, 0));
+
+        let small = atlas.regions["small"];
+        assert_eq!(small.uv_min, Vector2::new(0.75, 0.0));
+        assert_eq!(small.uv_max, Vector2::new(1.0, 0.5));
+    }
+
+    #[test]
+    fn deterministic() {
+        let a = packer(1024, &[("a", 16, 16), ("b", 16, 16), ("c", 8, 32)])
+            .pack()
+            .unwrap();
+        let b = packer(1024, &[("c", 8, 32), ("b", 16, 16), ("a", 16, 16)])
+            .pack()
+            .unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn no_overlap() {
+        let textures: Vec<_> = (0..64)
+            .map(|i| (format!("texture_{}", i), 4 + (i * 7) % 29, 4 + (i * 13) % 31))
+            .collect();
+        let mut packer = AtlasPacker::new(4096);
+        for (name, width, height) in &textures {
+            packer.add_texture(name.clone(), Vector2::new(*width, *height));
+        }
+        let atlas = packer.pack().unwrap();
+
+        let regions: Vec<_> = atlas.regions.values().collect();
+        for (i, a) in regions.iter().enumerate() {
+            assert!(a.position.x + a.size.x <= atlas.size.x);
+            assert!(a.position.y + a.size.y <= atlas.size.y);
+            for b in &regions[i + 1..] {
+                let separate_x = a.position.x + a.size.x <= b.position.x || b.position.x + b.size.x <= a.position.x;
+                let separate_y = a.position.y + a.size.y <= b.position.y || b.position.y + b.size.y <= a.position.y;
+                assert!(separate_x || separate_y, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            packer(64, &[("a", 0, 16)]).pack(),
+            Err(AtlasPackingError::EmptyTexture("a".into()))
+        );
+        assert_eq!(
+            packer(64, &[("a", 16, 16), ("a", 8, 8)]).pack(),
+            Err(AtlasPackingError::DuplicateTexture("a".into()))
+        );
+        assert_eq!(
+            packer(64, &[("a", 128, 16)]).pack(),
+            Err(AtlasPackingError::TextureTooLarge {
+                name: "a".into(),
+                size: Vector2::new(128, 16),
+                max_size: 64
+            })
+        );
+        assert_eq!(
+            packer(64, &[("a", 64, 64), ("b", 64, 64)]).pack(),
+            Err(AtlasPackingError::AtlasFull { max_size: 64 })
+        );
+    }
+}
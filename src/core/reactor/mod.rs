@@ -1,24 +1,43 @@
 //! Event loop reactors to turn blocking operations into async operations.
 
-use crossbeam::channel::{Receiver, Sender};
+use crossbeam::channel::{Receiver, Sender, TryRecvError};
+use failure::Fail;
 use futures::task::{Context, Waker};
 use futures::{Future, Poll};
 use std::mem;
 use std::pin::Pin;
+use std::time::{Duration, Instant};
 
 mod multi_thread;
 mod single_thread;
+mod timer;
 
 pub use multi_thread::*;
 pub use single_thread::*;
 
+/// How urgently a reactor should process an input.
+///
+/// Inputs waiting in the reactor's queue are processed highest priority first. Inputs with the same priority are
+/// processed in the order they were sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Bulk work that nothing is immediately waiting on.
+    Low,
+
+    /// The default priority.
+    Normal,
+
+    /// Work that other work is waiting on, and should jump the queue.
+    High,
+}
+
 /// Current state of the reactor.
 enum ReactorFutureData<S, R>
 where
     S: Send + 'static,
     R: Send + 'static,
 {
-    Unsent(S, SingleThreadReactor<S, R>),
+    Unsent(S, Priority, SingleThreadReactor<S, R>),
     Uninit,
     Sent(Receiver<R>),
     Finished,
@@ -26,7 +45,8 @@ where
 
 /// Future representing a computation happening on a [`SingleThreadReactor`].
 ///
-/// First time poll is called, sets up the computation, then will return pending until the answer arrives.
+/// First time poll is called, sets up the computation, then will return pending until the answer arrives. If the
+/// reactor's queue is full, the computation is set up once there is space for it.
 /// Currently only supports the [`SingleThreadReactor`].
 /// This will be changed in the future.
 pub struct ReactorFuture<S, R>
@@ -47,30 +67,134 @@ where
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let old_data = mem::replace(&mut self.data, ReactorFutureData::Uninit);
         let (new_data, result) = match old_data {
-            ReactorFutureData::Unsent(data, reactor) => {
-                let recv = reactor.send(data, cx.waker().clone());
+            ReactorFutureData::Unsent(data, priority, reactor) => match reactor.try_send(data, priority, cx.waker()) {
+                Ok(recv) => (ReactorFutureData::Sent(recv), Poll::Pending),
+                // The reactor's queue is full, we will be woken up once there is space.
+                Err(data) => (ReactorFutureData::Unsent(data, priority, reactor), Poll::Pending),
+            },
+            // The future may be woken up before the answer arrives if it was waiting on queue space.
+            ReactorFutureData::Sent(receiver) => match receiver.try_recv() {
+                Ok(result) => (ReactorFutureData::Finished, Poll::Ready(result)),
+                Err(TryRecvError::Empty) => (ReactorFutureData::Sent(receiver), Poll::Pending),
+                Err(TryRecvError::Disconnected) => panic!("Expected receiver to have data"),
+            },
+            _ => panic!("Incorrect state in reactor future. This is a bug."),
+        };
+        self.data = new_data;
+        result
+    }
+}
 
-                (ReactorFutureData::Sent(recv), Poll::Pending)
+impl<S, R> Unpin for ReactorFuture<S, R>
+where
+    S: Send + 'static,
+    R: Send + 'static,
+{
+}
+
+/// Error from an operation on a reactor.
+#[derive(Fail, Debug, Clone, PartialEq)]
+pub enum ReactorError {
+    /// The reactor didn't finish the operation in time.
+    #[fail(display = "Reactor operation timed out after {:?}", _0)]
+    TimedOut(Duration),
+}
+
+/// Future representing a computation happening on a [`SingleThreadReactor`] that the caller will only wait a limited
+/// time for.
+///
+/// Resolves to [`ReactorError::TimedOut`] if the answer hasn't arrived before the timeout. The reactor still finishes
+/// the computation, but the answer is thrown away.
+pub struct ReactorTimeoutFuture<S, R>
+where
+    S: Send + 'static,
+    R: Send + 'static,
+{
+    data: ReactorFutureData<S, R>,
+    timeout: Duration,
+    deadline: Option<Instant>,
+    timer_entry: Option<timer::TimerEntry>,
+}
+
+impl<S, R> ReactorTimeoutFuture<S, R>
+where
+    S: Send + 'static,
+    R: Send + 'static,
+{
+    /// Takes the future's waker out of the timer, so the timer doesn't hold on to it until the deadline.
+    fn cancel_timer(&mut self) {
+        if let Some(entry) = self.timer_entry.take() {
+            timer::cancel(entry);
+        }
+    }
+}
+
+impl<S, R> Future for ReactorTimeoutFuture<S, R>
+where
+    S: Send + 'static,
+    R: Send + 'static,
+{
+    type Output = Result<R, ReactorError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let old_data = mem::replace(&mut self.data, ReactorFutureData::Uninit);
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => {
+                let deadline = Instant::now() + self.timeout;
+                self.deadline = Some(deadline);
+
+                // Wake the future up once the timeout passes, so it can give up waiting.
+                self.timer_entry = Some(timer::wake_at(deadline, cx.waker().clone()));
+
+                deadline
             }
-            ReactorFutureData::Sent(receiver) => (
-                ReactorFutureData::Finished,
-                Poll::Ready(receiver.recv().expect("Expected receiver to have data")),
-            ),
+        };
+        let timed_out = (
+            ReactorFutureData::Finished,
+            Poll::Ready(Err(ReactorError::TimedOut(self.timeout))),
+        );
+
+        let (new_data, result) = match old_data {
+            ReactorFutureData::Unsent(data, priority, reactor) => match reactor.try_send(data, priority, cx.waker()) {
+                Ok(recv) => (ReactorFutureData::Sent(recv), Poll::Pending),
+                Err(_) if Instant::now() >= deadline => timed_out,
+                Err(data) => (ReactorFutureData::Unsent(data, priority, reactor), Poll::Pending),
+            },
+            ReactorFutureData::Sent(receiver) => match receiver.try_recv() {
+                Ok(result) => (ReactorFutureData::Finished, Poll::Ready(Ok(result))),
+                Err(TryRecvError::Empty) if Instant::now() >= deadline => timed_out,
+                Err(TryRecvError::Empty) => (ReactorFutureData::Sent(receiver), Poll::Pending),
+                Err(TryRecvError::Disconnected) => panic!("Expected receiver to have data"),
+            },
             _ => panic!("Incorrect state in reactor future. This is a bug."),
         };
         self.data = new_data;
+        if result.is_ready() {
+            self.cancel_timer();
+        }
         result
     }
 }
 
-impl<S, R> Unpin for ReactorFuture<S, R>
+impl<S, R> Drop for ReactorTimeoutFuture<S, R>
+where
+    S: Send + 'static,
+    R: Send + 'static,
+{
+    fn drop(&mut self) {
+        self.cancel_timer();
+    }
+}
+
+impl<S, R> Unpin for ReactorTimeoutFuture<S, R>
 where
     S: Send + 'static,
     R: Send + 'static,
 {
 }
 
-/// One message sent to the reactor. Contains the data, the waker to awake the waiting future,
+/// One message sent to the reactor. Contains the data, its priority, the waker to awake the waiting future,
 /// and the sender to send the data back.
 struct ReactorDatagram<S, R>
 where
@@ -78,20 +202,22 @@ where
     R: Send + 'static,
 {
     pub data: S,
+    pub priority: Priority,
     pub waker: Waker,
     pub sender: Sender<R>,
 }
 
-impl<S, R> From<(S, Waker, Sender<R>)> for ReactorDatagram<S, R>
+impl<S, R> From<(S, Priority, Waker, Sender<R>)> for ReactorDatagram<S, R>
 where
     S: Send + 'static,
     R: Send + 'static,
 {
-    fn from(tuple: (S, Waker, Sender<R>)) -> Self {
+    fn from(tuple: (S, Priority, Waker, Sender<R>)) -> Self {
         Self {
             data: tuple.0,
-            waker: tuple.1,
-            sender: tuple.2,
+            priority: tuple.1,
+            waker: tuple.2,
+            sender: tuple.3,
         }
     }
 }
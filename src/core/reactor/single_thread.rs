@@ -1,8 +1,12 @@
-use crate::core::reactor::{ReactorDatagram, ReactorFuture, ReactorFutureData};
-use crossbeam::channel::{bounded, unbounded, Receiver, Sender};
+use crate::core::reactor::{Priority, ReactorDatagram, ReactorFuture, ReactorFutureData, ReactorTimeoutFuture};
+use crossbeam::channel::{bounded, unbounded, Receiver, Sender, TrySendError};
 use futures::task::Waker;
-use std::sync::Arc;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::mem;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 /// Single thread reactor type. Uses a single sacrificial thread to process work.
 ///
@@ -38,8 +42,45 @@ where
     where
         A: (Fn(S) -> R) + Send + 'static,
     {
-        let (send, recv) = unbounded();
-        let reactor = Arc::new(SingleThreadedReactorImpl { receiver: recv });
+        Self::from_channel(f, unbounded())
+    }
+
+    /// Construct a reactor whose input queue only holds up to `capacity` inputs at a time.
+    ///
+    /// Once the queue is full, futures from [`send_async`](#method.send_async) wait for there to be space in the
+    /// queue before their input is sent, instead of growing the queue without bound.
+    ///
+    /// To process inputs in priority order, the reactor moves inputs from the queue into its own priority queue, which
+    /// also holds up to `capacity` inputs. A bounded reactor therefore holds at most `2 * capacity` inputs which
+    /// haven't been processed yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero. A queue without space only hands over an input while the reactor is blocked
+    /// waiting for one, so a future which found the queue full could miss the moment it frees up and wait forever.
+    ///
+    /// # Example
+    ///
+    /// ```edition2018
+    /// # use nova_rs::core::reactor::SingleThreadReactor;
+    /// let reactor: SingleThreadReactor<i32, i32> = SingleThreadReactor::from_action_bounded(|x| x * 2, 64);
+    /// ```
+    pub fn from_action_bounded<A>(f: A, capacity: usize) -> Self
+    where
+        A: (Fn(S) -> R) + Send + 'static,
+    {
+        assert!(capacity > 0, "Bounded reactors need space for at least one input");
+        Self::from_channel(f, bounded(capacity))
+    }
+
+    fn from_channel<A>(f: A, (send, recv): (Sender<ReactorDatagram<S, R>>, Receiver<ReactorDatagram<S, R>>)) -> Self
+    where
+        A: (Fn(S) -> R) + Send + 'static,
+    {
+        let reactor = Arc::new(SingleThreadedReactorImpl {
+            receiver: recv,
+            waiting: Mutex::new(Vec::new()),
+        });
         {
             let reactor = Arc::clone(&reactor);
             thread::spawn(move || reactor.run(f));
@@ -47,6 +88,11 @@ where
         Self { sender: send, reactor }
     }
 
+    /// Amount of inputs waiting in the queue to be processed.
+    pub fn queue_len(&self) -> usize {
+        self.sender.len()
+    }
+
     /// Send an input to the reactor for processing.
     ///
     /// # Example
@@ -64,16 +110,85 @@ where
     /// # )
     /// ```
     pub fn send_async(&self, data: S) -> ReactorFuture<S, R> {
+        self.send_async_prioritized(data, Priority::Normal)
+    }
+
+    /// Send an input to the reactor for processing, ahead of or behind other waiting inputs depending on `priority`.
+    ///
+    /// # Example
+    ///
+    /// ```edition2018
+    /// # #![feature(async_await)]
+    /// # use futures::executor::block_on;
+    /// # use nova_rs::core::reactor::{Priority, SingleThreadReactor};
+    /// # block_on(
+    /// # async {
+    /// let reactor = SingleThreadReactor::from_action(|x| x * 2);
+    /// let answer = reactor.send_async_prioritized(3, Priority::High).await;
+    /// assert_eq!(answer, 6);
+    /// # }
+    /// # )
+    /// ```
+    pub fn send_async_prioritized(&self, data: S, priority: Priority) -> ReactorFuture<S, R> {
         ReactorFuture {
-            data: ReactorFutureData::Unsent(data, self.clone()),
+            data: ReactorFutureData::Unsent(data, priority, self.clone()),
+        }
+    }
+
+    /// Send an input to the reactor for processing, giving up if the answer takes longer than `timeout`.
+    ///
+    /// # Example
+    ///
+    /// ```edition2018
+    /// # #![feature(async_await)]
+    /// # use futures::executor::block_on;
+    /// # use nova_rs::core::reactor::SingleThreadReactor;
+    /// # use std::time::Duration;
+    /// # block_on(
+    /// # async {
+    /// let reactor = SingleThreadReactor::from_action(|x| x * 2);
+    /// let answer = reactor.send_async_with_timeout(3, Duration::from_secs(1)).await;
+    /// assert_eq!(answer, Ok(6));
+    /// # }
+    /// # )
+    /// ```
+    pub fn send_async_with_timeout(&self, data: S, timeout: Duration) -> ReactorTimeoutFuture<S, R> {
+        ReactorTimeoutFuture {
+            data: ReactorFutureData::Unsent(data, Priority::Normal, self.clone()),
+            timeout,
+            deadline: None,
+            timer_entry: None,
         }
     }
 
-    pub(in crate::core::reactor) fn send(&self, data: S, waker: Waker) -> Receiver<R> {
+    /// Try to queue up an input, giving back the input if the queue is full.
+    ///
+    /// If the queue is full, the waker will be woken once the reactor takes an input out of the queue.
+    pub(in crate::core::reactor) fn try_send(
+        &self,
+        data: S,
+        priority: Priority,
+        waker: &Waker,
+    ) -> Result<Receiver<R>, S> {
         let (result_send, result_recv) = bounded(1);
-        let _ = self.sender.send((data, waker, result_send).into());
+        let datagram = match self
+            .sender
+            .try_send((data, priority, waker.clone(), result_send).into())
+        {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => return Ok(result_recv),
+            Err(TrySendError::Full(datagram)) => datagram,
+        };
 
-        result_recv
+        // Register to be woken up, then try again in case the reactor freed up space before we registered.
+        self.reactor
+            .waiting
+            .lock()
+            .expect("Reactor waiting list poisoned")
+            .push(waker.clone());
+        match self.sender.try_send(datagram) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => Ok(result_recv),
+            Err(TrySendError::Full(datagram)) => Err(datagram.data),
+        }
     }
 }
 
@@ -95,13 +210,14 @@ where
     }
 }
 
-/// Internal reactor. Contains only the receiver to receive new messages.
+/// Internal reactor. Contains the receiver to receive new messages and the futures waiting for space in the queue.
 struct SingleThreadedReactorImpl<S, R>
 where
     S: Send + 'static,
     R: Send + 'static,
 {
     receiver: Receiver<ReactorDatagram<S, R>>,
+    waiting: Mutex<Vec<Waker>>,
 }
 
 impl<S, R> SingleThreadedReactorImpl<S, R>
@@ -110,28 +226,119 @@ where
     R: Send + 'static,
 {
     /// Runs loop that runs the loop until the channel is hung up.
+    ///
+    /// Inputs waiting in the channel are pulled into a priority queue, so they can be processed in priority order.
+    /// For bounded reactors, the priority queue holds at most as many inputs as the channel, so backpressure still
+    /// applies, but up to twice the channel's capacity can be waiting in total.
     fn run<A>(&self, action: A)
     where
         A: Fn(S) -> R + Send + 'static,
     {
+        let mut queue = BinaryHeap::new();
+        let mut sequence = 0_u64;
+        let mut push = |queue: &mut BinaryHeap<QueuedDatagram<S, R>>, datagram| {
+            queue.push(QueuedDatagram { datagram, sequence });
+            sequence += 1;
+        };
+        let capacity = self.receiver.capacity();
+
         loop {
-            match self.receiver.recv() {
-                Err(_) => break,
-                Ok(datagram) => {
-                    let result = action(datagram.data);
-                    let _ = datagram.sender.send(result);
-                    datagram.waker.wake();
+            if queue.is_empty() {
+                match self.receiver.recv() {
+                    Err(_) => break,
+                    Ok(datagram) => push(&mut queue, datagram),
                 }
             }
+            while capacity.map_or(true, |capacity| queue.len() < capacity) {
+                match self.receiver.try_recv() {
+                    Ok(datagram) => push(&mut queue, datagram),
+                    Err(_) => break,
+                }
+            }
+
+            // There is space in the queue now, so let everyone waiting on it try again.
+            let waiting = mem::replace(
+                &mut *self.waiting.lock().expect("Reactor waiting list poisoned"),
+                Vec::new(),
+            );
+            for waker in waiting {
+                waker.wake();
+            }
+
+            if let Some(QueuedDatagram { datagram, .. }) = queue.pop() {
+                let result = action(datagram.data);
+                let _ = datagram.sender.send(result);
+                datagram.waker.wake();
+            }
         }
     }
 }
 
+/// Datagram waiting in the reactor's priority queue.
+///
+/// Ordered by priority, then by the order it was received in.
+struct QueuedDatagram<S, R>
+where
+    S: Send + 'static,
+    R: Send + 'static,
+{
+    datagram: ReactorDatagram<S, R>,
+    sequence: u64,
+}
+
+impl<S, R> Ord for QueuedDatagram<S, R>
+where
+    S: Send + 'static,
+    R: Send + 'static,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Earlier datagrams have a lower sequence number but need to compare as greater.
+        self.datagram
+            .priority
+            .cmp(&other.datagram.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl<S, R> PartialOrd for QueuedDatagram<S, R>
+where
+    S: Send + 'static,
+    R: Send + 'static,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S, R> PartialEq for QueuedDatagram<S, R>
+where
+    S: Send + 'static,
+    R: Send + 'static,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<S, R> Eq for QueuedDatagram<S, R>
+where
+    S: Send + 'static,
+    R: Send + 'static,
+{
+}
+
 #[cfg(test)]
 mod test {
-    use crate::core::reactor::SingleThreadReactor;
-    use futures::executor::LocalPool;
-    use futures::task::LocalSpawnExt;
+    use crate::core::reactor::{Priority, ReactorError, SingleThreadReactor};
+    use crossbeam::channel::unbounded;
+    use futures::executor::{block_on, LocalPool};
+    use futures::future::join_all;
+    use futures::task::{waker, ArcWake, Context, LocalSpawnExt};
+    use futures::{Future, Poll};
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn remote_doubler() {
@@ -157,4 +364,192 @@ mod test {
 
         pool.run();
     }
+
+    #[test]
+    fn timeout() {
+        let reactor: SingleThreadReactor<u64, u64> = SingleThreadReactor::from_action(|millis| {
+            thread::sleep(Duration::from_millis(millis));
+            millis
+        });
+
+        let slow = block_on(reactor.send_async_with_timeout(1000, Duration::from_millis(10)));
+        assert_eq!(slow, Err(ReactorError::TimedOut(Duration::from_millis(10))));
+
+        // The reactor is still busy with the slow operation, so this needs a generous timeout.
+        let fast = block_on(reactor.send_async_with_timeout(0, Duration::from_secs(10)));
+        assert_eq!(fast, Ok(0));
+    }
+
+    #[test]
+    fn many_timeouts() {
+        let reactor: SingleThreadReactor<u64, u64> = SingleThreadReactor::from_action(|millis| {
+            thread::sleep(Duration::from_millis(millis));
+            millis
+        });
+
+        // All of these share the timer thread, and time out while the reactor is stuck on the first one
+        let timeouts = (0..20).map(|i| reactor.send_async_with_timeout(200, Duration::from_millis(10 + i)));
+        for (i, result) in block_on(join_all(timeouts)).into_iter().enumerate() {
+            assert_eq!(
+                result,
+                Err(ReactorError::TimedOut(Duration::from_millis(10 + i as u64)))
+            );
+        }
+    }
+
+    /// Waker which does nothing, so the test can count its clones.
+    struct CountedWaker;
+
+    impl ArcWake for CountedWaker {
+        fn wake_by_ref(_: &Arc<Self>) {}
+    }
+
+    #[test]
+    fn timeouts_release_their_wakers() {
+        let reactor: SingleThreadReactor<i32, i32> = SingleThreadReactor::from_action(|x| x);
+        let counted = Arc::new(CountedWaker);
+        let waker = waker(Arc::clone(&counted));
+        let mut cx = Context::from_waker(&waker);
+
+        // Neither future gets near its deadline, so only finishing or being dropped takes its waker out of the timer
+        let mut finished = reactor.send_async_with_timeout(1, Duration::from_secs(3600));
+        loop {
+            if let Poll::Ready(result) = Pin::new(&mut finished).poll(&mut cx) {
+                assert_eq!(result, Ok(1));
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        let mut dropped = reactor.send_async_with_timeout(2, Duration::from_secs(3600));
+        assert!(Pin::new(&mut dropped).poll(&mut cx).is_pending());
+        drop(dropped);
+        drop(waker);
+
+        // The timer thread gets to the cancellations in its own time
+        let start = Instant::now();
+        while Arc::strong_count(&counted) > 1 {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "Timer kept the wakers of futures which no longer need them"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn bounded_queue() {
+        let (gate_send, gate_recv) = unbounded::<()>();
+        let reactor: SingleThreadReactor<i32, i32> = SingleThreadReactor::from_action_bounded(
+            move |x| {
+                gate_recv.recv().expect("Gate closed");
+                x
+            },
+            1,
+        );
+
+        let mut pool = LocalPool::new();
+        let mut spawner = pool.spawner();
+        let handles: Vec<_> = (0..4)
+            .map(|v| {
+                spawner
+                    .spawn_local_with_handle(reactor.send_async(v))
+                    .expect("couldn't spawn future")
+            })
+            .collect();
+
+        // The reactor is stuck on the first input, so only one more fits in the queue no matter how long we wait.
+        for _ in 0..5 {
+            pool.run_until_stalled();
+            assert!(reactor.queue_len() <= 1);
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(reactor.queue_len(), 1);
+
+        for _ in 0..4 {
+            gate_send.send(()).expect("Reactor hung up");
+        }
+        let results = pool.run_until(async move {
+            let mut results = Vec::new();
+            for handle in handles {
+                results.push(handle.await);
+            }
+            results
+        });
+        assert_eq!(results, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bounded_queue_without_space() {
+        let _: SingleThreadReactor<i32, i32> = SingleThreadReactor::from_action_bounded(|x| x, 0);
+    }
+
+    #[test]
+    fn bounded_queue_busy() {
+        let reactor: SingleThreadReactor<i32, i32> = SingleThreadReactor::from_action_bounded(
+            |x| {
+                thread::sleep(Duration::from_millis(1));
+                x
+            },
+            1,
+        );
+
+        // Senders on several threads keep finding the queue full while the reactor is busy, and all get through.
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let reactor = reactor.clone();
+                thread::spawn(move || block_on(join_all((0..20).map(|v| reactor.send_async(t * 100 + v)))))
+            })
+            .collect();
+        for (t, thread) in threads.into_iter().enumerate() {
+            let expected: Vec<_> = (0..20).map(|v| t as i32 * 100 + v).collect();
+            assert_eq!(thread.join().expect("Sender panicked"), expected);
+        }
+    }
+
+    #[test]
+    fn priority() {
+        let (gate_send, gate_recv) = unbounded::<()>();
+        let (started_send, started_recv) = unbounded::<()>();
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let reactor: SingleThreadReactor<i32, i32> = {
+            let processed = Arc::clone(&processed);
+            SingleThreadReactor::from_action(move |x| {
+                started_send.send(()).expect("Test hung up");
+                gate_recv.recv().expect("Gate closed");
+                processed.lock().unwrap().push(x);
+                x
+            })
+        };
+
+        let mut pool = LocalPool::new();
+        let mut spawner = pool.spawner();
+        let mut handles = Vec::new();
+
+        // Get the reactor stuck on its first input so everything else piles up in the queue.
+        handles.push(spawner.spawn_local_with_handle(reactor.send_async(0)).unwrap());
+        pool.run_until_stalled();
+        started_recv.recv().expect("Reactor hung up");
+
+        for v in 1..=10 {
+            let future = reactor.send_async_prioritized(v, Priority::Low);
+            handles.push(spawner.spawn_local_with_handle(future).unwrap());
+        }
+        let future = reactor.send_async_prioritized(100, Priority::High);
+        handles.push(spawner.spawn_local_with_handle(future).unwrap());
+        pool.run_until_stalled();
+
+        for _ in 0..handles.len() {
+            gate_send.send(()).expect("Reactor hung up");
+        }
+        pool.run_until(async move {
+            for handle in handles {
+                handle.await;
+            }
+        });
+
+        let mut expected = vec![0, 100];
+        expected.extend(1..=10);
+        assert_eq!(*processed.lock().unwrap(), expected);
+    }
 }
@@ -0,0 +1,87 @@
+//! A single timer thread which wakes futures up once their deadline passes.
+
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use futures::task::Waker;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Instant;
+
+lazy_static! {
+    /// Sends deadlines to the timer thread, which is started the first time a deadline is set.
+    static ref TIMER: Sender<TimerMessage> = {
+        let (sender, receiver) = unbounded();
+        thread::spawn(move || run_timer(&receiver));
+        sender
+    };
+}
+
+/// Source of [`TimerEntry`] ids. Wakers can't be ordered, so the ids keep equal deadlines apart.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A waker waiting in the timer, which can be taken out again with [`cancel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(in crate::core::reactor) struct TimerEntry {
+    deadline: Instant,
+    id: u64,
+}
+
+enum TimerMessage {
+    Add(TimerEntry, Waker),
+    Cancel(TimerEntry),
+}
+
+/// Wakes `waker` once `deadline` has passed, unless the returned entry is [cancelled](cancel) first.
+///
+/// Every deadline is handled by the same thread, so waiting on many timeouts at once doesn't cost a thread each.
+pub(in crate::core::reactor) fn wake_at(deadline: Instant, waker: Waker) -> TimerEntry {
+    let entry = TimerEntry {
+        deadline,
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+    };
+    // The timer thread only stops once the sender is dropped, which never happens to a static
+    let _ = TIMER.send(TimerMessage::Add(entry, waker));
+    entry
+}
+
+/// Drops the waker of an entry without waking it, for futures which no longer need waking.
+///
+/// Entries whose deadline already passed are ignored.
+pub(in crate::core::reactor) fn cancel(entry: TimerEntry) {
+    let _ = TIMER.send(TimerMessage::Cancel(entry));
+}
+
+/// Waits for the earliest deadline, waking its waker, while taking in new deadlines and cancellations as they arrive.
+fn run_timer(receiver: &Receiver<TimerMessage>) {
+    // Entries are ordered by deadline first, so the first one is always the next to wake
+    let mut wakers: BTreeMap<TimerEntry, Waker> = BTreeMap::new();
+
+    loop {
+        let now = Instant::now();
+        let next = loop {
+            let entry = match wakers.keys().next() {
+                Some(&entry) if entry.deadline <= now => entry,
+                next => break next.map(|entry| entry.deadline),
+            };
+            if let Some(waker) = wakers.remove(&entry) {
+                waker.wake();
+            }
+        };
+
+        let message = match next {
+            Some(deadline) => receiver.recv_timeout(deadline - now),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match message {
+            Ok(TimerMessage::Add(entry, waker)) => {
+                wakers.insert(entry, waker);
+            }
+            Ok(TimerMessage::Cancel(entry)) => {
+                wakers.remove(&entry);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Lookup table from virtual texture IDs to where the texture actually lives in an atlas.
+//!
+//! Every vertex carries a `virtual_texture_id` (see [`FullVertex`](crate::mesh::FullVertex)). Shaders use that ID to
+//! index into a buffer built from a [`VirtualTextureTable`] to find the real UV region of the texture in the atlas.
+
+use crate::core::atlas::{AtlasRegion, PackedAtlas};
+use crate::rhi::{BufferCreateInfo, BufferUsage, DeviceMemoryAllocation};
+use cgmath::Vector2;
+use std::collections::HashMap;
+
+/// Maps virtual texture IDs to UV regions in a texture atlas.
+///
+/// IDs are handed out in registration order starting at zero, and stay stable when a texture is registered again,
+/// so the atlas can be repacked without having to touch any vertex data.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualTextureTable {
+    ids: HashMap<String, u32>,
+    regions: Vec<UvRegion>,
+}
+
+/// UV rectangle of a single texture in the atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvRegion {
+    /// UV coordinate of the top left corner.
+    pub min: Vector2<f32>,
+
+    /// UV coordinate of the bottom right corner.
+    pub max: Vector2<f32>,
+}
+
+impl From<AtlasRegion> for UvRegion {
+    fn from(region: AtlasRegion) -> Self {
+        Self {
+            min: region.uv_min,
+            max: region.uv_max,
+        }
+    }
+}
+
+impl VirtualTextureTable {
+    /// Size in bytes of a single entry in the GPU buffer.
+    ///
+    /// Each entry is a `vec4` of `(min.x, min.y, max.x, max.y)`, so the buffer can be declared as a `vec4[]` in a
+    /// std430 storage buffer.
+    pub const ENTRY_SIZE: usize = 16;
+
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a texture's region, returning its virtual texture ID.
+    ///
+    /// If a texture with this name is already registered, its region is replaced and the existing ID is returned.
+    pub fn register(&mut self, name: &str, region: UvRegion) -> u32 {
+        if let Some(&id) = self.ids.get(name) {
+            self.regions[id as usize] = region;
+            return id;
+        }
+
+        let id = self.regions.len() as u32;
+        self.regions.push(region);
+        self.ids.insert(name.to_owned(), id);
+        id
+    }
+
+    /// Registers every texture in a packed atlas.
+    ///
+    /// Textures are registered in name order so that a fresh table always hands out the same IDs for the same atlas.
+    pub fn register_atlas(&mut self, atlas: &PackedAtlas) {
+        let mut names: Vec<&String> = atlas.regions.keys().collect();
+        names.sort();
+        for name in names {
+            self.register(name, atlas.regions[name].into());
+        }
+    }
+
+    /// Gets the ID of a registered texture.
+    pub fn id_of(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    /// Gets the region of the texture with the given ID.
+    pub fn region(&self, id: u32) -> Option<UvRegion> {
+        self.regions.get(id as usize).copied()
+    }
+
+    /// Amount of textures in the table.
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Whether the table has no textures.
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Serializes the table into the little endian buffer layout the shaders expect, indexed by virtual texture ID.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.regions.len() * Self::ENTRY_SIZE);
+        for region in &self.regions {
+            for value in &[region.min.x, region.min.y, region.max.x, region.max.y] {
+                bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Describes the buffer that [`to_bytes`](VirtualTextureTable::to_bytes) should be uploaded to.
+    ///
+    /// The table grows with every texture and is indexed by ID, so it's a storage buffer. Uniform buffers may be
+    /// limited to 16 KiB, which is only 1024 entries.
+    pub fn buffer_create_info(&self, allocation: DeviceMemoryAllocation) -> BufferCreateInfo {
+        BufferCreateInfo {
+            size: self.regions.len() * Self::ENTRY_SIZE,
+            buffer_usage: BufferUsage::StorageBuffer,
+            allocation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::atlas::AtlasPacker;
+
+    fn region(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> UvRegion {
+        UvRegion {
+            min: Vector2::new(min_x, min_y),
+            max: Vector2::new(max_x, max_y),
+        }
+    }
+
+    #[test]
+    fn stable_ids() {
+        let mut table = VirtualTextureTable::new();
+        assert_eq!(table.register("stone", region(0.0, 0.0, 0.5, 0.5)), 0);
+        assert_eq!(table.register("dirt", region(0.5, 0.0, 1.0, 0.5)), 1);
+        assert_eq!(table.register("stone", region(0.0, 0.5, 0.5, 1.0)), 0);
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.id_of("dirt"), Some(1));
+        assert_eq!(table.region(0), Some(region(0.0, 0.5, 0.5, 1.0)));
+        assert_eq!(table.region(2), None);
+    }
+
+    #[test]
+    fn buffer_layout() {
+        let mut table = VirtualTextureTable::new();
+        table.register("stone", region(0.0, 0.25, 0.5, 1.0));
+
+        let bytes = table.to_bytes();
+        assert_eq!(bytes.len(), VirtualTextureTable::ENTRY_SIZE);
+        assert_eq!(&bytes[4..8], &0.25_f32.to_bits().to_le_bytes());
+        assert_eq!(&bytes[12..16], &1.0_f32.to_bits().to_le_bytes());
+        let info = table.buffer_create_info(DeviceMemoryAllocation);
+        assert_eq!(info.size, bytes.len());
+        assert_eq!(info.buffer_usage, BufferUsage::StorageBuffer);
+    }
+
+    #[test]
+    fn from_atlas() {
+        let mut packer = AtlasPacker::new(256);
+        packer.add_texture("b".into(), Vector2::new(16, 16));
+        packer.add_texture("a".into(), Vector2::new(16, 16));
+        let atlas = packer.pack().unwrap();
+
+        let mut table = VirtualTextureTable::new();
+        table.register_atlas(&atlas);
+        assert_eq!(table.id_of("a"), Some(0));
+        assert_eq!(table.id_of("b"), Some(1));
+        assert_eq!(table.region(1), Some(atlas.regions["b"].into()));
+    }
+}
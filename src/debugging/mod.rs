@@ -2,3 +2,132 @@
 //!
 //! Includes helpful things like a wrapper around the RenderDoc API, CPU and memory profiling, and other things that can
 //! help runtime debugging.
+
+use crate::shaderpack::{ShaderSet, ShaderpackData};
+use std::fmt;
+
+/// A summary of Nova's current state, for applications to show in a debug overlay.
+///
+/// Everything in a snapshot is cheap to collect, so a new snapshot can be taken every frame. Each part is optional
+/// because it may not exist yet, such as when no shaderpack has been loaded.
+///
+/// The `Display` implementation formats the snapshot as a few lines of text, ready to be drawn on screen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugSnapshot {
+    /// Summary of the loaded shaderpack.
+    pub shaderpack: Option<ShaderpackSummary>,
+
+    /// Free VRAM on the device Nova renders with, in bytes.
+    pub free_device_memory: Option<u64>,
+
+    /// Number of file operations queued for the file loading thread which it hasn't started yet.
+    pub pending_file_operations: Option<usize>,
+}
+
+impl fmt::Display for DebugSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.shaderpack {
+            Some(summary) => writeln!(f, "{}", summary)?,
+            None => writeln!(f, "No shaderpack loaded")?,
+        }
+        if let Some(free) = self.free_device_memory {
+            writeln!(f, "Free VRAM: {} MiB", free / (1024 * 1024))?;
+        }
+        if let Some(pending) = self.pending_file_operations {
+            writeln!(f, "Pending file operations: {}", pending)?;
+        }
+        Ok(())
+    }
+}
+
+/// How many of each thing a shaderpack contains.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShaderpackSummary {
+    /// Number of render passes.
+    pub passes: usize,
+
+    /// Number of pipelines.
+    pub pipelines: usize,
+
+    /// Number of materials.
+    pub materials: usize,
+
+    /// Number of dynamic textures.
+    pub textures: usize,
+
+    /// Number of samplers.
+    pub samplers: usize,
+
+    /// Number of distinct shaders.
+    pub shaders: usize,
+}
+
+impl ShaderpackSummary {
+    /// Counts the contents of a shaderpack.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - The shaderpack to summarize.
+    pub fn new(data: &ShaderpackData) -> Self {
+        Self {
+            passes: data.passes.len(),
+            pipelines: data.pipelines.len(),
+            materials: data.materials.len(),
+            textures: data.resources.textures.len(),
+            samplers: data.resources.samplers.len(),
+            shaders: match &data.shaders {
+                ShaderSet::Sources(shaders) => shaders.len(),
+                ShaderSet::Compiled(shaders) => shaders.len(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ShaderpackSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Shaderpack: {} passes, {} pipelines, {} materials, {} textures, {} samplers, {} shaders",
+            self.passes, self.pipelines, self.materials, self.textures, self.samplers, self.shaders
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shaderpack::{RenderPassCreationInfo, ShaderpackResourceData};
+
+    #[test]
+    fn snapshot_display() {
+        assert_eq!(DebugSnapshot::default().to_string(), "No shaderpack loaded\n");
+
+        let passes: Vec<RenderPassCreationInfo> = serde_json::from_str(include_str!(
+            "../../tests/data/shaderpacks/nova/DefaultShaderpack/passes.json"
+        ))
+        .expect("Failed to parse passes.json");
+        let resources: ShaderpackResourceData = serde_json::from_str(include_str!(
+            "../../tests/data/shaderpacks/nova/DefaultShaderpack/resources.json"
+        ))
+        .expect("Failed to parse resources.json");
+        let data = ShaderpackData {
+            pipelines: Vec::new(),
+            passes,
+            materials: Vec::new(),
+            resources,
+            shaders: ShaderSet::Sources(Vec::new()),
+        };
+
+        let snapshot = DebugSnapshot {
+            shaderpack: Some(ShaderpackSummary::new(&data)),
+            free_device_memory: Some(512 * 1024 * 1024),
+            pending_file_operations: Some(3),
+        };
+        assert_eq!(
+            snapshot.to_string(),
+            "Shaderpack: 2 passes, 0 pipelines, 0 materials, 2 textures, 1 samplers, 0 shaders\n\
+             Free VRAM: 512 MiB\n\
+             Pending file operations: 3\n"
+        );
+    }
+}
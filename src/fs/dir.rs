@@ -1,9 +1,13 @@
 //! Directory reading/writing.
 
+use log::warn;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ffi::OsString;
+use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 /// A fully loaded directory tree. Result of calling [`read_recursive`] on a directory path.
 pub struct DirectoryTree {
@@ -73,33 +77,65 @@ impl DirectoryEntry {
     }
 }
 
-fn read_recursive_impl(root: &Path, relative: &Path) -> Result<DirectoryEntry, io::Error> {
-    let real_path = {
-        let mut p = root.to_path_buf();
-        p.push(relative);
-        p
-    };
+/// Reads a directory, along with all the directories inside of it.
+///
+/// Whether an entry is a directory comes from the directory listing itself, which needs no extra syscall on most
+/// platforms. Only symlinks which are followed are looked up on their own.
+fn read_directory_impl(
+    path: &Path,
+    follow_symlinks: bool,
+    ignore: &IgnorePatterns,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<DirectoryEntry, io::Error> {
+    // Only links can lead back into a directory being enumerated, so there's no need to track ancestors otherwise
+    if follow_symlinks {
+        ancestors.push(fs::canonicalize(path)?);
+    }
 
-    if real_path.is_file() {
-        Ok(DirectoryEntry::File)
-    } else {
-        let mut map = HashMap::new();
-        for entry_result in real_path.read_dir()? {
-            let entry = entry_result?;
-            let file_name = entry.file_name();
-            let new_path = {
-                let mut p = relative.to_path_buf();
-                p.push(file_name);
-                p
-            };
-            map.insert(entry.file_name(), read_recursive_impl(root, &new_path)?);
+    let mut map = HashMap::new();
+    for entry_result in path.read_dir()? {
+        let entry = entry_result?;
+        if ignore.matches(&entry.file_name().to_string_lossy()) {
+            continue;
         }
-        Ok(DirectoryEntry::Directory { entries: map })
+        let mut file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            if !follow_symlinks {
+                continue;
+            }
+            match fs::canonicalize(entry.path()) {
+                // A link to a directory we're already inside would be enumerated forever
+                Ok(target) if ancestors.contains(&target) => {
+                    warn!("Skipping symlink {:?}, which loops back to {:?}", entry.path(), target);
+                    continue;
+                }
+                Ok(target) => file_type = fs::metadata(target)?.file_type(),
+                Err(err) => {
+                    warn!("Skipping broken symlink {:?}: {}", entry.path(), err);
+                    continue;
+                }
+            }
+        }
+
+        let child = if file_type.is_dir() {
+            read_directory_impl(&entry.path(), follow_symlinks, ignore, ancestors)?
+        } else {
+            DirectoryEntry::File
+        };
+        map.insert(entry.file_name(), child);
     }
+
+    if follow_symlinks {
+        ancestors.pop();
+    }
+
+    Ok(DirectoryEntry::Directory { entries: map })
 }
 
 /// Reads a given path recursively. Succeeds on both files and directories.
 ///
+/// Symlinks inside the directory are skipped. Use [`read_recursive_with_options`] to follow them.
+///
 /// # Example
 ///
 /// ```edition2018,no_run
@@ -122,11 +158,466 @@ pub fn read_recursive<P>(root: P) -> Result<DirectoryTree, io::Error>
 where
     P: AsRef<Path>,
 {
-    let root = std::fs::canonicalize(root)?;
-    let entry = read_recursive_impl(&root, Path::new(""))?;
+    read_recursive_with_options(root, false, &IgnorePatterns::none())
+}
+
+/// Reads a given path recursively, choosing whether to follow symlinks inside the directory and which entries to
+/// leave out.
+///
+/// Not following symlinks keeps enumeration inside the directory. When following them, a link to a directory which is
+/// being enumerated already, such as a link to the parent directory, is skipped instead of being followed forever.
+/// Directories are told apart by their canonical path, which identifies a directory like an inode number would but
+/// works on every platform. Broken links are skipped either way.
+///
+/// # Parameters
+///
+/// * `root` - The file or directory to read.
+/// * `follow_symlinks` - Whether to enumerate the targets of symlinks, or skip the links.
+/// * `ignore` - Files and directories to leave out of the tree, along with everything inside them.
+pub fn read_recursive_with_options<P>(
+    root: P,
+    follow_symlinks: bool,
+    ignore: &IgnorePatterns,
+) -> Result<DirectoryTree, io::Error>
+where
+    P: AsRef<Path>,
+{
+    let root = fs::canonicalize(root)?;
+    let entry = if root.is_dir() {
+        read_directory_impl(&root, follow_symlinks, ignore, &mut Vec::new())?
+    } else {
+        DirectoryEntry::File
+    };
+
+    Ok(DirectoryTree { root, entry })
+}
+
+/// Names of files and directories to leave out when enumerating a directory.
+///
+/// Patterns are matched against the name of each entry, not its whole path. `*` matches any run of characters and `?`
+/// matches a single character. The [default](IgnorePatterns::default) patterns cover hidden files, version control
+/// directories and the temp and backup files of common editors, which show up when authors work on a pack in place.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IgnorePatterns {
+    patterns: Vec<String>,
+}
+
+impl IgnorePatterns {
+    /// Patterns which [`default`](IgnorePatterns::default) ignores.
+    pub const DEFAULT_PATTERNS: &'static [&'static str] = &[
+        ".*",
+        "*~",
+        "*.swp",
+        "*.swo",
+        "*.bak",
+        "*.tmp",
+        "#*#",
+        "Thumbs.db",
+        "desktop.ini",
+    ];
+
+    /// Ignores nothing.
+    pub fn none() -> Self {
+        Self { patterns: Vec::new() }
+    }
+
+    /// Ignores entries whose name matches any of the given patterns.
+    pub fn new<I, S>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            patterns: patterns.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Adds another pattern to ignore.
+    pub fn add<S>(&mut self, pattern: S)
+    where
+        S: Into<String>,
+    {
+        self.patterns.push(pattern.into());
+    }
+
+    /// The patterns being ignored.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Whether an entry with the given name is ignored.
+    pub fn matches(&self, name: &str) -> bool {
+        self.patterns.iter().any(|pattern| wildcard_matches(pattern, name))
+    }
+}
+
+impl Default for IgnorePatterns {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_PATTERNS.iter().copied())
+    }
+}
+
+/// Matches a name against a pattern where `*` stands for any run of characters and `?` for a single character.
+fn wildcard_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*`, if matching fails after it
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // Let the `*` swallow one more character
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Snapshot of a directory tree which remembers when each directory was last modified.
+///
+/// A directory's modification time changes whenever an entry is added, removed, or renamed inside of it, so a
+/// manifest can be brought up to date by only re-reading the directories whose modification time changed. Can be
+/// saved to and loaded from disk to speed up repeated enumerations of large, mostly unchanged trees.
+///
+/// Like [`read_recursive`], symlinks inside the directory are skipped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryManifest {
+    /// Root of the directory tree. This is always an absolute path.
+    pub root: PathBuf,
+
+    /// Entries left out of the tree. These are kept out when the manifest is refreshed too.
+    pub ignore: IgnorePatterns,
+
+    /// Tree of manifest entries.
+    pub entry: ManifestEntry,
+}
+
+/// A single entry of a [`DirectoryManifest`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ManifestEntry {
+    /// The entry is a directory.
+    Directory {
+        /// When the directory was last modified.
+        modified: SystemTime,
+        /// All entries inside this directory.
+        entries: HashMap<OsString, ManifestEntry>,
+    },
+
+    /// The entry is a file.
+    File,
+}
+
+impl DirectoryManifest {
+    /// Creates a manifest by fully enumerating the given path.
+    pub fn from_path<P>(root: P) -> Result<Self, io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_path_with_ignore(root, IgnorePatterns::none())
+    }
+
+    /// Creates a manifest by fully enumerating the given path, leaving out the entries matching `ignore` along with
+    /// everything inside them.
+    pub fn from_path_with_ignore<P>(root: P, ignore: IgnorePatterns) -> Result<Self, io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let root = fs::canonicalize(root)?;
+        let entry = read_manifest_entry(&root, &ignore)?;
+
+        Ok(Self { root, ignore, entry })
+    }
+
+    /// Loads a manifest previously written with [`save`](DirectoryManifest::save).
+    pub fn load<P>(path: P) -> Result<Self, io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let file = fs::File::open(path)?;
+        serde_json::from_reader(io::BufReader::new(file)).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Writes the manifest to disk.
+    pub fn save<P>(&self, path: P) -> Result<(), io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let file = fs::File::create(path)?;
+        serde_json::to_writer(io::BufWriter::new(file), self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Brings the manifest up to date with the filesystem, only re-reading directories which have changed.
+    ///
+    /// Returns the amount of directories which had to be re-read.
+    pub fn refresh(&mut self) -> Result<usize, io::Error> {
+        refresh_manifest_entry(&self.root, &mut self.entry, &self.ignore)
+    }
+
+    /// Converts the manifest into a [`DirectoryTree`].
+    pub fn to_tree(&self) -> DirectoryTree {
+        DirectoryTree {
+            root: self.root.clone(),
+            entry: self.entry.to_directory_entry(),
+        }
+    }
+}
+
+impl ManifestEntry {
+    fn to_directory_entry(&self) -> DirectoryEntry {
+        match self {
+            Self::File => DirectoryEntry::File,
+            Self::Directory { entries, .. } => DirectoryEntry::Directory {
+                entries: entries
+                    .iter()
+                    .map(|(name, entry)| (name.clone(), entry.to_directory_entry()))
+                    .collect(),
+            },
+        }
+    }
+}
+
+fn read_manifest_entry(path: &Path, ignore: &IgnorePatterns) -> Result<ManifestEntry, io::Error> {
+    if path.is_file() {
+        return Ok(ManifestEntry::File);
+    }
+
+    let modified = fs::metadata(path)?.modified()?;
+    let mut entries = HashMap::new();
+    for entry_result in path.read_dir()? {
+        let entry = entry_result?;
+        if ignore.matches(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        let child = if file_type.is_symlink() {
+            continue;
+        } else if file_type.is_dir() {
+            read_manifest_entry(&entry.path(), ignore)?
+        } else {
+            ManifestEntry::File
+        };
+        entries.insert(entry.file_name(), child);
+    }
+
+    Ok(ManifestEntry::Directory { modified, entries })
+}
+
+fn refresh_manifest_entry(path: &Path, entry: &mut ManifestEntry, ignore: &IgnorePatterns) -> Result<usize, io::Error> {
+    let entries = match entry {
+        ManifestEntry::Directory { modified, entries } if path.is_dir() => {
+            if fs::metadata(path)?.modified()? != *modified {
+                *entry = read_manifest_entry(path, ignore)?;
+                return Ok(1);
+            }
+            entries
+        }
+        // The entry changed between being a file and a directory, so the parent directory will have been re-read.
+        _ => return Ok(0),
+    };
+
+    let mut reread = 0;
+    for (name, child) in entries.iter_mut() {
+        if let ManifestEntry::Directory { .. } = child {
+            reread += refresh_manifest_entry(&path.join(name), child, ignore)?;
+        }
+    }
+    Ok(reread)
+}
+
+/// Reads a given path recursively, using the manifest at `manifest_path` to avoid re-reading unchanged directories.
+///
+/// If the manifest is missing, unreadable, or for a different path or different ignore patterns, the directory is
+/// fully enumerated. Either way, the up to date manifest is written back to `manifest_path`.
+///
+/// # Parameters
+///
+/// * `root` - The file or directory to read.
+/// * `manifest_path` - Where the manifest is stored.
+/// * `ignore` - Files and directories to leave out of the tree, along with everything inside them.
+pub fn read_recursive_with_manifest<P, M>(
+    root: P,
+    manifest_path: M,
+    ignore: &IgnorePatterns,
+) -> Result<DirectoryTree, io::Error>
+where
+    P: AsRef<Path>,
+    M: AsRef<Path>,
+{
+    let root = fs::canonicalize(root)?;
+
+    let manifest = match DirectoryManifest::load(&manifest_path) {
+        Ok(mut manifest) if manifest.root == root && manifest.ignore == *ignore => {
+            manifest.refresh()?;
+            manifest
+        }
+        _ => DirectoryManifest::from_path_with_ignore(&root, ignore.clone())?,
+    };
+    manifest.save(manifest_path)?;
+
+    Ok(manifest.to_tree())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fs::dir::{
+        read_recursive, read_recursive_with_manifest, read_recursive_with_options, DirectoryEntry, DirectoryManifest,
+        IgnorePatterns,
+    };
+    use crate::test_utils::TempDir;
+    use std::fs;
+
+    fn test_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("manifest-{}", name));
+        fs::create_dir_all(dir.path().join("shaders")).unwrap();
+        fs::write(dir.path().join("passes.json"), "[]").unwrap();
+        fs::write(dir.path().join("shaders").join("gui.vert"), "").unwrap();
+        dir
+    }
+
+    #[test]
+    fn manifest_unchanged() {
+        let temp = test_dir("unchanged");
+        let dir = temp.path();
+        let mut manifest = DirectoryManifest::from_path(&dir).unwrap();
+
+        assert_eq!(manifest.refresh().unwrap(), 0);
+        assert_eq!(manifest.to_tree().entry, read_recursive(&dir).unwrap().entry);
+    }
+
+    #[test]
+    fn manifest_invalidated() {
+        let temp = test_dir("invalidated");
+        let dir = temp.path();
+        let mut manifest = DirectoryManifest::from_path(&dir).unwrap();
 
-    Ok(DirectoryTree {
-        root: std::fs::canonicalize(root)?,
-        entry,
-    })
+        fs::write(dir.join("shaders").join("gui.frag"), "").unwrap();
+        assert_eq!(manifest.refresh().unwrap(), 1);
+        assert_eq!(
+            manifest.to_tree().entry.get("shaders/gui.frag"),
+            Some(&DirectoryEntry::File)
+        );
+        assert_eq!(manifest.to_tree().entry, read_recursive(&dir).unwrap().entry);
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let temp = test_dir("round-trip");
+        let dir = temp.path();
+        let manifest_dir = TempDir::new("manifest-round-trip-manifest");
+        let manifest_path = manifest_dir.path().join("pack.manifest");
+
+        // No manifest yet, so this fully enumerates and writes one.
+        let tree = read_recursive_with_manifest(&dir, &manifest_path, &IgnorePatterns::none()).unwrap();
+        assert_eq!(tree.entry, read_recursive(&dir).unwrap().entry);
+        assert_eq!(
+            DirectoryManifest::load(&manifest_path).unwrap(),
+            DirectoryManifest::from_path(&dir).unwrap()
+        );
+
+        fs::remove_file(dir.join("passes.json")).unwrap();
+        let tree = read_recursive_with_manifest(&dir, &manifest_path, &IgnorePatterns::none()).unwrap();
+        assert_eq!(tree.entry.get("passes.json"), None);
+    }
+
+    #[test]
+    fn manifest_ignore_patterns() {
+        let temp = test_dir("ignore");
+        let dir = temp.path();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join(".git").join("HEAD"), "").unwrap();
+        fs::write(dir.join("shaders").join("gui.vert~"), "").unwrap();
+        let manifest_dir = TempDir::new("manifest-ignore-manifest");
+        let manifest_path = manifest_dir.path().join("pack.manifest");
+
+        let ignore = IgnorePatterns::default();
+        let tree = read_recursive_with_manifest(&dir, &manifest_path, &ignore).unwrap();
+        assert_eq!(
+            tree.entry,
+            read_recursive_with_options(&dir, false, &ignore).unwrap().entry
+        );
+        assert_eq!(tree.entry.get(".git"), None);
+        assert_eq!(tree.entry.get("shaders/gui.vert~"), None);
+
+        // Refreshing keeps new matches out too
+        fs::write(dir.join("shaders").join(".gui.frag.swp"), "").unwrap();
+        let tree = read_recursive_with_manifest(&dir, &manifest_path, &ignore).unwrap();
+        assert_eq!(
+            tree.entry,
+            read_recursive_with_options(&dir, false, &ignore).unwrap().entry
+        );
+
+        // A manifest made with other patterns isn't reused
+        let tree = read_recursive_with_manifest(&dir, &manifest_path, &IgnorePatterns::none()).unwrap();
+        assert_eq!(tree.entry.get(".git/HEAD"), Some(&DirectoryEntry::File));
+    }
+
+    #[test]
+    fn ignore_patterns() {
+        let ignore = IgnorePatterns::default();
+        for name in &[
+            ".git",
+            ".DS_Store",
+            ".gbuffers.frag.swp",
+            "final.frag~",
+            "#final.frag#",
+            "Thumbs.db",
+        ] {
+            assert!(ignore.matches(name), "{} isn't ignored", name);
+        }
+        for name in &["passes.json", "gui.vert", "swp", "a.swp.json"] {
+            assert!(!ignore.matches(name), "{} is ignored", name);
+        }
+
+        let mut custom = IgnorePatterns::none();
+        assert!(!custom.matches(".git"));
+        custom.add("draft_*.ma?");
+        assert!(custom.matches("draft_sky.mat"));
+        assert!(custom.matches("draft_.map"));
+        assert!(!custom.matches("draft_sky.mat.json"));
+        assert!(!custom.matches("sky.mat"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycle_terminates() {
+        use std::os::unix::fs::symlink;
+
+        let temp = test_dir("symlinks");
+        let dir = temp.path();
+        symlink(&dir, dir.join("shaders").join("loop")).unwrap();
+        symlink(dir.join("passes.json"), dir.join("linked.json")).unwrap();
+        symlink(dir.join("missing"), dir.join("broken")).unwrap();
+
+        // Links are skipped by default
+        let tree = read_recursive(&dir).unwrap();
+        assert_eq!(tree.entry.get("shaders/gui.vert"), Some(&DirectoryEntry::File));
+        assert_eq!(tree.entry.get("shaders/loop"), None);
+        assert_eq!(tree.entry.get("linked.json"), None);
+        assert_eq!(DirectoryManifest::from_path(&dir).unwrap().to_tree().entry, tree.entry);
+
+        // Following them still doesn't follow the loop back to the root
+        let tree = read_recursive_with_options(&dir, true, &IgnorePatterns::none()).unwrap();
+        assert_eq!(tree.entry.get("linked.json"), Some(&DirectoryEntry::File));
+        assert_eq!(tree.entry.get("shaders/gui.vert"), Some(&DirectoryEntry::File));
+        assert_eq!(tree.entry.get("shaders/loop"), None);
+        assert_eq!(tree.entry.get("broken"), None);
+    }
 }
@@ -49,7 +49,12 @@ pub mod debugging;
 pub mod fs;
 pub mod loading;
 pub mod logging;
+pub mod mesh;
+pub mod renderer;
 pub mod rhi;
 pub mod settings;
 pub mod shaderpack;
 pub mod surface;
+
+#[cfg(test)]
+mod test_utils;
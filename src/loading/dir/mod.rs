@@ -1,11 +1,11 @@
-use crate::core::reactor::SingleThreadReactor;
-use crate::fs::dir::{DirectoryEntry, DirectoryTree};
-use crate::loading::{FileTree, LoadingError};
+use crate::core::reactor::{Priority, SingleThreadReactor};
+use crate::fs::dir::{DirectoryEntry, DirectoryTree, IgnorePatterns};
+use crate::loading::{FileTree, LoadingError, LoadingLimits, SizeLimiter};
 use futures::Future;
 use matches::matches;
 use std::collections::HashSet;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
 
@@ -26,17 +26,80 @@ pub struct DirectoryFileTree(Arc<DirectoryFileTreeData>);
 struct DirectoryFileTreeData {
     cache: DirectoryTree,
     reactor: SingleThreadReactor<FileSystemOp, FileSystemOpResult>,
+    size_limiter: SizeLimiter,
 }
 
-impl DirectoryFileTree {
-    fn get_node_at_location(&self, path: &Path) -> Option<&DirectoryEntry> {
-        self.0.cache.entry.get(path)
+impl DirectoryFileTreeData {
+    /// Path of a file of the tree on disk, after checking that it's a file of the enumerated tree and that its size is
+    /// within the limits.
+    ///
+    /// Only files which were enumerated can be read, so a symlink which wasn't followed or a path with `..` in it
+    /// can't reach outside of the tree.
+    fn checked_real_path(&self, path: &Path) -> Result<PathBuf, LoadingError> {
+        if path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return Err(LoadingError::PathNotFound);
+        }
+        match self.cache.entry.get(path) {
+            Some(DirectoryEntry::File) => {}
+            Some(DirectoryEntry::Directory { .. }) => return Err(LoadingError::NotFile),
+            None => return Err(LoadingError::PathNotFound),
+        }
+
+        let real_path = self.cache.root.join(path);
+        if let Ok(metadata) = std::fs::metadata(&real_path) {
+            self.size_limiter.check_file(path, metadata.len())?;
+        }
+        Ok(real_path)
     }
 }
 
-impl FileTree for DirectoryFileTree {
-    fn from_path(path: &Path) -> Self::FromPathResult {
-        let path = path.to_path_buf();
+/// How a [`DirectoryFileTree`] reads its directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryOptions {
+    /// Whether to follow symlinks inside the directory, see
+    /// [`read_recursive_with_options`](crate::fs::dir::read_recursive_with_options).
+    pub follow_symlinks: bool,
+
+    /// Limits on the size of the files read from the directory.
+    pub limits: LoadingLimits,
+
+    /// Files and directories to leave out of the tree. By default, these are hidden files such as `.git`, and editor
+    /// temp and backup files.
+    pub ignore: IgnorePatterns,
+}
+
+impl DirectoryFileTree {
+    /// Create a file tree from the path provided, using a manifest to avoid re-enumerating unchanged directories.
+    ///
+    /// The manifest at `manifest_path` is brought up to date and written back. If it is missing or stale, the
+    /// directory is fully enumerated. See [`DirectoryManifest`](crate::fs::dir::DirectoryManifest). The
+    /// [default ignore patterns](IgnorePatterns) apply, so the tree is the same as the one
+    /// [`from_path`](FileTree::from_path) reads.
+    pub fn from_path_with_manifest(path: &Path, manifest_path: &Path) -> <Self as FileTree>::FromPathResult {
+        let op = FileSystemOp::RecursiveEnumerateWithManifest(
+            path.to_path_buf(),
+            manifest_path.to_path_buf(),
+            IgnorePatterns::default(),
+        );
+        Self::from_path_impl(path.to_path_buf(), op, LoadingLimits::default())
+    }
+
+    /// Create a file tree from the path provided, choosing whether to follow symlinks inside the directory and how
+    /// much may be read from it.
+    ///
+    /// [`from_path`](FileTree::from_path) uses the default options: symlinks aren't followed, so a pack can't make Nova
+    /// read files from outside of it, and the [default limits](LoadingLimits::default) apply. Reads which would go
+    /// over the limits fail with [`LoadingError::ResourceTooLarge`]. The [default ignore patterns](IgnorePatterns)
+    /// keep hidden and temp files out of the tree.
+    pub fn from_path_with_options(path: &Path, options: DirectoryOptions) -> <Self as FileTree>::FromPathResult {
+        let op = FileSystemOp::RecursiveEnumerate(path.to_path_buf(), options.follow_symlinks, options.ignore);
+        Self::from_path_impl(path.to_path_buf(), op, options.limits)
+    }
+
+    fn from_path_impl(path: PathBuf, op: FileSystemOp, limits: LoadingLimits) -> <Self as FileTree>::FromPathResult {
         Pin::from(Box::new(async move {
             if !path.exists() {
                 return Err(LoadingError::ResourceNotFound);
@@ -47,17 +110,29 @@ impl FileTree for DirectoryFileTree {
 
             let reactor = SingleThreadReactor::from_action(file_system_reactor_core);
 
-            let future = reactor.send_async(FileSystemOp::RecursiveEnumerate(path));
+            let future = reactor.send_async(op);
 
             match future.await {
-                FileSystemOpResult::RecursiveEnumerate(cache) => {
-                    Ok(Self(Arc::new(DirectoryFileTreeData { cache, reactor })))
-                }
+                FileSystemOpResult::RecursiveEnumerate(cache) => Ok(Self(Arc::new(DirectoryFileTreeData {
+                    cache,
+                    reactor,
+                    size_limiter: SizeLimiter::new(limits),
+                }))),
                 FileSystemOpResult::Error(err) => Err(LoadingError::FileSystemError { sub_error: err.into() }),
                 _ => panic!("Incorrect directory action response received"),
             }
         }))
     }
+
+    fn get_node_at_location(&self, path: &Path) -> Option<&DirectoryEntry> {
+        self.0.cache.entry.get(path)
+    }
+}
+
+impl FileTree for DirectoryFileTree {
+    fn from_path(path: &Path) -> Self::FromPathResult {
+        Self::from_path_with_options(path, DirectoryOptions::default())
+    }
     type FromPathResult = Pin<Box<dyn Future<Output = Result<Self, LoadingError>> + Send>>;
 
     fn exists(&self, path: &Path) -> bool {
@@ -85,15 +160,17 @@ impl FileTree for DirectoryFileTree {
     }
 
     fn read(&self, path: &Path) -> Self::ReadResult {
+        self.read_prioritized(path, Priority::Normal)
+    }
+
+    fn read_prioritized(&self, path: &Path, priority: Priority) -> Self::ReadResult {
         let path = path.to_owned();
         let data = Arc::clone(&self.0);
         Pin::from(Box::new(async move {
-            let real_path = {
-                let mut p = data.cache.root.clone();
-                p.push(path);
-                p
-            };
-            let future = data.reactor.send_async(FileSystemOp::FileRead(real_path));
+            let real_path = data.checked_real_path(&path)?;
+            let future = data
+                .reactor
+                .send_async_prioritized(FileSystemOp::FileRead(real_path), priority);
 
             match future.await {
                 FileSystemOpResult::Error(error) => match error.error.kind() {
@@ -102,7 +179,10 @@ impl FileTree for DirectoryFileTree {
                         sub_error: error.into(),
                     }),
                 },
-                FileSystemOpResult::FileRead(data) => Ok(data),
+                FileSystemOpResult::FileRead(bytes) => {
+                    data.size_limiter.add(&path, bytes.len() as u64)?;
+                    Ok(bytes)
+                }
                 _ => panic!("Incorrect file read action response received."),
             }
         }))
@@ -113,11 +193,7 @@ impl FileTree for DirectoryFileTree {
         let path = path.to_owned();
         let data = Arc::clone(&self.0);
         Pin::from(Box::new(async move {
-            let real_path = {
-                let mut p = data.cache.root.clone();
-                p.push(path);
-                p
-            };
+            let real_path = data.checked_real_path(&path)?;
             let future = data.reactor.send_async(FileSystemOp::FileReadU32(real_path));
 
             match future.await {
@@ -127,7 +203,10 @@ impl FileTree for DirectoryFileTree {
                         sub_error: error.into(),
                     }),
                 },
-                FileSystemOpResult::FileReadU32(data) => Ok(data),
+                FileSystemOpResult::FileReadU32(words) => {
+                    data.size_limiter.add(&path, words.len() as u64 * 4)?;
+                    Ok(words)
+                }
                 _ => panic!("Incorrect file read action response received."),
             }
         }))
@@ -138,11 +217,7 @@ impl FileTree for DirectoryFileTree {
         let path = path.to_owned();
         let data = Arc::clone(&self.0);
         Pin::from(Box::new(async move {
-            let real_path = {
-                let mut p = data.cache.root.clone();
-                p.push(path);
-                p
-            };
+            let real_path = data.checked_real_path(&path)?;
             let future = data.reactor.send_async(FileSystemOp::FileReadText(real_path));
 
             match future.await {
@@ -152,7 +227,10 @@ impl FileTree for DirectoryFileTree {
                         sub_error: error.into(),
                     }),
                 },
-                FileSystemOpResult::FileReadText(data) => Ok(data),
+                FileSystemOpResult::FileReadText(text) => {
+                    data.size_limiter.add(&path, text.len() as u64)?;
+                    Ok(text)
+                }
                 _ => panic!("Incorrect file read action response received."),
             }
         }))
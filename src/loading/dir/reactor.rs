@@ -1,12 +1,13 @@
 use crate::fs;
-use crate::fs::dir::DirectoryTree;
+use crate::fs::dir::{DirectoryTree, IgnorePatterns};
 use failure::{Backtrace, Fail};
 use std::io;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub enum FileSystemOp {
-    RecursiveEnumerate(PathBuf),
+    RecursiveEnumerate(PathBuf, bool, IgnorePatterns),
+    RecursiveEnumerateWithManifest(PathBuf, PathBuf, IgnorePatterns),
     FileRead(PathBuf),
     FileReadU32(PathBuf),
     FileReadText(PathBuf),
@@ -42,10 +43,18 @@ impl FileSystemOpError {
 /// Core operation of the file system reactor
 pub(in crate::loading::dir) fn file_system_reactor_core(op: FileSystemOp) -> FileSystemOpResult {
     match &op {
-        FileSystemOp::RecursiveEnumerate(path) => match fs::dir::read_recursive(path) {
-            Ok(cache) => FileSystemOpResult::RecursiveEnumerate(cache),
-            Err(err) => FileSystemOpResult::Error(FileSystemOpError::from_path(err, op)),
-        },
+        FileSystemOp::RecursiveEnumerate(path, follow_symlinks, ignore) => {
+            match fs::dir::read_recursive_with_options(path, *follow_symlinks, ignore) {
+                Ok(cache) => FileSystemOpResult::RecursiveEnumerate(cache),
+                Err(err) => FileSystemOpResult::Error(FileSystemOpError::from_path(err, op)),
+            }
+        }
+        FileSystemOp::RecursiveEnumerateWithManifest(path, manifest, ignore) => {
+            match fs::dir::read_recursive_with_manifest(path, manifest, ignore) {
+                Ok(cache) => FileSystemOpResult::RecursiveEnumerate(cache),
+                Err(err) => FileSystemOpResult::Error(FileSystemOpError::from_path(err, op)),
+            }
+        }
         FileSystemOp::FileRead(path) => {
             let file = std::fs::File::open(path);
             match file {
@@ -0,0 +1,300 @@
+use crate::fs::dir::{read_recursive_with_options, DirectoryEntry, IgnorePatterns};
+use crate::fs::file::{read_stream_string, read_stream_u32};
+use crate::loading::{FileTree, LoadingError, LoadingLimits, SizeLimiter};
+use futures::future::{ready, Ready};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// File tree whose files are all held in memory.
+///
+/// Lets applications load a pack without it being on disk, such as one downloaded into memory or embedded in the
+/// binary with `include_bytes!`, through
+/// [`load_nova_shaderpack_from_tree`](crate::shaderpack::load_nova_shaderpack_from_tree). It's also what archive
+/// backends can decompress into.
+///
+/// Like [`DirectoryFileTree`](crate::loading::DirectoryFileTree), it is a thin [`Arc`] wrapper, so it's cheap to
+/// clone.
+#[derive(Debug, Clone)]
+pub struct MemoryFileTree(Arc<MemoryFileTreeData>);
+
+#[derive(Debug)]
+struct MemoryFileTreeData {
+    entry: DirectoryEntry,
+    files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFileTree {
+    /// Creates a file tree out of the contents of each file. Directories are created for the files' parents.
+    ///
+    /// Paths are relative to the root of the tree. If two files have the same path, the last one wins.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the paths is invalid, see [`try_new`](MemoryFileTree::try_new).
+    pub fn new<I, P>(files: I) -> Self
+    where
+        I: IntoIterator<Item = (P, Vec<u8>)>,
+        P: Into<PathBuf>,
+    {
+        Self::try_new(files).expect("Invalid path for memory file tree")
+    }
+
+    /// Creates a file tree out of the contents of each file, failing with [`LoadingError::InvalidPath`] for paths
+    /// which can't be part of the tree. Directories are created for the files' parents.
+    ///
+    /// Paths are relative to the root of the tree, so they can't be empty or contain anything other than plain names,
+    /// such as `..` or a root. A file also can't have the same path as another file's parent directory. If two files
+    /// have the same path, the last one wins.
+    pub fn try_new<I, P>(files: I) -> Result<Self, LoadingError>
+    where
+        I: IntoIterator<Item = (P, Vec<u8>)>,
+        P: Into<PathBuf>,
+    {
+        let mut entry = DirectoryEntry::Directory {
+            entries: HashMap::new(),
+        };
+        let mut file_map = HashMap::new();
+        for (path, contents) in files {
+            let path = path.into();
+            let names: Option<Vec<OsString>> = path
+                .components()
+                .map(|component| match component {
+                    Component::Normal(name) => Some(name.to_owned()),
+                    _ => None,
+                })
+                .collect();
+            let names = match names {
+                Some(names) if insert_file(&mut entry, &names) => names,
+                _ => return Err(LoadingError::InvalidPath { path }),
+            };
+            file_map.insert(names.iter().collect(), contents);
+        }
+
+        Ok(Self(Arc::new(MemoryFileTreeData { entry, files: file_map })))
+    }
+
+    /// Reads every file in a directory into memory, within the [default limits](LoadingLimits::default).
+    ///
+    /// Like [`DirectoryFileTree::from_path`](crate::loading::DirectoryFileTree), symlinks aren't followed and the
+    /// [default ignore patterns](IgnorePatterns::default) keep hidden and temp files out of the tree.
+    pub fn from_directory(path: &Path) -> Result<Self, LoadingError> {
+        Self::from_directory_with_limits(path, LoadingLimits::default())
+    }
+
+    /// Reads every file in a directory into memory, failing with [`LoadingError::ResourceTooLarge`] instead of reading
+    /// more than `limits` allow.
+    pub fn from_directory_with_limits(path: &Path, limits: LoadingLimits) -> Result<Self, LoadingError> {
+        if !path.exists() {
+            return Err(LoadingError::ResourceNotFound);
+        }
+        if !path.is_dir() {
+            return Err(LoadingError::NotDirectory);
+        }
+
+        let tree = read_recursive_with_options(path, false, &IgnorePatterns::default()).map_err(io_error)?;
+        let mut files = Vec::new();
+        let limiter = SizeLimiter::new(limits);
+        collect_files(&tree.root, &PathBuf::new(), &tree.entry, &limiter, &mut files)?;
+        Ok(Self::new(files))
+    }
+
+    fn read_bytes(&self, path: &Path) -> Result<&[u8], LoadingError> {
+        match self.0.files.get(path) {
+            Some(contents) => Ok(contents.as_slice()),
+            None if self.exists(path) => Err(LoadingError::NotFile),
+            None => Err(LoadingError::PathNotFound),
+        }
+    }
+}
+
+/// Adds directory entries for a file and its parents.
+///
+/// Returns false without adding the file if there are no names, or if the file or one of its parents would replace an
+/// entry of the other kind.
+fn insert_file(entry: &mut DirectoryEntry, names: &[OsString]) -> bool {
+    let (name, parents) = match names.split_last() {
+        Some(split) => split,
+        None => return false,
+    };
+
+    let mut node = entry;
+    for parent in parents {
+        let entries = match node {
+            DirectoryEntry::Directory { entries } => entries,
+            DirectoryEntry::File => return false,
+        };
+        node = entries
+            .entry(parent.clone())
+            .or_insert_with(|| DirectoryEntry::Directory {
+                entries: HashMap::new(),
+            });
+    }
+    match node {
+        DirectoryEntry::Directory { entries } => match entries.get(name) {
+            Some(DirectoryEntry::Directory { .. }) => false,
+            _ => {
+                entries.insert(name.clone(), DirectoryEntry::File);
+                true
+            }
+        },
+        DirectoryEntry::File => false,
+    }
+}
+
+/// Reads the contents of every file in an enumerated directory.
+///
+/// Files are checked against the limits before they're read, going by their size on disk, and again after.
+fn collect_files(
+    root: &Path,
+    relative: &Path,
+    entry: &DirectoryEntry,
+    limiter: &SizeLimiter,
+    files: &mut Vec<(PathBuf, Vec<u8>)>,
+) -> Result<(), LoadingError> {
+    match entry {
+        DirectoryEntry::File => {
+            let real_path = root.join(relative);
+            limiter.add(relative, fs::metadata(&real_path).map_err(io_error)?.len())?;
+            let contents = fs::read(real_path).map_err(io_error)?;
+            limiter.add(relative, contents.len() as u64)?;
+            files.push((relative.to_path_buf(), contents));
+        }
+        DirectoryEntry::Directory { entries } => {
+            for (name, child) in entries {
+                collect_files(root, &relative.join(name), child, limiter, files)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn io_error(err: io::Error) -> LoadingError {
+    LoadingError::FileSystemError { sub_error: err.into() }
+}
+
+impl FileTree for MemoryFileTree {
+    /// Reads every file in the directory into memory, see [`from_directory`](MemoryFileTree::from_directory).
+    fn from_path(path: &Path) -> Self::FromPathResult {
+        ready(Self::from_directory(path))
+    }
+    type FromPathResult = Ready<Result<Self, LoadingError>>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.0.entry.get(path).is_some()
+    }
+
+    fn is_file(&self, path: &Path) -> Result<bool, LoadingError> {
+        match self.0.entry.get(path) {
+            Some(DirectoryEntry::File) => Ok(true),
+            Some(DirectoryEntry::Directory { .. }) => Ok(false),
+            None => Err(LoadingError::PathNotFound),
+        }
+    }
+
+    fn is_dir(&self, path: &Path) -> Result<bool, LoadingError> {
+        self.is_file(path).map(|is_file| !is_file)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<HashSet<PathBuf>, LoadingError> {
+        match self.0.entry.get(path) {
+            Some(DirectoryEntry::File) => Err(LoadingError::NotDirectory),
+            Some(DirectoryEntry::Directory { entries }) => Ok(entries.keys().map(PathBuf::from).collect()),
+            None => Err(LoadingError::PathNotFound),
+        }
+    }
+
+    fn read(&self, path: &Path) -> Self::ReadResult {
+        ready(self.read_bytes(path).map(<[u8]>::to_vec))
+    }
+    type ReadResult = Ready<Result<Vec<u8>, LoadingError>>;
+
+    fn read_u32(&self, path: &Path) -> Self::ReadU32Result {
+        ready(
+            self.read_bytes(path)
+                .and_then(|bytes| read_stream_u32(io::Cursor::new(bytes)).map_err(io_error)),
+        )
+    }
+    type ReadU32Result = Ready<Result<Vec<u32>, LoadingError>>;
+
+    fn read_text(&self, path: &Path) -> Self::ReadTextResult {
+        ready(
+            self.read_bytes(path)
+                .and_then(|bytes| read_stream_string(io::Cursor::new(bytes)).map_err(io_error)),
+        )
+    }
+    type ReadTextResult = Ready<Result<String, LoadingError>>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor::block_on;
+    use matches::matches;
+
+    #[test]
+    fn memory_file_tree() {
+        let tree = MemoryFileTree::new(vec![
+            ("passes.json", b"[]".to_vec()),
+            ("shaders/gui.vert", b"void main() {}".to_vec()),
+            ("shaders/gui.spv", vec![3, 2, 0x23, 7, 0, 0, 1, 0]),
+        ]);
+
+        assert_eq!(tree.is_file(Path::new("passes.json")).unwrap(), true);
+        assert_eq!(tree.is_dir(Path::new("shaders")).unwrap(), true);
+        assert!(!tree.exists(Path::new("materials")));
+        let expected: HashSet<PathBuf> = vec!["gui.vert".into(), "gui.spv".into()].into_iter().collect();
+        assert_eq!(tree.read_dir(Path::new("shaders")).unwrap(), expected);
+
+        assert_eq!(block_on(tree.read(Path::new("passes.json"))).unwrap(), b"[]");
+        assert_eq!(
+            block_on(tree.read_text(Path::new("shaders/gui.vert"))).unwrap(),
+            "void main() {}"
+        );
+        assert_eq!(
+            block_on(tree.read_u32(Path::new("shaders/gui.spv"))).unwrap(),
+            vec![0x0723_0203, 0x0001_0000]
+        );
+        assert!(matches!(
+            block_on(tree.read(Path::new("shaders"))),
+            Err(LoadingError::NotFile)
+        ));
+        assert!(matches!(
+            block_on(tree.read(Path::new("missing.json"))),
+            Err(LoadingError::PathNotFound)
+        ));
+    }
+
+    #[test]
+    fn invalid_paths() {
+        let invalid = |files: Vec<(&str, Vec<u8>)>| match MemoryFileTree::try_new(files) {
+            Err(LoadingError::InvalidPath { path }) => path,
+            other => panic!("Expected invalid path, got {:?}", other),
+        };
+
+        assert_eq!(
+            invalid(vec![("../passes.json", vec![])]),
+            PathBuf::from("../passes.json")
+        );
+        assert_eq!(invalid(vec![("/passes.json", vec![])]), PathBuf::from("/passes.json"));
+        assert_eq!(invalid(vec![("", vec![])]), PathBuf::from(""));
+
+        // A file can't be both a file and a directory, in either order
+        assert_eq!(
+            invalid(vec![("shaders", vec![]), ("shaders/gui.vert", vec![])]),
+            PathBuf::from("shaders/gui.vert")
+        );
+        assert_eq!(
+            invalid(vec![("shaders/gui.vert", vec![]), ("shaders", vec![])]),
+            PathBuf::from("shaders")
+        );
+
+        // The same file twice is fine, the last one wins
+        let tree = MemoryFileTree::try_new(vec![("passes.json", b"{".to_vec()), ("passes.json", b"[]".to_vec())])
+            .expect("Failed to create tree with a repeated file");
+        assert_eq!(block_on(tree.read(Path::new("passes.json"))).unwrap(), b"[]");
+    }
+}
@@ -6,14 +6,20 @@
 //! pack loader will also be able to read resource packs in either filesystem folders or a zip folder. It should be
 //! constructed in a way that will allow support for other zip formats.
 
+use crate::core::reactor::Priority;
 use failure::{Error, Fail};
 use futures::Future;
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
 
 mod dir;
+mod memory;
+mod pack_metadata;
 
 pub use dir::*;
-use std::collections::HashSet;
+pub use memory::*;
+pub use pack_metadata::*;
+use std::collections::{HashMap, HashSet};
 
 /// View over an abstract tree of directories and files.
 ///
@@ -68,6 +74,16 @@ pub trait FileTree: Sized {
     /// Stopgap until async fn in traits happens.
     type ReadResult: Future<Output = Result<Vec<u8>, LoadingError>> + Send;
 
+    /// Reads a file into a vector of u8, ahead of or behind other pending reads depending on `priority`.
+    ///
+    /// File trees that don't queue up their reads are free to ignore the priority, which is what the default
+    /// implementation does.
+    ///
+    /// Fails if file doesn't exist or isn't readable.
+    fn read_prioritized(&self, path: &Path, _priority: Priority) -> Self::ReadResult {
+        self.read(path)
+    }
+
     /// Reads a file as little endian into an array of u32.
     ///
     /// Fails if file doesn't exist or isn't readable.
@@ -87,6 +103,149 @@ pub trait FileTree: Sized {
     type ReadTextResult: Future<Output = Result<String, LoadingError>> + Send;
 }
 
+/// Callback for [`dispatch_archive`], which gets handed the [`FileTree`] type of the backend for an archive format.
+pub trait ArchiveVisitor {
+    /// Result of the visit.
+    type Output;
+
+    /// Called with the file tree type which can read the archive.
+    fn visit<T>(self) -> Self::Output
+    where
+        T: FileTree + Send + Sync + Clone + 'static;
+}
+
+/// Generates [`supported_archive_extensions`] and [`dispatch_archive`] from a list of `extension => FileTree` pairs,
+/// so both stay in sync.
+macro_rules! archive_backends {
+    ($($extension:literal => $tree:ty),* $(,)?) => {
+        /// File extensions, without the leading dot, of the single-file archive formats which have a [`FileTree`]
+        /// backend.
+        ///
+        /// Useful for building file filters in a UI.
+        pub fn supported_archive_extensions() -> &'static [&'static str] {
+            &[$($extension),*]
+        }
+
+        /// Hands the [`FileTree`] type for archives with the given extension to `visitor`.
+        ///
+        /// Returns `None` if no backend handles the extension.
+        pub fn dispatch_archive<V: ArchiveVisitor>(extension: &str, visitor: V) -> Option<V::Output> {
+            match extension {
+                $($extension => Some(visitor.visit::<$tree>()),)*
+                _ => {
+                    drop(visitor);
+                    None
+                }
+            }
+        }
+    };
+}
+
+// Registry of archive backends. Supporting a new archive format only takes adding its extension and file tree here.
+#[cfg(not(test))]
+archive_backends! {}
+
+// Stand-in backend so the registry's tests have an entry to dispatch to
+#[cfg(test)]
+archive_backends! {
+    "memtest" => MemoryFileTree,
+}
+
+/// Limits on how much data a [`FileTree`] hands out, which keep untrusted packs from exhausting memory.
+///
+/// Archives can decompress to far more than their own size, so archive backends must check every file against these
+/// as it's decompressed. The defaults are far larger than any reasonable pack, but finite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadingLimits {
+    /// Largest size of a single file, in bytes.
+    pub max_file_size: u64,
+
+    /// Largest size of all the files read from a file tree together, in bytes.
+    pub max_total_size: u64,
+}
+
+impl Default for LoadingLimits {
+    fn default() -> Self {
+        Self {
+            max_file_size: 256 << 20,
+            max_total_size: 2 << 30,
+        }
+    }
+}
+
+/// Enforces [`LoadingLimits`] for a file tree, keeping count of the bytes it has read so far. Can be shared between
+/// the threads reading the tree.
+///
+/// Each file counts once towards the total, with the size it had when it was last read, so a tree which is kept around
+/// and reads the same files again, such as for hot reloading, doesn't run into the limit.
+#[derive(Debug, Default)]
+pub struct SizeLimiter {
+    limits: LoadingLimits,
+    counted: Mutex<CountedFiles>,
+}
+
+/// Files a [`SizeLimiter`] has counted, along with their total size.
+#[derive(Debug, Default)]
+struct CountedFiles {
+    total: u64,
+    sizes: HashMap<PathBuf, u64>,
+}
+
+impl SizeLimiter {
+    /// Creates a limiter which hasn't counted any bytes yet.
+    pub fn new(limits: LoadingLimits) -> Self {
+        Self {
+            limits,
+            counted: Mutex::new(CountedFiles::default()),
+        }
+    }
+
+    /// The limits being enforced.
+    pub fn limits(&self) -> LoadingLimits {
+        self.limits
+    }
+
+    /// Number of bytes counted so far.
+    pub fn total(&self) -> u64 {
+        self.counted.lock().unwrap_or_else(PoisonError::into_inner).total
+    }
+
+    /// Checks a file's size against [`max_file_size`](LoadingLimits::max_file_size) without counting it. Use this to
+    /// refuse files before reading them, when their size is known up front.
+    pub fn check_file(&self, path: &Path, size: u64) -> Result<(), LoadingError> {
+        if size > self.limits.max_file_size {
+            return Err(LoadingError::ResourceTooLarge {
+                path: path.to_path_buf(),
+                size,
+                limit: self.limits.max_file_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Counts a file which has been read, failing if it's too large itself or takes the total read from the tree past
+    /// [`max_total_size`](LoadingLimits::max_total_size).
+    ///
+    /// A file which was counted before only counts with its new size. A file which fails the check isn't counted.
+    pub fn add(&self, path: &Path, size: u64) -> Result<(), LoadingError> {
+        self.check_file(path, size)?;
+
+        let mut counted = self.counted.lock().unwrap_or_else(PoisonError::into_inner);
+        let previous = counted.sizes.get(path).copied().unwrap_or(0);
+        let total = counted.total - previous + size;
+        if total > self.limits.max_total_size {
+            return Err(LoadingError::ResourceTooLarge {
+                path: path.to_path_buf(),
+                size: total,
+                limit: self.limits.max_total_size,
+            });
+        }
+        counted.total = total;
+        counted.sizes.insert(path.to_path_buf(), size);
+        Ok(())
+    }
+}
+
 /// Error when trying to load a resource.
 #[derive(Debug, Fail)]
 pub enum LoadingError {
@@ -106,6 +265,28 @@ pub enum LoadingError {
     #[fail(display = "Expected file.")]
     NotFile,
 
+    /// A path can't be part of the file tree being created, because it isn't a plain relative path or would make a
+    /// file and a directory have the same path.
+    #[fail(display = "Path {:?} can't be part of the file tree.", path)]
+    InvalidPath {
+        /// The invalid path.
+        path: PathBuf,
+    },
+
+    /// Reading a file would go over the [`LoadingLimits`] of the file tree.
+    #[fail(
+        display = "Reading {:?} takes {} bytes, which is over the limit of {} bytes.",
+        path, size, limit
+    )]
+    ResourceTooLarge {
+        /// The file which went over the limit.
+        path: PathBuf,
+        /// Size of the file, or of everything read from the tree including it if the total went over the limit.
+        size: u64,
+        /// The limit which was exceeded, in bytes.
+        limit: u64,
+    },
+
     /// Error within the filesystem.
     #[fail(display = "Error inside filesystem.")]
     FileSystemError {
@@ -114,3 +295,70 @@ pub enum LoadingError {
         sub_error: Error,
     },
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use matches::matches;
+    use std::any::TypeId;
+
+    /// Reports which file tree it was dispatched to.
+    struct TreeTypeVisitor;
+
+    impl ArchiveVisitor for TreeTypeVisitor {
+        type Output = TypeId;
+
+        fn visit<T>(self) -> TypeId
+        where
+            T: FileTree + Send + Sync + Clone + 'static,
+        {
+            TypeId::of::<T>()
+        }
+    }
+
+    #[test]
+    fn archive_registry() {
+        assert_eq!(supported_archive_extensions(), &["memtest"]);
+        assert_eq!(
+            dispatch_archive("memtest", TreeTypeVisitor),
+            Some(TypeId::of::<MemoryFileTree>())
+        );
+        assert_eq!(dispatch_archive("rar", TreeTypeVisitor), None);
+        assert_eq!(dispatch_archive("", TreeTypeVisitor), None);
+    }
+
+    #[test]
+    fn size_limits() {
+        let limiter = SizeLimiter::new(LoadingLimits {
+            max_file_size: 100,
+            max_total_size: 150,
+        });
+        let path = Path::new("materials/terrain.mat");
+        assert!(limiter.check_file(path, 100).is_ok());
+        assert!(matches!(
+            limiter.check_file(path, 101),
+            Err(LoadingError::ResourceTooLarge {
+                size: 101, limit: 100, ..
+            })
+        ));
+
+        assert!(limiter.add(path, 80).is_ok());
+        let other = Path::new("materials/sky.mat");
+        assert!(matches!(
+            limiter.add(other, 80),
+            Err(LoadingError::ResourceTooLarge {
+                size: 160, limit: 150, ..
+            })
+        ));
+        assert_eq!(limiter.total(), 80);
+
+        // Reading a file again only counts it once, with its new size
+        for _ in 0..10 {
+            assert!(limiter.add(path, 80).is_ok());
+        }
+        assert!(limiter.add(path, 60).is_ok());
+        assert_eq!(limiter.total(), 60);
+        assert!(limiter.add(other, 80).is_ok());
+        assert_eq!(limiter.total(), 140);
+    }
+}
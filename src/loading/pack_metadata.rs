@@ -0,0 +1,130 @@
+use crate::loading::FileTree;
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+
+/// File at the root of a Minecraft: Java Edition resourcepack which describes the pack.
+pub const PACK_METADATA_FILE: &str = "pack.mcmeta";
+
+/// Description of a resourcepack, from its [`pack.mcmeta`](PACK_METADATA_FILE).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackMetadata {
+    /// Version of the resourcepack format the pack was made for. Each version of Minecraft accepts one format.
+    pub pack_format: u32,
+
+    /// Description of the pack, as shown in the pack list.
+    ///
+    /// Descriptions may be [text components](https://minecraft.gamepedia.com/Raw_JSON_text_format) rather than plain
+    /// strings, in which case this only holds their text, without any formatting.
+    pub description: String,
+}
+
+#[derive(Deserialize)]
+struct PackMetadataFile {
+    pack: PackSection,
+}
+
+#[derive(Deserialize)]
+struct PackSection {
+    pack_format: u32,
+    #[serde(default)]
+    description: Value,
+}
+
+/// Reads the [`pack.mcmeta`](PACK_METADATA_FILE) at the root of a resourcepack.
+///
+/// Returns `None` if the pack has no metadata. Metadata which can't be read or parsed is logged and treated as missing,
+/// like Minecraft does.
+pub async fn read_pack_metadata<T>(tree: &T) -> Option<PackMetadata>
+where
+    T: FileTree,
+{
+    let path = Path::new(PACK_METADATA_FILE);
+    if !tree.exists(path) {
+        return None;
+    }
+
+    let text = match tree.read_text(path).await {
+        Ok(text) => text,
+        Err(err) => {
+            warn!("Failed to read {}: {}", PACK_METADATA_FILE, err);
+            return None;
+        }
+    };
+    match serde_json::from_str::<PackMetadataFile>(text.trim_start_matches('\u{feff}')) {
+        Ok(file) => Some(PackMetadata {
+            pack_format: file.pack.pack_format,
+            description: component_text(&file.pack.description),
+        }),
+        Err(err) => {
+            warn!("Failed to parse {}: {}", PACK_METADATA_FILE, err);
+            None
+        }
+    }
+}
+
+/// Gets the plain text out of a text component, which is a string, an object with `text` and `extra` components, or a
+/// list of components.
+fn component_text(component: &Value) -> String {
+    match component {
+        Value::String(text) => text.clone(),
+        Value::Array(components) => components.iter().map(component_text).collect(),
+        Value::Object(fields) => {
+            let mut text = fields.get("text").map(component_text).unwrap_or_default();
+            if let Some(extra) = fields.get("extra") {
+                text.push_str(&component_text(extra));
+            }
+            text
+        }
+        Value::Number(number) => number.to_string(),
+        Value::Bool(value) => value.to_string(),
+        Value::Null => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::loading::MemoryFileTree;
+    use futures::executor::block_on;
+
+    fn pack(mcmeta: &str) -> MemoryFileTree {
+        MemoryFileTree::new(vec![
+            (PACK_METADATA_FILE, mcmeta.as_bytes().to_vec()),
+            ("assets/minecraft/textures/block/stone.png", Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn pack_metadata() {
+        let tree = pack(r#"{ "pack": { "pack_format": 4, "description": "Smooth stone everywhere" } }"#);
+        assert_eq!(
+            block_on(read_pack_metadata(&tree)),
+            Some(PackMetadata {
+                pack_format: 4,
+                description: String::from("Smooth stone everywhere"),
+            })
+        );
+
+        let tree = pack(
+            r#"{
+                "pack": {
+                    "pack_format": 5,
+                    "description": [{ "text": "Nova ", "color": "gold" }, { "text": "Test", "extra": ["Pack"] }]
+                },
+                "language": {}
+            }"#,
+        );
+        assert_eq!(
+            block_on(read_pack_metadata(&tree)).map(|metadata| metadata.description),
+            Some(String::from("Nova TestPack"))
+        );
+
+        assert_eq!(block_on(read_pack_metadata(&pack(r#"{ "pack": {} }"#))), None);
+        assert_eq!(
+            block_on(read_pack_metadata(&MemoryFileTree::new(Vec::<(&str, _)>::new()))),
+            None
+        );
+    }
+}
@@ -7,7 +7,62 @@
 //! logging. All of Nova's logs go through these callbacks.
 //!
 //! Thanks to the logging crate we can simply log from everywhere in Nova's source. We however provide a very basic
-//! logger for tests and in case the application doesn't set one.
+//! logger for tests and in case the application doesn't set one, which [`init`] installs.
+//!
+//! Nova logs with the `log` crate's default targets, which are module paths, so every one of Nova's log targets starts
+//! with `nova_rs`. Applications with their own logger can use that prefix to filter Nova's logs.
+
+use log::{LevelFilter, Log};
+use std::env;
+
+/// Environment variable [`init`] reads the log level from.
+///
+/// The value is one of `off`, `error`, `warn`, `info`, `debug` or `trace`, in any case.
+pub const LOG_LEVEL_VAR: &str = "NOVA_LOG";
+
+/// Installs a [`BasicLogger`] as the global logger, unless the application already set a logger.
+///
+/// The log level is read from the [`NOVA_LOG`](LOG_LEVEL_VAR) environment variable, and is `info` if the variable
+/// isn't set or isn't a valid level. Calling this again after a logger is installed does nothing.
+///
+/// Returns true if the logger was installed.
+pub fn init() -> bool {
+    let level = level_from(env::var(LOG_LEVEL_VAR).ok().as_ref().map(String::as_str));
+    let logger = BasicLogger::new(level >= LevelFilter::Debug, level >= LevelFilter::Trace);
+    install(Box::new(logger), level)
+}
+
+/// Installs a custom logger as the global logger, unless the application already set a logger.
+///
+/// All levels are enabled, so the logger must do its own filtering. Calling this again after a logger is installed
+/// does nothing.
+///
+/// Returns true if the logger was installed.
+///
+/// # Parameters
+///
+/// * `logger` - The logger to send all logs to.
+pub fn init_with<L>(logger: L) -> bool
+where
+    L: Log + 'static,
+{
+    install(Box::new(logger), LevelFilter::Trace)
+}
+
+fn install(logger: Box<dyn Log>, level: LevelFilter) -> bool {
+    let installed = log::set_boxed_logger(logger).is_ok();
+    if installed {
+        log::set_max_level(level);
+    }
+    installed
+}
+
+/// Parses a log level, falling back to `info`.
+fn level_from(value: Option<&str>) -> LevelFilter {
+    value
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(LevelFilter::Info)
+}
 
 /// Very basic logger struct, containing info if debug and trace level logs are enabled.
 ///
@@ -72,3 +127,24 @@ impl log::Log for BasicLogger {
         // Flushing is not required when using the std output streams
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn level_parsing() {
+        assert_eq!(level_from(None), LevelFilter::Info);
+        assert_eq!(level_from(Some("trace")), LevelFilter::Trace);
+        assert_eq!(level_from(Some(" Warn ")), LevelFilter::Warn);
+        assert_eq!(level_from(Some("off")), LevelFilter::Off);
+        assert_eq!(level_from(Some("verbose")), LevelFilter::Info);
+    }
+
+    #[test]
+    fn init_is_idempotent() {
+        init();
+        assert!(!init());
+        assert!(!init_with(BasicLogger::new(true, true)));
+    }
+}
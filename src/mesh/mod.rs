@@ -0,0 +1,319 @@
+//! Mesh data that applications hand to Nova for rendering.
+//!
+//! Meshes are uploaded to the GPU as-is, so malformed data (such as an index pointing past the end of the vertex
+//! buffer) can't be caught once it leaves the CPU. Everything here is validated before it gets that far.
+
+use crate::shaderpack::PrimitiveTopology;
+use cgmath::{Matrix4, Vector2, Vector3, Vector4};
+use failure::Fail;
+
+/// The vertex format that Nova uses for all of its geometry.
+///
+/// Each field lines up with one of the [`VertexField`](crate::shaderpack::VertexField)s that a shaderpack may request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FullVertex {
+    /// Position of the vertex in model space.
+    pub position: Vector3<f32>,
+
+    /// Normal of the vertex.
+    pub normal: Vector3<f32>,
+
+    /// Tangent of the vertex.
+    pub tangent: Vector3<f32>,
+
+    /// UV coordinate in the texture referenced by `virtual_texture_id`.
+    pub main_uv: Vector2<f32>,
+
+    /// UV coordinate in the lightmap.
+    pub secondary_uv: Vector2<u16>,
+
+    /// Identifier of the virtual texture this vertex samples from.
+    pub virtual_texture_id: u32,
+
+    /// Extra per-vertex data, such as the ID of the block or entity this vertex belongs to.
+    pub additional_stuff: Vector4<f32>,
+}
+
+/// A mesh to be rendered by Nova.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshData {
+    /// All the vertices in the mesh.
+    pub vertex_data: Vec<FullVertex>,
+
+    /// Indices into `vertex_data` which describe the primitives of the mesh.
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// Checks that the mesh can be safely rendered as the given topology.
+    ///
+    /// Every index must refer to an existing vertex, and the index count must describe a whole number of primitives.
+    pub fn validate(&self, topology: PrimitiveTopology) -> Result<(), MeshValidationError> {
+        let vertex_count = self.vertex_data.len();
+        if let Some((position, &index)) = self
+            .indices
+            .iter()
+            .enumerate()
+            .find(|(_, &index)| index as usize >= vertex_count)
+        {
+            return Err(MeshValidationError::IndexOutOfRange {
+                position,
+                index,
+                vertex_count,
+            });
+        }
+
+        let indices_per_primitive = match topology {
+            PrimitiveTopology::Triangles => 3,
+            PrimitiveTopology::Lines => 2,
+        };
+        if self.indices.len() % indices_per_primitive != 0 {
+            return Err(MeshValidationError::IncompletePrimitive {
+                index_count: self.indices.len(),
+                topology,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Identifies a mesh which has been handed to Nova.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct MeshId(pub u64);
+
+/// Identifies a draw command which has been handed to Nova.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct DrawCommandId(pub u64);
+
+/// What kind of geometry a draw command draws. Each type has a `geometry_type::` tag that
+/// [geometry filters](crate::shaderpack::MaterialData::geometry_filter) select it with.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum GeometryType {
+    /// Terrain, tag `geometry_type::block`.
+    Block,
+    /// Mobs, players and other moving objects, tag `geometry_type::entity`.
+    Entity,
+    /// Particles, tag `geometry_type::particle`.
+    Particle,
+    /// The sun, moon and stars, tag `geometry_type::sky_object`.
+    SkyObject,
+    /// User interface elements, tag `geometry_type::gui`.
+    Gui,
+    /// The background behind menus, tag `geometry_type::gui_background`.
+    GuiBackground,
+    /// Text, tag `geometry_type::text`.
+    Text,
+    /// A quad covering the screen, used by postprocessing passes, tag `geometry_type::fullscreen_quad`.
+    FullscreenQuad,
+}
+
+impl GeometryType {
+    /// Every geometry type.
+    pub const ALL: [Self; 8] = [
+        Self::Block,
+        Self::Entity,
+        Self::Particle,
+        Self::SkyObject,
+        Self::Gui,
+        Self::GuiBackground,
+        Self::Text,
+        Self::FullscreenQuad,
+    ];
+
+    /// The tag geometry filters use for this geometry type.
+    pub fn filter_tag(self) -> &'static str {
+        match self {
+            Self::Block => "geometry_type::block",
+            Self::Entity => "geometry_type::entity",
+            Self::Particle => "geometry_type::particle",
+            Self::SkyObject => "geometry_type::sky_object",
+            Self::Gui => "geometry_type::gui",
+            Self::GuiBackground => "geometry_type::gui_background",
+            Self::Text => "geometry_type::text",
+            Self::FullscreenQuad => "geometry_type::fullscreen_quad",
+        }
+    }
+
+    /// Finds the geometry type with the given filter tag.
+    pub fn from_filter_tag(tag: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|geometry_type| geometry_type.filter_tag() == tag)
+    }
+}
+
+/// The tags of a draw command, which decide which materials draw it.
+///
+/// See [`GeometryFilter::matches`](crate::shaderpack::GeometryFilter::matches) for how the tags of geometry filters
+/// map onto these.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct GeometryTags {
+    /// What kind of geometry is drawn.
+    pub geometry_type: GeometryType,
+
+    /// Whether the geometry is drawn with blending. Matches the `transparent` tag, or `not_transparent` if false.
+    pub transparent: bool,
+
+    /// Custom tags of the host app. Bit `n` is set for the tag
+    /// [`GeometryTagRegistry::register`](crate::shaderpack::GeometryTagRegistry::register) returned `n` for.
+    pub custom: u64,
+}
+
+impl GeometryTags {
+    /// Tags for opaque geometry of the given type, without any custom tags.
+    pub fn new(geometry_type: GeometryType) -> Self {
+        Self {
+            geometry_type,
+            transparent: false,
+            custom: 0,
+        }
+    }
+
+    /// Adds the custom tag with the given bit, as returned by
+    /// [`GeometryTagRegistry::register`](crate::shaderpack::GeometryTagRegistry::register).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bit` isn't less than
+    /// [`MAX_CUSTOM_TAGS`](crate::shaderpack::GeometryTagRegistry::MAX_CUSTOM_TAGS), since no tag can have it.
+    pub fn with_custom(mut self, bit: u32) -> Self {
+        match 1_u64.checked_shl(bit) {
+            Some(mask) => self.custom |= mask,
+            None => panic!("Custom tag bit {} is out of range", bit),
+        }
+        self
+    }
+
+    /// Whether the custom tag with the given bit is set. Bits no tag can have are never set.
+    pub fn has_custom(&self, bit: u32) -> bool {
+        1_u64.checked_shl(bit).map_or(false, |mask| self.custom & mask != 0)
+    }
+}
+
+/// Request to draw a mesh which doesn't get deformed, such as a chunk of terrain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StaticMeshDrawCommand {
+    /// The mesh to draw.
+    pub mesh: MeshId,
+
+    /// The tags materials' geometry filters select this draw command by.
+    pub tags: GeometryTags,
+
+    /// Transformation from the mesh's model space to world space.
+    pub model_matrix: Matrix4<f32>,
+
+    /// Whether the mesh should be drawn at all. Hiding a draw command is cheaper than removing and re-adding it.
+    pub is_visible: bool,
+}
+
+/// Reasons a mesh can fail validation.
+#[derive(Fail, Debug, Clone, PartialEq)]
+pub enum MeshValidationError {
+    /// An index refers to a vertex that doesn't exist.
+    #[fail(
+        display = "Index {} at position {} is out of range for a mesh with {} vertices.",
+        index, position, vertex_count
+    )]
+    IndexOutOfRange {
+        /// Position of the bad index in the index buffer.
+        position: usize,
+        /// Value of the bad index.
+        index: u32,
+        /// Amount of vertices in the mesh.
+        vertex_count: usize,
+    },
+
+    /// The amount of indices doesn't make up a whole number of primitives.
+    #[fail(
+        display = "Index count {} is not a whole number of primitives for topology {:?}.",
+        index_count, topology
+    )]
+    IncompletePrimitive {
+        /// Amount of indices in the mesh.
+        index_count: usize,
+        /// Topology the mesh was validated against.
+        topology: PrimitiveTopology,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cgmath::Zero;
+
+    fn vertex() -> FullVertex {
+        FullVertex {
+            position: Vector3::zero(),
+            normal: Vector3::unit_y(),
+            tangent: Vector3::unit_x(),
+            main_uv: Vector2::zero(),
+            secondary_uv: Vector2::zero(),
+            virtual_texture_id: 0,
+            additional_stuff: Vector4::zero(),
+        }
+    }
+
+    fn mesh(vertex_count: usize, indices: Vec<u32>) -> MeshData {
+        MeshData {
+            vertex_data: vec![vertex(); vertex_count],
+            indices,
+        }
+    }
+
+    #[test]
+    fn valid_mesh() {
+        assert_eq!(
+            mesh(4, vec![0, 1, 2, 2, 3, 0]).validate(PrimitiveTopology::Triangles),
+            Ok(())
+        );
+        assert_eq!(mesh(3, vec![0, 1, 1, 2]).validate(PrimitiveTopology::Lines), Ok(()));
+    }
+
+    #[test]
+    fn out_of_range_index() {
+        assert_eq!(
+            mesh(3, vec![0, 1, 3]).validate(PrimitiveTopology::Triangles),
+            Err(MeshValidationError::IndexOutOfRange {
+                position: 2,
+                index: 3,
+                vertex_count: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn dangling_triangle() {
+        assert_eq!(
+            mesh(4, vec![0, 1, 2, 3]).validate(PrimitiveTopology::Triangles),
+            Err(MeshValidationError::IncompletePrimitive {
+                index_count: 4,
+                topology: PrimitiveTopology::Triangles,
+            })
+        );
+        assert_eq!(
+            mesh(4, vec![0, 1, 2]).validate(PrimitiveTopology::Lines),
+            Err(MeshValidationError::IncompletePrimitive {
+                index_count: 3,
+                topology: PrimitiveTopology::Lines,
+            })
+        );
+    }
+
+    #[test]
+    fn custom_tag_bits() {
+        let tags = GeometryTags::new(GeometryType::Block).with_custom(0).with_custom(63);
+        assert!(tags.has_custom(0));
+        assert!(tags.has_custom(63));
+        assert!(!tags.has_custom(1));
+        assert!(!tags.has_custom(64));
+        assert!(!tags.has_custom(u32::max_value()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn custom_tag_bit_out_of_range() {
+        GeometryTags::new(GeometryType::Block).with_custom(64);
+    }
+}
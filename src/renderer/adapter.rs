@@ -0,0 +1,272 @@
+//! Choosing which graphics adapter to render with.
+
+use crate::rhi::{DeviceRequirements, GraphicsApi, PhysicalDevice};
+use crate::settings::AdapterSelection;
+use failure::Fail;
+
+/// Failure type for creating a renderer.
+#[derive(Fail, Debug, Clone, Eq, PartialEq)]
+pub enum RendererCreationError {
+    /// The graphics API didn't report any adapters at all. There is probably no GPU, or its driver doesn't support the
+    /// API.
+    #[fail(display = "No graphics adapters were found.")]
+    NoAdaptersFound,
+
+    /// There are adapters, but none of them can be used.
+    #[fail(display = "No graphics adapter is suitable: {:?}", reasons)]
+    NoSuitableAdapter {
+        /// Why each adapter was rejected, prefixed by the adapter's name.
+        reasons: Vec<String>,
+    },
+
+    /// The settings ask for an adapter by index, but there are fewer adapters.
+    #[fail(display = "Adapter {} was requested, but there are only {} adapters.", index, count)]
+    AdapterIndexOutOfRange {
+        /// The requested index.
+        index: usize,
+        /// The number of adapters.
+        count: usize,
+    },
+
+    /// The settings ask for an adapter by name, but no adapter has that name.
+    #[fail(display = "No adapter is named {:?}. Adapters: {:?}", name, available)]
+    AdapterNotFound {
+        /// The requested name.
+        name: String,
+        /// The names of all adapters.
+        available: Vec<String>,
+    },
+}
+
+/// Picks the adapter to render with.
+///
+/// With [`AdapterSelection::Auto`] this is the most preferred adapter which is suitable for Nova and meets the
+/// requirements, as ordered by [`GraphicsApi::get_ordered_adapters`]. Otherwise it's the requested adapter, which still
+/// has to be suitable and meet the requirements.
+///
+/// # Parameters
+///
+/// * `api` - The graphics API to get the adapters from.
+/// * `requirements` - What the adapter needs to support on top of Nova's own requirements.
+/// * `selection` - Which adapter the settings ask for.
+pub fn select_adapter<A>(
+    api: &A,
+    requirements: &DeviceRequirements,
+    selection: &AdapterSelection,
+) -> Result<A::PhysicalDevice, RendererCreationError>
+where
+    A: GraphicsApi,
+{
+    let mut adapters = api.get_ordered_adapters();
+    if adapters.is_empty() {
+        return Err(RendererCreationError::NoAdaptersFound);
+    }
+
+    match selection {
+        AdapterSelection::Auto => {}
+        AdapterSelection::Index(index) => {
+            if *index >= adapters.len() {
+                return Err(RendererCreationError::AdapterIndexOutOfRange {
+                    index: *index,
+                    count: adapters.len(),
+                });
+            }
+            adapters = vec![adapters.swap_remove(*index)];
+        }
+        AdapterSelection::Name(name) => {
+            let names: Vec<String> = adapters
+                .iter()
+                .map(|adapter| adapter.get_properties().device_name)
+                .collect();
+            match names.iter().position(|adapter_name| adapter_name == name) {
+                Some(index) => adapters = vec![adapters.swap_remove(index)],
+                None => {
+                    return Err(RendererCreationError::AdapterNotFound {
+                        name: name.clone(),
+                        available: names,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut reasons = Vec::new();
+    for adapter in adapters {
+        let properties = adapter.get_properties();
+        let mut unmet = requirements.unmet_by(&properties);
+        if !adapter.can_be_used_by_nova() {
+            unmet.insert(0, String::from("Does not meet Nova's minimum requirements"));
+        }
+
+        if unmet.is_empty() {
+            return Ok(adapter);
+        }
+        reasons.extend(
+            unmet
+                .into_iter()
+                .map(|reason| format!("{}: {}", properties.device_name, reason)),
+        );
+    }
+
+    Err(RendererCreationError::NoSuitableAdapter { reasons })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rhi::mock::{MockGraphicsApi, MockPhysicalDevice};
+    use crate::rhi::PhysicalDeviceType;
+
+    #[test]
+    fn no_adapters() {
+        let api = MockGraphicsApi { adapters: Vec::new() };
+        let result = select_adapter(&api, &DeviceRequirements::default(), &AdapterSelection::Auto);
+        assert_eq!(result.err(), Some(RendererCreationError::NoAdaptersFound));
+    }
+
+    #[test]
+    fn no_suitable_adapter() {
+        let mut old = MockPhysicalDevice::new("Old GPU");
+        old.suitable = false;
+        let basic = MockPhysicalDevice::new("Basic GPU");
+        let api = MockGraphicsApi {
+            adapters: vec![old, basic],
+        };
+        let requirements = DeviceRequirements {
+            tessellation_shader: true,
+            ..DeviceRequirements::default()
+        };
+
+        let error = select_adapter(&api, &requirements, &AdapterSelection::Auto)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error,
+            RendererCreationError::NoSuitableAdapter {
+                reasons: vec![
+                    String::from("Basic GPU: Tessellation is not supported"),
+                    String::from("Old GPU: Does not meet Nova's minimum requirements"),
+                    String::from("Old GPU: Tessellation is not supported"),
+                ]
+            }
+        );
+        assert!(
+            error
+                .to_string()
+                .contains(r#""Basic GPU: Tessellation is not supported""#)
+        );
+    }
+
+    #[test]
+    fn first_suitable_adapter() {
+        let basic = MockPhysicalDevice::new("Basic GPU");
+        let mut tessellating = MockPhysicalDevice::new("Tessellating GPU");
+        tessellating.properties.features.tessellation_shader = true;
+        let api = MockGraphicsApi {
+            adapters: vec![basic, tessellating],
+        };
+        let requirements = DeviceRequirements {
+            tessellation_shader: true,
+            ..DeviceRequirements::default()
+        };
+
+        let adapter = select_adapter(&api, &requirements, &AdapterSelection::Auto).unwrap();
+        assert_eq!(adapter.properties.device_name, "Tessellating GPU");
+    }
+
+    #[test]
+    fn enumerate_adapters() {
+        let mut software = MockPhysicalDevice::new("Software Rasterizer");
+        software.properties.device_type = PhysicalDeviceType::CPU;
+        software.free_memory = 256 << 20;
+        software.suitable = false;
+        let api = MockGraphicsApi {
+            adapters: vec![MockPhysicalDevice::new("Discrete GPU"), software],
+        };
+
+        let adapters = api.enumerate_adapters();
+        assert_eq!(adapters.len(), 2);
+        assert_eq!(adapters[1].index, 1);
+        assert_eq!(adapters[1].properties.device_name, "Software Rasterizer");
+        assert_eq!(adapters[1].properties.device_type, PhysicalDeviceType::CPU);
+        assert_eq!(adapters[1].free_memory, 256 << 20);
+        assert!(adapters[0].usable);
+        assert!(!adapters[1].usable);
+    }
+
+    #[test]
+    fn adapter_selection() {
+        let mut integrated = MockPhysicalDevice::new("Integrated GPU");
+        integrated.properties.device_type = PhysicalDeviceType::Integrated;
+        let mut broken = MockPhysicalDevice::new("Broken GPU");
+        broken.suitable = false;
+        let api = MockGraphicsApi {
+            adapters: vec![integrated, MockPhysicalDevice::new("Discrete GPU"), broken],
+        };
+        let requirements = DeviceRequirements::default();
+        let name = |selection: AdapterSelection| {
+            select_adapter(&api, &requirements, &selection).map(|adapter| adapter.properties.device_name)
+        };
+
+        // Adapters are ordered Broken GPU, Discrete GPU, Integrated GPU
+        assert_eq!(name(AdapterSelection::Auto), Ok(String::from("Discrete GPU")));
+        assert_eq!(name(AdapterSelection::Index(2)), Ok(String::from("Integrated GPU")));
+        assert_eq!(
+            name(AdapterSelection::Name(String::from("Discrete GPU"))),
+            Ok(String::from("Discrete GPU"))
+        );
+
+        assert_eq!(
+            name(AdapterSelection::Index(3)),
+            Err(RendererCreationError::AdapterIndexOutOfRange { index: 3, count: 3 })
+        );
+        assert_eq!(
+            name(AdapterSelection::Name(String::from("Discrete"))),
+            Err(RendererCreationError::AdapterNotFound {
+                name: String::from("Discrete"),
+                available: vec![
+                    String::from("Broken GPU"),
+                    String::from("Discrete GPU"),
+                    String::from("Integrated GPU"),
+                ],
+            })
+        );
+
+        // A chosen adapter still has to be usable
+        assert_eq!(
+            name(AdapterSelection::Index(0)),
+            Err(RendererCreationError::NoSuitableAdapter {
+                reasons: vec![String::from("Broken GPU: Does not meet Nova's minimum requirements")],
+            })
+        );
+    }
+
+    #[test]
+    fn adapter_order() {
+        let mut integrated = MockPhysicalDevice::new("Integrated GPU");
+        integrated.properties.device_type = PhysicalDeviceType::Integrated;
+        let mut second = MockPhysicalDevice::new("GPU B");
+        second.properties.device_id = 2;
+        let mut first = MockPhysicalDevice::new("GPU A");
+        first.properties.device_id = 1;
+        let mut renamed = MockPhysicalDevice::new("GPU C");
+        renamed.properties.device_id = 1;
+        assert_eq!(first.properties.score(), second.properties.score());
+
+        let names = |adapters: Vec<MockPhysicalDevice>| {
+            let api = MockGraphicsApi { adapters };
+            api.enumerate_adapters()
+                .into_iter()
+                .map(|adapter| adapter.properties.device_name)
+                .collect::<Vec<_>>()
+        };
+        let expected = vec!["GPU A", "GPU C", "GPU B", "Integrated GPU"];
+
+        // The driver's order doesn't matter
+        assert_eq!(
+            names(vec![integrated.clone(), second.clone(), renamed.clone(), first.clone()]),
+            expected
+        );
+        assert_eq!(names(vec![first, renamed, second, integrated]), expected);
+    }
+}
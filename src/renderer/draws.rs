@@ -0,0 +1,232 @@
+//! Ordering the draws of a pass to keep state changes down, and sorting transparent draws back to front.
+
+use crate::mesh::DrawCommandId;
+use cgmath::{InnerSpace, Matrix4, Vector3};
+use std::cmp::Ordering;
+
+/// A command to record while drawing a pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DrawStep {
+    /// Bind the pipeline with the given name.
+    BindPipeline(String),
+
+    /// Draw the mesh of a draw command with the pipeline that's currently bound.
+    Draw(DrawCommandId),
+}
+
+/// Counts of the commands recorded for a frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Number of times a pipeline was bound.
+    pub pipeline_binds: u32,
+
+    /// Number of draw calls.
+    pub draw_calls: u32,
+}
+
+/// Orders the draws of a pass by pipeline, so every pipeline is bound only once.
+///
+/// Pipelines are bound in the order they are first used in `draws`, and draws using the same pipeline keep their
+/// order, so callers can still control which pipelines are drawn first. The recorded binds and draws are added to
+/// `stats`.
+///
+/// # Parameters
+///
+/// * `draws` - The name of the pipeline each draw needs, and the draw command to draw.
+/// * `stats` - Counters for the frame the draws are recorded in.
+pub fn plan_draws(draws: &[(&str, DrawCommandId)], stats: &mut FrameStats) -> Vec<DrawStep> {
+    let mut pipelines: Vec<(&str, Vec<DrawCommandId>)> = Vec::new();
+    for &(pipeline, draw_command) in draws {
+        match pipelines.iter().position(|(name, _)| *name == pipeline) {
+            Some(index) => pipelines[index].1.push(draw_command),
+            None => pipelines.push((pipeline, vec![draw_command])),
+        }
+    }
+
+    let mut steps = Vec::with_capacity(pipelines.len() + draws.len());
+    for (pipeline, draw_commands) in pipelines {
+        steps.push(DrawStep::BindPipeline(pipeline.to_owned()));
+        stats.pipeline_binds += 1;
+
+        stats.draw_calls += draw_commands.len() as u32;
+        steps.extend(draw_commands.into_iter().map(DrawStep::Draw));
+    }
+    steps
+}
+
+/// A draw in the [transparent queue](crate::shaderpack::RenderQueue::Transparent), with what's needed to find out how
+/// far away it is.
+#[derive(Debug, Clone, Copy)]
+pub struct TransparentDraw<'a> {
+    /// The draw command to draw.
+    pub draw_command: DrawCommandId,
+
+    /// Transformation from the mesh's model space to world space.
+    pub model_matrix: Matrix4<f32>,
+
+    /// Model space positions of the mesh's vertices.
+    pub positions: &'a [Vector3<f32>],
+}
+
+impl TransparentDraw<'_> {
+    /// Iterates over the world space positions of the mesh's vertices.
+    pub fn world_positions<'s>(&'s self) -> impl Iterator<Item = Vector3<f32>> + 's {
+        self.positions
+            .iter()
+            .map(move |position| (self.model_matrix * position.extend(1.0)).truncate())
+    }
+}
+
+/// How to measure the distance of a transparent draw from the camera when sorting the transparent queue.
+///
+/// No mode is right for every scene: overlapping transparent meshes are drawn in the wrong order with either built in
+/// mode for some camera positions. Applications which know more about their meshes can provide their own metric.
+pub enum TransparencySort {
+    /// Distance from the camera to the average of the mesh's vertices. Works well for small, similarly sized meshes.
+    CentroidDistance,
+
+    /// Distance from the camera to the mesh's closest vertex. Works better for large meshes close to the camera.
+    NearestVertex,
+
+    /// Distance computed by the application from the draw and the camera position. Draws with larger distances are
+    /// drawn first.
+    Custom(Box<dyn Fn(&TransparentDraw<'_>, Vector3<f32>) -> f32>),
+}
+
+impl TransparencySort {
+    /// Measures how far away a draw is. Only the ordering of the results is meaningful.
+    fn distance(&self, draw: &TransparentDraw<'_>, camera_position: Vector3<f32>) -> f32 {
+        match self {
+            Self::CentroidDistance => {
+                let (sum, count) = draw
+                    .world_positions()
+                    .fold((Vector3::new(0.0, 0.0, 0.0), 0), |(sum, count), position| {
+                        (sum + position, count + 1)
+                    });
+                if count == 0 {
+                    return 0.0;
+                }
+                (sum / count as f32 - camera_position).magnitude2()
+            }
+            Self::NearestVertex => draw
+                .world_positions()
+                .map(|position| (position - camera_position).magnitude2())
+                .fold(std::f32::INFINITY, f32::min),
+            Self::Custom(distance) => distance(draw, camera_position),
+        }
+    }
+}
+
+impl Default for TransparencySort {
+    fn default() -> Self {
+        Self::CentroidDistance
+    }
+}
+
+/// Orders the draws of the transparent queue back to front, so each one blends over everything behind it.
+///
+/// Draws at the same distance keep their order. The result must be recorded in this order, so transparent draws
+/// shouldn't be grouped by pipeline with [`plan_draws`].
+///
+/// # Parameters
+///
+/// * `draws` - The transparent draws of a pass.
+/// * `camera_position` - World space position of the camera.
+/// * `sort` - How to measure the distance of each draw.
+pub fn sort_transparent_draws(
+    draws: &[TransparentDraw<'_>],
+    camera_position: Vector3<f32>,
+    sort: &TransparencySort,
+) -> Vec<DrawCommandId> {
+    let mut distances: Vec<(f32, DrawCommandId)> = draws
+        .iter()
+        .map(|draw| (sort.distance(draw, camera_position), draw.draw_command))
+        .collect();
+    distances.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    distances.into_iter().map(|(_, draw_command)| draw_command).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cgmath::SquareMatrix;
+
+    #[test]
+    fn shared_pipeline_is_bound_once() {
+        let draws: Vec<(&str, DrawCommandId)> = (0..100).map(|id| ("terrain", DrawCommandId(id))).collect();
+        let mut stats = FrameStats::default();
+
+        let steps = plan_draws(&draws, &mut stats);
+        assert_eq!(
+            stats,
+            FrameStats {
+                pipeline_binds: 1,
+                draw_calls: 100
+            }
+        );
+        assert_eq!(steps[0], DrawStep::BindPipeline(String::from("terrain")));
+        assert_eq!(steps.len(), 101);
+    }
+
+    #[test]
+    fn draws_are_grouped_by_pipeline() {
+        let draws = [
+            ("terrain", DrawCommandId(0)),
+            ("water", DrawCommandId(1)),
+            ("terrain", DrawCommandId(2)),
+            ("water", DrawCommandId(3)),
+        ];
+        let mut stats = FrameStats::default();
+
+        let steps = plan_draws(&draws, &mut stats);
+        assert_eq!(
+            steps,
+            vec![
+                DrawStep::BindPipeline(String::from("terrain")),
+                DrawStep::Draw(DrawCommandId(0)),
+                DrawStep::Draw(DrawCommandId(2)),
+                DrawStep::BindPipeline(String::from("water")),
+                DrawStep::Draw(DrawCommandId(1)),
+                DrawStep::Draw(DrawCommandId(3)),
+            ]
+        );
+        assert_eq!(stats.pipeline_binds, 2);
+    }
+
+    #[test]
+    fn transparency_sort_modes() {
+        // A long mesh reaching from right in front of the camera far into the distance, and a small mesh in between
+        let long = [Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 0.0, -21.0)];
+        let small = [Vector3::new(0.0, 0.0, 0.0)];
+        let draws = [
+            TransparentDraw {
+                draw_command: DrawCommandId(0),
+                model_matrix: Matrix4::identity(),
+                positions: &long,
+            },
+            TransparentDraw {
+                draw_command: DrawCommandId(1),
+                model_matrix: Matrix4::from_translation(Vector3::new(0.0, 0.0, -5.0)),
+                positions: &small,
+            },
+        ];
+        let camera = Vector3::new(0.0, 0.0, 0.0);
+        let order = |sort: &TransparencySort| sort_transparent_draws(&draws, camera, sort);
+
+        // The long mesh's centroid is further away, but its nearest vertex is closer
+        assert_eq!(
+            order(&TransparencySort::default()),
+            vec![DrawCommandId(0), DrawCommandId(1)]
+        );
+        assert_eq!(
+            order(&TransparencySort::NearestVertex),
+            vec![DrawCommandId(1), DrawCommandId(0)]
+        );
+
+        // Custom metrics replace the distance entirely
+        let by_id = TransparencySort::Custom(Box::new(|draw: &TransparentDraw<'_>, _: Vector3<f32>| {
+            draw.draw_command.0 as f32
+        }));
+        assert_eq!(order(&by_id), vec![DrawCommandId(1), DrawCommandId(0)]);
+    }
+}
@@ -0,0 +1,868 @@
+//! Processing of the render graph described by a shaderpack's passes.
+
+use crate::rhi::{Device, FormatUsage, Resource, ResourceBarrier, ResourceState};
+use crate::settings::Settings;
+use crate::shaderpack::{PixelFormat, RenderPassCreationInfo, ShaderpackData, BACKBUFFER_NAME};
+use cgmath::Vector2;
+use failure::Fail;
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+/// Exports the render graph of a shaderpack as a Graphviz DOT description.
+///
+/// Passes are drawn as boxes and textures as ellipses, with edges from each pass to the textures it writes and from
+/// each texture to the passes that read it. Buffers are drawn the same way as textures, but with a cylinder shape.
+/// Explicit dependencies between passes are drawn as dashed edges.
+pub fn export_graphviz(data: &ShaderpackData) -> String {
+    let mut dot = String::from("digraph render_graph {\n");
+    let mut textures = HashSet::new();
+    let mut buffers = HashSet::new();
+
+    for pass in &data.passes {
+        let pass_node = node_id("pass", &pass.name);
+        dot.push_str(&format!(
+            "    {} [label={}, shape=box];\n",
+            pass_node,
+            quote(&pass.name)
+        ));
+
+        for dependency in &pass.dependencies {
+            let dependency_node = node_id("pass", dependency);
+            dot.push_str(&format!("    {} -> {} [style=dashed];\n", dependency_node, pass_node));
+        }
+
+        for texture in &pass.texture_inputs {
+            add_resource_node(&mut dot, &mut textures, "texture", texture);
+            dot.push_str(&format!("    {} -> {};\n", node_id("texture", texture), pass_node));
+        }
+        for texture in pass.texture_outputs.iter().chain(pass.depth_texture.iter()) {
+            add_resource_node(&mut dot, &mut textures, "texture", &texture.name);
+            dot.push_str(&format!(
+                "    {} -> {};\n",
+                pass_node,
+                node_id("texture", &texture.name)
+            ));
+        }
+
+        for buffer in &pass.input_buffers {
+            add_resource_node(&mut dot, &mut buffers, "buffer", buffer);
+            dot.push_str(&format!("    {} -> {};\n", node_id("buffer", buffer), pass_node));
+        }
+        for buffer in &pass.output_buffers {
+            add_resource_node(&mut dot, &mut buffers, "buffer", buffer);
+            dot.push_str(&format!("    {} -> {};\n", pass_node, node_id("buffer", buffer)));
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Adds the dependencies implied by the textures and buffers each pass reads.
+///
+/// A pass depends on every other pass that writes to one of its texture or buffer inputs. Inferred dependencies are
+/// appended after the explicitly declared ones, and dependencies that were already declared are not duplicated.
+pub fn infer_dependencies(passes: &mut [RenderPassCreationInfo]) {
+    let inferred: Vec<Vec<String>> = passes
+        .iter()
+        .map(|pass| {
+            passes
+                .iter()
+                .filter(|producer| producer.name != pass.name)
+                .filter(|producer| {
+                    let writes_texture = pass.texture_inputs.iter().any(|texture| {
+                        producer
+                            .texture_outputs
+                            .iter()
+                            .chain(producer.depth_texture.iter())
+                            .any(|output| &output.name == texture)
+                    });
+                    let writes_buffer = pass
+                        .input_buffers
+                        .iter()
+                        .any(|buffer| producer.output_buffers.contains(buffer));
+                    writes_texture || writes_buffer
+                })
+                .map(|producer| producer.name.clone())
+                .collect()
+        })
+        .collect();
+
+    for (pass, dependencies) in passes.iter_mut().zip(inferred) {
+        for dependency in dependencies {
+            if !pass.dependencies.contains(&dependency) {
+                pass.dependencies.push(dependency);
+            }
+        }
+    }
+}
+
+/// A state change a texture needs before a pass can use it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextureBarrier {
+    /// Name of the texture.
+    pub texture: String,
+
+    /// The state the texture was left in by the last pass to use it.
+    pub before: ResourceState,
+
+    /// The state the pass needs the texture to be in.
+    pub after: ResourceState,
+}
+
+impl TextureBarrier {
+    /// Creates the barrier to record for this state change.
+    ///
+    /// # Parameters
+    ///
+    /// * `resource` - The image this barrier's texture was created as.
+    pub fn to_resource_barrier(&self, resource: Arc<dyn Resource>) -> ResourceBarrier {
+        ResourceBarrier::transition(resource, self.before.clone(), self.after.clone())
+    }
+}
+
+/// Works out the texture barriers to record before each pass, in submission order.
+///
+/// Textures a pass writes need to be attachments, and textures it reads need to be readable by fragment shaders. A
+/// barrier is needed whenever a pass uses a texture in a different state than the pass before it left it in. Textures
+/// start out [undefined](ResourceState::Undefined), since their contents don't carry over from the previous frame.
+///
+/// Buffers don't change state, so they don't get barriers here.
+pub fn plan_barriers(passes: &[RenderPassCreationInfo]) -> Vec<Vec<TextureBarrier>> {
+    let mut states: HashMap<&str, ResourceState> = HashMap::new();
+
+    passes
+        .iter()
+        .map(|pass| {
+            let reads = pass
+                .texture_inputs
+                .iter()
+                .map(|texture| (texture.as_str(), ResourceState::FragmentShaderReadOnly));
+            let writes = pass
+                .texture_outputs
+                .iter()
+                .map(|texture| (texture.name.as_str(), ResourceState::ColorAttachment));
+            let depth = pass
+                .depth_texture
+                .iter()
+                .map(|texture| (texture.name.as_str(), ResourceState::DepthStencilAttachment));
+
+            let mut barriers = Vec::new();
+            for (texture, needed) in reads.chain(writes).chain(depth) {
+                let current = states
+                    .insert(texture, needed.clone())
+                    .unwrap_or(ResourceState::Undefined);
+                if current != needed {
+                    barriers.push(TextureBarrier {
+                        texture: texture.to_owned(),
+                        before: current,
+                        after: needed,
+                    });
+                }
+            }
+            barriers
+        })
+        .collect()
+}
+
+/// Which passes of a render graph are switched off.
+///
+/// Lets shaderpack developers debug an effect by toggling passes without editing and reloading the shaderpack.
+/// Disabled passes aren't recorded at all. Textures which only disabled passes write get cleared instead, so the passes
+/// reading them don't read garbage.
+#[derive(Debug, Clone, Default)]
+pub struct PassToggles {
+    disabled: HashSet<String>,
+}
+
+impl PassToggles {
+    /// Switches a pass on or off.
+    ///
+    /// Logs a warning when switching off a pass whose output other passes read, since they'll read a cleared texture
+    /// instead.
+    pub fn set_pass_enabled(&mut self, data: &ShaderpackData, pass_name: &str, enabled: bool) {
+        if enabled {
+            self.disabled.remove(pass_name);
+            return;
+        }
+
+        match data.passes.iter().find(|pass| pass.name == pass_name) {
+            Some(pass) => {
+                let outputs = pass.texture_outputs.iter().chain(pass.depth_texture.iter());
+                for output in outputs {
+                    let readers = data.consumers_of(&output.name);
+                    if !readers.is_empty() {
+                        let readers: Vec<&str> = readers.iter().map(|reader| reader.name.as_str()).collect();
+                        warn!(
+                            "Disabling pass {:?}, but {:?} read its output {:?}",
+                            pass_name, readers, output.name
+                        );
+                    }
+                }
+            }
+            None => warn!("Disabling pass {:?}, which doesn't exist", pass_name),
+        }
+        self.disabled.insert(pass_name.to_owned());
+    }
+
+    /// Checks if a pass is switched on. Passes are on unless they've been switched off.
+    pub fn is_pass_enabled(&self, pass_name: &str) -> bool {
+        !self.disabled.contains(pass_name)
+    }
+
+    /// Gets the passes to record, in submission order.
+    pub fn enabled_passes<'a>(&self, passes: &'a [RenderPassCreationInfo]) -> Vec<&'a RenderPassCreationInfo> {
+        passes.iter().filter(|pass| self.is_pass_enabled(&pass.name)).collect()
+    }
+
+    /// Gets the textures which enabled passes read, but only disabled passes write. These have to be cleared before
+    /// the passes reading them.
+    pub fn textures_to_clear(&self, passes: &[RenderPassCreationInfo]) -> Vec<String> {
+        let written_by = |enabled: bool| -> HashSet<&str> {
+            passes
+                .iter()
+                .filter(|pass| self.is_pass_enabled(&pass.name) == enabled)
+                .flat_map(|pass| pass.texture_outputs.iter().chain(pass.depth_texture.iter()))
+                .map(|texture| texture.name.as_str())
+                .collect()
+        };
+        let written_by_enabled = written_by(true);
+        let written_by_disabled = written_by(false);
+
+        let mut textures: Vec<String> = Vec::new();
+        for pass in self.enabled_passes(passes) {
+            for input in &pass.texture_inputs {
+                let only_disabled =
+                    written_by_disabled.contains(input.as_str()) && !written_by_enabled.contains(input.as_str());
+                if only_disabled && !textures.contains(input) {
+                    textures.push(input.clone());
+                }
+            }
+        }
+        textures
+    }
+}
+
+/// Problem with a render graph that doesn't stop it from working, but is most likely a mistake by the shaderpack
+/// author.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderGraphWarning {
+    /// A texture is declared in the shaderpack's resources, but never used.
+    UnusedTexture(String),
+
+    /// A sampler is declared in the shaderpack's resources, but never used.
+    UnusedSampler(String),
+
+    /// Nothing reads anything the pass writes, so the pass does no useful work.
+    DeadPass(String),
+}
+
+impl fmt::Display for RenderGraphWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnusedTexture(name) => write!(f, "Texture {:?} is declared but never used", name),
+            Self::UnusedSampler(name) => write!(f, "Sampler {:?} is declared but never used", name),
+            Self::DeadPass(name) => write!(f, "Nothing reads the output of pass {:?}", name),
+        }
+    }
+}
+
+/// Finds declared resources that nothing uses, and passes whose output nothing reads.
+///
+/// A resource is used if any pass reads or writes it, or any material binds it. The pass writing to the
+/// [backbuffer](BACKBUFFER_NAME) is never considered dead. Every warning found is also logged.
+pub fn find_unused_resources(data: &ShaderpackData) -> Vec<RenderGraphWarning> {
+    let bound: HashSet<&str> = data
+        .materials
+        .iter()
+        .flat_map(|material| material.passes.iter())
+        .flat_map(|pass| pass.bindings.values())
+        .map(String::as_str)
+        .collect();
+    let read: HashSet<&str> = data
+        .passes
+        .iter()
+        .flat_map(|pass| pass.texture_inputs.iter().chain(pass.input_buffers.iter()))
+        .map(String::as_str)
+        .chain(bound.iter().copied())
+        .collect();
+
+    let mut used_textures = read.clone();
+    for pass in &data.passes {
+        for texture in pass.texture_outputs.iter().chain(pass.depth_texture.iter()) {
+            used_textures.insert(&texture.name);
+        }
+    }
+
+    let mut warnings = Vec::new();
+    for texture in &data.resources.textures {
+        if !used_textures.contains(texture.name.as_str()) {
+            warnings.push(RenderGraphWarning::UnusedTexture(texture.name.clone()));
+        }
+    }
+    for sampler in &data.resources.samplers {
+        if !bound.contains(sampler.name.as_str()) {
+            warnings.push(RenderGraphWarning::UnusedSampler(sampler.name.clone()));
+        }
+    }
+    for pass in &data.passes {
+        let mut outputs = pass
+            .texture_outputs
+            .iter()
+            .chain(pass.depth_texture.iter())
+            .map(|texture| texture.name.as_str())
+            .chain(pass.output_buffers.iter().map(String::as_str));
+        let useful = outputs.any(|output| output == BACKBUFFER_NAME || read.contains(output));
+        if !useful {
+            warnings.push(RenderGraphWarning::DeadPass(pass.name.clone()));
+        }
+    }
+
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+
+    warnings
+}
+
+/// Failure type for render graphs which can't be created on a device.
+#[derive(Fail, Debug, Clone, Eq, PartialEq)]
+pub enum RenderGraphError {
+    /// The device can't use a texture's pixel format the way the render graph uses the texture.
+    #[fail(
+        display = "Texture {:?} is used as {:?}, but the device doesn't support {:?} for that. Suggested format: {:?}",
+        texture, usage, format, fallback
+    )]
+    UnsupportedFormat {
+        /// Name of the texture.
+        texture: String,
+        /// Pixel format the texture is declared with.
+        format: PixelFormat,
+        /// How the render graph uses the texture.
+        usage: FormatUsage,
+        /// A format the device supports for the same usage, if there is one.
+        fallback: Option<PixelFormat>,
+    },
+}
+
+/// Gets the formats to try, in order, when a device doesn't support a pixel format.
+///
+/// Every fallback can hold everything the requested format can, so substituting it never loses data:
+///
+/// | Requested      | Fallbacks            |
+/// |----------------|----------------------|
+/// | `RGBA8`        | `RGBA16F`, `RGBA32F` |
+/// | `RGBA16F`      | `RGBA32F`            |
+/// | `RGBA32F`      | None                 |
+/// | `Depth`        | `DepthStencil`       |
+/// | `DepthStencil` | None                 |
+pub fn fallback_formats(format: PixelFormat) -> &'static [PixelFormat] {
+    match format {
+        PixelFormat::RGBA8 => &[PixelFormat::RGBA16F, PixelFormat::RGBA32F],
+        PixelFormat::RGBA16F => &[PixelFormat::RGBA32F],
+        PixelFormat::Depth => &[PixelFormat::DepthStencil],
+        PixelFormat::RGBA32F | PixelFormat::DepthStencil => &[],
+    }
+}
+
+/// Finds the format closest to `requested` which the device supports for all the given usages.
+///
+/// This is `requested` itself if it's supported, otherwise the first supported format from
+/// [`fallback_formats`]. Returns `None` if neither the format nor any of its fallbacks are supported.
+pub fn negotiate_format<D>(requested: PixelFormat, usages: &[FormatUsage], device: &D) -> Option<PixelFormat>
+where
+    D: Device,
+{
+    let supported = |format: PixelFormat| usages.iter().all(|&usage| device.supports_format(format, usage));
+
+    if supported(requested) {
+        return Some(requested);
+    }
+    fallback_formats(requested)
+        .iter()
+        .copied()
+        .find(|&format| supported(format))
+}
+
+/// Checks that the device supports the pixel format of every texture for everything the render graph uses it for.
+///
+/// Textures a pass writes are used as render targets or depth attachments, and textures a pass reads or a material
+/// binds are sampled. Textures which aren't declared in the shaderpack's resources, such as the
+/// [backbuffer](BACKBUFFER_NAME), are skipped.
+pub fn validate_texture_formats<D>(data: &ShaderpackData, device: &D) -> Result<(), RenderGraphError>
+where
+    D: Device,
+{
+    for texture in &data.resources.textures {
+        let format = texture.format.pixel_format;
+        for usage in texture_usages(data, &texture.name) {
+            if !device.supports_format(format, usage) {
+                return Err(RenderGraphError::UnsupportedFormat {
+                    texture: texture.name.clone(),
+                    format,
+                    usage,
+                    fallback: negotiate_format(format, &[usage], device),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the format of every texture the device doesn't support for all its uses with the closest supported
+/// format, as found by [`negotiate_format`].
+///
+/// The formats of the pass attachments using a substituted texture are updated too. Each substitution is logged as a
+/// warning. Fails if a texture has no supported format at all.
+pub fn negotiate_texture_formats<D>(data: &mut ShaderpackData, device: &D) -> Result<(), RenderGraphError>
+where
+    D: Device,
+{
+    let mut substitutions = HashMap::new();
+    for texture in &data.resources.textures {
+        let requested = texture.format.pixel_format;
+        let usages = texture_usages(data, &texture.name);
+        match negotiate_format(requested, &usages, device) {
+            Some(format) if format == requested => {}
+            Some(format) => {
+                warn!(
+                    "Texture {:?} uses {:?}, which the device doesn't support. Using {:?} instead",
+                    texture.name, requested, format
+                );
+                substitutions.insert(texture.name.clone(), format);
+            }
+            None => {
+                let usage = usages
+                    .iter()
+                    .copied()
+                    .find(|&usage| !device.supports_format(requested, usage))
+                    .unwrap_or(FormatUsage::Sampled);
+                return Err(RenderGraphError::UnsupportedFormat {
+                    texture: texture.name.clone(),
+                    format: requested,
+                    usage,
+                    fallback: None,
+                });
+            }
+        }
+    }
+
+    for texture in &mut data.resources.textures {
+        if let Some(format) = substitutions.get(&texture.name) {
+            texture.format.pixel_format = *format;
+        }
+    }
+    for pass in &mut data.passes {
+        for attachment in pass.texture_outputs.iter_mut().chain(pass.depth_texture.iter_mut()) {
+            if let Some(format) = substitutions.get(&attachment.name) {
+                attachment.pixel_format = *format;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Works out the size, in pixels, of every texture in the shaderpack's resources.
+///
+/// Screen-relative textures larger than [`Settings::max_texture_dimension`] are scaled down to fit, keeping their
+/// aspect ratio. A warning is logged for every texture which gets scaled down.
+///
+/// Renderers should use [`ScreenResolution`](crate::renderer::ScreenResolution) instead of calling this directly, so
+/// screen-relative textures are resized when the resolution changes.
+pub fn texture_sizes(
+    data: &ShaderpackData,
+    resolution: Vector2<u32>,
+    settings: &Settings,
+) -> HashMap<String, Vector2<f32>> {
+    let screen_size = Vector2::new(resolution.x as f32, resolution.y as f32);
+    data.resources
+        .textures
+        .iter()
+        .map(|texture| {
+            let requested = texture.format.get_size_in_pixels(screen_size);
+            let size = texture
+                .format
+                .get_clamped_size_in_pixels(screen_size, settings.max_texture_dimension);
+            if size != requested {
+                warn!(
+                    "Texture {:?} would be {}x{} pixels, clamping it to {}x{}",
+                    texture.name, requested.x, requested.y, size.x, size.y
+                );
+            }
+            (texture.name.clone(), size)
+        })
+        .collect()
+}
+
+/// Finds everything the render graph uses a texture for.
+///
+/// Textures a pass writes are used as render targets or depth attachments, and textures a pass reads or a material
+/// binds are sampled.
+fn texture_usages(data: &ShaderpackData, texture: &str) -> Vec<FormatUsage> {
+    let mut usages = Vec::new();
+    let mut add = |usage: FormatUsage| {
+        if !usages.contains(&usage) {
+            usages.push(usage);
+        }
+    };
+
+    for pass in &data.passes {
+        if pass.texture_outputs.iter().any(|output| output.name == texture) {
+            add(FormatUsage::RenderTarget);
+        }
+        if pass.depth_texture.iter().any(|depth| depth.name == texture) {
+            add(FormatUsage::DepthStencil);
+        }
+        if pass.texture_inputs.iter().any(|input| input == texture) {
+            add(FormatUsage::Sampled);
+        }
+    }
+    let bound = data
+        .materials
+        .iter()
+        .flat_map(|material| material.passes.iter())
+        .flat_map(|pass| pass.bindings.values())
+        .any(|resource| resource == texture);
+    if bound {
+        add(FormatUsage::Sampled);
+    }
+
+    usages
+}
+
+/// Declares the node for a texture or buffer the first time it is seen.
+fn add_resource_node<'a>(dot: &mut String, seen: &mut HashSet<&'a str>, kind: &str, name: &'a str) {
+    if seen.insert(name) {
+        let shape = if kind == "buffer" { "cylinder" } else { "ellipse" };
+        dot.push_str(&format!(
+            "    {} [label={}, shape={}];\n",
+            node_id(kind, name),
+            quote(name),
+            shape
+        ));
+    }
+}
+
+/// Identifier of a node. Prefixed by kind so a pass and a texture with the same name don't collide.
+fn node_id(kind: &str, name: &str) -> String {
+    quote(&format!("{}:{}", kind, name))
+}
+
+/// Turns a name into a quoted DOT string.
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rhi::mock::MockDevice;
+    use crate::shaderpack::{ShaderSet, ShaderpackResourceData};
+
+    fn default_pack() -> ShaderpackData {
+        let passes: Vec<RenderPassCreationInfo> = serde_json::from_str(include_str!(
+            "../../tests/data/shaderpacks/nova/DefaultShaderpack/passes.json"
+        ))
+        .expect("Failed to parse passes.json");
+        let resources: ShaderpackResourceData = serde_json::from_str(include_str!(
+            "../../tests/data/shaderpacks/nova/DefaultShaderpack/resources.json"
+        ))
+        .expect("Failed to parse resources.json");
+
+        ShaderpackData {
+            pipelines: Vec::new(),
+            passes,
+            materials: Vec::new(),
+            resources,
+            shaders: ShaderSet::Sources(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn graphviz_default_pack() {
+        let dot = export_graphviz(&default_pack());
+
+        assert!(dot.starts_with("digraph render_graph {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains(r#""pass:Forward" [label="Forward", shape=box];"#));
+        assert!(dot.contains(r#""pass:Final" [label="Final", shape=box];"#));
+        assert!(dot.contains(r#""pass:Forward" -> "texture:LitWorld";"#));
+        assert!(dot.contains(r#""texture:LitWorld" -> "pass:Final";"#));
+        assert!(dot.contains(r#""buffer:NovaMegaMesh_Vertices" -> "pass:Forward";"#));
+    }
+
+    #[test]
+    fn graphviz_escapes_names() {
+        assert_eq!(quote(r#"a "quoted" name"#), r#""a \"quoted\" name""#);
+    }
+
+    fn parse_passes(json: &str) -> Vec<RenderPassCreationInfo> {
+        serde_json::from_str(json).expect("Failed to parse passes json")
+    }
+
+    #[test]
+    fn infer_texture_dependency() {
+        let mut passes = parse_passes(
+            r#"[
+                { "name": "A", "textureOutputs": [{ "name": "LitWorld", "clear": true }] },
+                { "name": "B", "textureInputs": ["LitWorld"] }
+            ]"#,
+        );
+        infer_dependencies(&mut passes);
+
+        assert!(passes[0].dependencies.is_empty());
+        assert_eq!(passes[1].dependencies, ["A"]);
+    }
+
+    #[test]
+    fn infer_merges_with_declared() {
+        let mut passes = parse_passes(
+            r#"[
+                { "name": "A", "bufferOutputs": ["Particles"] },
+                { "name": "B", "depthTexture": { "name": "DepthBuffer", "clear": true } },
+                { "name": "C", "dependencies": ["A"], "bufferInputs": ["Particles"], "textureInputs": ["DepthBuffer"] }
+            ]"#,
+        );
+        infer_dependencies(&mut passes);
+
+        assert_eq!(passes[2].dependencies, ["A", "B"]);
+    }
+
+    #[test]
+    fn infer_default_pack() {
+        let mut data = default_pack();
+        infer_dependencies(&mut data.passes);
+
+        assert!(data.passes[0].dependencies.is_empty());
+        assert_eq!(data.passes[1].dependencies, ["Forward"]);
+    }
+
+    #[test]
+    fn unused_default_pack() {
+        let warnings = find_unused_resources(&default_pack());
+        assert_eq!(warnings, [RenderGraphWarning::UnusedSampler("Point".into())]);
+    }
+
+    #[test]
+    fn unused_texture_and_dead_pass() {
+        let mut data = default_pack();
+        data.resources = serde_json::from_str(
+            r#"{
+                "textures": [
+                    { "name": "LitWorld", "format": { "pixelFormat": "RGBA8" } },
+                    { "name": "Bloom", "format": { "pixelFormat": "RGBA8" } }
+                ],
+                "samplers": []
+            }"#,
+        )
+        .expect("Failed to parse resources json");
+        data.passes.extend(parse_passes(
+            r#"[{ "name": "Debug", "textureInputs": ["LitWorld"], "textureOutputs": [{ "name": "DebugView" }] }]"#,
+        ));
+
+        let warnings = find_unused_resources(&data);
+        assert_eq!(
+            warnings,
+            [
+                RenderGraphWarning::UnusedTexture("Bloom".into()),
+                RenderGraphWarning::DeadPass("Debug".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn barriers_default_pack() {
+        let barriers = plan_barriers(&default_pack().passes);
+        let barrier = |texture: &str, before, after| TextureBarrier {
+            texture: texture.into(),
+            before,
+            after,
+        };
+
+        assert_eq!(
+            barriers[0],
+            [
+                barrier("LitWorld", ResourceState::Undefined, ResourceState::ColorAttachment),
+                barrier(
+                    "DepthBuffer",
+                    ResourceState::Undefined,
+                    ResourceState::DepthStencilAttachment
+                ),
+            ]
+        );
+        assert_eq!(
+            barriers[1],
+            [
+                barrier(
+                    "LitWorld",
+                    ResourceState::ColorAttachment,
+                    ResourceState::FragmentShaderReadOnly
+                ),
+                barrier("Backbuffer", ResourceState::Undefined, ResourceState::ColorAttachment),
+            ]
+        );
+    }
+
+    #[test]
+    fn barriers_skip_unchanged_state() {
+        let passes = parse_passes(
+            r#"[
+                { "name": "A", "textureOutputs": [{ "name": "Bloom" }] },
+                { "name": "B", "textureOutputs": [{ "name": "Bloom" }] },
+                { "name": "C", "textureInputs": ["Bloom"] },
+                { "name": "D", "textureInputs": ["Bloom"] }
+            ]"#,
+        );
+
+        let barriers = plan_barriers(&passes);
+        assert_eq!(barriers[0].len(), 1);
+        assert!(barriers[1].is_empty());
+        assert_eq!(barriers[2][0].after, ResourceState::FragmentShaderReadOnly);
+        assert!(barriers[3].is_empty());
+    }
+
+    #[test]
+    fn texture_formats() {
+        let data = default_pack();
+        assert_eq!(validate_texture_formats(&data, &MockDevice::default()), Ok(()));
+
+        let device = MockDevice {
+            unsupported_formats: vec![(PixelFormat::RGBA8, FormatUsage::RenderTarget)],
+            ..MockDevice::default()
+        };
+        assert_eq!(
+            validate_texture_formats(&data, &device),
+            Err(RenderGraphError::UnsupportedFormat {
+                texture: String::from("LitWorld"),
+                format: PixelFormat::RGBA8,
+                usage: FormatUsage::RenderTarget,
+                fallback: Some(PixelFormat::RGBA16F),
+            })
+        );
+
+        // No depth format works at all
+        let device = MockDevice {
+            unsupported_formats: vec![
+                (PixelFormat::Depth, FormatUsage::DepthStencil),
+                (PixelFormat::DepthStencil, FormatUsage::DepthStencil),
+            ],
+            ..MockDevice::default()
+        };
+        assert_eq!(
+            validate_texture_formats(&data, &device),
+            Err(RenderGraphError::UnsupportedFormat {
+                texture: String::from("DepthBuffer"),
+                format: PixelFormat::Depth,
+                usage: FormatUsage::DepthStencil,
+                fallback: None,
+            })
+        );
+    }
+
+    #[test]
+    fn format_negotiation() {
+        let usages = [FormatUsage::RenderTarget, FormatUsage::Sampled];
+        let mut device = MockDevice::default();
+        assert_eq!(
+            negotiate_format(PixelFormat::RGBA8, &usages, &device),
+            Some(PixelFormat::RGBA8)
+        );
+
+        // Follows the chain until a format is supported for every usage
+        device.unsupported_formats = vec![
+            (PixelFormat::RGBA8, FormatUsage::RenderTarget),
+            (PixelFormat::RGBA16F, FormatUsage::Sampled),
+        ];
+        assert_eq!(
+            negotiate_format(PixelFormat::RGBA8, &usages, &device),
+            Some(PixelFormat::RGBA32F)
+        );
+        assert_eq!(
+            negotiate_format(PixelFormat::RGBA8, &[FormatUsage::RenderTarget], &device),
+            Some(PixelFormat::RGBA16F)
+        );
+
+        device.unsupported_formats = vec![(PixelFormat::RGBA32F, FormatUsage::RenderTarget)];
+        assert_eq!(negotiate_format(PixelFormat::RGBA32F, &usages, &device), None);
+    }
+
+    #[test]
+    fn texture_format_substitution() {
+        let mut data = default_pack();
+        let device = MockDevice {
+            unsupported_formats: vec![(PixelFormat::Depth, FormatUsage::DepthStencil)],
+            ..MockDevice::default()
+        };
+
+        negotiate_texture_formats(&mut data, &device).unwrap();
+        assert_eq!(data.resources.textures[0].format.pixel_format, PixelFormat::RGBA8);
+        assert_eq!(
+            data.resources.textures[1].format.pixel_format,
+            PixelFormat::DepthStencil
+        );
+        assert_eq!(
+            data.passes[0].depth_texture.as_ref().unwrap().pixel_format,
+            PixelFormat::DepthStencil
+        );
+        assert_eq!(validate_texture_formats(&data, &device), Ok(()));
+
+        let device = MockDevice {
+            unsupported_formats: vec![(PixelFormat::DepthStencil, FormatUsage::DepthStencil)],
+            ..MockDevice::default()
+        };
+        assert_eq!(
+            negotiate_texture_formats(&mut data, &device),
+            Err(RenderGraphError::UnsupportedFormat {
+                texture: String::from("DepthBuffer"),
+                format: PixelFormat::DepthStencil,
+                usage: FormatUsage::DepthStencil,
+                fallback: None,
+            })
+        );
+    }
+
+    #[test]
+    fn disabled_passes() {
+        let data = default_pack();
+        let mut toggles = PassToggles::default();
+        assert_eq!(toggles.enabled_passes(&data.passes).len(), 2);
+        assert!(toggles.textures_to_clear(&data.passes).is_empty());
+
+        toggles.set_pass_enabled(&data, "Forward", false);
+        assert!(!toggles.is_pass_enabled("Forward"));
+        let enabled: Vec<&str> = toggles
+            .enabled_passes(&data.passes)
+            .iter()
+            .map(|pass| pass.name.as_str())
+            .collect();
+        assert_eq!(enabled, vec!["Final"]);
+        assert_eq!(toggles.textures_to_clear(&data.passes), vec![String::from("LitWorld")]);
+
+        toggles.set_pass_enabled(&data, "Forward", true);
+        assert!(toggles.is_pass_enabled("Forward"));
+        assert_eq!(toggles.enabled_passes(&data.passes).len(), 2);
+    }
+
+    #[test]
+    fn oversized_textures_are_clamped() {
+        let mut data = default_pack();
+        data.resources.textures[1].format.width = 0.5;
+        let settings = Settings {
+            max_texture_dimension: 4096,
+            ..Settings::default()
+        };
+
+        let sizes = texture_sizes(&data, Vector2::new(1920, 1080), &settings);
+        assert_eq!(sizes["LitWorld"], Vector2::new(1920.0, 1080.0));
+
+        // 8K screen
+        let sizes = texture_sizes(&data, Vector2::new(7680, 4320), &settings);
+        assert_eq!(sizes["LitWorld"], Vector2::new(4096.0, 2304.0));
+        // Half as wide, so the height is what gets clamped
+        assert_eq!(sizes["DepthBuffer"], Vector2::new(3641.0, 4096.0));
+    }
+}
@@ -0,0 +1,208 @@
+//! Keeping the renderer's GPU allocations within the memory the GPU has free.
+
+use crate::rhi::PhysicalDevice;
+use failure::Fail;
+use log::info;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+/// Failure type for making room in a [`MemoryBudget`].
+#[derive(Fail, Debug, Clone, Eq, PartialEq)]
+pub enum MemoryBudgetError {
+    /// Evicting every resource which may be evicted still wouldn't leave enough memory. Nothing was evicted.
+    #[fail(
+        display = "Allocating {} bytes would exceed the GPU memory budget of {} bytes, {} of which can't be evicted",
+        requested, budget, essential
+    )]
+    OverBudget {
+        /// Size of the allocation, in bytes.
+        requested: u64,
+        /// The budget, in bytes.
+        budget: u64,
+        /// Memory used by resources which can't be evicted, in bytes.
+        essential: u64,
+    },
+}
+
+/// A resource tracked by a [`MemoryBudget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TrackedResource {
+    size: u64,
+    /// Resources which the renderer needs every frame, such as render targets, are never evicted.
+    evictable: bool,
+    last_used_frame: u64,
+}
+
+/// Tracks how much GPU memory the renderer has allocated, and picks resources to evict when an allocation wouldn't
+/// fit otherwise.
+///
+/// Running out of GPU memory usually ends in a lost device, which Nova can't recover from. Checking every allocation
+/// against the budget first means low-end GPUs evict unused resources, such as the buffers of meshes which haven't been
+/// drawn for a while, or fail the allocation with a [`MemoryBudgetError`] instead.
+///
+/// Resources are identified by keys of type `K`, such as [`MeshId`](crate::mesh::MeshId).
+#[derive(Debug, Clone)]
+pub struct MemoryBudget<K> {
+    budget: u64,
+    allocated: u64,
+    resources: BTreeMap<K, TrackedResource>,
+}
+
+impl<K> MemoryBudget<K>
+where
+    K: Ord + Copy + Debug,
+{
+    /// Creates a budget of the given number of bytes.
+    pub fn new(budget: u64) -> Self {
+        Self {
+            budget,
+            allocated: 0,
+            resources: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a budget from the memory the GPU has free, keeping a tenth of it spare for the driver and other
+    /// applications. Must be called before the renderer allocates anything.
+    pub fn for_device<P>(device: &P) -> Self
+    where
+        P: PhysicalDevice,
+    {
+        let free = device.get_free_memory();
+        Self::new(free - free / 10)
+    }
+
+    /// The budget, in bytes.
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    /// Memory used by all tracked resources, in bytes.
+    pub fn allocated(&self) -> u64 {
+        self.allocated
+    }
+
+    /// Evicts the least recently used evictable resources until an allocation of the given size fits in the budget.
+    ///
+    /// Returns the evicted resources, which are no longer tracked. The caller must free them before allocating. Fails
+    /// without evicting anything if the allocation wouldn't fit even with every evictable resource gone.
+    pub fn make_room(&mut self, size: u64) -> Result<Vec<K>, MemoryBudgetError> {
+        if self.allocated + size <= self.budget {
+            return Ok(Vec::new());
+        }
+
+        let essential: u64 = self
+            .resources
+            .values()
+            .filter(|resource| !resource.evictable)
+            .map(|resource| resource.size)
+            .sum();
+        if essential + size > self.budget {
+            return Err(MemoryBudgetError::OverBudget {
+                requested: size,
+                budget: self.budget,
+                essential,
+            });
+        }
+
+        let mut candidates: Vec<(u64, K)> = self
+            .resources
+            .iter()
+            .filter(|(_, resource)| resource.evictable)
+            .map(|(&key, resource)| (resource.last_used_frame, key))
+            .collect();
+        candidates.sort();
+
+        let mut evicted = Vec::new();
+        for (last_used_frame, key) in candidates {
+            if self.allocated + size <= self.budget {
+                break;
+            }
+            if let Some(freed) = self.release(key) {
+                info!(
+                    "Evicting {:?} ({} bytes, last used in frame {}) to stay within the GPU memory budget",
+                    key, freed, last_used_frame
+                );
+                evicted.push(key);
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// Starts tracking a resource which has just been allocated. Call [`make_room`](Self::make_room) before allocating
+    /// it.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - Identifies the resource.
+    /// * `size` - Size of the resource's memory, in bytes.
+    /// * `evictable` - Whether the resource may be evicted to make room for others.
+    /// * `frame` - Index of the current frame.
+    pub fn track(&mut self, key: K, size: u64, evictable: bool, frame: u64) {
+        self.release(key);
+        self.allocated += size;
+        self.resources.insert(
+            key,
+            TrackedResource {
+                size,
+                evictable,
+                last_used_frame: frame,
+            },
+        );
+    }
+
+    /// Marks a resource as used in the given frame, so it's evicted after resources which haven't been used since.
+    pub fn touch(&mut self, key: K, frame: u64) {
+        if let Some(resource) = self.resources.get_mut(&key) {
+            resource.last_used_frame = frame;
+        }
+    }
+
+    /// Stops tracking a resource, such as after it was freed. Returns its size, or `None` if it wasn't tracked.
+    pub fn release(&mut self, key: K) -> Option<u64> {
+        let resource = self.resources.remove(&key)?;
+        self.allocated -= resource.size;
+        Some(resource.size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::MeshId;
+    use crate::rhi::mock::MockPhysicalDevice;
+
+    #[test]
+    fn least_recently_used_meshes_are_evicted() {
+        let mut adapter = MockPhysicalDevice::new("Low end GPU");
+        adapter.free_memory = 1000;
+        let mut budget = MemoryBudget::for_device(&adapter);
+        assert_eq!(budget.budget(), 900);
+
+        // The render targets can't be evicted
+        budget.track(MeshId(100), 300, false, 0);
+        for mesh in 0..4 {
+            assert_eq!(budget.make_room(100), Ok(Vec::new()));
+            budget.track(MeshId(mesh), 100, true, mesh);
+        }
+        budget.touch(MeshId(0), 10);
+        assert_eq!(budget.allocated(), 700);
+
+        // Meshes 1 and 2 haven't been drawn for the longest time
+        assert_eq!(budget.make_room(350), Ok(vec![MeshId(1), MeshId(2)]));
+        assert_eq!(budget.allocated(), 500);
+        budget.track(MeshId(4), 350, true, 11);
+
+        // Too big even with every mesh evicted
+        assert_eq!(
+            budget.make_room(700),
+            Err(MemoryBudgetError::OverBudget {
+                requested: 700,
+                budget: 900,
+                essential: 300,
+            })
+        );
+        assert_eq!(budget.allocated(), 850);
+        assert_eq!(budget.release(MeshId(4)), Some(350));
+        assert_eq!(budget.release(MeshId(4)), None);
+    }
+}
@@ -0,0 +1,29 @@
+//! Nova's renderer.
+//!
+//! Turns the data loaded from a shaderpack into work for the RHI. Currently this only contains choosing an adapter,
+//! the draw commands of the scene and the order to record them in, the GPU-independent processing of the shaderpack's
+//! render graph, the screen resolution textures are sized from, tracking what to rebuild when shaders change, the
+//! staging buffers for uploads, keeping the pipeline cache between runs, creating pipelines in parallel and keeping
+//! GPU allocations within budget.
+
+mod adapter;
+mod draws;
+mod graph;
+mod memory_budget;
+mod pipeline_cache;
+mod pipelines;
+mod reload;
+mod resolution;
+mod scene;
+mod staging;
+
+pub use adapter::*;
+pub use draws::*;
+pub use graph::*;
+pub use memory_budget::*;
+pub use pipeline_cache::*;
+pub use pipelines::*;
+pub use reload::*;
+pub use resolution::*;
+pub use scene::*;
+pub use staging::*;
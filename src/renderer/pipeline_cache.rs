@@ -0,0 +1,195 @@
+//! Keeping the graphics API's pipeline cache on disk between runs.
+
+use crate::rhi::PhysicalDeviceProperties;
+use log::warn;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+
+/// Identifies pipeline cache files written by Nova.
+const MAGIC: &[u8; 8] = b"NOVAPIPE";
+
+/// Version of the layout of pipeline cache files. Files with any other version are discarded.
+const FORMAT_VERSION: u32 = 1;
+
+/// Saves a pipeline cache, along with which device it's for.
+///
+/// # Parameters
+///
+/// * `path` - File to save the cache in.
+/// * `properties` - Properties of the device the cache is from.
+/// * `data` - The cache, as returned by
+///   [`Device::get_pipeline_cache_data`](crate::rhi::Device::get_pipeline_cache_data).
+pub fn save_pipeline_cache(path: &Path, properties: &PhysicalDeviceProperties, data: &[u8]) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+
+    let mut contents = Vec::with_capacity(header_size(properties) + data.len());
+    contents.extend_from_slice(MAGIC);
+    contents.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    contents.extend_from_slice(&properties.device_id.to_le_bytes());
+    contents.extend_from_slice(&(properties.device_name.len() as u32).to_le_bytes());
+    contents.extend_from_slice(properties.device_name.as_bytes());
+    contents.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    contents.extend_from_slice(&checksum(data).to_le_bytes());
+    contents.extend_from_slice(data);
+    fs::write(path, contents)
+}
+
+/// Loads a pipeline cache saved by [`save_pipeline_cache`].
+///
+/// Returns an empty cache if there is no cache yet, and discards caches which are corrupt or from another device, so
+/// the result can always be passed to
+/// [`PhysicalDevice::create_logical_device`](crate::rhi::PhysicalDevice::create_logical_device).
+///
+/// # Parameters
+///
+/// * `path` - File the cache was saved in.
+/// * `properties` - Properties of the device the cache will be used with.
+pub fn load_pipeline_cache(path: &Path, properties: &PhysicalDeviceProperties) -> Vec<u8> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(err) => {
+            warn!(
+                "Failed to read pipeline cache {:?}, starting with an empty one: {}",
+                path, err
+            );
+            return Vec::new();
+        }
+    };
+
+    match parse_pipeline_cache(&contents, properties) {
+        Ok(data) => data.to_vec(),
+        Err(reason) => {
+            warn!("Discarding pipeline cache {:?}: {}", path, reason);
+            Vec::new()
+        }
+    }
+}
+
+/// Gets the API's data out of a pipeline cache file, or says why the file can't be used.
+fn parse_pipeline_cache<'a>(contents: &'a [u8], properties: &PhysicalDeviceProperties) -> Result<&'a [u8], String> {
+    let mut reader = ByteReader(contents);
+    if reader.take(MAGIC.len()) != Some(&MAGIC[..]) {
+        return Err(String::from("not a Nova pipeline cache"));
+    }
+    match reader.u32() {
+        Some(FORMAT_VERSION) => {}
+        Some(version) => return Err(format!("unsupported version {}", version)),
+        None => return Err(String::from("file is truncated")),
+    }
+
+    let (device_id, device_name, data_length, expected_checksum) =
+        read_device_header(&mut reader).ok_or_else(|| String::from("file is truncated"))?;
+    if device_id != properties.device_id || device_name != properties.device_name.as_bytes() {
+        return Err(format!(
+            "cache is for device {:?} ({}), not {:?} ({})",
+            String::from_utf8_lossy(device_name),
+            device_id,
+            properties.device_name,
+            properties.device_id
+        ));
+    }
+
+    let data = reader.0;
+    if data.len() as u64 != data_length || checksum(data) != expected_checksum {
+        return Err(String::from("cache data is corrupt"));
+    }
+    Ok(data)
+}
+
+/// Reads the id and name of the device a cache is for, followed by the length and checksum of the API's data.
+fn read_device_header<'a>(reader: &mut ByteReader<'a>) -> Option<(u32, &'a [u8], u64, u64)> {
+    let device_id = reader.u32()?;
+    let name_length = reader.u32()? as usize;
+    let device_name = reader.take(name_length)?;
+    Some((device_id, device_name, reader.u64()?, reader.u64()?))
+}
+
+/// Size of the header [`save_pipeline_cache`] writes in front of the API's data, in bytes.
+fn header_size(properties: &PhysicalDeviceProperties) -> usize {
+    MAGIC.len() + 4 + 4 + 4 + properties.device_name.len() + 8 + 8
+}
+
+/// Hashes the API's data, to find caches which were damaged on disk.
+fn checksum(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Reads little endian values from the front of a byte slice.
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.0.len() < count {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|bytes| {
+            let mut word = [0; 8];
+            word.copy_from_slice(bytes);
+            u64::from_le_bytes(word)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rhi::mock::MockPhysicalDevice;
+    use crate::rhi::{Device, PhysicalDevice};
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn pipeline_cache_round_trip() {
+        let directory = TempDir::new("pipeline-cache");
+        let path = directory.path().join("pipelines.bin");
+        let adapter = MockPhysicalDevice::new("Discrete GPU");
+        let properties = adapter.get_properties();
+
+        // Nothing saved yet
+        assert!(load_pipeline_cache(&path, &properties).is_empty());
+
+        let device = adapter.create_logical_device(&[]).unwrap();
+        let mut cache = device.get_pipeline_cache_data().unwrap();
+        cache.extend_from_slice(b"compiled pipelines");
+        save_pipeline_cache(&path, &properties, &cache).unwrap();
+
+        let loaded = load_pipeline_cache(&path, &properties);
+        assert_eq!(loaded, cache);
+        let device = adapter.create_logical_device(&loaded).unwrap();
+        assert_eq!(device.get_pipeline_cache_data().unwrap(), cache);
+
+        // Caches from another GPU are discarded
+        let mut other = properties.clone();
+        other.device_id = 7;
+        assert!(load_pipeline_cache(&path, &other).is_empty());
+
+        // So are damaged ones
+        let mut contents = fs::read(&path).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 0xFF;
+        fs::write(&path, &contents).unwrap();
+        assert!(load_pipeline_cache(&path, &properties).is_empty());
+        fs::write(&path, &contents[..10]).unwrap();
+        assert!(load_pipeline_cache(&path, &properties).is_empty());
+        fs::write(&path, b"garbage").unwrap();
+        assert!(load_pipeline_cache(&path, &properties).is_empty());
+    }
+}
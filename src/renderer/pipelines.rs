@@ -0,0 +1,199 @@
+//! Creating the pipelines of a shaderpack on several threads at once.
+
+use crate::async_utils::NovaExecutor;
+use crate::rhi::PipelineCreationError;
+use crate::shaderpack::PipelineCreationInfo;
+use failure::Fail;
+use futures::task::{SpawnError, SpawnExt};
+use std::sync::Arc;
+
+/// Failure type for [`create_pipelines_in_parallel`].
+#[derive(Fail, Debug)]
+pub enum PipelineBuildError {
+    /// The API failed to create one of the pipelines.
+    #[fail(display = "Failed to create pipeline {:?}: {}", pipeline, error)]
+    Creation {
+        /// Name of the offending pipeline
+        pipeline: String,
+        /// Actual error
+        #[fail(cause)]
+        error: PipelineCreationError,
+    },
+
+    /// The executor refused to run one of the pipeline creation tasks, usually because it is shutting down.
+    #[fail(display = "Executor failed to spawn pipeline creation task: {:?}", sub_error)]
+    ExecutorError {
+        /// Actual error
+        #[fail(cause)]
+        sub_error: SpawnError,
+    },
+}
+
+/// Creates every pipeline on the executor, returning them in the same order as `pipelines`.
+///
+/// Compiling a pipeline's shaders and creating it don't depend on any other pipeline, so this spreads the most
+/// expensive part of loading or reloading a shaderpack over all the executor's threads. `create` should create its
+/// pipelines with a device made from the saved pipeline cache (see
+/// [`load_pipeline_cache`](super::load_pipeline_cache)), so every thread still benefits from it.
+///
+/// Every pipeline is created even if an earlier one fails; the error returned is the one for the first pipeline which
+/// failed. If the executor refuses a task, the tasks it already accepted are waited for before the error is returned,
+/// so no pipeline is still being created once this returns.
+///
+/// # Parameters
+///
+/// * `executor` - Executor to create the pipelines on.
+/// * `pipelines` - The pipelines to create.
+/// * `create` - Creates a single pipeline, usually with
+///   [`Device::create_pipeline`](crate::rhi::Device::create_pipeline). It's called from several threads at once.
+pub async fn create_pipelines_in_parallel<E, F, P>(
+    mut executor: E,
+    pipelines: Vec<PipelineCreationInfo>,
+    create: F,
+) -> Result<Vec<P>, PipelineBuildError>
+where
+    E: NovaExecutor,
+    F: Fn(PipelineCreationInfo) -> Result<P, PipelineCreationError> + Send + Sync + 'static,
+    P: Send + 'static,
+{
+    let create = Arc::new(create);
+    let mut handles = Vec::with_capacity(pipelines.len());
+    for pipeline in pipelines {
+        let name = pipeline.name.clone();
+        let create = Arc::clone(&create);
+        match executor.spawn_with_handle(async move { (*create)(pipeline) }) {
+            Ok(handle) => handles.push((name, handle)),
+            Err(sub_error) => {
+                for (_, handle) in handles {
+                    let _ = handle.await;
+                }
+                return Err(PipelineBuildError::ExecutorError { sub_error });
+            }
+        }
+    }
+
+    let mut created = Vec::with_capacity(handles.len());
+    let mut first_error = None;
+    for (pipeline, handle) in handles {
+        match handle.await {
+            Ok(created_pipeline) => created.push(created_pipeline),
+            Err(error) => {
+                if first_error.is_none() {
+                    first_error = Some(PipelineBuildError::Creation { pipeline, error });
+                }
+            }
+        }
+    }
+
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(created),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor::{ThreadPool, ThreadPoolBuilder};
+    use futures::future::FutureObj;
+    use futures::task::Spawn;
+    use matches::matches;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    fn pipeline(name: &str) -> PipelineCreationInfo {
+        serde_json::from_str(&format!(
+            r#"{{ "name": "{}", "pass": "main", "vertexFields": [] }}"#,
+            name
+        ))
+        .expect("Failed to parse pipeline")
+    }
+
+    #[test]
+    fn pipelines_are_created_concurrently() {
+        let mut thread_pool = ThreadPoolBuilder::new().pool_size(2).create().unwrap();
+        let executor = thread_pool.clone();
+        let names = ["gbuffer", "shadows", "lighting", "final"];
+        let pipelines: Vec<_> = names.iter().map(|name| pipeline(name)).collect();
+
+        // Each pipeline waits for another one to be created at the same time, so this hangs if creation is serial
+        let barrier = Barrier::new(2);
+        let created = thread_pool
+            .run(create_pipelines_in_parallel(
+                executor.clone(),
+                pipelines.clone(),
+                move |pipeline| {
+                    barrier.wait();
+                    Ok(pipeline.name)
+                },
+            ))
+            .unwrap();
+        assert_eq!(created, names);
+
+        let result = thread_pool.run(create_pipelines_in_parallel(
+            executor,
+            pipelines,
+            |pipeline| match pipeline.name.as_str() {
+                "shadows" | "final" => Err(PipelineCreationError::InvalidShader),
+                _ => Ok(()),
+            },
+        ));
+        assert!(matches!(
+            result,
+            Err(PipelineBuildError::Creation {
+                ref pipeline,
+                error: PipelineCreationError::InvalidShader,
+            }) if pipeline == "shadows"
+        ));
+    }
+
+    /// Executor which runs a few tasks, then refuses the rest as if it was shutting down.
+    #[derive(Clone)]
+    struct ShuttingDownExecutor {
+        pool: ThreadPool,
+        accepted: Arc<AtomicUsize>,
+        limit: usize,
+    }
+
+    impl Spawn for ShuttingDownExecutor {
+        fn spawn_obj(&mut self, future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+            if self.accepted.fetch_add(1, Ordering::SeqCst) >= self.limit {
+                return Err(SpawnError::shutdown());
+            }
+            self.pool.spawn_obj(future)
+        }
+    }
+
+    #[test]
+    fn spawned_pipelines_are_waited_for() {
+        let mut thread_pool = ThreadPoolBuilder::new().pool_size(2).create().unwrap();
+        let executor = ShuttingDownExecutor {
+            pool: thread_pool.clone(),
+            accepted: Arc::new(AtomicUsize::new(0)),
+            limit: 2,
+        };
+        let pipelines: Vec<_> = ["gbuffer", "shadows", "lighting"]
+            .iter()
+            .map(|name| pipeline(name))
+            .collect();
+
+        let created = Arc::new(AtomicUsize::new(0));
+        let result = {
+            let created = Arc::clone(&created);
+            thread_pool.run(create_pipelines_in_parallel(executor, pipelines, move |_| {
+                thread::sleep(Duration::from_millis(50));
+                created.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }))
+        };
+        assert!(matches!(
+            result,
+            Err(PipelineBuildError::ExecutorError { ref sub_error }) if sub_error.is_shutdown()
+        ));
+
+        // Both pipelines which were spawned have been created by the time the error comes back
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,147 @@
+//! Finding the least amount of work needed to pick up edits to a shaderpack's shaders.
+
+use crate::shaderpack::{ShaderSet, ShaderpackData};
+use std::collections::HashMap;
+
+/// Which pipelines use which shaders.
+///
+/// When only the code of some shaders changed, the renderer only has to recreate the shader modules of the pipelines
+/// using those shaders and relink them. Renderpasses, framebuffers and descriptor pools can all be kept.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderDependencies {
+    /// Indices of the pipelines using each shader, by shader index.
+    users: HashMap<u32, Vec<usize>>,
+}
+
+impl ShaderDependencies {
+    /// Finds the shaders used by each pipeline of a shaderpack.
+    pub fn new(data: &ShaderpackData) -> Self {
+        let mut users: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (pipeline_index, pipeline) in data.pipelines.iter().enumerate() {
+            for shader in pipeline.loaded_shaders() {
+                let pipelines = users.entry(shader).or_default();
+                if !pipelines.contains(&pipeline_index) {
+                    pipelines.push(pipeline_index);
+                }
+            }
+        }
+        Self { users }
+    }
+
+    /// Gets the indices of the pipelines using a shader.
+    pub fn pipelines_using(&self, shader: u32) -> &[usize] {
+        self.users.get(&shader).map_or(&[][..], Vec::as_slice)
+    }
+
+    /// Gets the indices of the pipelines which have to be rebuilt after the given shaders changed, in ascending order.
+    pub fn pipelines_to_rebuild(&self, changed_shaders: &[u32]) -> Vec<usize> {
+        let mut pipelines: Vec<usize> = changed_shaders
+            .iter()
+            .flat_map(|&shader| self.pipelines_using(shader).iter().copied())
+            .collect();
+        pipelines.sort_unstable();
+        pipelines.dedup();
+        pipelines
+    }
+}
+
+/// Finds the indices of the shaders whose code differs between two versions of a shaderpack's shaders.
+///
+/// Returns `None` if the versions can't be compared shader by shader because shaders were added, removed or renamed,
+/// or one version is compiled and the other isn't. Then the shader indices of the pipelines may have changed as well,
+/// so the whole shaderpack has to be reloaded.
+pub fn changed_shaders(old: &ShaderSet, new: &ShaderSet) -> Option<Vec<u32>> {
+    match (old, new) {
+        (ShaderSet::Sources(old), ShaderSet::Sources(new)) => {
+            if old.len() != new.len() || old.iter().zip(new).any(|(old, new)| old.filename != new.filename) {
+                return None;
+            }
+            Some(changed_indices(
+                old.iter().zip(new).map(|(old, new)| old.source != new.source),
+            ))
+        }
+        (ShaderSet::Compiled(old), ShaderSet::Compiled(new)) => {
+            if old.len() != new.len() || old.iter().zip(new).any(|(old, new)| old.filename != new.filename) {
+                return None;
+            }
+            Some(changed_indices(
+                old.iter().zip(new).map(|(old, new)| old.module != new.module),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Turns a list of flags into the indices of the set flags.
+fn changed_indices(changed: impl Iterator<Item = bool>) -> Vec<u32> {
+    changed
+        .enumerate()
+        .filter(|(_, changed)| *changed)
+        .map(|(index, _)| index as u32)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shaderpack::{LoadedShader, PipelineCreationInfo, ShaderSource, ShaderpackResourceData};
+
+    fn shader(filename: &str, source: &str) -> LoadedShader {
+        LoadedShader {
+            filename: filename.into(),
+            source: String::from(source),
+        }
+    }
+
+    fn pipeline(name: &str, vertex: u32, fragment: u32) -> PipelineCreationInfo {
+        let mut pipeline: PipelineCreationInfo = serde_json::from_str(&format!(
+            r#"{{ "name": "{}", "pass": "main", "vertexFields": [] }}"#,
+            name
+        ))
+        .expect("Failed to parse pipeline");
+        pipeline.vertex_shader = ShaderSource::Loaded(vertex);
+        pipeline.fragment_shader = Some(ShaderSource::Loaded(fragment));
+        pipeline
+    }
+
+    #[test]
+    fn only_dependent_pipelines_are_rebuilt() {
+        let shaders = vec![
+            shader("shaders/common.vert", "void main() {}"),
+            shader("shaders/gbuffer.frag", "void main() {}"),
+            shader("shaders/final.frag", "void main() {}"),
+        ];
+        let data = ShaderpackData {
+            pipelines: vec![pipeline("gbuffer", 0, 1), pipeline("final", 0, 2)],
+            passes: Vec::new(),
+            materials: Vec::new(),
+            resources: ShaderpackResourceData {
+                textures: Vec::new(),
+                samplers: Vec::new(),
+            },
+            shaders: ShaderSet::Sources(shaders.clone()),
+        };
+        let dependencies = ShaderDependencies::new(&data);
+        assert_eq!(dependencies.pipelines_using(0), &[0, 1]);
+
+        let mut edited = shaders.clone();
+        edited[2].source = String::from("void main() { discard; }");
+        let changed = changed_shaders(&data.shaders, &ShaderSet::Sources(edited)).unwrap();
+        assert_eq!(changed, vec![2]);
+        assert_eq!(dependencies.pipelines_to_rebuild(&changed), vec![1]);
+
+        let mut edited = shaders;
+        edited[0].source = String::from("void main() { gl_Position = vec4(0); }");
+        let changed = changed_shaders(&data.shaders, &ShaderSet::Sources(edited)).unwrap();
+        assert_eq!(dependencies.pipelines_to_rebuild(&changed), vec![0, 1]);
+    }
+
+    #[test]
+    fn renamed_shaders_need_full_reload() {
+        let old = ShaderSet::Sources(vec![shader("a.vert", "")]);
+        assert!(changed_shaders(&old, &ShaderSet::Sources(vec![shader("b.vert", "")])).is_none());
+        assert!(changed_shaders(&old, &ShaderSet::Sources(Vec::new())).is_none());
+        assert!(changed_shaders(&old, &ShaderSet::Compiled(Vec::new())).is_none());
+        assert_eq!(changed_shaders(&old, &old), Some(Vec::new()));
+    }
+}
@@ -0,0 +1,131 @@
+//! The resolution everything screen-relative is sized from.
+
+use crate::renderer::texture_sizes;
+use crate::settings::Settings;
+use crate::shaderpack::ShaderpackData;
+use cgmath::Vector2;
+use std::collections::HashMap;
+
+/// The authoritative resolution of the screen, and the sizes of the shaderpack's textures at that resolution.
+///
+/// The resolution is kept in whole pixels, as the swapchain reports it. Texture sizes are only ever worked out from it
+/// here, so every screen-relative texture agrees on how big the screen is.
+#[derive(Debug, Clone)]
+pub struct ScreenResolution {
+    resolution: Vector2<u32>,
+    texture_sizes: HashMap<String, Vector2<f32>>,
+}
+
+impl ScreenResolution {
+    /// Sizes the shaderpack's textures for the given resolution.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - The shaderpack whose textures to size.
+    /// * `resolution` - Size of the swapchain, in pixels.
+    /// * `settings` - Limits on the texture sizes.
+    pub fn new(data: &ShaderpackData, resolution: Vector2<u32>, settings: &Settings) -> Self {
+        Self {
+            resolution,
+            texture_sizes: texture_sizes(data, resolution, settings),
+        }
+    }
+
+    /// Size of the swapchain, in pixels.
+    pub fn current_resolution(&self) -> Vector2<u32> {
+        self.resolution
+    }
+
+    /// Gets the size of one of the shaderpack's textures, in pixels.
+    pub fn texture_size(&self, texture: &str) -> Option<Vector2<f32>> {
+        self.texture_sizes.get(texture).cloned()
+    }
+
+    /// Changes the resolution, such as after the window was resized.
+    ///
+    /// Returns the names of the textures whose size changed, in alphabetical order. Those textures have to be created
+    /// again at their new size.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - The shaderpack whose textures to size.
+    /// * `resolution` - New size of the swapchain, in pixels.
+    /// * `settings` - Limits on the texture sizes.
+    pub fn set_resolution(
+        &mut self,
+        data: &ShaderpackData,
+        resolution: Vector2<u32>,
+        settings: &Settings,
+    ) -> Vec<String> {
+        if resolution == self.resolution {
+            return Vec::new();
+        }
+
+        let sizes = texture_sizes(data, resolution, settings);
+        let mut changed: Vec<String> = sizes
+            .iter()
+            .filter(|(name, size)| self.texture_sizes.get(*name) != Some(size))
+            .map(|(name, _)| name.clone())
+            .collect();
+        changed.sort();
+
+        self.resolution = resolution;
+        self.texture_sizes = sizes;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shaderpack::{ShaderSet, ShaderpackResourceData};
+
+    fn pack() -> ShaderpackData {
+        let resources: ShaderpackResourceData = serde_json::from_str(
+            r#"{
+                "textures": [
+                    {
+                        "name": "HalfRes",
+                        "format": { "dimensionType": "ScreenRelative", "width": 0.5, "height": 0.5 }
+                    },
+                    {
+                        "name": "Noise",
+                        "format": { "dimensionType": "Absolute", "width": 256, "height": 256 }
+                    }
+                ],
+                "samplers": []
+            }"#,
+        )
+        .expect("Failed to parse resources");
+
+        ShaderpackData {
+            pipelines: Vec::new(),
+            passes: Vec::new(),
+            materials: Vec::new(),
+            resources,
+            shaders: ShaderSet::Sources(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn resizing_screen_relative_textures() {
+        let data = pack();
+        let settings = Settings::default();
+        let mut resolution = ScreenResolution::new(&data, Vector2::new(1280, 720), &settings);
+        assert_eq!(resolution.current_resolution(), Vector2::new(1280, 720));
+        assert_eq!(resolution.texture_size("HalfRes"), Some(Vector2::new(640.0, 360.0)));
+
+        let rebuild = resolution.set_resolution(&data, Vector2::new(1920, 1080), &settings);
+        assert_eq!(rebuild, vec![String::from("HalfRes")]);
+        assert_eq!(resolution.current_resolution(), Vector2::new(1920, 1080));
+        assert_eq!(resolution.texture_size("HalfRes"), Some(Vector2::new(960.0, 540.0)));
+        assert_eq!(resolution.texture_size("Noise"), Some(Vector2::new(256.0, 256.0)));
+
+        // Nothing to do if the size didn't actually change
+        assert!(
+            resolution
+                .set_resolution(&data, Vector2::new(1920, 1080), &settings)
+                .is_empty()
+        );
+    }
+}
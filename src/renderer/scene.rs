@@ -0,0 +1,343 @@
+//! Everything the host application asked Nova to draw.
+
+use crate::mesh::{DrawCommandId, MeshData, MeshId, MeshValidationError, StaticMeshDrawCommand};
+use crate::shaderpack::{GeometryFilter, GeometryTagRegistry, PrimitiveTopology};
+use cgmath::Matrix4;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The meshes and draw commands the renderer consumes every frame.
+///
+/// Draw commands are kept in the order they were added, so frames are recorded deterministically. The scene remembers
+/// which draw commands were added or changed since the renderer last uploaded their model matrices, so all changes made
+/// during a frame end up in a single upload.
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    next_draw_command: u64,
+    draw_commands: BTreeMap<DrawCommandId, StaticMeshDrawCommand>,
+    dirty: BTreeSet<DrawCommandId>,
+    next_mesh: u64,
+    meshes: BTreeSet<MeshId>,
+    /// Meshes which have been removed, with the last frame which may still use them.
+    pending_mesh_deletions: Vec<(u64, MeshId)>,
+}
+
+impl Scene {
+    /// Creates an empty scene.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a mesh to the scene, returning its ID.
+    ///
+    /// The mesh is [validated](MeshData::validate) first, since malformed data can't be caught once it's on the GPU.
+    /// The renderer uploads the mesh's data. The scene only keeps track of which meshes exist, so it knows when their
+    /// buffers can be freed.
+    ///
+    /// # Parameters
+    ///
+    /// * `mesh` - The mesh's vertices and indices.
+    /// * `topology` - The primitives the mesh is drawn as.
+    pub fn add_mesh(&mut self, mesh: &MeshData, topology: PrimitiveTopology) -> Result<MeshId, MeshValidationError> {
+        mesh.validate(topology)?;
+
+        let id = MeshId(self.next_mesh);
+        self.next_mesh += 1;
+        self.meshes.insert(id);
+        Ok(id)
+    }
+
+    /// Number of meshes in the scene. Meshes waiting to be freed don't count.
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.len()
+    }
+
+    /// Removes every draw command and mesh, such as when the host application unloads its world.
+    ///
+    /// Frames which are still in flight may be drawing the meshes, so their buffers aren't freed right away. Instead,
+    /// [`take_meshes_to_free`](Scene::take_meshes_to_free) hands them out once the GPU is done with those frames.
+    ///
+    /// # Parameters
+    ///
+    /// * `last_submitted_frame` - Index of the last frame which has been submitted to the GPU.
+    pub fn clear_scene(&mut self, last_submitted_frame: u64) {
+        self.draw_commands.clear();
+        self.dirty.clear();
+        self.pending_mesh_deletions
+            .extend(self.meshes.iter().map(|&mesh| (last_submitted_frame, mesh)));
+        self.meshes.clear();
+    }
+
+    /// Gets the removed meshes whose buffers can be freed, because every frame which may use them has finished.
+    ///
+    /// # Parameters
+    ///
+    /// * `completed_frame` - Index of the last frame whose fence has been signalled.
+    pub fn take_meshes_to_free(&mut self, completed_frame: u64) -> Vec<MeshId> {
+        let mut to_free = Vec::new();
+        self.pending_mesh_deletions.retain(|&(frame, mesh)| {
+            if frame <= completed_frame {
+                to_free.push(mesh);
+                false
+            } else {
+                true
+            }
+        });
+        to_free
+    }
+
+    /// Adds a draw command to the scene, returning the ID to update or remove it with.
+    pub fn add_draw_command(&mut self, command: StaticMeshDrawCommand) -> DrawCommandId {
+        let id = DrawCommandId(self.next_draw_command);
+        self.next_draw_command += 1;
+        self.draw_commands.insert(id, command);
+        self.dirty.insert(id);
+        id
+    }
+
+    /// Changes where a draw command's mesh is drawn and whether it's drawn at all.
+    ///
+    /// Returns false if there is no draw command with the ID.
+    pub fn update_draw_command(&mut self, id: DrawCommandId, model_matrix: Matrix4<f32>, is_visible: bool) -> bool {
+        match self.draw_commands.get_mut(&id) {
+            Some(command) => {
+                command.model_matrix = model_matrix;
+                command.is_visible = is_visible;
+                self.dirty.insert(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Changes the model matrix and visibility of many draw commands at once. This is the fast path for scenes where
+    /// lots of objects move every frame.
+    ///
+    /// Updates for IDs without a draw command are skipped. Returns the number of draw commands that were updated.
+    pub fn update_draw_commands(&mut self, updates: &[(DrawCommandId, Matrix4<f32>, bool)]) -> usize {
+        let mut updated = 0;
+        for &(id, model_matrix, is_visible) in updates {
+            if let Some(command) = self.draw_commands.get_mut(&id) {
+                command.model_matrix = model_matrix;
+                command.is_visible = is_visible;
+                self.dirty.insert(id);
+                updated += 1;
+            }
+        }
+        updated
+    }
+
+    /// Removes a draw command from the scene, returning it if it existed.
+    pub fn remove_draw_command(&mut self, id: DrawCommandId) -> Option<StaticMeshDrawCommand> {
+        self.dirty.remove(&id);
+        self.draw_commands.remove(&id)
+    }
+
+    /// Gets the draw commands which were added or changed since the last call, in the order they were added.
+    ///
+    /// The renderer calls this once per frame to upload the model matrices of all of them at once.
+    pub fn take_dirty_draw_commands(&mut self) -> Vec<DrawCommandId> {
+        let dirty = self.dirty.iter().copied().collect();
+        self.dirty.clear();
+        dirty
+    }
+
+    /// Gets a draw command.
+    pub fn get_draw_command(&self, id: DrawCommandId) -> Option<&StaticMeshDrawCommand> {
+        self.draw_commands.get(&id)
+    }
+
+    /// Iterates over the draw commands which should be drawn this frame, in the order they were added.
+    pub fn visible_draw_commands(&self) -> impl Iterator<Item = (DrawCommandId, &StaticMeshDrawCommand)> {
+        self.draw_commands
+            .iter()
+            .filter(|(_, command)| command.is_visible)
+            .map(|(id, command)| (*id, command))
+    }
+
+    /// Iterates over the visible draw commands whose tags pass a material's geometry filter, in the order they were
+    /// added.
+    ///
+    /// # Parameters
+    ///
+    /// * `filter` - The parsed [geometry filter](crate::shaderpack::MaterialData::geometry_filter) of the material.
+    /// * `registry` - The custom tags the host app registered.
+    pub fn draw_commands_matching<'a>(
+        &'a self,
+        filter: &'a GeometryFilter,
+        registry: &'a GeometryTagRegistry,
+    ) -> impl Iterator<Item = (DrawCommandId, &'a StaticMeshDrawCommand)> {
+        self.visible_draw_commands()
+            .filter(move |(_, command)| filter.matches(&command.tags, registry))
+    }
+
+    /// Number of draw commands in the scene, including hidden ones.
+    pub fn draw_command_count(&self) -> usize {
+        self.draw_commands.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mesh::{FullVertex, GeometryTags, GeometryType, MeshId};
+    use cgmath::{SquareMatrix, Vector2, Vector3, Vector4, Zero};
+
+    fn command(mesh: u64) -> StaticMeshDrawCommand {
+        StaticMeshDrawCommand {
+            mesh: MeshId(mesh),
+            tags: GeometryTags::new(GeometryType::Block),
+            model_matrix: Matrix4::identity(),
+            is_visible: true,
+        }
+    }
+
+    fn triangle() -> MeshData {
+        let vertex = FullVertex {
+            position: Vector3::zero(),
+            normal: Vector3::unit_y(),
+            tangent: Vector3::unit_x(),
+            main_uv: Vector2::zero(),
+            secondary_uv: Vector2::zero(),
+            virtual_texture_id: 0,
+            additional_stuff: Vector4::zero(),
+        };
+        MeshData {
+            vertex_data: vec![vertex; 3],
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    fn visible_meshes(scene: &Scene) -> Vec<MeshId> {
+        scene.visible_draw_commands().map(|(_, command)| command.mesh).collect()
+    }
+
+    #[test]
+    fn add_update_remove() {
+        let mut scene = Scene::new();
+        let first = scene.add_draw_command(command(1));
+        let second = scene.add_draw_command(command(2));
+        let third = scene.add_draw_command(command(3));
+        assert_ne!(first, second);
+        assert_eq!(visible_meshes(&scene), vec![MeshId(1), MeshId(2), MeshId(3)]);
+
+        let moved = Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0));
+        assert!(scene.update_draw_command(second, moved, false));
+        assert_eq!(scene.get_draw_command(second).unwrap().model_matrix, moved);
+        assert_eq!(visible_meshes(&scene), vec![MeshId(1), MeshId(3)]);
+
+        assert_eq!(scene.remove_draw_command(first), Some(command(1)));
+        assert_eq!(scene.remove_draw_command(first), None);
+        assert!(!scene.update_draw_command(first, moved, true));
+        assert_eq!(visible_meshes(&scene), vec![MeshId(3)]);
+        assert_eq!(scene.draw_command_count(), 2);
+
+        // IDs aren't reused after a removal
+        let fourth = scene.add_draw_command(command(4));
+        assert!(fourth != first && fourth != third);
+        assert_eq!(visible_meshes(&scene), vec![MeshId(3), MeshId(4)]);
+    }
+
+    #[test]
+    fn batch_updates() {
+        let mut scene = Scene::new();
+        let ids: Vec<DrawCommandId> = (0..4).map(|mesh| scene.add_draw_command(command(mesh))).collect();
+        assert_eq!(scene.take_dirty_draw_commands(), ids);
+        assert!(scene.take_dirty_draw_commands().is_empty());
+
+        let moved = Matrix4::from_translation(Vector3::new(0.0, 1.0, 0.0));
+        let updates = [
+            (ids[3], moved, true),
+            (ids[1], moved, false),
+            (DrawCommandId(100), moved, true),
+            (ids[3], Matrix4::identity(), true),
+        ];
+        assert_eq!(scene.update_draw_commands(&updates), 3);
+        assert_eq!(scene.get_draw_command(ids[1]).unwrap().model_matrix, moved);
+        assert_eq!(
+            scene.get_draw_command(ids[3]).unwrap().model_matrix,
+            Matrix4::identity()
+        );
+        assert_eq!(visible_meshes(&scene), vec![MeshId(0), MeshId(2), MeshId(3)]);
+
+        scene.remove_draw_command(ids[1]);
+        assert_eq!(scene.take_dirty_draw_commands(), vec![ids[3]]);
+    }
+
+    #[test]
+    fn clear_scene_defers_mesh_deletion() {
+        let mut scene = Scene::new();
+        let meshes: Vec<MeshId> = (0..3)
+            .map(|_| scene.add_mesh(&triangle(), PrimitiveTopology::Triangles).unwrap())
+            .collect();
+        for mesh in &meshes {
+            scene.add_draw_command(command(mesh.0));
+        }
+
+        // Frame 5 has been submitted and may still be drawing the meshes
+        scene.clear_scene(5);
+        assert_eq!(scene.draw_command_count(), 0);
+        assert_eq!(scene.mesh_count(), 0);
+        assert!(scene.visible_draw_commands().next().is_none());
+        assert!(scene.take_dirty_draw_commands().is_empty());
+
+        assert!(scene.take_meshes_to_free(4).is_empty());
+        assert_eq!(scene.take_meshes_to_free(5), meshes);
+        assert!(scene.take_meshes_to_free(6).is_empty());
+
+        // New meshes get new IDs
+        assert_eq!(scene.add_mesh(&triangle(), PrimitiveTopology::Triangles), Ok(MeshId(3)));
+    }
+
+    #[test]
+    fn invalid_meshes_are_rejected() {
+        let mut scene = Scene::new();
+        let mut mesh = triangle();
+        mesh.indices.push(3);
+
+        assert_eq!(
+            scene.add_mesh(&mesh, PrimitiveTopology::Triangles),
+            Err(MeshValidationError::IndexOutOfRange {
+                position: 3,
+                index: 3,
+                vertex_count: 3,
+            })
+        );
+        assert_eq!(scene.mesh_count(), 0);
+
+        // The same indices are a whole number of lines once they're in range
+        mesh.indices[3] = 0;
+        assert_eq!(scene.add_mesh(&mesh, PrimitiveTopology::Lines), Ok(MeshId(0)));
+    }
+
+    #[test]
+    fn filter_draw_commands_by_tags() {
+        let mut scene = Scene::new();
+        let block = scene.add_draw_command(command(0));
+        let gui = scene.add_draw_command(StaticMeshDrawCommand {
+            tags: GeometryTags::new(GeometryType::Gui),
+            ..command(1)
+        });
+        let glass = scene.add_draw_command(StaticMeshDrawCommand {
+            tags: GeometryTags {
+                transparent: true,
+                ..GeometryTags::new(GeometryType::Block)
+            },
+            ..command(2)
+        });
+        let hidden = scene.add_draw_command(command(3));
+        scene.update_draw_command(hidden, Matrix4::identity(), false);
+
+        let registry = GeometryTagRegistry::default();
+        let matching = |filter| -> Vec<DrawCommandId> {
+            let filter = GeometryFilter::parse(filter).expect("Failed to parse geometry filter");
+            scene
+                .draw_commands_matching(&filter, &registry)
+                .map(|(id, _)| id)
+                .collect()
+        };
+        assert_eq!(matching("geometry_type::block"), vec![block, glass]);
+        assert_eq!(matching("geometry_type::block AND not_transparent"), vec![block]);
+        assert_eq!(matching("geometry_type::gui"), vec![gui]);
+        assert!(matching("geometry_type::text").is_empty());
+    }
+}
@@ -0,0 +1,395 @@
+//! Reusable staging buffers for uploading data to the GPU.
+
+use crate::rhi::{
+    AllocationError, BufferCreateInfo, BufferUsage, Device, DeviceMemoryAllocation, Memory, MemoryError, MemoryUsage,
+    ObjectType,
+};
+use failure::Fail;
+
+/// Failure type for getting space from a [`StagingBufferPool`].
+#[derive(Fail, Debug, Clone, Eq, PartialEq)]
+pub enum StagingError {
+    /// The upload doesn't fit into a single staging buffer.
+    #[fail(
+        display = "Upload of {} bytes is larger than the staging buffers ({} bytes)",
+        size, buffer_size
+    )]
+    TooLarge {
+        /// Size of the upload, in bytes.
+        size: u64,
+        /// Size of each staging buffer, in bytes.
+        buffer_size: u64,
+    },
+
+    /// Allocating the memory for a new staging buffer failed.
+    #[fail(display = "Failed to allocate staging memory: {}", _0)]
+    Allocation(#[fail(cause)] AllocationError),
+
+    /// Creating a new staging buffer failed.
+    #[fail(display = "Failed to create staging buffer: {}", _0)]
+    Buffer(#[fail(cause)] MemoryError),
+
+    /// Making the uploads visible to the GPU failed.
+    #[fail(display = "Failed to flush staging memory: {}", _0)]
+    Flush(#[fail(cause)] MemoryError),
+}
+
+/// Part of a staging buffer which an upload can be written to.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StagingRange {
+    /// Index of the buffer in the pool. Use [`StagingBufferPool::get_buffer`] to get the buffer itself.
+    pub buffer: usize,
+
+    /// Offset into the buffer, in bytes.
+    pub offset: u64,
+
+    /// Size of the range, in bytes.
+    pub size: u64,
+}
+
+/// Identifies all the ranges handed out by a [`StagingBufferPool`] between two calls to
+/// [`submit`](StagingBufferPool::submit).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct UploadBatch(u64);
+
+/// What a staging buffer is currently used for.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum BufferState {
+    /// Ready to be handed out.
+    Free,
+
+    /// Ranges of the buffer are being handed out for the batch that hasn't been submitted yet.
+    Filling,
+
+    /// The GPU may still be copying out of the buffer.
+    InFlight(UploadBatch),
+}
+
+struct StagingBuffer<M: Memory> {
+    memory: M,
+    buffer: M::Buffer,
+    used: u64,
+    state: BufferState,
+}
+
+/// A ring of host-visible buffers which uploads get their staging space from.
+///
+/// Each upload gets a range of one of the buffers. Once all the commands which copy out of the ranges have been
+/// recorded, [`submit`](StagingBufferPool::submit) closes the batch. When the fence signalled by those commands has
+/// been signalled, [`recycle`](StagingBufferPool::recycle) makes the batch's buffers available again. New buffers are
+/// only allocated when every existing buffer is full or still in flight, so the amount of staging memory is bounded
+/// by the number of uploads in flight at once.
+pub struct StagingBufferPool<M: Memory> {
+    buffer_size: u64,
+    buffers: Vec<StagingBuffer<M>>,
+    current: Option<usize>,
+    next_batch: u64,
+}
+
+impl<M: Memory> StagingBufferPool<M> {
+    /// Alignment of the offset of ranges from [`allocate`](StagingBufferPool::allocate), in bytes. This is enough for
+    /// copies into buffers, but not into images, which need
+    /// [`BufferImageCopy::offset_alignment`](crate::rhi::BufferImageCopy::offset_alignment) passed to
+    /// [`allocate_aligned`](StagingBufferPool::allocate_aligned).
+    pub const ALIGNMENT: u64 = 16;
+
+    /// Creates an empty pool. No memory is allocated until the first upload.
+    ///
+    /// # Parameters
+    ///
+    /// * `buffer_size` - Size of each staging buffer, in bytes. This is the largest upload the pool can handle.
+    pub fn new(buffer_size: u64) -> Self {
+        Self {
+            buffer_size,
+            buffers: Vec::new(),
+            current: None,
+            next_batch: 0,
+        }
+    }
+
+    /// Gets space for an upload of `size` bytes, allocating a new staging buffer if no existing one has room.
+    ///
+    /// The range is aligned to [`ALIGNMENT`](StagingBufferPool::ALIGNMENT).
+    ///
+    /// # Parameters
+    ///
+    /// * `device` - The device to allocate new staging buffers from.
+    /// * `size` - Size of the upload, in bytes.
+    pub fn allocate<D>(&mut self, device: &D, size: u64) -> Result<StagingRange, StagingError>
+    where
+        D: Device<Memory = M>,
+    {
+        self.allocate_aligned(device, size, Self::ALIGNMENT)
+    }
+
+    /// Gets space for an upload of `size` bytes whose offset is a multiple of `alignment`, allocating a new staging
+    /// buffer if no existing one has room.
+    ///
+    /// # Parameters
+    ///
+    /// * `device` - The device to allocate new staging buffers from.
+    /// * `size` - Size of the upload, in bytes.
+    /// * `alignment` - Required alignment of the offset, in bytes. Doesn't have to be a power of two, since images with
+    ///   three channel formats need offsets which are a multiple of their pixel size.
+    pub fn allocate_aligned<D>(&mut self, device: &D, size: u64, alignment: u64) -> Result<StagingRange, StagingError>
+    where
+        D: Device<Memory = M>,
+    {
+        if size > self.buffer_size {
+            return Err(StagingError::TooLarge {
+                size,
+                buffer_size: self.buffer_size,
+            });
+        }
+
+        if let Some(index) = self.current {
+            let staging = &mut self.buffers[index];
+            let offset = align(staging.used, alignment);
+            if offset + size <= self.buffer_size {
+                staging.used = offset + size;
+                return Ok(StagingRange {
+                    buffer: index,
+                    offset,
+                    size,
+                });
+            }
+        }
+
+        let index = match self
+            .buffers
+            .iter()
+            .position(|staging| staging.state == BufferState::Free)
+        {
+            Some(index) => index,
+            None => {
+                let staging = self.create_buffer(device)?;
+                self.buffers.push(staging);
+                self.buffers.len() - 1
+            }
+        };
+
+        let staging = &mut self.buffers[index];
+        staging.state = BufferState::Filling;
+        staging.used = size;
+        self.current = Some(index);
+
+        Ok(StagingRange {
+            buffer: index,
+            offset: 0,
+            size,
+        })
+    }
+
+    /// Closes the current batch of uploads. Call this after writing the uploads and recording the commands which copy
+    /// out of their ranges, and pass the result to [`recycle`](StagingBufferPool::recycle) once the fence those
+    /// commands signal is signalled.
+    ///
+    /// The written parts of the batch's buffers are flushed, so the writes are visible to the GPU even if the staging
+    /// memory isn't host coherent.
+    pub fn submit(&mut self) -> Result<UploadBatch, StagingError> {
+        for staging in &self.buffers {
+            if staging.state == BufferState::Filling {
+                staging.memory.flush(0, staging.used).map_err(StagingError::Flush)?;
+            }
+        }
+
+        let batch = UploadBatch(self.next_batch);
+        self.next_batch += 1;
+
+        for staging in &mut self.buffers {
+            if staging.state == BufferState::Filling {
+                staging.state = BufferState::InFlight(batch);
+            }
+        }
+        self.current = None;
+
+        Ok(batch)
+    }
+
+    /// Makes the buffers of a batch available again. The GPU must be done with every copy out of the batch.
+    pub fn recycle(&mut self, batch: UploadBatch) {
+        for staging in &mut self.buffers {
+            if staging.state == BufferState::InFlight(batch) {
+                staging.state = BufferState::Free;
+                staging.used = 0;
+            }
+        }
+    }
+
+    /// Gets the staging buffer a range is part of.
+    pub fn get_buffer(&self, range: &StagingRange) -> &M::Buffer {
+        &self.buffers[range.buffer].buffer
+    }
+
+    /// Number of staging buffers the pool has allocated.
+    pub fn buffer_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Waits for the GPU to finish every copy out of the pool's buffers, then frees all of them.
+    ///
+    /// Dropping the pool while uploads are in flight frees memory the GPU may still be reading, so call this first when
+    /// tearing down. The pool can still be used afterwards and allocates new buffers as needed.
+    ///
+    /// # Parameters
+    ///
+    /// * `device` - The device the buffers were allocated from.
+    pub fn shutdown<D>(&mut self, device: &D)
+    where
+        D: Device<Memory = M>,
+    {
+        if self.buffers.iter().any(|staging| staging.state != BufferState::Free) {
+            device.wait_idle();
+        }
+        self.buffers.clear();
+        self.current = None;
+    }
+
+    fn create_buffer<D>(&self, device: &D) -> Result<StagingBuffer<M>, StagingError>
+    where
+        D: Device<Memory = M>,
+    {
+        let memory = device
+            .allocate_memory(self.buffer_size, MemoryUsage::StagingBuffer, ObjectType::Buffer)
+            .map_err(StagingError::Allocation)?;
+        let buffer = memory
+            .create_buffer(BufferCreateInfo {
+                size: self.buffer_size as usize,
+                buffer_usage: BufferUsage::StagingBuffer,
+                allocation: DeviceMemoryAllocation,
+            })
+            .map_err(StagingError::Buffer)?;
+
+        Ok(StagingBuffer {
+            memory,
+            buffer,
+            used: 0,
+            state: BufferState::Filling,
+        })
+    }
+}
+
+/// Rounds `value` up to the next multiple of `alignment`.
+const fn align(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rhi::mock::{MockDevice, MockMemory};
+    use crate::rhi::BufferImageCopy;
+
+    #[test]
+    fn ranges_share_buffers() {
+        let device = MockDevice::default();
+        let mut pool = StagingBufferPool::<MockMemory>::new(64);
+
+        let first = pool.allocate(&device, 10).unwrap();
+        let second = pool.allocate(&device, 20).unwrap();
+        assert_eq!((first.buffer, first.offset), (0, 0));
+        assert_eq!((second.buffer, second.offset), (0, 16));
+
+        // Doesn't fit behind the second range any more
+        let third = pool.allocate(&device, 40).unwrap();
+        assert_eq!((third.buffer, third.offset), (1, 0));
+        assert_eq!(device.allocations.get(), 2);
+
+        assert_eq!(
+            pool.allocate(&device, 65),
+            Err(StagingError::TooLarge {
+                size: 65,
+                buffer_size: 64
+            })
+        );
+    }
+
+    #[test]
+    fn image_ranges_are_aligned() {
+        let device = MockDevice::default();
+        let mut pool = StagingBufferPool::<MockMemory>::new(4096);
+
+        pool.allocate(&device, 10).unwrap();
+        let rgba8 = pool
+            .allocate_aligned(&device, 100, BufferImageCopy::offset_alignment(4))
+            .unwrap();
+        assert_eq!(rgba8.offset, 512);
+
+        // Three channel 32-bit float pixels are 12 bytes, so the offset has to be a multiple of both 512 and 12
+        let rgb32f = pool
+            .allocate_aligned(&device, 100, BufferImageCopy::offset_alignment(12))
+            .unwrap();
+        assert_eq!(rgb32f.offset, 1536);
+    }
+
+    #[test]
+    fn buffers_are_reused() {
+        let device = MockDevice::default();
+        let mut pool = StagingBufferPool::<MockMemory>::new(64);
+
+        for _ in 0..10 {
+            pool.allocate(&device, 32).unwrap();
+            pool.allocate(&device, 32).unwrap();
+            let batch = pool.submit().unwrap();
+            pool.recycle(batch);
+        }
+        assert_eq!(pool.buffer_count(), 1);
+        assert_eq!(device.allocations.get(), 1);
+
+        // Buffers still in flight aren't handed out again
+        pool.allocate(&device, 32).unwrap();
+        let in_flight = pool.submit().unwrap();
+        let range = pool.allocate(&device, 32).unwrap();
+        assert_eq!(range.buffer, 1);
+
+        let next = pool.submit().unwrap();
+        pool.recycle(in_flight);
+        assert_eq!(pool.allocate(&device, 32).unwrap().buffer, 0);
+        pool.recycle(next);
+        assert_eq!(pool.buffer_count(), 2);
+    }
+
+    #[test]
+    fn shutdown_waits_for_uploads() {
+        let device = MockDevice::default();
+        let mut pool = StagingBufferPool::<MockMemory>::new(64);
+
+        pool.allocate(&device, 32).unwrap();
+        pool.submit().unwrap();
+        pool.allocate(&device, 32).unwrap();
+        pool.shutdown(&device);
+        assert_eq!(device.idle_waits.get(), 1);
+        assert_eq!(pool.buffer_count(), 0);
+
+        // Nothing left to wait for
+        pool.shutdown(&device);
+        assert_eq!(device.idle_waits.get(), 1);
+        assert_eq!(pool.allocate(&device, 32).unwrap().buffer, 0);
+    }
+
+    #[test]
+    fn non_coherent_memory_is_flushed() {
+        let device = MockDevice {
+            non_coherent_memory: true,
+            ..MockDevice::default()
+        };
+        let mut pool = StagingBufferPool::<MockMemory>::new(64);
+
+        pool.allocate(&device, 32).unwrap();
+        pool.allocate(&device, 64).unwrap();
+        let batch = pool.submit().unwrap();
+        assert_eq!(device.flushes.get(), 2);
+
+        // Buffers in flight were flushed when their own batch was submitted
+        pool.allocate(&device, 16).unwrap();
+        pool.submit().unwrap();
+        assert_eq!(device.flushes.get(), 3);
+        pool.recycle(batch);
+
+        let coherent = MockDevice::default();
+        let mut pool = StagingBufferPool::<MockMemory>::new(64);
+        pool.allocate(&coherent, 32).unwrap();
+        pool.submit().unwrap();
+        assert_eq!(coherent.flushes.get(), 0);
+    }
+}
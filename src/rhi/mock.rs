@@ -0,0 +1,381 @@
+//! A fake graphics API for testing code which is generic over the RHI.
+//!
+//! Physical devices report whatever properties the test gives them. Devices hand out memory and buffers and count the
+//! allocations and flushes. Everything else is a stand-in object which panics if it's actually used.
+
+use super::*;
+use crate::shaderpack;
+use crate::surface::Surface;
+use cgmath::Vector2;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Graphics API with a fixed list of adapters.
+pub struct MockGraphicsApi {
+    pub adapters: Vec<MockPhysicalDevice>,
+}
+
+impl GraphicsApi for MockGraphicsApi {
+    type PhysicalDevice = MockPhysicalDevice;
+    type PlatformSurface = ();
+
+    const DESCRIPTOR_BINDING_MODEL: DescriptorBindingModel = DescriptorBindingModel::CombinedSamplers;
+
+    fn get_adapters(&self) -> Vec<MockPhysicalDevice> {
+        self.adapters.clone()
+    }
+
+    fn get_surface(&self) -> Rc<dyn Surface<()>> {
+        unimplemented!()
+    }
+}
+
+/// Physical device with the given properties.
+#[derive(Debug, Clone)]
+pub struct MockPhysicalDevice {
+    pub properties: PhysicalDeviceProperties,
+
+    /// Result of `can_be_used_by_nova`.
+    pub suitable: bool,
+
+    pub free_memory: u64,
+}
+
+impl MockPhysicalDevice {
+    /// Creates a suitable discrete GPU with the given name and no optional features.
+    pub fn new(name: &str) -> Self {
+        Self {
+            properties: PhysicalDeviceProperties {
+                manufacturer: PhysicalDeviceManufacturer::Other,
+                device_id: 0,
+                device_name: String::from(name),
+                device_type: PhysicalDeviceType::Discrete,
+                max_color_attachments: 8,
+                features: PhysicalDeviceFeatures::default(),
+            },
+            suitable: true,
+            free_memory: 1 << 30,
+        }
+    }
+}
+
+impl PhysicalDevice for MockPhysicalDevice {
+    type Device = MockDevice;
+
+    fn get_properties(&self) -> PhysicalDeviceProperties {
+        self.properties.clone()
+    }
+
+    fn can_be_used_by_nova(&self) -> bool {
+        self.suitable
+    }
+
+    fn create_logical_device(&self, pipeline_cache: &[u8]) -> Result<MockDevice, DeviceCreationError> {
+        Ok(MockDevice {
+            pipeline_cache: pipeline_cache.to_vec(),
+            ..MockDevice::default()
+        })
+    }
+
+    fn get_free_memory(&self) -> u64 {
+        self.free_memory
+    }
+}
+
+/// Logical device whose objects are all [`MockObject`]s.
+#[derive(Default)]
+pub struct MockDevice {
+    /// Number of successful calls to `allocate_memory`.
+    pub allocations: Cell<u32>,
+
+    /// Formats `supports_format` rejects. Every other format is supported.
+    pub unsupported_formats: Vec<(shaderpack::PixelFormat, FormatUsage)>,
+
+    /// Number of calls to `wait_idle`.
+    pub idle_waits: Cell<u32>,
+
+    /// Whether allocated memory needs flushing. Memory is host coherent by default.
+    pub non_coherent_memory: bool,
+
+    /// Number of flushes of memory from this device which weren't skipped because the memory is coherent.
+    pub flushes: Rc<Cell<u32>>,
+
+    /// Result of `get_pipeline_cache_data`. Starts out as the data the device was created with.
+    pub pipeline_cache: Vec<u8>,
+}
+
+impl Device for MockDevice {
+    type Queue = MockObject;
+    type Memory = MockMemory;
+    type CommandAllocator = MockObject;
+    type Image = MockObject;
+    type Renderpass = MockObject;
+    type Framebuffer = MockObject;
+    type PipelineInterface = MockObject;
+    type DescriptorPool = MockObject;
+    type Pipeline = MockObject;
+    type Semaphore = MockObject;
+    type Fence = MockObject;
+
+    fn get_queue(&self, _queue_type: QueueType, _queue_index: u32) -> Result<MockObject, QueueGettingError> {
+        unimplemented!()
+    }
+
+    fn allocate_memory(
+        &self,
+        _size: u64,
+        _memory_usage: MemoryUsage,
+        _allowed_objects: ObjectType,
+    ) -> Result<MockMemory, AllocationError> {
+        self.allocations.set(self.allocations.get() + 1);
+        Ok(MockMemory {
+            coherent: !self.non_coherent_memory,
+            flushes: self.flushes.clone(),
+        })
+    }
+
+    fn create_command_allocator(&self, _create_info: CommandAllocatorCreateInfo) -> Result<MockObject, MemoryError> {
+        unimplemented!()
+    }
+
+    fn create_renderpass(&self, _data: shaderpack::RenderPassCreationInfo) -> Result<MockObject, MemoryError> {
+        unimplemented!()
+    }
+
+    fn create_framebuffer(
+        &self,
+        _renderpass: MockObject,
+        _attachments: Vec<MockObject>,
+        _framebuffer_size: Vector2<f32>,
+    ) -> Result<MockObject, MemoryError> {
+        unimplemented!()
+    }
+
+    fn create_pipeline_interface(
+        &self,
+        _bindings: &HashMap<String, ResourceBindingDescription>,
+        _color_attachments: &[shaderpack::TextureAttachmentInfo],
+        _depth_texture: &Option<shaderpack::TextureAttachmentInfo>,
+    ) -> Result<MockObject, MemoryError> {
+        unimplemented!()
+    }
+
+    fn create_descriptor_pool(
+        &self,
+        _num_sampled_images: u32,
+        _num_samplers: u32,
+        _num_uniform_buffers: u32,
+    ) -> Result<Vec<MockObject>, DescriptorPoolCreationError> {
+        unimplemented!()
+    }
+
+    fn create_pipeline(
+        &self,
+        _pipeline_interface: MockObject,
+        _data: shaderpack::PipelineCreationInfo,
+    ) -> Result<MockObject, PipelineCreationError> {
+        unimplemented!()
+    }
+
+    fn create_image(&self, _data: shaderpack::TextureCreateInfo) -> Result<MockObject, MemoryError> {
+        unimplemented!()
+    }
+
+    fn supports_format(&self, format: shaderpack::PixelFormat, usage: FormatUsage) -> bool {
+        !self.unsupported_formats.contains(&(format, usage))
+    }
+
+    fn create_semaphore(&self) -> Result<MockObject, MemoryError> {
+        unimplemented!()
+    }
+
+    fn create_semaphores(&self, _count: u32) -> Result<Vec<MockObject>, MemoryError> {
+        unimplemented!()
+    }
+
+    fn create_fence(&self) -> Result<MockObject, MemoryError> {
+        unimplemented!()
+    }
+
+    fn create_fences(&self, _count: u32) -> Result<Vec<MockObject>, MemoryError> {
+        unimplemented!()
+    }
+
+    fn wait_for_fences(&self, _fences: Vec<MockObject>) {
+        unimplemented!()
+    }
+
+    fn reset_fences(&self, _fences: Vec<MockObject>) {
+        unimplemented!()
+    }
+
+    fn wait_idle(&self) {
+        self.idle_waits.set(self.idle_waits.get() + 1);
+    }
+
+    fn update_descriptor_sets(&self, _updates: Vec<DescriptorSetWrite>) {
+        unimplemented!()
+    }
+
+    fn get_pipeline_cache_data(&self) -> Result<Vec<u8>, MemoryError> {
+        Ok(self.pipeline_cache.clone())
+    }
+}
+
+/// Memory which counts how often it's flushed.
+pub struct MockMemory {
+    /// Result of `is_host_coherent`.
+    pub coherent: bool,
+
+    /// Shared with the device which allocated the memory.
+    flushes: Rc<Cell<u32>>,
+}
+
+impl Memory for MockMemory {
+    type Buffer = MockObject;
+
+    fn create_buffer(&self, _data: BufferCreateInfo) -> Result<MockObject, MemoryError> {
+        Ok(MockObject)
+    }
+
+    fn is_host_coherent(&self) -> bool {
+        self.coherent
+    }
+
+    fn flush(&self, _offset: u64, _size: u64) -> Result<(), MemoryError> {
+        if !self.coherent {
+            self.flushes.set(self.flushes.get() + 1);
+        }
+        Ok(())
+    }
+
+    fn invalidate(&self, _offset: u64, _size: u64) -> Result<(), MemoryError> {
+        Ok(())
+    }
+}
+
+/// Stand-in for every kind of object a [`MockDevice`] creates.
+pub struct MockObject;
+
+impl Queue for MockObject {
+    type CommandList = Self;
+    type Fence = Self;
+    type Semaphore = Self;
+
+    fn submit_commands(
+        _commands: Self,
+        _fence_to_signal: Self,
+        _wait_semaphores: Vec<Self>,
+        _signal_semaphores: Vec<Self>,
+    ) {
+        unimplemented!()
+    }
+}
+
+impl Buffer for MockObject {
+    fn write_data(&self, _data: BufferCreateInfo, _num_bytes: u64, _offset: u64) {
+        unimplemented!()
+    }
+}
+
+impl CommandAllocator for MockObject {
+    type CommandList = Self;
+
+    fn create_command_list(&self, _secondary_list: bool) -> Result<Self, MemoryError> {
+        unimplemented!()
+    }
+}
+
+impl DescriptorPool for MockObject {
+    type PipelineInterface = Self;
+    type DescriptorSet = Self;
+
+    fn create_descriptor_sets(&self, _pipeline_interface: Self) -> Vec<Self> {
+        unimplemented!()
+    }
+}
+
+impl CommandList for MockObject {
+    type Buffer = Self;
+    type Image = Self;
+    type CommandList = Self;
+    type Renderpass = Self;
+    type Framebuffer = Self;
+    type Pipeline = Self;
+    type DescriptorSet = Self;
+    type PipelineInterface = Self;
+
+    fn resource_barriers(
+        _stages_before_barrier: PipelineStageFlags,
+        _stages_after_barrier: PipelineStageFlags,
+        _barriers: Vec<ResourceBarrier>,
+    ) {
+        unimplemented!()
+    }
+
+    fn copy_buffer(
+        _destination_buffer: Self,
+        _destination_offset: u64,
+        _source_buffer: Self,
+        _source_offset: u64,
+        _num_bytes: u64,
+    ) {
+        unimplemented!()
+    }
+
+    fn copy_buffer_to_image(_source_buffer: Self, _destination_image: Self, _region: BufferImageCopy) {
+        unimplemented!()
+    }
+
+    fn execute_command_lists(_lists: Vec<Self>) {
+        unimplemented!()
+    }
+
+    fn begin_renderpass(_renderpass: Self, _framebuffer: Self) {
+        unimplemented!()
+    }
+
+    fn end_renderpass() {
+        unimplemented!()
+    }
+
+    fn bind_pipeline(_pipeline: Self) {
+        unimplemented!()
+    }
+
+    fn set_viewport(_viewport: Viewport) {
+        unimplemented!()
+    }
+
+    fn set_scissor(_scissor: Rect2D) {
+        unimplemented!()
+    }
+
+    fn bind_descriptor_sets(_descriptor_sets: Vec<Self>, _pipeline_interface: Self) {
+        unimplemented!()
+    }
+
+    fn bind_vertex_buffers(_buffers: Vec<Self>) {
+        unimplemented!()
+    }
+
+    fn bind_index_buffer(_buffer: Self) {
+        unimplemented!()
+    }
+
+    fn draw_indexed_mesh(_num_indices: u32, _num_instances: u32) {
+        unimplemented!()
+    }
+}
+
+impl Resource for MockObject {}
+impl Image for MockObject {}
+impl Sampler for MockObject {}
+impl DescriptorSet for MockObject {}
+impl Renderpass for MockObject {}
+impl Framebuffer for MockObject {}
+impl PipelineInterface for MockObject {}
+impl Pipeline for MockObject {}
+impl Semaphore for MockObject {}
+impl Fence for MockObject {}
@@ -9,6 +9,9 @@ mod rhi_enums;
 mod rhi_structs;
 mod rhi_traits;
 
+#[cfg(test)]
+pub(crate) mod mock;
+
 mod vulkan {
     // Only export the implementation of the GraphicsApi trait. Clients of Nova's RHI should only
     // use the API-specific structs to create a GraphicsApi, and for no other reason
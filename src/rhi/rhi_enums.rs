@@ -1,5 +1,8 @@
+use crate::shaderpack;
 use bitflags::bitflags;
 use failure::Fail;
+use log::debug;
+use std::time::Duration;
 
 /// Actual manufacturer of the gpu.
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -54,6 +57,108 @@ pub enum MemoryUsage {
     StagingBuffer,
 }
 
+impl MemoryUsage {
+    /// Gets the properties to look for in a memory type, from most to least preferred.
+    ///
+    /// Memory layouts differ a lot between GPUs, so every usage falls back to weaker properties when no memory type has
+    /// the ideal ones. The last tier is what the usage can't do without.
+    pub fn property_tiers(&self) -> Vec<MemoryPropertyFlags> {
+        match self {
+            Self::DeviceOnly => vec![MemoryPropertyFlags::DEVICE_LOCAL, MemoryPropertyFlags::empty()],
+            Self::LowFrequencyUpload => vec![
+                MemoryPropertyFlags::DEVICE_LOCAL | MemoryPropertyFlags::HOST_VISIBLE,
+                MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+                MemoryPropertyFlags::HOST_VISIBLE,
+            ],
+            Self::StagingBuffer => vec![
+                MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_CACHED,
+                MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+                MemoryPropertyFlags::HOST_VISIBLE,
+            ],
+        }
+    }
+
+    /// Picks the memory type to allocate memory for this usage from.
+    ///
+    /// Returns the index of the first allowed memory type with the properties of the most preferred
+    /// [tier](MemoryUsage::property_tiers) any allowed memory type has.
+    ///
+    /// # Parameters
+    ///
+    /// * `memory_types` - The properties of each of the device's memory types.
+    /// * `allowed_types` - Bitmask of the memory types the resource can be bound to, with bit `i` standing for memory
+    /// type `i`.
+    pub fn find_memory_type(
+        &self,
+        memory_types: &[MemoryPropertyFlags],
+        allowed_types: u32,
+    ) -> Result<u32, AllocationError> {
+        for (tier, properties) in self.property_tiers().into_iter().enumerate() {
+            let found = memory_types
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index < 32 && allowed_types & (1 << *index) != 0)
+                .find(|(_, memory_type)| memory_type.contains(properties));
+            if let Some((index, memory_type)) = found {
+                debug!(
+                    "Using memory type {} ({:?}) for {:?} memory, fallback tier {}",
+                    index, memory_type, self, tier
+                );
+                return Ok(index as u32);
+            }
+        }
+
+        Err(AllocationError::NoSuitableMemoryFound)
+    }
+}
+
+/// What a texture of a given pixel format will be used for, when checking if a device supports the format.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum FormatUsage {
+    /// Color attachment that passes render to.
+    RenderTarget,
+
+    /// Texture which shaders sample from.
+    Sampled,
+
+    /// Depth or depth/stencil attachment.
+    DepthStencil,
+}
+
+/// How an image is viewed when shaders sample it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ImageViewType {
+    /// A single 2D image.
+    Type2D,
+
+    /// An array of 2D images.
+    Type2DArray,
+
+    /// Six 2D array layers viewed as the faces of a cube.
+    Cube,
+
+    /// A 3D image.
+    Type3D,
+}
+
+impl ImageViewType {
+    /// Returns true if the image behind views of this type has to be a 3D image rather than a 2D one.
+    pub fn is_3d(self) -> bool {
+        self == Self::Type3D
+    }
+}
+
+impl From<shaderpack::TextureType> for ImageViewType {
+    fn from(texture_type: shaderpack::TextureType) -> Self {
+        match texture_type {
+            shaderpack::TextureType::Tex2D => Self::Type2D,
+            shaderpack::TextureType::Tex2DArray => Self::Type2DArray,
+            shaderpack::TextureType::TexCube => Self::Cube,
+            shaderpack::TextureType::Tex3D => Self::Type3D,
+        }
+    }
+}
+
 /// Describes what kind of object you want to allocate from a new memory pool.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum ObjectType {
@@ -102,6 +207,10 @@ pub enum DeviceCreationError {
     /// Failed to create device.
     #[fail(display = "Failed to create device.")]
     Failed,
+
+    /// The API returned a result Nova doesn't know how to handle. Contains the raw result for diagnostics.
+    #[fail(display = "Unknown API result: {}", _0)]
+    Unknown(String),
 }
 
 /// Failure type for memory-related errors.
@@ -114,6 +223,10 @@ pub enum MemoryError {
     /// Not enough device memory to create the requested object.
     #[fail(display = "There's not enough device memory to create the requested object.")]
     OutOfDeviceMemory,
+
+    /// The API returned a result Nova doesn't know how to handle. Contains the raw result for diagnostics.
+    #[fail(display = "Unknown API result: {}", _0)]
+    Unknown(String),
 }
 
 /// Failure type for errors that can happen when you try to get a queue from a device.
@@ -130,6 +243,10 @@ pub enum QueueGettingError {
     /// Queue index is out of range.
     #[fail(display = "Queue index is out of range.")]
     IndexOutOfRange,
+
+    /// The API returned a result Nova doesn't know how to handle. Contains the raw result for diagnostics.
+    #[fail(display = "Unknown API result: {}", _0)]
+    Unknown(String),
 }
 
 /// Failure type for errors you get when allocating memory.
@@ -158,6 +275,10 @@ pub enum AllocationError {
     /// No memory matching the requirements found.
     #[fail(display = "No memory matching the requirements found.")]
     NoSuitableMemoryFound,
+
+    /// The API returned a result Nova doesn't know how to handle. Contains the raw result for diagnostics.
+    #[fail(display = "Unknown API result: {}", _0)]
+    Unknown(String),
 }
 
 /// Failure type for errors when creating a descriptor pool.
@@ -174,6 +295,10 @@ pub enum DescriptorPoolCreationError {
     /// Memory is too fragmented to create the descriptor pool.
     #[fail(display = "Memory is too fragmented to create the descriptor pool.")]
     Fragmentation,
+
+    /// The API returned a result Nova doesn't know how to handle. Contains the raw result for diagnostics.
+    #[fail(display = "Unknown API result: {}", _0)]
+    Unknown(String),
 }
 
 /// Failure type for errors when creating a pipeline.
@@ -193,6 +318,35 @@ pub enum PipelineCreationError {
         display = "One or more shaders failed to compile or link. If debug reports are enabled, details are reported through a debug report."
     )]
     InvalidShader,
+
+    /// The API returned a result Nova doesn't know how to handle. Contains the raw result for diagnostics.
+    #[fail(display = "Unknown API result: {}", _0)]
+    Unknown(String),
+}
+
+/// Failure type for acquiring and presenting swapchain images.
+#[derive(Fail, Debug, Clone, Eq, PartialEq)]
+pub enum SwapchainError {
+    /// The surface changed so that the swapchain no longer matches it, usually because the window was resized. The
+    /// swapchain must be recreated.
+    #[fail(display = "The swapchain is out of date with its surface.")]
+    OutOfDate,
+
+    /// The surface is no longer available. The surface and swapchain must be recreated.
+    #[fail(display = "The swapchain's surface was lost.")]
+    SurfaceLost,
+
+    /// No image became available before the timeout.
+    #[fail(display = "No swapchain image became available within {:?}.", _0)]
+    TimedOut(Duration),
+
+    /// The device was lost.
+    #[fail(display = "The device was lost.")]
+    DeviceLost,
+
+    /// The API returned a result Nova doesn't know how to handle. Contains the raw result for diagnostics.
+    #[fail(display = "Unknown API result: {}", _0)]
+    Unknown(String),
 }
 
 /// The state of a resource. The resource will be optimized for the given use case, though it may still be used in
@@ -236,6 +390,43 @@ pub enum ResourceState {
     TransferDestination,
 }
 
+impl ResourceState {
+    /// Gets the ways a resource in this state is accessed, for use in a
+    /// [`ResourceBarrier`](crate::rhi::ResourceBarrier).
+    ///
+    /// Presenting doesn't need any access flags, since presentation is synchronized with semaphores.
+    pub fn access_flags(&self) -> ResourceAccessFlags {
+        match self {
+            Self::Undefined | Self::PresentSource => ResourceAccessFlags::NO_FLAGS,
+            Self::General => ResourceAccessFlags::SHADER_READ_BIT | ResourceAccessFlags::SHADER_WRITE_BIT,
+            Self::ColorAttachment => {
+                ResourceAccessFlags::COLOR_ATTACHMENT_READ_BIT | ResourceAccessFlags::COLOR_ATTACHMENT_WRITE_BIT
+            }
+            Self::DepthStencilAttachment
+            | Self::DepthReadOnlyStencilAttachment
+            | Self::DepthAttachmentStencilReadOnly => {
+                ResourceAccessFlags::DEPTH_STENCIL_ATTACHMENT_READ_BIT
+                    | ResourceAccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE_BIT
+            }
+            Self::DepthStencilReadOnlyAttachment => ResourceAccessFlags::DEPTH_STENCIL_ATTACHMENT_READ_BIT,
+            Self::NonFragmentShaderReadOnly | Self::FragmentShaderReadOnly => ResourceAccessFlags::SHADER_READ_BIT,
+            Self::TransferSource => ResourceAccessFlags::TRANSFER_READ_BIT,
+            Self::TransferDestination => ResourceAccessFlags::TRANSFER_WRITE_BIT,
+        }
+    }
+
+    /// Returns true if this is one of the depth-stencil attachment states.
+    pub fn is_depth_stencil(&self) -> bool {
+        match self {
+            Self::DepthStencilAttachment
+            | Self::DepthReadOnlyStencilAttachment
+            | Self::DepthAttachmentStencilReadOnly
+            | Self::DepthStencilReadOnlyAttachment => true,
+            _ => false,
+        }
+    }
+}
+
 /// Type of object current descriptor points to.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum DescriptorType {
@@ -249,12 +440,51 @@ pub enum DescriptorType {
     StorageBuffer,
 }
 
+/// How a graphics API binds textures and the samplers that read them.
+///
+/// Shaderpacks use combined image samplers, such as GLSL's `sampler2D`, which reflect as
+/// [`DescriptorType::CombinedImageSampler`]. APIs which keep textures and samplers apart need two descriptors for each
+/// of those bindings. [`backend_descriptors`](crate::rhi::ResourceBindingDescription::backend_descriptors) does that
+/// translation, so every backend lines its bindings up the same way.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DescriptorBindingModel {
+    /// A texture and its sampler are bound through a single descriptor. Used by Vulkan.
+    CombinedSamplers,
+
+    /// A texture and its sampler are bound through separate descriptors, at the same binding but in different
+    /// descriptor heaps. Used by Direct3D 12.
+    SeparateSamplers,
+}
+
+/// Type of a descriptor in the API's own terms, after translating a [`DescriptorType`] for a
+/// [`DescriptorBindingModel`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BackendDescriptorType {
+    /// An image and its sampler, such as `VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER`.
+    CombinedImageSampler,
+
+    /// An image read through a separate sampler, such as a Direct3D 12 SRV.
+    SampledImage,
+
+    /// A sampler for a separate image.
+    Sampler,
+
+    /// A uniform buffer, or a Direct3D 12 CBV.
+    UniformBuffer,
+
+    /// A storage buffer, or a Direct3D 12 UAV.
+    StorageBuffer,
+}
+
 /// Current use of a buffer.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum BufferUsage {
     /// A buffer of uniform values.
     UniformBuffer,
 
+    /// A buffer which shaders can index freely, such as an array whose size isn't known when the shader is compiled.
+    StorageBuffer,
+
     /// The index buffer for rasterization.
     IndexBuffer,
 
@@ -423,6 +653,39 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Properties of a type of device memory.
+    ///
+    /// The values match [`VkMemoryPropertyFlagBits`](https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/VkMemoryPropertyFlagBits.html).
+    pub struct MemoryPropertyFlags: u32 {
+        /// Memory is fastest for the device to access.
+        const DEVICE_LOCAL = 0x0000_0001;
+        /// Memory can be mapped for the host to access.
+        const HOST_VISIBLE = 0x0000_0002;
+        /// Host writes are visible to the device, and device writes to the host, without flushing or invalidating.
+        const HOST_COHERENT = 0x0000_0004;
+        /// Memory is cached on the host, which makes host reads fast.
+        const HOST_CACHED = 0x0000_0008;
+    }
+}
+
+bitflags! {
+    /// What an image may be used for. Images can only be used in the ways they were created for, and drivers can
+    /// lay out images with fewer usages more efficiently.
+    ///
+    /// The values match [`VkImageUsageFlagBits`](https://www.khronos.org/registry/vulkan/specs/1.1-extensions/man/html/VkImageUsageFlagBits.html).
+    pub struct ImageUsage: u32 {
+        /// Shaders sample from the image.
+        const SAMPLED = 0x0000_0004;
+        /// Shaders read and write the image directly.
+        const STORAGE = 0x0000_0008;
+        /// Passes render to the image.
+        const COLOR_ATTACHMENT = 0x0000_0010;
+        /// Passes use the image as their depth or depth/stencil buffer.
+        const DEPTH_ATTACHMENT = 0x0000_0020;
+    }
+}
+
 bitflags! {
     /// Aspect of an image included in a view.
     pub struct ImageAspectFlags: u32 {
@@ -466,3 +729,49 @@ bitflags! {
         const MESH = 0x0080;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn image_view_types() {
+        use shaderpack::TextureType;
+
+        assert_eq!(ImageViewType::from(TextureType::Tex2D), ImageViewType::Type2D);
+        assert_eq!(ImageViewType::from(TextureType::Tex2DArray), ImageViewType::Type2DArray);
+        assert_eq!(ImageViewType::from(TextureType::TexCube), ImageViewType::Cube);
+        assert_eq!(ImageViewType::from(TextureType::Tex3D), ImageViewType::Type3D);
+
+        // Cubemaps are stored in 2D images, only volumes need a 3D one
+        assert!(!ImageViewType::Cube.is_3d());
+        assert!(ImageViewType::Type3D.is_3d());
+    }
+
+    #[test]
+    fn memory_type_fallback() {
+        let device_local = MemoryPropertyFlags::DEVICE_LOCAL;
+        let host_coherent = MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT;
+        let host_cached = host_coherent | MemoryPropertyFlags::HOST_CACHED;
+
+        // No HOST_CACHED memory, so staging buffers fall back to coherent memory
+        let memory_types = [device_local, host_coherent];
+        assert_eq!(MemoryUsage::StagingBuffer.find_memory_type(&memory_types, !0), Ok(1));
+        assert_eq!(
+            MemoryUsage::LowFrequencyUpload.find_memory_type(&memory_types, !0),
+            Ok(1)
+        );
+        assert_eq!(MemoryUsage::DeviceOnly.find_memory_type(&memory_types, !0), Ok(0));
+
+        let memory_types = [device_local, host_coherent, host_cached];
+        assert_eq!(MemoryUsage::StagingBuffer.find_memory_type(&memory_types, !0), Ok(2));
+
+        // Memory types the resource can't be bound to are skipped
+        assert_eq!(MemoryUsage::StagingBuffer.find_memory_type(&memory_types, 0b011), Ok(1));
+        assert_eq!(MemoryUsage::DeviceOnly.find_memory_type(&memory_types, 0b110), Ok(1));
+        assert_eq!(
+            MemoryUsage::StagingBuffer.find_memory_type(&memory_types, 0b001),
+            Err(AllocationError::NoSuitableMemoryFound)
+        );
+    }
+}
@@ -1,5 +1,9 @@
 use super::{rhi_enums::*, rhi_traits::*};
 use crate::shaderpack;
+use cgmath::{Vector2, Vector3};
+use std::cmp::Ordering;
+use std::error;
+use std::fmt;
 use std::sync::Arc;
 
 /// Describes what kind of command allocator you want to create.
@@ -36,6 +40,190 @@ pub struct PhysicalDeviceProperties {
 
     /// Count of color attachments usable.
     pub max_color_attachments: u32,
+
+    /// Optional features the device supports.
+    pub features: PhysicalDeviceFeatures,
+}
+
+impl PhysicalDeviceProperties {
+    /// How much Nova prefers an adapter of this type. Higher is better.
+    pub fn score(&self) -> u32 {
+        match self.device_type {
+            PhysicalDeviceType::Discrete => 4,
+            PhysicalDeviceType::Integrated => 3,
+            PhysicalDeviceType::Virtual => 2,
+            PhysicalDeviceType::CPU => 1,
+            PhysicalDeviceType::Other => 0,
+        }
+    }
+
+    /// Orders adapters from most to least preferred.
+    ///
+    /// Adapters with a higher [`score`](PhysicalDeviceProperties::score) come first. Ties are broken by device id and
+    /// then by name, so the order doesn't depend on the order the driver happens to report adapters in.
+    pub fn preference_order(&self, other: &Self) -> Ordering {
+        other
+            .score()
+            .cmp(&self.score())
+            .then_with(|| self.device_id.cmp(&other.device_id))
+            .then_with(|| self.device_name.cmp(&other.device_name))
+    }
+}
+
+/// Summary of a graphics adapter, for showing users a list of adapters to pick from.
+#[derive(Debug, Clone)]
+pub struct AdapterInfo {
+    /// Position of the adapter in the list returned by
+    /// [`GraphicsApi::get_ordered_adapters`](super::GraphicsApi::get_ordered_adapters).
+    pub index: usize,
+
+    /// Name, type, features and limits of the adapter.
+    pub properties: PhysicalDeviceProperties,
+
+    /// Amount of free VRAM on the adapter, in bytes.
+    pub free_memory: u64,
+
+    /// Whether the adapter has everything Nova itself needs.
+    pub usable: bool,
+}
+
+/// Optional features of a physical device which shaderpacks may need.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhysicalDeviceFeatures {
+    /// Supports tessellation control and tessellation evaluation shaders.
+    pub tessellation_shader: bool,
+
+    /// Supports geometry shaders.
+    pub geometry_shader: bool,
+
+    /// Supports compute shaders.
+    pub compute_shader: bool,
+
+    /// Supports anisotropic texture filtering.
+    pub sampler_anisotropy: bool,
+
+    /// Highest anisotropy level samplers may use. Only meaningful if `sampler_anisotropy` is supported.
+    pub max_sampler_anisotropy: f32,
+
+    /// Supports rasterizing polygons as lines or points.
+    pub fill_mode_non_solid: bool,
+
+    /// Supports lines wider than one pixel.
+    pub wide_lines: bool,
+
+    /// Supports clamping fragment depth instead of clipping.
+    pub depth_clamp: bool,
+}
+
+/// Features and limits a physical device needs for Nova to render something, such as a loaded shaderpack.
+///
+/// This describes needs on top of what [`PhysicalDevice::can_be_used_by_nova`] checks, which is the minimum any
+/// device has to support.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceRequirements {
+    /// Needs tessellation control and tessellation evaluation shaders.
+    pub tessellation_shader: bool,
+
+    /// Needs geometry shaders.
+    pub geometry_shader: bool,
+
+    /// Needs compute shaders.
+    pub compute_shader: bool,
+
+    /// Needs samplers to support at least this anisotropy level. Anything at or below 1 doesn't need anisotropic
+    /// filtering.
+    pub min_sampler_anisotropy: f32,
+
+    /// Needs to rasterize polygons as lines or points.
+    pub fill_mode_non_solid: bool,
+
+    /// Needs lines wider than one pixel.
+    pub wide_lines: bool,
+
+    /// Needs to clamp fragment depth.
+    pub depth_clamp: bool,
+
+    /// Needs to render to at least this many color attachments at once.
+    pub min_color_attachments: u32,
+}
+
+impl DeviceRequirements {
+    /// Finds the requirements of everything a shaderpack uses.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - The shaderpack to find the requirements of.
+    pub fn for_shaderpack(data: &shaderpack::ShaderpackData) -> Self {
+        let mut requirements = Self::default();
+        for pipeline in &data.pipelines {
+            requirements.tessellation_shader |= pipeline.has_tessellation_shaders();
+            requirements.geometry_shader |= pipeline.geometry_shader.is_some();
+            requirements.fill_mode_non_solid |= pipeline.requires_non_solid_fill();
+            requirements.wide_lines |= pipeline.requires_wide_lines();
+            requirements.depth_clamp |= pipeline.depth_clamp;
+        }
+        requirements.min_color_attachments = data
+            .passes
+            .iter()
+            .map(|pass| pass.texture_outputs.len() as u32)
+            .max()
+            .unwrap_or(0);
+        requirements
+    }
+
+    /// Lists every requirement the device doesn't meet, as a human readable reason.
+    ///
+    /// # Parameters
+    ///
+    /// * `properties` - Properties of the device to check.
+    pub fn unmet_by(&self, properties: &PhysicalDeviceProperties) -> Vec<String> {
+        let features = &properties.features;
+        let mut reasons = Vec::new();
+        let mut require = |needed: bool, supported: bool, feature: &str| {
+            if needed && !supported {
+                reasons.push(format!("{} is not supported", feature));
+            }
+        };
+
+        require(self.tessellation_shader, features.tessellation_shader, "Tessellation");
+        require(self.geometry_shader, features.geometry_shader, "Geometry shading");
+        require(self.compute_shader, features.compute_shader, "Compute shading");
+        require(
+            self.fill_mode_non_solid,
+            features.fill_mode_non_solid,
+            "Non-solid fill mode",
+        );
+        require(self.wide_lines, features.wide_lines, "Wide lines");
+        require(self.depth_clamp, features.depth_clamp, "Depth clamping");
+
+        if self.min_sampler_anisotropy > 1.0 {
+            if !features.sampler_anisotropy {
+                reasons.push(String::from("Anisotropic filtering is not supported"));
+            } else if features.max_sampler_anisotropy < self.min_sampler_anisotropy {
+                reasons.push(format!(
+                    "Anisotropy of {} is needed, but at most {} is supported",
+                    self.min_sampler_anisotropy, features.max_sampler_anisotropy
+                ));
+            }
+        }
+        if properties.max_color_attachments < self.min_color_attachments {
+            reasons.push(format!(
+                "{} color attachments are needed, but at most {} are supported",
+                self.min_color_attachments, properties.max_color_attachments
+            ));
+        }
+
+        reasons
+    }
+
+    /// Checks if a device meets all of these requirements.
+    ///
+    /// # Parameters
+    ///
+    /// * `properties` - Properties of the device to check.
+    pub fn is_met_by(&self, properties: &PhysicalDeviceProperties) -> bool {
+        self.unmet_by(properties).is_empty()
+    }
 }
 
 /// Data corresponding to a particular resource.
@@ -45,6 +233,8 @@ pub enum ResourceSpecificData {
     Image {
         /// The aspect of the image.
         aspect: ImageAspectFlags,
+        /// The mip levels and array layers of the image.
+        range: ImageSubresourceRange,
     },
 
     /// The resource is a buffer.
@@ -56,6 +246,99 @@ pub enum ResourceSpecificData {
     },
 }
 
+/// A range of mip levels and array layers of an image.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ImageSubresourceRange {
+    /// First mip level in the range.
+    pub base_mip_level: u32,
+
+    /// Number of mip levels in the range, or [`REMAINING`](ImageSubresourceRange::REMAINING) for every level from
+    /// `base_mip_level` on.
+    pub mip_level_count: u32,
+
+    /// First array layer in the range. Cubemap faces count as layers.
+    pub base_array_layer: u32,
+
+    /// Number of array layers in the range, or [`REMAINING`](ImageSubresourceRange::REMAINING) for every layer from
+    /// `base_array_layer` on.
+    pub array_layer_count: u32,
+}
+
+impl ImageSubresourceRange {
+    /// Count which stands for everything from the base mip level or array layer to the end of the image. This is the
+    /// same value as Vulkan's `VK_REMAINING_MIP_LEVELS` and `VK_REMAINING_ARRAY_LAYERS`.
+    pub const REMAINING: u32 = u32::max_value();
+
+    /// Every mip level and array layer of an image.
+    pub const ALL: Self = Self {
+        base_mip_level: 0,
+        mip_level_count: Self::REMAINING,
+        base_array_layer: 0,
+        array_layer_count: Self::REMAINING,
+    };
+
+    /// Creates a range with some mip levels of every array layer.
+    ///
+    /// # Parameters
+    ///
+    /// * `base_mip_level` - First mip level in the range.
+    /// * `mip_level_count` - Number of mip levels in the range.
+    pub const fn mip_levels(base_mip_level: u32, mip_level_count: u32) -> Self {
+        Self {
+            base_mip_level,
+            mip_level_count,
+            base_array_layer: 0,
+            array_layer_count: Self::REMAINING,
+        }
+    }
+
+    /// Gets the Direct3D 12 subresource indices in this range, in ascending order.
+    ///
+    /// Subresources are numbered mip level first, so the index of a subresource is `mip_level + array_layer *
+    /// mip_levels`.
+    ///
+    /// # Parameters
+    ///
+    /// * `mip_levels` - Number of mip levels the image has.
+    /// * `array_layers` - Number of array layers the image has.
+    pub fn subresources(&self, mip_levels: u32, array_layers: u32) -> Vec<u32> {
+        let mip_end = Self::end(self.base_mip_level, self.mip_level_count, mip_levels);
+        let layer_end = Self::end(self.base_array_layer, self.array_layer_count, array_layers);
+        (self.base_array_layer..layer_end)
+            .flat_map(|layer| (self.base_mip_level..mip_end).map(move |mip| mip + layer * mip_levels))
+            .collect()
+    }
+
+    /// Checks if this range covers every mip level and array layer of an image. Barriers on the whole image can be
+    /// recorded as a single barrier on Direct3D 12.
+    ///
+    /// # Parameters
+    ///
+    /// * `mip_levels` - Number of mip levels the image has.
+    /// * `array_layers` - Number of array layers the image has.
+    pub fn covers_all(&self, mip_levels: u32, array_layers: u32) -> bool {
+        self.base_mip_level == 0
+            && self.base_array_layer == 0
+            && Self::end(0, self.mip_level_count, mip_levels) == mip_levels
+            && Self::end(0, self.array_layer_count, array_layers) == array_layers
+    }
+
+    /// Works out the end of a range of mip levels or array layers, clamped to the size of the image.
+    fn end(base: u32, count: u32, total: u32) -> u32 {
+        if count == Self::REMAINING {
+            total
+        } else {
+            total.min(base.saturating_add(count))
+        }
+    }
+}
+
+impl Default for ImageSubresourceRange {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
 /// Barrier for resources.
 #[derive(Clone)]
 pub struct ResourceBarrier {
@@ -74,16 +357,127 @@ pub struct ResourceBarrier {
     /// Accessibility before barrier.
     pub access_after_barrier: ResourceAccessFlags,
 
-    /// Starting queue.
+    /// Queue which owns the resource before the barrier.
     pub source_queue: QueueType,
 
-    /// Finishing queue.
+    /// Queue which owns the resource after the barrier. Different from `source_queue` when the barrier hands the
+    /// resource to another queue.
     pub destination_queue: QueueType,
 
     /// Information about the resource being guarded.
     pub resource_info: ResourceSpecificData,
 }
 
+impl ResourceBarrier {
+    /// Creates a barrier which transitions an image between two states on the graphics queue.
+    ///
+    /// The access masks are the ones [`ResourceState::access_flags`] gives for each state, the image aspect is depth
+    /// for depth-stencil states and color for everything else, and every mip level and array layer is transitioned.
+    /// Use [`with_queues`](ResourceBarrier::with_queues), [`with_range`](ResourceBarrier::with_range) or
+    /// [`with_resource_info`](ResourceBarrier::with_resource_info) to change the defaults.
+    ///
+    /// # Parameters
+    ///
+    /// * `resource` - The resource to transition.
+    /// * `from` - The state the resource is in before the barrier.
+    /// * `to` - The state the resource will be in after the barrier.
+    pub fn transition(resource: Arc<dyn Resource>, from: ResourceState, to: ResourceState) -> Self {
+        let aspect = if from.is_depth_stencil() || to.is_depth_stencil() {
+            ImageAspectFlags::DEPTH
+        } else {
+            ImageAspectFlags::COLOR
+        };
+
+        Self {
+            resource,
+            access_before_barrier: from.access_flags(),
+            access_after_barrier: to.access_flags(),
+            initial_state: from,
+            final_state: to,
+            source_queue: QueueType::Graphics,
+            destination_queue: QueueType::Graphics,
+            resource_info: ResourceSpecificData::Image {
+                aspect,
+                range: ImageSubresourceRange::ALL,
+            },
+        }
+    }
+
+    /// Creates a barrier which makes an image ready to be rendered to as a color attachment.
+    ///
+    /// # Parameters
+    ///
+    /// * `resource` - The image to transition.
+    /// * `from` - The state the image is in before the barrier.
+    pub fn to_color_attachment(resource: Arc<dyn Resource>, from: ResourceState) -> Self {
+        Self::transition(resource, from, ResourceState::ColorAttachment)
+    }
+
+    /// Creates a barrier which makes an image ready to be sampled by fragment shaders.
+    ///
+    /// # Parameters
+    ///
+    /// * `resource` - The image to transition.
+    /// * `from` - The state the image is in before the barrier.
+    pub fn to_shader_read(resource: Arc<dyn Resource>, from: ResourceState) -> Self {
+        Self::transition(resource, from, ResourceState::FragmentShaderReadOnly)
+    }
+
+    /// Creates a barrier which makes a rendered image ready to be presented.
+    ///
+    /// # Parameters
+    ///
+    /// * `resource` - The image to transition.
+    /// * `from` - The state the image is in before the barrier.
+    pub fn to_present(resource: Arc<dyn Resource>, from: ResourceState) -> Self {
+        Self::transition(resource, from, ResourceState::PresentSource)
+    }
+
+    /// Changes which queues the resource is transferred between.
+    ///
+    /// # Parameters
+    ///
+    /// * `source_queue` - The queue which used the resource before the barrier.
+    /// * `destination_queue` - The queue which uses the resource after the barrier.
+    pub fn with_queues(mut self, source_queue: QueueType, destination_queue: QueueType) -> Self {
+        self.source_queue = source_queue;
+        self.destination_queue = destination_queue;
+        self
+    }
+
+    /// Checks if the barrier hands the resource from one queue to another.
+    ///
+    /// Such a barrier has to be recorded twice: once in a command list submitted to the source queue, to release the
+    /// resource, and once in a command list submitted to the destination queue, to acquire it. The acquiring command
+    /// list must wait on a semaphore signalled by the releasing one.
+    pub fn is_queue_transfer(&self) -> bool {
+        self.source_queue != self.destination_queue
+    }
+
+    /// Changes which mip levels and array layers of an image the barrier guards. Barriers on buffers are left as they
+    /// are.
+    ///
+    /// # Parameters
+    ///
+    /// * `range` - The mip levels and array layers to guard.
+    pub fn with_range(mut self, range: ImageSubresourceRange) -> Self {
+        if let ResourceSpecificData::Image { range: image_range, .. } = &mut self.resource_info {
+            *image_range = range;
+        }
+        self
+    }
+
+    /// Changes which part of the resource the barrier guards.
+    ///
+    /// # Parameters
+    ///
+    /// * `resource_info` - The part of the resource to guard.
+    pub fn with_resource_info(mut self, resource_info: ResourceSpecificData) -> Self {
+        self.resource_info = resource_info;
+        self
+    }
+}
+
 /// Data that goes into updating a descriptor.
 #[derive(Clone)]
 pub enum DescriptorUpdateInfo {
@@ -132,6 +526,117 @@ pub struct ResourceBindingDescription {
     pub stages: ShaderStageFlags,
 }
 
+impl ResourceBindingDescription {
+    /// Amount of descriptors reserved for a runtime-sized descriptor array, such as `sampler2D textures[]`.
+    ///
+    /// Runtime-sized arrays don't have a size Nova can find out before the shaderpack binds resources to them, so they
+    /// are given this many descriptors. Shaders must not index past it.
+    pub const MAX_RUNTIME_ARRAY_COUNT: u32 = 1024;
+
+    /// Gets the amount of descriptors a binding needs from the length of its array type.
+    ///
+    /// # Parameters
+    ///
+    /// * `array_length` - Length of the binding's array, `Some(1)` if the binding isn't an array, or `None` if the
+    /// array is runtime-sized.
+    pub fn count_for_array(array_length: Option<u32>) -> u32 {
+        array_length.unwrap_or(Self::MAX_RUNTIME_ARRAY_COUNT)
+    }
+
+    /// Translates this binding into the descriptors an API with the given binding model needs.
+    ///
+    /// Every descriptor keeps the binding's set, binding and count. Under
+    /// [`SeparateSamplers`](DescriptorBindingModel::SeparateSamplers), a combined image sampler becomes a sampled
+    /// image and a sampler at the same binding, which Direct3D 12 puts in the `t` and `s` registers of the space of
+    /// the set. Every other binding becomes a single descriptor under either model.
+    ///
+    /// Backends must create their layouts and descriptor writes from this, rather than from the
+    /// [`descriptor_type`](Self::descriptor_type), so that bindings line up across APIs.
+    pub fn backend_descriptors(&self, model: DescriptorBindingModel) -> Vec<BackendDescriptor> {
+        let descriptor = |descriptor_type| BackendDescriptor {
+            set: self.set,
+            binding: self.binding,
+            count: self.count,
+            descriptor_type,
+        };
+
+        match (&self.descriptor_type, model) {
+            (DescriptorType::CombinedImageSampler, DescriptorBindingModel::CombinedSamplers) => {
+                vec![descriptor(BackendDescriptorType::CombinedImageSampler)]
+            }
+            (DescriptorType::CombinedImageSampler, DescriptorBindingModel::SeparateSamplers) => vec![
+                descriptor(BackendDescriptorType::SampledImage),
+                descriptor(BackendDescriptorType::Sampler),
+            ],
+            (DescriptorType::UniformBuffer, _) => vec![descriptor(BackendDescriptorType::UniformBuffer)],
+            (DescriptorType::StorageBuffer, _) => vec![descriptor(BackendDescriptorType::StorageBuffer)],
+        }
+    }
+}
+
+/// A descriptor of a graphics API, made from a [`ResourceBindingDescription`] by
+/// [`backend_descriptors`](ResourceBindingDescription::backend_descriptors).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BackendDescriptor {
+    /// Descriptor set, or Direct3D 12 register space, of the descriptor.
+    pub set: u32,
+
+    /// Binding, or Direct3D 12 register, of the descriptor.
+    pub binding: u32,
+
+    /// Number of descriptors in the array.
+    pub count: u32,
+
+    /// The API's type of the descriptor.
+    pub descriptor_type: BackendDescriptorType,
+}
+
+/// The amount of each kind of descriptor a descriptor pool needs.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct DescriptorPoolSizes {
+    /// The number of sampled image descriptors.
+    pub sampled_images: u32,
+
+    /// The number of sampler descriptors.
+    pub samplers: u32,
+
+    /// The number of UBO/CBV or SSBO/UAV descriptors.
+    pub uniform_buffers: u32,
+}
+
+impl DescriptorPoolSizes {
+    /// Adds up the descriptors needed by all the provided bindings.
+    ///
+    /// Descriptor arrays need one descriptor per element, so every binding counts [`count`] times. A combined image
+    /// sampler needs both a sampled image and a sampler.
+    ///
+    /// [`count`]: ResourceBindingDescription::count
+    ///
+    /// # Parameters
+    ///
+    /// * `bindings` - The bindings to make room for.
+    pub fn from_bindings<'a, I>(bindings: I) -> Self
+    where
+        I: IntoIterator<Item = &'a ResourceBindingDescription>,
+    {
+        bindings
+            .into_iter()
+            .flat_map(|binding| binding.backend_descriptors(DescriptorBindingModel::SeparateSamplers))
+            .fold(Self::default(), |mut sizes, descriptor| {
+                match descriptor.descriptor_type {
+                    BackendDescriptorType::SampledImage | BackendDescriptorType::CombinedImageSampler => {
+                        sizes.sampled_images += descriptor.count;
+                    }
+                    BackendDescriptorType::Sampler => sizes.samplers += descriptor.count,
+                    BackendDescriptorType::UniformBuffer | BackendDescriptorType::StorageBuffer => {
+                        sizes.uniform_buffers += descriptor.count;
+                    }
+                }
+                sizes
+            })
+    }
+}
+
 /// Data for buffer creation.
 #[derive(Debug, Clone)]
 pub struct BufferCreateInfo {
@@ -148,3 +653,493 @@ pub struct BufferCreateInfo {
 /// Memory allocation on a specific device.
 #[derive(Debug, Clone)]
 pub struct DeviceMemoryAllocation;
+
+/// The region of the framebuffer that rendering gets mapped to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    /// Distance from the left edge of the framebuffer, in pixels.
+    pub x: f32,
+
+    /// Distance from the top edge of the framebuffer, in pixels.
+    pub y: f32,
+
+    /// Width of the viewport, in pixels.
+    pub width: f32,
+
+    /// Height of the viewport, in pixels.
+    pub height: f32,
+
+    /// Depth that the near plane gets mapped to.
+    pub min_depth: f32,
+
+    /// Depth that the far plane gets mapped to.
+    pub max_depth: f32,
+}
+
+impl Viewport {
+    /// Creates a viewport which covers the whole of a framebuffer of the given size, with the full depth range.
+    ///
+    /// # Parameters
+    ///
+    /// * `size` - Size of the framebuffer, in pixels.
+    pub fn from_size(size: Vector2<u32>) -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: size.x as f32,
+            height: size.y as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+}
+
+/// An integer rectangle in framebuffer space, used for scissor tests.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Rect2D {
+    /// Distance from the left edge of the framebuffer, in pixels.
+    pub x: i32,
+
+    /// Distance from the top edge of the framebuffer, in pixels.
+    pub y: i32,
+
+    /// Width of the rectangle, in pixels.
+    pub width: u32,
+
+    /// Height of the rectangle, in pixels.
+    pub height: u32,
+}
+
+impl Rect2D {
+    /// Creates a rectangle which covers the whole of a framebuffer of the given size.
+    ///
+    /// # Parameters
+    ///
+    /// * `size` - Size of the framebuffer, in pixels.
+    pub const fn from_size(size: Vector2<u32>) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: size.x,
+            height: size.y,
+        }
+    }
+}
+
+/// Which pixels of a buffer get copied into which part of an image.
+///
+/// Rows of pixels in the buffer are [`row_pitch`](BufferImageCopy::row_pitch) bytes apart, which may be more than the
+/// size of the pixels in a row. Direct3D 12 needs the row pitch to be a multiple of
+/// [`ROW_PITCH_ALIGNMENT`](BufferImageCopy::ROW_PITCH_ALIGNMENT), and Vulkan needs it to be a whole number of pixels,
+/// so data uploaded through [`BufferImageCopy::new`] has padding at the end of each row.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BufferImageCopy {
+    /// Offset of the first pixel in the buffer, in bytes.
+    pub buffer_offset: u64,
+
+    /// Distance between the starts of two rows of pixels in the buffer, in bytes.
+    pub row_pitch: u32,
+
+    /// Size of one pixel, in bytes.
+    pub bytes_per_pixel: u32,
+
+    /// Mip level of the image to copy into.
+    pub mip_level: u32,
+
+    /// Array layer of the image to copy into. Cubemap faces count as layers.
+    pub array_layer: u32,
+
+    /// Position of the first pixel to write in the image, in pixels.
+    pub image_offset: Vector3<u32>,
+
+    /// Size of the region to write, in pixels. Images which aren't 3D have a depth of 1.
+    pub image_extent: Vector3<u32>,
+}
+
+impl BufferImageCopy {
+    /// Alignment of the row pitch, in bytes. This is Direct3D 12's `D3D12_TEXTURE_DATA_PITCH_ALIGNMENT`. Vulkan has no
+    /// such requirement but handles padded rows just as well.
+    pub const ROW_PITCH_ALIGNMENT: u32 = 256;
+
+    /// Alignment of [`buffer_offset`](BufferImageCopy::buffer_offset), in bytes. This is Direct3D 12's
+    /// `D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT`.
+    pub const OFFSET_ALIGNMENT: u64 = 512;
+
+    /// Alignment the buffer offset of a copy into an image with pixels of the given size needs, in bytes.
+    ///
+    /// This is [`OFFSET_ALIGNMENT`](BufferImageCopy::OFFSET_ALIGNMENT) for Direct3D 12, and also a multiple of the
+    /// pixel size, which Vulkan needs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_pixel` is zero.
+    pub fn offset_alignment(bytes_per_pixel: u32) -> u64 {
+        assert!(bytes_per_pixel != 0, "Pixels must be at least one byte");
+        u64::from(lcm(Self::OFFSET_ALIGNMENT as u32, bytes_per_pixel))
+    }
+
+    /// Creates a copy into the whole of mip level 0 and array layer 0 of an image, with rows padded to
+    /// [`ROW_PITCH_ALIGNMENT`](BufferImageCopy::ROW_PITCH_ALIGNMENT).
+    ///
+    /// Rows are also padded to a whole number of pixels, so that [`row_length`](BufferImageCopy::row_length) is exact
+    /// for pixel sizes which don't divide the alignment, such as the 12 bytes of a three channel 32-bit float format.
+    ///
+    /// # Parameters
+    ///
+    /// * `buffer_offset` - Offset of the first pixel in the buffer, in bytes.
+    /// * `image_extent` - Size of the image, in pixels.
+    /// * `bytes_per_pixel` - Size of one pixel, in bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_pixel` is zero.
+    pub fn new(buffer_offset: u64, image_extent: Vector3<u32>, bytes_per_pixel: u32) -> Self {
+        assert!(bytes_per_pixel != 0, "Pixels must be at least one byte");
+
+        let alignment = lcm(Self::ROW_PITCH_ALIGNMENT, bytes_per_pixel);
+        let row_size = image_extent.x * bytes_per_pixel;
+        let row_pitch = (row_size + alignment - 1) / alignment * alignment;
+        Self {
+            buffer_offset,
+            row_pitch,
+            bytes_per_pixel,
+            mip_level: 0,
+            array_layer: 0,
+            image_offset: Vector3::new(0, 0, 0),
+            image_extent,
+        }
+    }
+
+    /// Distance between the starts of two rows in the buffer, in pixels. This is Vulkan's `bufferRowLength`.
+    pub fn row_length(&self) -> u32 {
+        debug_assert!(
+            self.bytes_per_pixel != 0 && self.row_pitch % self.bytes_per_pixel == 0,
+            "Row pitch of {} bytes isn't a whole number of {} byte pixels",
+            self.row_pitch,
+            self.bytes_per_pixel
+        );
+        self.row_pitch / self.bytes_per_pixel
+    }
+
+    /// Number of bytes of the buffer the copy reads, starting at [`buffer_offset`](BufferImageCopy::buffer_offset).
+    ///
+    /// The last row isn't padded, so this is a little less than `row_pitch * height * depth` when rows are padded.
+    pub fn buffer_size(&self) -> u64 {
+        let rows = u64::from(self.image_extent.y) * u64::from(self.image_extent.z);
+        if rows == 0 {
+            return 0;
+        }
+        (rows - 1) * u64::from(self.row_pitch) + u64::from(self.image_extent.x) * u64::from(self.bytes_per_pixel)
+    }
+
+    /// Gets where in the buffer a pixel of the region has to be written, in bytes from the start of the buffer.
+    ///
+    /// # Parameters
+    ///
+    /// * `pixel` - Position of the pixel relative to [`image_offset`](BufferImageCopy::image_offset).
+    pub fn pixel_offset(&self, pixel: Vector3<u32>) -> u64 {
+        let row = u64::from(pixel.z) * u64::from(self.image_extent.y) + u64::from(pixel.y);
+        self.buffer_offset + row * u64::from(self.row_pitch) + u64::from(pixel.x) * u64::from(self.bytes_per_pixel)
+    }
+}
+
+/// Least common multiple of two numbers which aren't zero.
+fn lcm(a: u32, b: u32) -> u32 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let remainder = x % y;
+        x = y;
+        y = remainder;
+    }
+    a / x * b
+}
+
+/// An error code reported by one of the underlying graphics APIs, along with a human readable message.
+///
+/// `T` is the API's native result type, such as `HRESULT` on Direct3D 12 or `vk::Result` on Vulkan. Backends convert
+/// their native results into this type, which lets code above the RHI report errors from any backend the same way.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ErrorCode<T> {
+    /// The raw result code from the API.
+    pub code: T,
+
+    /// Description of the error, either from the API itself or written by the backend.
+    pub message: String,
+}
+
+impl<T> ErrorCode<T> {
+    /// Creates a new error code.
+    ///
+    /// # Parameters
+    ///
+    /// * `code` - The raw result code from the API.
+    /// * `message` - Description of the error.
+    pub fn new<M>(code: T, message: M) -> Self
+    where
+        M: Into<String>,
+    {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl<T> fmt::Display for ErrorCode<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "{:?}", self.code)
+        } else {
+            write!(f, "{} ({:?})", self.message, self.code)
+        }
+    }
+}
+
+impl<T> error::Error for ErrorCode<T> where T: fmt::Debug {}
+
+#[cfg(test)]
+mod test {
+    use crate::rhi::{
+        BufferImageCopy, DescriptorPoolSizes, DescriptorType, DeviceRequirements, ErrorCode, ImageAspectFlags,
+        ImageSubresourceRange, PhysicalDeviceFeatures, PhysicalDeviceManufacturer, PhysicalDeviceProperties,
+        PhysicalDeviceType, QueueType, Resource, ResourceAccessFlags, ResourceBarrier, ResourceBindingDescription,
+        ResourceSpecificData, ResourceState, ShaderStageFlags,
+    };
+    use cgmath::Vector3;
+    use std::sync::Arc;
+
+    #[test]
+    fn error_code_display() {
+        let error = ErrorCode::new(-5_i32, "Out of memory");
+        assert_eq!(error.to_string(), "Out of memory (-5)");
+
+        let error = ErrorCode::new(0x8007_000E_u32, "");
+        assert_eq!(error.to_string(), "2147942414");
+    }
+
+    fn binding(binding: u32, descriptor_type: DescriptorType, count: u32) -> ResourceBindingDescription {
+        ResourceBindingDescription {
+            set: 0,
+            binding,
+            count,
+            descriptor_type,
+            stages: ShaderStageFlags::FRAGMENT,
+        }
+    }
+
+    #[test]
+    fn pool_sizes_count_arrays() {
+        let bindings = [
+            binding(0, DescriptorType::UniformBuffer, 1),
+            binding(1, DescriptorType::CombinedImageSampler, 16),
+            binding(2, DescriptorType::StorageBuffer, 2),
+            binding(
+                3,
+                DescriptorType::CombinedImageSampler,
+                ResourceBindingDescription::count_for_array(None),
+            ),
+        ];
+
+        let sizes = DescriptorPoolSizes::from_bindings(&bindings);
+        assert_eq!(
+            sizes,
+            DescriptorPoolSizes {
+                sampled_images: 16 + ResourceBindingDescription::MAX_RUNTIME_ARRAY_COUNT,
+                samplers: 16 + ResourceBindingDescription::MAX_RUNTIME_ARRAY_COUNT,
+                uniform_buffers: 3,
+            }
+        );
+    }
+
+    fn device_properties(features: PhysicalDeviceFeatures) -> PhysicalDeviceProperties {
+        PhysicalDeviceProperties {
+            manufacturer: PhysicalDeviceManufacturer::Other,
+            device_id: 0,
+            device_name: String::from("Test Device"),
+            device_type: PhysicalDeviceType::Discrete,
+            max_color_attachments: 4,
+            features,
+        }
+    }
+
+    #[test]
+    fn device_requirements() {
+        let device = device_properties(PhysicalDeviceFeatures {
+            tessellation_shader: true,
+            sampler_anisotropy: true,
+            max_sampler_anisotropy: 8.0,
+            ..PhysicalDeviceFeatures::default()
+        });
+
+        assert!(DeviceRequirements::default().is_met_by(&device));
+
+        let requirements = DeviceRequirements {
+            tessellation_shader: true,
+            min_sampler_anisotropy: 8.0,
+            min_color_attachments: 4,
+            ..DeviceRequirements::default()
+        };
+        assert!(requirements.is_met_by(&device));
+
+        let requirements = DeviceRequirements {
+            geometry_shader: true,
+            min_sampler_anisotropy: 16.0,
+            min_color_attachments: 8,
+            ..DeviceRequirements::default()
+        };
+        assert_eq!(
+            requirements.unmet_by(&device),
+            [
+                "Geometry shading is not supported",
+                "Anisotropy of 16 is needed, but at most 8 is supported",
+                "8 color attachments are needed, but at most 4 are supported",
+            ]
+        );
+
+        let device = device_properties(PhysicalDeviceFeatures::default());
+        let requirements = DeviceRequirements {
+            min_sampler_anisotropy: 4.0,
+            ..DeviceRequirements::default()
+        };
+        assert_eq!(
+            requirements.unmet_by(&device),
+            ["Anisotropic filtering is not supported"]
+        );
+    }
+
+    struct TestImage;
+
+    impl Resource for TestImage {}
+
+    #[test]
+    fn barrier_transitions() {
+        let image: Arc<dyn Resource> = Arc::new(TestImage);
+
+        let barrier = ResourceBarrier::to_color_attachment(image.clone(), ResourceState::Undefined);
+        assert_eq!(barrier.access_before_barrier, ResourceAccessFlags::NO_FLAGS);
+        assert_eq!(
+            barrier.access_after_barrier,
+            ResourceAccessFlags::COLOR_ATTACHMENT_READ_BIT | ResourceAccessFlags::COLOR_ATTACHMENT_WRITE_BIT
+        );
+        assert_eq!(barrier.destination_queue, QueueType::Graphics);
+
+        let barrier = ResourceBarrier::to_shader_read(image.clone(), ResourceState::ColorAttachment);
+        assert_eq!(barrier.final_state, ResourceState::FragmentShaderReadOnly);
+        assert_eq!(
+            barrier.access_before_barrier,
+            ResourceAccessFlags::COLOR_ATTACHMENT_READ_BIT | ResourceAccessFlags::COLOR_ATTACHMENT_WRITE_BIT
+        );
+        assert_eq!(barrier.access_after_barrier, ResourceAccessFlags::SHADER_READ_BIT);
+
+        let barrier = ResourceBarrier::to_present(image.clone(), ResourceState::ColorAttachment);
+        assert_eq!(barrier.access_after_barrier, ResourceAccessFlags::NO_FLAGS);
+
+        let barrier = ResourceBarrier::to_shader_read(image.clone(), ResourceState::DepthStencilAttachment);
+        assert_eq!(
+            barrier.access_before_barrier,
+            ResourceAccessFlags::DEPTH_STENCIL_ATTACHMENT_READ_BIT
+                | ResourceAccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE_BIT
+        );
+        match barrier.resource_info {
+            ResourceSpecificData::Image { aspect, range } => {
+                assert_eq!(aspect, ImageAspectFlags::DEPTH);
+                assert_eq!(range, ImageSubresourceRange::ALL);
+            }
+            _ => panic!("Expected an image barrier"),
+        }
+
+        let barrier =
+            ResourceBarrier::transition(image, ResourceState::TransferDestination, ResourceState::TransferSource)
+                .with_queues(QueueType::Copy, QueueType::Graphics);
+        assert_eq!(barrier.access_before_barrier, ResourceAccessFlags::TRANSFER_WRITE_BIT);
+        assert_eq!(barrier.access_after_barrier, ResourceAccessFlags::TRANSFER_READ_BIT);
+        assert_eq!(barrier.source_queue, QueueType::Copy);
+    }
+
+    #[test]
+    fn cross_queue_transfer() {
+        let mesh: Arc<dyn Resource> = Arc::new(TestImage);
+        let barrier =
+            ResourceBarrier::transition(mesh.clone(), ResourceState::TransferDestination, ResourceState::General)
+                .with_resource_info(ResourceSpecificData::Buffer { offset: 0, size: 1024 });
+        assert!(!barrier.is_queue_transfer());
+
+        let barrier = barrier.with_queues(QueueType::Copy, QueueType::Graphics);
+        assert!(barrier.is_queue_transfer());
+        assert_eq!(barrier.source_queue, QueueType::Copy);
+        assert_eq!(barrier.destination_queue, QueueType::Graphics);
+
+        let barrier = ResourceBarrier::to_shader_read(mesh, ResourceState::TransferDestination)
+            .with_queues(QueueType::Compute, QueueType::Compute);
+        assert!(!barrier.is_queue_transfer());
+    }
+
+    #[test]
+    fn barrier_mip_range() {
+        let image: Arc<dyn Resource> = Arc::new(TestImage);
+        let range = ImageSubresourceRange::mip_levels(2, 3);
+        let barrier = ResourceBarrier::to_shader_read(image, ResourceState::TransferDestination).with_range(range);
+        match barrier.resource_info {
+            ResourceSpecificData::Image {
+                range: barrier_range, ..
+            } => assert_eq!(barrier_range, range),
+            _ => panic!("Expected an image barrier"),
+        }
+
+        // Mips 2 to 4 of both layers of an image with 8 mips
+        assert_eq!(range.subresources(8, 2), vec![2, 3, 4, 10, 11, 12]);
+        assert!(!range.covers_all(8, 2));
+
+        // Counts past the end of the image are clamped
+        assert_eq!(ImageSubresourceRange::mip_levels(6, 4).subresources(8, 1), vec![6, 7]);
+        assert_eq!(ImageSubresourceRange::ALL.subresources(2, 6).len(), 12);
+        assert!(ImageSubresourceRange::ALL.covers_all(8, 6));
+        assert!(ImageSubresourceRange::mip_levels(0, 8).covers_all(8, 6));
+    }
+
+    #[test]
+    fn buffer_image_copy_pitch() {
+        // 100 RGBA8 pixels are 400 bytes, which gets padded to 512
+        let copy = BufferImageCopy::new(1024, Vector3::new(100, 50, 1), 4);
+        assert_eq!(copy.row_pitch, 512);
+        assert_eq!(copy.row_length(), 128);
+        assert_eq!(copy.buffer_size(), 49 * 512 + 400);
+        assert_eq!(copy.pixel_offset(Vector3::new(0, 0, 0)), 1024);
+        assert_eq!(copy.pixel_offset(Vector3::new(3, 2, 0)), 1024 + 2 * 512 + 3 * 4);
+
+        // Rows which are already aligned aren't padded
+        let copy = BufferImageCopy::new(0, Vector3::new(64, 64, 1), 4);
+        assert_eq!(copy.row_pitch, 256);
+        assert_eq!(copy.buffer_size(), 64 * 256);
+
+        // Slices of a 3D image follow each other
+        let copy = BufferImageCopy::new(0, Vector3::new(4, 4, 4), 16);
+        assert_eq!(copy.row_pitch, 256);
+        assert_eq!(copy.pixel_offset(Vector3::new(1, 0, 2)), 8 * 256 + 16);
+        assert_eq!(copy.buffer_size(), 15 * 256 + 64);
+    }
+
+    #[test]
+    fn buffer_image_copy_pitch_odd_pixel_size() {
+        // 100 RGB32F pixels are 1200 bytes. The pitch has to be a multiple of both 256 and 12, so it's padded to 1536
+        let copy = BufferImageCopy::new(0, Vector3::new(100, 4, 1), 12);
+        assert_eq!(copy.row_pitch, 1536);
+        assert_eq!(copy.row_pitch % BufferImageCopy::ROW_PITCH_ALIGNMENT, 0);
+        assert_eq!(copy.row_length(), 128);
+        assert_eq!(copy.pixel_offset(Vector3::new(1, 1, 0)), 1536 + 12);
+
+        // A row which fits in 256 bytes still gets padded to the 768 bytes both sizes divide
+        let copy = BufferImageCopy::new(0, Vector3::new(1, 1, 1), 12);
+        assert_eq!(copy.row_pitch, 768);
+        assert_eq!(copy.row_length(), 64);
+    }
+
+    #[test]
+    #[should_panic]
+    fn buffer_image_copy_zero_pixel_size() {
+        BufferImageCopy::new(0, Vector3::new(4, 4, 1), 0);
+    }
+}
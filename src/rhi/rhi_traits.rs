@@ -14,6 +14,7 @@ use crate::shaderpack;
 use crate::surface::Surface;
 use cgmath::Vector2;
 use std::rc::Rc;
+use std::time::Duration;
 
 /// Top-level trait for functions that don't belong to any specific device object.
 pub trait GraphicsApi {
@@ -23,9 +24,46 @@ pub trait GraphicsApi {
     /// Corresponding platform surface.
     type PlatformSurface;
 
-    /// Gets a list of all available graphics adapters.
+    /// How the API binds textures and samplers. Pipeline interfaces and descriptor sets must be created from
+    /// [`ResourceBindingDescription::backend_descriptors`] with this model.
+    const DESCRIPTOR_BINDING_MODEL: DescriptorBindingModel;
+
+    /// Gets a list of all available graphics adapters, in whatever order the driver reports them.
     fn get_adapters(&self) -> Vec<Self::PhysicalDevice>;
 
+    /// Gets a list of all available graphics adapters, from most to least preferred.
+    ///
+    /// Unlike [`get_adapters`](GraphicsApi::get_adapters), the order is the same every run on the same machine. See
+    /// [`PhysicalDeviceProperties::preference_order`].
+    fn get_ordered_adapters(&self) -> Vec<Self::PhysicalDevice> {
+        let mut adapters: Vec<_> = self
+            .get_adapters()
+            .into_iter()
+            .map(|adapter| (adapter.get_properties(), adapter))
+            .collect();
+        adapters.sort_by(|(first, _), (second, _)| first.preference_order(second));
+        adapters.into_iter().map(|(_, adapter)| adapter).collect()
+    }
+
+    /// Gets a summary of every available graphics adapter, without creating any logical devices.
+    ///
+    /// Meant for letting users pick an adapter before a renderer is created. The [`index`](AdapterInfo::index) of the
+    /// chosen adapter identifies it in [`get_ordered_adapters`](GraphicsApi::get_ordered_adapters). Backends with a
+    /// cheaper way to list their adapters than going through [`PhysicalDevice`] may override this, but have to keep the
+    /// same order.
+    fn enumerate_adapters(&self) -> Vec<AdapterInfo> {
+        self.get_ordered_adapters()
+            .iter()
+            .enumerate()
+            .map(|(index, adapter)| AdapterInfo {
+                index,
+                properties: adapter.get_properties(),
+                free_memory: adapter.get_free_memory(),
+                usable: adapter.can_be_used_by_nova(),
+            })
+            .collect()
+    }
+
     /// Gets the surface this API was created with.
     fn get_surface(&self) -> Rc<dyn Surface<Self::PlatformSurface>>;
 }
@@ -53,12 +91,27 @@ pub trait PhysicalDevice {
     /// least suitable, but for now this is fine.
     fn can_be_used_by_nova(&self) -> bool;
 
+    /// Checks if this physical device is suitable for Nova and has everything in `requirements`.
+    ///
+    /// # Parameters
+    ///
+    /// * `requirements` - The features and limits needed on top of Nova's own requirements.
+    fn meets_requirements(&self, requirements: &DeviceRequirements) -> bool {
+        self.can_be_used_by_nova() && requirements.is_met_by(&self.get_properties())
+    }
+
     /// Creates a new logical device.
     ///
     /// Nova has very specific requirements for a logical device, and how you express those
     /// requirements varies significantly by API. Thus, this method doesn't take a create info
     /// struct of any sort.
-    fn create_logical_device(&self) -> Result<Self::Device, DeviceCreationError>;
+    ///
+    /// # Parameters
+    ///
+    /// * `pipeline_cache` - Pipeline cache saved from an earlier run with
+    /// [`Device::get_pipeline_cache_data`], or an empty slice. Pipelines the cache has data for are created faster.
+    /// Data the API doesn't accept, such as a cache from another driver version, is discarded.
+    fn create_logical_device(&self, pipeline_cache: &[u8]) -> Result<Self::Device, DeviceCreationError>;
 
     /// Gets the amount of free VRAM on this physical device.
     fn get_free_memory(&self) -> u64;
@@ -167,6 +220,10 @@ pub trait Device {
 
     /// Creates a PipelineInterface from the provided information.
     ///
+    /// The bindings come from [`reflect_pipeline_bindings`](crate::shaderpack::reflect_pipeline_bindings), which makes
+    /// sure their descriptor sets are numbered from 0 without gaps. Implementations can therefore create one layout
+    /// per set, in order.
+    ///
     /// # Parameters
     ///
     /// * `bindings` - The bindings that the pipeline exposes.
@@ -181,6 +238,9 @@ pub trait Device {
 
     /// Creates a DescriptorPool with the desired descriptors.
     ///
+    /// Use [`DescriptorPoolSizes::from_bindings`] to find out how many descriptors a set of bindings needs, including
+    /// the extra descriptors for descriptor arrays.
+    ///
     /// # Parameters
     ///
     /// * `num_sampled_images` - The number of sampled image descriptors you'll make from the new pool.
@@ -195,6 +255,10 @@ pub trait Device {
 
     /// Creates a Pipeline with the provided PipelineInterface and the given PipelineCreateInfo.
     ///
+    /// The renderer creates pipelines from several threads at once (see
+    /// [`create_pipelines_in_parallel`](crate::renderer::create_pipelines_in_parallel)), so implementations must
+    /// guard any state the API doesn't allow to be used concurrently.
+    ///
     /// # Parameters
     ///
     /// * `pipeline_interface` - The interface you want the new pipeline to have.
@@ -216,6 +280,16 @@ pub trait Device {
     /// * `data` - The ImageData to create the image from.
     fn create_image(&self, data: shaderpack::TextureCreateInfo) -> Result<Self::Image, MemoryError>;
 
+    /// Checks if textures with the given pixel format can be used for the given purpose on this device.
+    ///
+    /// Backends answer this with `vkGetPhysicalDeviceFormatProperties` or `ID3D12Device::CheckFeatureSupport`.
+    ///
+    /// # Parameters
+    ///
+    /// * `format` - The pixel format to check.
+    /// * `usage` - What textures with the format would be used for.
+    fn supports_format(&self, format: shaderpack::PixelFormat, usage: FormatUsage) -> bool;
+
     /// Creates a new Semaphore.
     fn create_semaphore(&self) -> Result<Self::Semaphore, MemoryError>;
 
@@ -250,12 +324,22 @@ pub trait Device {
     /// * `fences` - The fences to reset.
     fn reset_fences(&self, fences: Vec<Self::Fence>);
 
+    /// Waits until the GPU has finished all work submitted to any of this device's queues.
+    ///
+    /// Nothing the GPU may still be using can be destroyed before this returns.
+    fn wait_idle(&self);
+
     /// Executes the provided DescriptorSetWrites on this device.
     ///
     /// # Parameters
     ///
     /// * `updates` - The DescriptorSetWrites to execute.
     fn update_descriptor_sets(&self, updates: Vec<DescriptorSetWrite>);
+
+    /// Gets the contents of the device's pipeline cache, which has data for every pipeline created so far.
+    ///
+    /// Save this when shutting down and pass it to [`PhysicalDevice::create_logical_device`] on the next run.
+    fn get_pipeline_cache_data(&self) -> Result<Vec<u8>, MemoryError>;
 }
 
 /// Represents a queue of command lists to run.
@@ -285,6 +369,66 @@ pub trait Queue {
     );
 }
 
+/// The images that get presented to the screen.
+///
+/// Rendering a frame works like this:
+///
+/// 1. Call [`acquire_next_image`](Swapchain::acquire_next_image) to find out which image to render to. The image may
+/// still be in use by the presentation engine when this returns, so rendering to it must wait for the semaphore
+/// passed to `acquire_next_image` to be signalled.
+/// 2. Submit the command lists which render to the image, waiting for that semaphore and signalling a semaphore of
+/// their own when they're done.
+/// 3. Call [`present`](Swapchain::present) with the image index and the semaphore the command lists signal.
+///
+/// If either method returns [`SwapchainError::OutOfDate`] or [`SwapchainError::SurfaceLost`] the swapchain must be
+/// recreated before rendering can continue.
+pub trait Swapchain {
+    /// Swapchain's image type.
+    type Image: Image;
+
+    /// Swapchain's semaphore type.
+    type Semaphore: Semaphore;
+
+    /// Swapchain's fence type.
+    type Fence: Fence;
+
+    /// Gets the index of the next image to render to.
+    ///
+    /// Blocks until an image is available or `timeout` has passed. `signal_semaphore` and `signal_fence` are signalled
+    /// once the presentation engine is done reading from the image, which may be after this returns. Neither is
+    /// signalled if this returns an error.
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - How long to wait for an image to become available.
+    /// * `signal_semaphore` - The semaphore to signal when the image can be rendered to.
+    /// * `signal_fence` - The fence to signal when the image can be rendered to, if the CPU needs to know.
+    fn acquire_next_image(
+        &self,
+        timeout: Duration,
+        signal_semaphore: &Self::Semaphore,
+        signal_fence: Option<&Self::Fence>,
+    ) -> Result<u32, SwapchainError>;
+
+    /// Queues an image for presentation.
+    ///
+    /// # Parameters
+    ///
+    /// * `image_index` - Index of the image to present, as returned by `acquire_next_image`.
+    /// * `wait_semaphores` - The semaphores to wait for before presenting the image.
+    fn present(&self, image_index: u32, wait_semaphores: Vec<Self::Semaphore>) -> Result<(), SwapchainError>;
+
+    /// Gets one of the swapchain's images.
+    ///
+    /// # Parameters
+    ///
+    /// * `image_index` - Index of the image to get, as returned by `acquire_next_image`.
+    fn get_image(&self, image_index: u32) -> Self::Image;
+
+    /// Gets the size of the swapchain's images, in pixels.
+    fn get_size(&self) -> Vector2<u32>;
+}
+
 /// A block of memory and an allocation strategy.
 pub trait Memory {
     /// Memory's underlying buffer type.
@@ -298,6 +442,30 @@ pub trait Memory {
     ///
     /// * `data` - The BufferData to create the new buffer from.
     fn create_buffer(&self, data: BufferCreateInfo) -> Result<Self::Buffer, MemoryError>;
+
+    /// Checks if host writes to this memory are visible to the device, and device writes are visible to the host,
+    /// without flushing or invalidating.
+    fn is_host_coherent(&self) -> bool;
+
+    /// Makes host writes to a range of this memory visible to the device. Does nothing if the memory is host coherent.
+    ///
+    /// Call this after writing to mapped memory and before submitting the commands which read the writes.
+    ///
+    /// # Parameters
+    ///
+    /// * `offset` - Start of the range, in bytes from the start of the memory.
+    /// * `size` - Size of the range, in bytes.
+    fn flush(&self, offset: u64, size: u64) -> Result<(), MemoryError>;
+
+    /// Makes device writes to a range of this memory visible to the host. Does nothing if the memory is host coherent.
+    ///
+    /// Call this after the commands which write the range have finished and before reading mapped memory.
+    ///
+    /// # Parameters
+    ///
+    /// * `offset` - Start of the range, in bytes from the start of the memory.
+    /// * `size` - Size of the range, in bytes.
+    fn invalidate(&self, offset: u64, size: u64) -> Result<(), MemoryError>;
 }
 
 /// A buffer or texture. Often interchangeable.
@@ -378,6 +546,8 @@ pub trait CommandAllocator {
 pub trait CommandList {
     /// CommandList's buffer type.
     type Buffer: Buffer;
+    /// CommandList's image type.
+    type Image: Image;
     /// CommandList's sub command list type.
     type CommandList: CommandList;
     /// CommandList's renderpass type.
@@ -422,6 +592,17 @@ pub trait CommandList {
         num_bytes: u64,
     );
 
+    /// Records a command to copy pixels from a buffer into an image.
+    ///
+    /// The image must be in the [`TransferDestination`](ResourceState::TransferDestination) state.
+    ///
+    /// # Parameters
+    ///
+    /// * `source_buffer` - The buffer to read pixels from.
+    /// * `destination_image` - The image to write pixels to.
+    /// * `region` - Where the pixels are in the buffer, and which part of the image they go to.
+    fn copy_buffer_to_image(source_buffer: Self::Buffer, destination_image: Self::Image, region: BufferImageCopy);
+
     /// Records a command to execute the provided command lists.
     ///
     /// # Parameters
@@ -447,6 +628,25 @@ pub trait CommandList {
     /// * `pipeline` - The pipeline to bind.
     fn bind_pipeline(pipeline: Self::Pipeline);
 
+    /// Records a command to set the viewport for subsequent draws.
+    ///
+    /// Pipelines don't bake in a viewport, so this must be recorded after beginning a renderpass and before the first
+    /// draw. This lets the same pipelines be used after the window has been resized.
+    ///
+    /// # Parameters
+    ///
+    /// * `viewport` - The region of the framebuffer to render to.
+    fn set_viewport(viewport: Viewport);
+
+    /// Records a command to set the scissor rectangle for subsequent draws.
+    ///
+    /// Like the viewport, the scissor rectangle is dynamic state and must be recorded before the first draw.
+    ///
+    /// # Parameters
+    ///
+    /// * `scissor` - Fragments outside of this rectangle are discarded.
+    fn set_scissor(scissor: Rect2D);
+
     /// Records a command to bind DescriptorSet to a PipelineInterface.
     ///
     /// # Parameters
@@ -5,6 +5,7 @@ pub struct VulkanGraphicsApi {}
 
 // impl GraphicsApi for VulkanGraphicsApi {
 //    type PhysicalDevice = VulkanPhysicalDevice;
+//    const DESCRIPTOR_BINDING_MODEL: DescriptorBindingModel = DescriptorBindingModel::CombinedSamplers;
 //
 //    fn get_adapters() -> Vec<VulkanPhysicalDevice> {
 //        unimplemented!()
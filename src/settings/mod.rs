@@ -4,3 +4,45 @@
 //! possibly by reading from an on-disk configuration file or asking the end user for settings. The settings are then
 //! used throughout Nova for various purposes. While most of these settings will be pretty technical and only useful to
 //! the application developer, a few of these, such as the API to use, will likely be more interesting for the end user.
+
+/// Settings which Nova gets from the application using it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Which graphics adapter to render with.
+    pub adapter_selection: AdapterSelection,
+
+    /// Largest width or height, in pixels, of any screen-relative texture the render graph creates.
+    ///
+    /// Screen-relative textures which would be larger at the current screen size are scaled down to fit, keeping their
+    /// aspect ratio. This keeps very high resolutions from exhausting the GPU's memory.
+    pub max_texture_dimension: u32,
+}
+
+impl Settings {
+    /// Default for [`max_texture_dimension`](Settings::max_texture_dimension). The largest size every desktop GPU
+    /// supports.
+    pub const DEFAULT_MAX_TEXTURE_DIMENSION: u32 = 16384;
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            adapter_selection: AdapterSelection::Auto,
+            max_texture_dimension: Self::DEFAULT_MAX_TEXTURE_DIMENSION,
+        }
+    }
+}
+
+/// How Nova picks the graphics adapter to render with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterSelection {
+    /// Let Nova pick a suitable adapter.
+    Auto,
+
+    /// Use the adapter at this position in the list of adapters, as returned by
+    /// [`GraphicsApi::enumerate_adapters`](crate::rhi::GraphicsApi::enumerate_adapters).
+    Index(usize),
+
+    /// Use the adapter with exactly this name.
+    Name(String),
+}
@@ -0,0 +1,270 @@
+//! Keeping the SPIR-V compiled from shader sources on disk, so unchanged shaders don't have to be compiled again.
+
+use crate::shaderpack::CompiledShader;
+use log::warn;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Everything the SPIR-V compiled from a shader depends on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderCompileInput {
+    /// Name and version of the compiler, such as `shaderc 2019.0`. Upgrading the compiler can change its output, so
+    /// modules compiled by another version aren't used.
+    pub compiler: String,
+
+    /// Name and value of every compiler option which changes the output, such as the target environment and the
+    /// optimization level.
+    pub options: Vec<(String, String)>,
+
+    /// Filename of the shader, relative to the shaderpack root. The stage is worked out from the extension.
+    pub filename: PathBuf,
+
+    /// Source code of the shader.
+    pub source: String,
+
+    /// Filename and contents of every file the shader includes, in the order they're included.
+    pub includes: Vec<(PathBuf, String)>,
+
+    /// Name and value of every preprocessor define the shader is compiled with, including specialization constants
+    /// which are baked in as defines.
+    pub defines: Vec<(String, String)>,
+}
+
+impl ShaderCompileInput {
+    /// Hashes all the inputs. Shaders with the same key compile to the same SPIR-V.
+    ///
+    /// The key is stored on disk, so it's a 128-bit FNV-1a hash, which stays the same across Rust releases and
+    /// platforms, unlike the hashers in `std`.
+    pub fn cache_key(&self) -> u128 {
+        let mut hasher = StableHasher::new();
+        hasher.write_str(&self.compiler);
+        hasher.write_pairs(&self.options);
+        hasher.write_str(&self.filename.to_string_lossy());
+        hasher.write_str(&self.source);
+        hasher.write_len(self.includes.len());
+        for (filename, contents) in &self.includes {
+            hasher.write_str(&filename.to_string_lossy());
+            hasher.write_str(contents);
+        }
+        hasher.write_pairs(&self.defines);
+        hasher.0
+    }
+}
+
+/// 128-bit FNV-1a hasher. Strings and lists are prefixed with their length, so moving text from one input to the next
+/// changes the hash.
+struct StableHasher(u128);
+
+impl StableHasher {
+    const OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    const PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u128::from(byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_len(&mut self, len: usize) {
+        self.write(&(len as u64).to_le_bytes());
+    }
+
+    fn write_str(&mut self, string: &str) {
+        self.write_len(string.len());
+        self.write(string.as_bytes());
+    }
+
+    fn write_pairs(&mut self, pairs: &[(String, String)]) {
+        self.write_len(pairs.len());
+        for (name, value) in pairs {
+            self.write_str(name);
+            self.write_str(value);
+        }
+    }
+}
+
+/// A directory of SPIR-V modules, named after the [key](ShaderCompileInput::cache_key) of the inputs they were
+/// compiled from.
+///
+/// Editing a shader, one of its includes or its defines, or changing the compiler, changes its key, so the stale module
+/// is never used again. Stale modules stay in the directory until it's cleared.
+///
+/// Each module starts with the key it was cached under, which is checked when it's read, so a file which was renamed or
+/// copied over never gets used for the wrong inputs.
+#[derive(Debug, Clone)]
+pub struct ShaderCompileCache {
+    directory: PathBuf,
+}
+
+impl ShaderCompileCache {
+    /// Extension of the cached modules.
+    const EXTENSION: &'static str = "spv";
+
+    /// Creates a cache in the given directory. The directory is created when the first module is cached.
+    pub fn new<P>(directory: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// The directory the modules are cached in.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Gets the SPIR-V compiled from the given inputs, if it's cached.
+    ///
+    /// Modules which can't be read, were cached for other inputs or aren't valid SPIR-V are treated as not cached.
+    pub fn get(&self, input: &ShaderCompileInput) -> Option<Vec<u32>> {
+        let key = input.cache_key();
+        let path = self.module_path(key);
+        let bytes = fs::read(&path).ok()?;
+        if bytes.len() < KEY_SIZE || bytes[..KEY_SIZE] != key.to_le_bytes() {
+            warn!("Ignoring cached shader {:?}, which was cached for other inputs", path);
+            return None;
+        }
+        match CompiledShader::from_bytes(path, &bytes[KEY_SIZE..]) {
+            Ok(shader) => Some(shader.module.words().to_vec()),
+            Err(err) => {
+                warn!("Ignoring corrupt cached shader: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Caches the SPIR-V compiled from the given inputs.
+    pub fn insert(&self, input: &ShaderCompileInput, spirv: &[u32]) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        let key = input.cache_key();
+        let mut bytes = key.to_le_bytes().to_vec();
+        bytes.extend(spirv.iter().flat_map(|word| word.to_le_bytes().to_vec()));
+        fs::write(self.module_path(key), bytes)
+    }
+
+    /// Gets the SPIR-V for a shader from the cache, only calling `compile` if it isn't cached yet.
+    ///
+    /// Newly compiled SPIR-V is added to the cache. Failing to write the cache only costs a compile next time, so it's
+    /// logged instead of failing.
+    ///
+    /// # Parameters
+    ///
+    /// * `input` - Everything the SPIR-V depends on.
+    /// * `compile` - Compiles the inputs to SPIR-V.
+    pub fn get_or_compile<F, E>(&self, input: &ShaderCompileInput, compile: F) -> Result<Vec<u32>, E>
+    where
+        F: FnOnce(&ShaderCompileInput) -> Result<Vec<u32>, E>,
+    {
+        if let Some(spirv) = self.get(input) {
+            return Ok(spirv);
+        }
+
+        let spirv = compile(input)?;
+        if let Err(err) = self.insert(input, &spirv) {
+            warn!("Failed to cache compiled shader {:?}: {}", input.filename, err);
+        }
+        Ok(spirv)
+    }
+
+    fn module_path(&self, key: u128) -> PathBuf {
+        self.directory
+            .join(format!("{:032x}", key))
+            .with_extension(Self::EXTENSION)
+    }
+}
+
+/// Size of the key at the start of each cached module, in bytes.
+const KEY_SIZE: usize = 16;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+    use std::cell::Cell;
+
+    #[test]
+    fn unchanged_shaders_hit_the_cache() {
+        let directory = TempDir::new("shader-compile-cache");
+        let cache = ShaderCompileCache::new(directory.path());
+
+        let compiles = Cell::new(0);
+        let compile = |_: &ShaderCompileInput| -> Result<Vec<u32>, ()> {
+            compiles.set(compiles.get() + 1);
+            Ok(vec![CompiledShader::SPIRV_MAGIC, 0x0001_0000, 0, compiles.get(), 0])
+        };
+        let mut input = ShaderCompileInput {
+            compiler: String::from("shaderc 2019.0"),
+            options: vec![(String::from("target-env"), String::from("vulkan1.1"))],
+            filename: "shaders/gbuffer.frag".into(),
+            source: String::from("#include \"common.glsl\"\nvoid main() {}"),
+            includes: vec![("shaders/common.glsl".into(), String::from("#define PI 3.14"))],
+            defines: Vec::new(),
+        };
+
+        let first = cache.get_or_compile(&input, compile).unwrap();
+        let second = cache.get_or_compile(&input, compile).unwrap();
+        assert_eq!(compiles.get(), 1);
+        assert_eq!(first, second);
+
+        // Any change to the inputs needs a new compile
+        input.includes[0].1 = String::from("#define PI 3.1416");
+        cache.get_or_compile(&input, compile).unwrap();
+        assert_eq!(compiles.get(), 2);
+        input.defines.push((String::from("SHADOWS"), String::from("1")));
+        cache.get_or_compile(&input, compile).unwrap();
+        cache.get_or_compile(&input, compile).unwrap();
+        assert_eq!(compiles.get(), 3);
+
+        // So does a new compiler or different options
+        input.compiler = String::from("shaderc 2019.1");
+        cache.get_or_compile(&input, compile).unwrap();
+        assert_eq!(compiles.get(), 4);
+        input
+            .options
+            .push((String::from("optimization"), String::from("performance")));
+        cache.get_or_compile(&input, compile).unwrap();
+        assert_eq!(compiles.get(), 5);
+
+        // Corrupt modules are compiled again
+        let path = cache.module_path(input.cache_key());
+        fs::write(&path, [1_u8, 2, 3]).unwrap();
+        assert_eq!(cache.get(&input), None);
+        cache.get_or_compile(&input, compile).unwrap();
+        assert_eq!(compiles.get(), 6);
+
+        // A module cached for other inputs isn't used, even if it ends up under this input's name
+        let mut other = input.clone();
+        other.source.push_str("\n// edited");
+        cache.insert(&other, &first).unwrap();
+        fs::rename(cache.module_path(other.cache_key()), &path).unwrap();
+        assert_eq!(cache.get(&input), None);
+    }
+
+    #[test]
+    fn cache_key_is_stable() {
+        // The key names files on disk, so it must never change for the same inputs
+        let input = ShaderCompileInput {
+            compiler: String::from("shaderc 2019.0"),
+            filename: "shaders/gui.vert".into(),
+            source: String::from("void main() {}"),
+            ..ShaderCompileInput::default()
+        };
+        assert_eq!(input.cache_key(), 0x12b3_af5c_398b_a913_a9ad_dccc_874a_b8ee);
+
+        // Text moved from one input to the next is a different input
+        let moved = ShaderCompileInput {
+            compiler: String::from("shaderc 2019."),
+            options: vec![(String::from("0"), String::new())],
+            ..input.clone()
+        };
+        assert_ne!(moved.cache_key(), input.cache_key());
+    }
+}
@@ -0,0 +1,467 @@
+//! The tags [geometry filters](MaterialData::geometry_filter) are built from, and matching draws against them.
+//!
+//! A geometry filter combines tags with `AND`, `OR`, `NOT` and parentheses, such as
+//! `geometry_type::block AND not_transparent`. `NOT` binds tightest and `OR` loosest. Each tag is matched against the
+//! [`GeometryTags`] of a draw command:
+//!
+//! - `geometry_type::<type>` matches draws whose [`GeometryType`] has that [filter tag](GeometryType::filter_tag).
+//! - `transparent` and `not_transparent` match on [`GeometryTags::transparent`].
+//! - Any other tag must have been [registered](GeometryTagRegistry::register) by the host app, and matches draws with
+//!   the tag's bit set in [`GeometryTags::custom`].
+
+use crate::mesh::{GeometryTags, GeometryType};
+use crate::shaderpack::{MaterialData, ShaderpackLoadingFailure};
+use failure::Fail;
+
+/// Tags Nova puts on the geometry it renders. These don't need to be registered.
+pub const BUILTIN_GEOMETRY_TAGS: &[&str] = &[
+    "geometry_type::block",
+    "geometry_type::entity",
+    "geometry_type::particle",
+    "geometry_type::sky_object",
+    "geometry_type::gui",
+    "geometry_type::gui_background",
+    "geometry_type::text",
+    "geometry_type::fullscreen_quad",
+    "transparent",
+    "not_transparent",
+];
+
+/// Words which combine the tags of a geometry filter, rather than being tags themselves.
+const FILTER_OPERATORS: &[&str] = &["AND", "OR", "NOT"];
+
+/// The tags a geometry filter may use.
+///
+/// Starts out with Nova's [built in tags](BUILTIN_GEOMETRY_TAGS). Host apps which tag their own geometry register
+/// their tags on top of those, so that shaderpacks can filter on them. Each custom tag gets one of the bits of
+/// [`GeometryTags::custom`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeometryTagRegistry {
+    custom: Vec<String>,
+}
+
+impl GeometryTagRegistry {
+    /// Number of custom tags that can be registered.
+    pub const MAX_CUSTOM_TAGS: usize = 64;
+
+    /// Adds a custom tag, such as `geometry_type::hologram`, returning the bit of [`GeometryTags::custom`] which draws
+    /// with the tag must set.
+    ///
+    /// Registering a tag again returns the same bit. Returns `None` if [`MAX_CUSTOM_TAGS`](Self::MAX_CUSTOM_TAGS) tags
+    /// are already registered.
+    pub fn register<S>(&mut self, tag: S) -> Option<u32>
+    where
+        S: Into<String>,
+    {
+        let tag = tag.into();
+        if let Some(bit) = self.custom_bit(&tag) {
+            return Some(bit);
+        }
+        if self.custom.len() == Self::MAX_CUSTOM_TAGS {
+            return None;
+        }
+        self.custom.push(tag);
+        Some(self.custom.len() as u32 - 1)
+    }
+
+    /// The bit of [`GeometryTags::custom`] for a registered custom tag.
+    pub fn custom_bit(&self, tag: &str) -> Option<u32> {
+        self.custom
+            .iter()
+            .position(|custom| custom == tag)
+            .map(|bit| bit as u32)
+    }
+
+    /// Whether a geometry filter may use the given tag.
+    pub fn contains(&self, tag: &str) -> bool {
+        BUILTIN_GEOMETRY_TAGS.contains(&tag) || self.custom_bit(tag).is_some()
+    }
+
+    /// Finds the first tag in a geometry filter which isn't known, if there is one.
+    pub fn find_unknown<'a>(&self, filter: &'a GeometryFilter) -> Option<&'a str> {
+        filter.tags().into_iter().find(|tag| !self.contains(tag))
+    }
+}
+
+/// Failure type for parsing a geometry filter.
+#[derive(Fail, Debug, Clone, Eq, PartialEq)]
+pub enum GeometryFilterError {
+    /// The filter ends where a tag was expected, such as right after an `AND`, or it is empty.
+    #[fail(display = "filter ends where a tag was expected")]
+    UnexpectedEnd,
+
+    /// A word or parenthesis is somewhere it can't be, such as a tag right after another tag without an `AND` or `OR`
+    /// in between, or a `)` which closes nothing.
+    #[fail(display = "unexpected {:?}", token)]
+    UnexpectedToken {
+        /// The misplaced word or parenthesis
+        token: String,
+    },
+
+    /// A `(` is never closed.
+    #[fail(display = "missing closing parenthesis")]
+    UnclosedParenthesis,
+
+    /// Parentheses and `NOT`s are nested deeper than [`MAX_NESTING`](GeometryFilter::MAX_NESTING).
+    #[fail(display = "nested deeper than {} levels", limit)]
+    TooDeeplyNested {
+        /// The deepest nesting allowed.
+        limit: usize,
+    },
+}
+
+/// A parsed geometry filter, such as `geometry_type::block AND not_transparent`.
+///
+/// Parsing checks the syntax of the whole filter, so a malformed filter is caught once when its shaderpack loads,
+/// rather than matching arbitrary draws. Materials are matched against many draws, so their filter should be parsed
+/// once and kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeometryFilter {
+    /// Matches draws which have the tag.
+    Tag(String),
+
+    /// Matches draws the inner filter doesn't match.
+    Not(Box<GeometryFilter>),
+
+    /// Matches draws every one of the inner filters matches.
+    All(Vec<GeometryFilter>),
+
+    /// Matches draws any of the inner filters matches.
+    Any(Vec<GeometryFilter>),
+}
+
+impl GeometryFilter {
+    /// How deeply parentheses and `NOT`s may be nested. Filters come from untrusted shaderpacks, and both parsing and
+    /// matching recurse once per level.
+    pub const MAX_NESTING: usize = 64;
+
+    /// Parses a geometry filter. Every word and parenthesis of the filter has to be part of the expression.
+    pub fn parse(filter: &str) -> Result<Self, GeometryFilterError> {
+        let spaced = filter.replace('(', " ( ").replace(')', " ) ");
+        let mut parser = FilterParser {
+            tokens: spaced.split_whitespace().collect(),
+            position: 0,
+            depth: 0,
+        };
+        let parsed = parser.any()?;
+        match parser.peek() {
+            Some(token) => Err(GeometryFilterError::UnexpectedToken {
+                token: String::from(token),
+            }),
+            None => Ok(parsed),
+        }
+    }
+
+    /// Whether a draw with the given tags passes the filter.
+    ///
+    /// Unknown tags match nothing. Filters of loaded shaderpacks have been checked by [`validate_geometry_filters`],
+    /// so this only happens for filters which didn't come from a shaderpack.
+    pub fn matches(&self, tags: &GeometryTags, registry: &GeometryTagRegistry) -> bool {
+        match self {
+            GeometryFilter::Tag(tag) => has_tag(tag, tags, registry),
+            GeometryFilter::Not(filter) => !filter.matches(tags, registry),
+            GeometryFilter::All(filters) => filters.iter().all(|filter| filter.matches(tags, registry)),
+            GeometryFilter::Any(filters) => filters.iter().any(|filter| filter.matches(tags, registry)),
+        }
+    }
+
+    /// The tags the filter uses, in the order they appear in it.
+    pub fn tags(&self) -> Vec<&str> {
+        let mut tags = Vec::new();
+        self.collect_tags(&mut tags);
+        tags
+    }
+
+    fn collect_tags<'a>(&'a self, tags: &mut Vec<&'a str>) {
+        match self {
+            GeometryFilter::Tag(tag) => tags.push(tag),
+            GeometryFilter::Not(filter) => filter.collect_tags(tags),
+            GeometryFilter::All(filters) | GeometryFilter::Any(filters) => {
+                for filter in filters {
+                    filter.collect_tags(tags);
+                }
+            }
+        }
+    }
+}
+
+/// Whether a draw with the given tags has a single tag of a geometry filter.
+fn has_tag(tag: &str, tags: &GeometryTags, registry: &GeometryTagRegistry) -> bool {
+    match tag {
+        "transparent" => tags.transparent,
+        "not_transparent" => !tags.transparent,
+        _ => match GeometryType::from_filter_tag(tag) {
+            Some(geometry_type) => geometry_type == tags.geometry_type,
+            None => registry.custom_bit(tag).map_or(false, |bit| tags.has_custom(bit)),
+        },
+    }
+}
+
+/// Recursive descent parser for a tokenized geometry filter.
+struct FilterParser<'a> {
+    tokens: Vec<&'a str>,
+    position: usize,
+    /// Number of parentheses and `NOT`s the parser is inside of.
+    depth: usize,
+}
+
+impl<'a> FilterParser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    /// Terms joined by `OR`.
+    fn any(&mut self) -> Result<GeometryFilter, GeometryFilterError> {
+        let mut terms = vec![self.all()?];
+        while self.peek() == Some("OR") {
+            self.position += 1;
+            terms.push(self.all()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            GeometryFilter::Any(terms)
+        })
+    }
+
+    /// Terms joined by `AND`.
+    fn all(&mut self) -> Result<GeometryFilter, GeometryFilterError> {
+        let mut terms = vec![self.single()?];
+        while self.peek() == Some("AND") {
+            self.position += 1;
+            terms.push(self.single()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            GeometryFilter::All(terms)
+        })
+    }
+
+    /// Goes one level deeper into the filter, failing if that's too deep.
+    fn nest<F>(&mut self, parse: F) -> Result<GeometryFilter, GeometryFilterError>
+    where
+        F: FnOnce(&mut Self) -> Result<GeometryFilter, GeometryFilterError>,
+    {
+        if self.depth == GeometryFilter::MAX_NESTING {
+            return Err(GeometryFilterError::TooDeeplyNested {
+                limit: GeometryFilter::MAX_NESTING,
+            });
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// A tag, a negated term or a term in parentheses.
+    fn single(&mut self) -> Result<GeometryFilter, GeometryFilterError> {
+        match self.next() {
+            Some("NOT") => Ok(GeometryFilter::Not(Box::new(self.nest(Self::single)?))),
+            Some("(") => {
+                let filter = self.nest(Self::any)?;
+                match self.next() {
+                    Some(")") => Ok(filter),
+                    Some(token) => Err(GeometryFilterError::UnexpectedToken {
+                        token: String::from(token),
+                    }),
+                    None => Err(GeometryFilterError::UnclosedParenthesis),
+                }
+            }
+            Some(token) if token == ")" || FILTER_OPERATORS.contains(&token) => {
+                Err(GeometryFilterError::UnexpectedToken {
+                    token: String::from(token),
+                })
+            }
+            Some(tag) => Ok(GeometryFilter::Tag(String::from(tag))),
+            None => Err(GeometryFilterError::UnexpectedEnd),
+        }
+    }
+}
+
+/// Makes sure the geometry filter of each material parses and only uses known tags. A misspelled tag would otherwise
+/// match no geometry, so the material would silently never be drawn.
+pub fn validate_geometry_filters(
+    materials: &[MaterialData],
+    registry: &GeometryTagRegistry,
+) -> Result<(), ShaderpackLoadingFailure> {
+    for material in materials {
+        let filter = GeometryFilter::parse(&material.geometry_filter).map_err(|sub_error| {
+            ShaderpackLoadingFailure::InvalidGeometryFilter {
+                material: material.name.clone(),
+                filter: material.geometry_filter.clone(),
+                sub_error,
+            }
+        })?;
+        if let Some(tag) = registry.find_unknown(&filter) {
+            return Err(ShaderpackLoadingFailure::UnknownGeometryTag {
+                material: material.name.clone(),
+                tag: String::from(tag),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use matches::matches;
+
+    fn material(name: &str, filter: &str) -> MaterialData {
+        MaterialData {
+            name: String::from(name),
+            passes: Vec::new(),
+            geometry_filter: String::from(filter),
+        }
+    }
+
+    #[test]
+    fn default_pack_filters_are_known() {
+        // The filters of the default shaderpack's materials
+        let mut materials = vec![
+            material("final", "geometry_type::fullscreen_quad"),
+            material("gbuffers_terrain", "geometry_type::block AND not_transparent"),
+            material("gui", "geometry_type::gui"),
+            material("gui_background", "geometry_type::gui_background"),
+            material("text", "geometry_type::text"),
+        ];
+        let mut registry = GeometryTagRegistry::default();
+        assert!(validate_geometry_filters(&materials, &registry).is_ok());
+
+        materials.push(material(
+            "typo",
+            "(geomtery_type::block OR geometry_type::entity) AND NOT transparent",
+        ));
+        assert!(matches!(
+            validate_geometry_filters(&materials, &registry),
+            Err(ShaderpackLoadingFailure::UnknownGeometryTag { ref material, ref tag })
+                if material == "typo" && tag == "geomtery_type::block"
+        ));
+
+        // Host apps can make their own tags valid
+        materials.pop();
+        materials.push(material("hologram", "geometry_type::hologram OR dimension::end"));
+        assert!(validate_geometry_filters(&materials, &registry).is_err());
+        assert_eq!(registry.register("geometry_type::hologram"), Some(0));
+        assert_eq!(registry.register("dimension::end"), Some(1));
+        assert_eq!(registry.register("geometry_type::hologram"), Some(0));
+        assert!(validate_geometry_filters(&materials, &registry).is_ok());
+    }
+
+    #[test]
+    fn filters_match_draw_tags() {
+        let mut registry = GeometryTagRegistry::default();
+        let hologram = registry.register("geometry_type::hologram").unwrap();
+        let block = GeometryTags::new(GeometryType::Block);
+        let glass = GeometryTags {
+            transparent: true,
+            ..block
+        };
+        let projected = GeometryTags::new(GeometryType::Entity).with_custom(hologram);
+
+        for geometry_type in GeometryType::ALL.iter() {
+            assert!(registry.contains(geometry_type.filter_tag()));
+        }
+
+        let matches = |filter: &str, tags: &GeometryTags| {
+            GeometryFilter::parse(filter)
+                .expect("Failed to parse geometry filter")
+                .matches(tags, &registry)
+        };
+        assert!(matches("geometry_type::block", &block));
+        assert!(matches("geometry_type::block AND not_transparent", &block));
+        assert!(!matches("geometry_type::block AND not_transparent", &glass));
+        assert!(!matches("geometry_type::gui", &block));
+        assert!(matches(
+            "geometry_type::gui OR geometry_type::block AND transparent",
+            &glass
+        ));
+        assert!(!matches(
+            "(geometry_type::gui OR geometry_type::block) AND NOT transparent",
+            &glass
+        ));
+        assert!(matches("geometry_type::hologram", &projected));
+        assert!(matches("geometry_type::entity AND geometry_type::hologram", &projected));
+        assert!(!matches("geometry_type::hologram", &block));
+        assert!(!matches("geomtery_type::block", &block));
+    }
+
+    #[test]
+    fn deeply_nested_filters() {
+        let too_deep = Err(GeometryFilterError::TooDeeplyNested {
+            limit: GeometryFilter::MAX_NESTING,
+        });
+
+        let negated = |count: usize| format!("{}transparent", "NOT ".repeat(count));
+        assert!(GeometryFilter::parse(&negated(GeometryFilter::MAX_NESTING)).is_ok());
+        assert_eq!(
+            GeometryFilter::parse(&negated(GeometryFilter::MAX_NESTING + 1)),
+            too_deep
+        );
+
+        let parenthesized = |count: usize| format!("{}transparent{}", "(".repeat(count), ")".repeat(count));
+        assert!(GeometryFilter::parse(&parenthesized(GeometryFilter::MAX_NESTING)).is_ok());
+        assert_eq!(
+            GeometryFilter::parse(&parenthesized(GeometryFilter::MAX_NESTING + 1)),
+            too_deep
+        );
+
+        // Far too deep to recurse into, which has to fail instead of overflowing the stack
+        assert_eq!(GeometryFilter::parse(&"(".repeat(1_000_000)), too_deep);
+        assert_eq!(GeometryFilter::parse(&negated(1_000_000)), too_deep);
+    }
+
+    #[test]
+    fn malformed_filters() {
+        let parse = GeometryFilter::parse;
+        let unexpected = |token: &str| -> Result<GeometryFilter, GeometryFilterError> {
+            Err(GeometryFilterError::UnexpectedToken {
+                token: String::from(token),
+            })
+        };
+        assert_eq!(
+            parse("geometry_type::block not_transparent"),
+            unexpected("not_transparent")
+        );
+        assert_eq!(
+            parse("geometry_type::block AND"),
+            Err(GeometryFilterError::UnexpectedEnd)
+        );
+        assert_eq!(parse("NOT"), Err(GeometryFilterError::UnexpectedEnd));
+        assert_eq!(parse(""), Err(GeometryFilterError::UnexpectedEnd));
+        assert_eq!(parse("AND geometry_type::block"), unexpected("AND"));
+        assert_eq!(parse("geometry_type::block OR OR transparent"), unexpected("OR"));
+        assert_eq!(
+            parse("(geometry_type::block OR geometry_type::entity"),
+            Err(GeometryFilterError::UnclosedParenthesis)
+        );
+        assert_eq!(parse("geometry_type::block)"), unexpected(")"));
+        assert_eq!(parse("(geometry_type::block transparent)"), unexpected("transparent"));
+        assert_eq!(parse("()"), unexpected(")"));
+
+        assert_eq!(
+            parse("(geometry_type::gui OR geometry_type::block) AND NOT transparent"),
+            Ok(GeometryFilter::All(vec![
+                GeometryFilter::Any(vec![
+                    GeometryFilter::Tag(String::from("geometry_type::gui")),
+                    GeometryFilter::Tag(String::from("geometry_type::block")),
+                ]),
+                GeometryFilter::Not(Box::new(GeometryFilter::Tag(String::from("transparent")))),
+            ]))
+        );
+
+        let materials = vec![material("terrain", "geometry_type::block not_transparent")];
+        assert!(matches!(
+            validate_geometry_filters(&materials, &GeometryTagRegistry::default()),
+            Err(ShaderpackLoadingFailure::InvalidGeometryFilter { ref material, .. }) if material == "terrain"
+        ));
+    }
+}
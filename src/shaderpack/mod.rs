@@ -1,21 +1,49 @@
 //! Loaders for user shaderpacks.
 //!
-//! There is currently a single entrypoint: [`load_nova_shaderpack`](shaderpack::load_nova_shaderpack).
-//! Use this function to load a shaderpack from disk.
+//! The main entrypoint is [`load_nova_shaderpack`](shaderpack::load_nova_shaderpack).
+//! Use this function to load a shaderpack from disk. Synchronous code may use
+//! [`load_nova_shaderpack_blocking`](shaderpack::load_nova_shaderpack_blocking) instead. Shaderpacks stored somewhere
+//! Nova can't read on its own can be loaded through a custom [`FileTree`] with
+//! [`load_nova_shaderpack_from_tree`](shaderpack::load_nova_shaderpack_from_tree).
+//!
+//! [`reflect_pipeline_bindings`](shaderpack::reflect_pipeline_bindings) finds the resources a pipeline expects from its
+//! compiled shaders, without needing a GPU. [`ShaderCompileCache`](shaderpack::ShaderCompileCache) keeps SPIR-V
+//! compiled from GLSL on disk between loads. [`GeometryTagRegistry`](shaderpack::GeometryTagRegistry) holds the tags
+//! materials' geometry filters may use, and [`GeometryFilter`](shaderpack::GeometryFilter) parses those filters and
+//! matches draws against them.
+//!
+//! Problems which don't stop a pack from loading are [`ShaderpackWarning`](shaderpack::ShaderpackWarning)s. They are
+//! logged, or returned by [`load_nova_shaderpack_with_warnings`](shaderpack::load_nova_shaderpack_with_warnings).
 //!
 //! TOOD(cwfitzgerald): Unify shaderpack entrypoints.
 
-use crate::loading::{DirectoryFileTree, FileTree, LoadingError};
+use crate::async_utils::NovaExecutor;
+use crate::core::reactor::Priority;
+use crate::loading::{dispatch_archive, ArchiveVisitor, DirectoryFileTree, FileTree, LoadingError};
 use failure::Error;
 use failure::Fail;
-use futures::task::SpawnExt;
+use futures::executor::{ThreadPool, ThreadPoolBuilder};
+use futures::task::{SpawnError, SpawnExt};
+use futures::Future;
+use lazy_static::lazy_static;
+use log::warn;
 use path_dsl::path;
 use std::collections::{HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Mutex, PoisonError};
 
+mod compile_cache;
+mod geometry_filter;
+mod reflection;
 mod structs;
+mod warnings;
+pub use compile_cache::*;
+pub use geometry_filter::*;
+pub use reflection::*;
 pub use structs::*;
+pub use warnings::*;
 
 /// Failure type for shaderpack loading.
 #[derive(Fail, Debug)]
@@ -28,6 +56,10 @@ pub enum ShaderpackLoadingFailure {
     #[fail(display = "Unsupported shaderpack extension {:?}", _0)]
     UnsupportedExtension(String),
 
+    /// A path the loader needs to understand isn't valid UTF-8
+    #[fail(display = "Path is not valid UTF-8: {:?}", _0)]
+    NonUtf8Path(OsString),
+
     /// Required file not found inside shaderpack
     #[fail(display = "File {:?} not found in shaderpack.", _0)]
     MissingFile(OsString),
@@ -50,6 +82,118 @@ pub enum ShaderpackLoadingFailure {
     #[fail(display = "Directory member is a directory not a file {:?}", _0)]
     NotFile(OsString),
 
+    /// A pipeline has an invalid combination of states.
+    #[fail(display = "Pipeline {:?} is invalid: {}", pipeline, reason)]
+    InvalidPipeline {
+        /// Name of the offending pipeline
+        pipeline: String,
+        /// Why the pipeline is invalid
+        reason: String,
+    },
+
+    /// The executor refused to run one of the loading tasks, usually because it is shutting down.
+    #[fail(display = "Executor failed to spawn loading task: {:?}", sub_error)]
+    ExecutorError {
+        /// Actual error
+        #[fail(cause)]
+        sub_error: SpawnError,
+    },
+
+    /// A file of the shaderpack is larger than the [`LoadingLimits`](crate::loading::LoadingLimits) allow, or reading
+    /// it took the whole pack past them.
+    #[fail(
+        display = "Shaderpack file {:?} is too large: {} bytes, limit {} bytes",
+        path, size, limit
+    )]
+    ResourceTooLarge {
+        /// The file which went over the limit
+        path: PathBuf,
+        /// Size of the file, or of the whole pack so far
+        size: u64,
+        /// The limit which was exceeded
+        limit: u64,
+    },
+
+    /// A precompiled shader isn't valid SPIR-V.
+    #[fail(display = "Shader {:?} is not valid SPIR-V: {}", filename, reason)]
+    InvalidSpirv {
+        /// Filename of the offending shader
+        filename: PathBuf,
+        /// Why the shader is invalid
+        reason: String,
+    },
+
+    /// A material doesn't bind a resource the shaders of its pipeline use.
+    #[fail(
+        display = "Material {:?} doesn't bind {:?} in pass {:?}, but its pipeline needs it",
+        material, binding, pass
+    )]
+    MissingBinding {
+        /// Name of the material
+        material: String,
+        /// Name of the material's pass
+        pass: String,
+        /// Name of the missing binding
+        binding: String,
+    },
+
+    /// The resources used by the shaders of a pipeline couldn't be found out.
+    #[fail(display = "Failed to reflect shader bindings: {}", sub_error)]
+    ReflectionFailed {
+        /// Actual error
+        #[fail(cause)]
+        sub_error: ReflectionError,
+    },
+
+    /// The shaders folder has both compiled shaders and shader sources, but a pack can only have one or the other.
+    #[fail(
+        display = "Shaderpack has both compiled shaders, such as {:?}, and shader sources, such as {:?}",
+        compiled, source
+    )]
+    MixedShaderForms {
+        /// One of the compiled shaders
+        compiled: PathBuf,
+        /// One of the shader sources
+        source: PathBuf,
+    },
+
+    /// The shaderpack was written for a newer version of the shaderpack format than this version of Nova understands.
+    #[fail(
+        display = "Shaderpack schema version {} is not supported, the newest supported version is {}",
+        found, supported
+    )]
+    UnsupportedSchemaVersion {
+        /// Schema version the shaderpack asks for
+        found: u32,
+        /// Newest schema version Nova supports
+        supported: u32,
+    },
+
+    /// A material's geometry filter uses a tag which isn't in the [`GeometryTagRegistry`] the pack was loaded with,
+    /// which is usually a typo.
+    #[fail(display = "Material {:?} filters on unknown geometry tag {:?}", material, tag)]
+    UnknownGeometryTag {
+        /// Name of the offending material
+        material: String,
+        /// The unknown tag
+        tag: String,
+    },
+
+    /// A material's geometry filter isn't a valid combination of tags, operators and parentheses.
+    #[fail(
+        display = "Geometry filter {:?} of material {:?} is invalid: {}",
+        filter, material, sub_error
+    )]
+    InvalidGeometryFilter {
+        /// Name of the offending material
+        material: String,
+        /// The geometry filter
+        filter: String,
+        /// What is wrong with the filter
+        #[fail(cause)]
+        sub_error: GeometryFilterError,
+    },
+
     /// An unknown error occurred internally. This is generally a bug.
     #[fail(display = "Unknown internal error: {:?}", sub_error)]
     UnknownError {
@@ -67,26 +211,81 @@ pub enum ShaderpackLoadingFailure {
     },
 }
 
+/// Name of the optional file in the root of a shaderpack which holds the defaults for every pipeline.
+pub const PIPELINE_DEFAULTS_FILE: &str = "pipeline_defaults.json";
+
+/// Name of the optional file in the root of a shaderpack which describes the pack itself. See [`PackInfo`].
+pub const PACK_INFO_FILE: &str = "pack.json";
+
+/// Extension of precompiled SPIR-V shaders in the `shaders` folder of a shaderpack.
+pub const COMPILED_SHADER_EXTENSION: &str = "spv";
+
+/// Newest version of the shaderpack format. Packs without a [`PACK_INFO_FILE`] are assumed to be version 1.
+pub const SHADERPACK_SCHEMA_VERSION: u32 = 1;
+
+/// Upgrades loaded shaderpack data by one schema version. `MIGRATIONS[i]` upgrades a pack from version `i + 1` to
+/// version `i + 2`, so there is always one less migration than [`SHADERPACK_SCHEMA_VERSION`].
+///
+/// When the format changes in a way which breaks old packs, bump [`SHADERPACK_SCHEMA_VERSION`] and add a migration
+/// which converts the data of old packs to the new meaning.
+const MIGRATIONS: [fn(&mut ShaderpackData); SHADERPACK_SCHEMA_VERSION as usize - 1] = [];
+
 /// Load a nova shaderpack from a file or folder.
 ///
 /// File names are currently case sensitive.
 ///
 /// # File Tree
 ///
+/// - `pack.json` (optional)
 /// - `passes.json`
 /// - `resources.json`
+/// - `pipeline_defaults.json` (optional)
 /// - `materials`
 ///   - `*.mat`
 ///   - `*.pipeline`
 /// - `shaders`
 ///   - `*.frag`
 ///   - `*.vert`
+///   - `*.spv`
+///
+/// # Pipeline Inheritance
+///
+/// Fields of a pipeline which aren't set in its `.pipeline` file come from its `parent` pipeline, or from
+/// `pipeline_defaults.json` if it has no parent. The precedence is: pack defaults < parent < the pipeline's own
+/// fields. `pipeline_defaults.json` holds a single object with any of the fields a `.pipeline` file can have.
+///
+/// # Compiled Shaders
+///
+/// Shaders can either all be sources or all be precompiled little endian SPIR-V. The extension decides which: if the
+/// files in `shaders` end in `.spv`, the pack's shaders are loaded as [`ShaderSet::Compiled`] and don't need to be
+/// compiled again. Otherwise they are loaded as [`ShaderSet::Sources`]. Having both fails with
+/// [`ShaderpackLoadingFailure::MixedShaderForms`].
+///
+/// # Material Bindings
+///
+/// Every pass of a material must bind all the resources its pipeline's shaders use. This is only checked for compiled
+/// shaders, see [`validate_material_bindings`].
+///
+/// # Geometry Filters
+///
+/// A material's geometry filter must parse as a [`GeometryFilter`], or the pack fails to load with
+/// [`ShaderpackLoadingFailure::InvalidGeometryFilter`]. Every tag in it must be one of Nova's
+/// [built in tags](BUILTIN_GEOMETRY_TAGS), or the pack fails to load with
+/// [`ShaderpackLoadingFailure::UnknownGeometryTag`]. See [`load_nova_shaderpack_from_tree`] for
+/// loading packs which use tags the host app registered.
+///
+/// # Schema Versions
+///
+/// `pack.json` holds a single object whose `schemaVersion` is the version of the shaderpack format the pack was
+/// written for. Packs for older versions are upgraded to the current [`SHADERPACK_SCHEMA_VERSION`] after loading.
+/// Packs for newer versions fail to load with [`ShaderpackLoadingFailure::UnsupportedSchemaVersion`].
 ///
 /// # File Formats
 ///
 /// While the file tree must be the same, the shaderpacks can either come as an unpacked folder
-/// or as one of the following single-file formats:
-/// - None
+/// or as a single-file archive with one of the extensions in
+/// [`supported_archive_extensions`](crate::loading::supported_archive_extensions). There are currently no archive
+/// backends.
 ///
 /// Future Supported Formats:
 /// - BZIP2/Deflate/Uncompressed `.zip`
@@ -95,37 +294,45 @@ pub enum ShaderpackLoadingFailure {
 ///
 /// # Arguments
 ///
-/// - `executor` - Executor to run sub-tasks on
+/// - `executor` - Executor to run sub-tasks on. See [`NovaExecutor`] for using executors other than the ones from
+///   `futures`. It has to be `Send`, so that the returned future is too and can itself be spawned on a thread pool.
 /// - `path` - Path to the root of the shaderpack, or the file the shaderpack is contained in.
 pub async fn load_nova_shaderpack<E>(executor: E, path: PathBuf) -> Result<ShaderpackData, ShaderpackLoadingFailure>
 where
-    E: SpawnExt + Clone + 'static,
+    E: NovaExecutor + Send,
+{
+    let (data, warnings) = load_nova_shaderpack_with_warnings(executor, path).await?;
+    log_warnings(&warnings);
+    Ok(data)
+}
+
+/// Load a nova shaderpack from a file or folder, along with any [`ShaderpackWarning`]s about it.
+///
+/// The same as [`load_nova_shaderpack`], except that warnings are returned instead of logged, so tools can show pack
+/// authors what might be wrong with a pack that loads fine.
+pub async fn load_nova_shaderpack_with_warnings<E>(
+    executor: E,
+    path: PathBuf,
+) -> Result<(ShaderpackData, Vec<ShaderpackWarning>), ShaderpackLoadingFailure>
+where
+    E: NovaExecutor + Send,
 {
     // This function is a wrapper which properly dispatches to various sub functions
 
     // This should actually really be a if let chain, but that's not in the language yet
-    match (path.exists(), path.is_dir(), path.extension().and_then(OsStr::to_str)) {
+    match (path.exists(), path.is_dir(), path.extension().map(OsStr::to_str)) {
         // Directory
-        (true, true, _) => {
-            // Get the file tree
-            let file_tree_res: Result<DirectoryFileTree, _> = DirectoryFileTree::from_path(&path).await;
-
-            // Map error from the LoadingError type to the ShaderpackLoading Failure type
-            let file_tree = file_tree_res.map_err(|err| match err {
-                LoadingError::ResourceNotFound => ShaderpackLoadingFailure::PathNotFound(path),
-                LoadingError::FileSystemError { sub_error: e } => {
-                    ShaderpackLoadingFailure::FileSystemError { sub_error: e }
-                }
-                e => ShaderpackLoadingFailure::UnknownError { sub_error: e.into() },
-            })?;
-
-            // Actually load the file path
-            load_nova_shaderpack_impl(executor, file_tree).await
+        (true, true, _) => load_nova_shaderpack_from_path::<E, DirectoryFileTree>(executor, path).await,
+        // Single file archive
+        (true, false, Some(Some(ext))) => {
+            let ext = ext.to_owned();
+            match dispatch_archive(&ext, ArchiveLoader { executor, path }) {
+                Some(fut) => fut.await,
+                None => Err(ShaderpackLoadingFailure::UnsupportedExtension(ext)),
+            }
         }
-        // Zip File
-        (true, false, Some("zip")) => unimplemented!(),
-        // File with unknown extant
-        (true, false, Some(ext)) => Err(ShaderpackLoadingFailure::UnsupportedExtension(ext.to_owned())),
+        // File with an extant we can't even read
+        (true, false, Some(None)) => Err(ShaderpackLoadingFailure::NonUtf8Path(path.into_os_string())),
         // File with no extant
         (true, false, None) => Err(ShaderpackLoadingFailure::UnsupportedExtension("<blank>".into())),
         // Path doesn't exist
@@ -133,13 +340,91 @@ where
     }
 }
 
+/// Opens the file tree at the given path and loads the shaderpack inside it.
+async fn load_nova_shaderpack_from_path<E, T>(
+    executor: E,
+    path: PathBuf,
+) -> Result<(ShaderpackData, Vec<ShaderpackWarning>), ShaderpackLoadingFailure>
+where
+    E: NovaExecutor,
+    T: FileTree + Send + Sync + Clone + 'static,
+{
+    // Get the file tree
+    let file_tree_res: Result<T, _> = T::from_path(&path).await;
+
+    // Map error from the LoadingError type to the ShaderpackLoading Failure type
+    let file_tree = file_tree_res.map_err(|err| match err {
+        LoadingError::ResourceNotFound => ShaderpackLoadingFailure::PathNotFound(path),
+        LoadingError::FileSystemError { sub_error: e } => ShaderpackLoadingFailure::FileSystemError { sub_error: e },
+        e => ShaderpackLoadingFailure::UnknownError { sub_error: e.into() },
+    })?;
+
+    // Actually load the file path
+    load_nova_shaderpack_from_tree_with_warnings(executor, file_tree, &GeometryTagRegistry::default()).await
+}
+
+/// Loads a single-file shaderpack with the file tree [`dispatch_archive`] picked for its extension.
+struct ArchiveLoader<E> {
+    executor: E,
+    path: PathBuf,
+}
+
+impl<E> ArchiveVisitor for ArchiveLoader<E>
+where
+    E: NovaExecutor + Send,
+{
+    type Output = Pin<
+        Box<dyn Future<Output = Result<(ShaderpackData, Vec<ShaderpackWarning>), ShaderpackLoadingFailure>> + Send>,
+    >;
+
+    fn visit<T>(self) -> Self::Output
+    where
+        T: FileTree + Send + Sync + Clone + 'static,
+    {
+        Box::pin(load_nova_shaderpack_from_path::<E, T>(self.executor, self.path))
+    }
+}
+
+/// Load a nova shaderpack from a file or folder, blocking until it is loaded.
+///
+/// Convenience wrapper around [`load_nova_shaderpack`] for synchronous callers that don't want to manage their own
+/// executor. The thread pool used for loading is created on first use and shared by every following call, from any
+/// thread.
+pub fn load_nova_shaderpack_blocking(path: PathBuf) -> Result<ShaderpackData, ShaderpackLoadingFailure> {
+    lazy_static! {
+        static ref THREAD_POOL: Mutex<Option<ThreadPool>> = Mutex::new(None);
+    }
+
+    let mut thread_pool = {
+        // The pool is only ever replaced as a whole, so it's fine to use after a panic elsewhere
+        let mut cached = THREAD_POOL.lock().unwrap_or_else(PoisonError::into_inner);
+        match &*cached {
+            Some(thread_pool) => thread_pool.clone(),
+            None => {
+                let thread_pool = ThreadPoolBuilder::new()
+                    .name_prefix("nova_shaderpack_loading")
+                    .create()
+                    .map_err(|err| ShaderpackLoadingFailure::UnknownError { sub_error: err.into() })?;
+                *cached = Some(thread_pool.clone());
+                thread_pool
+            }
+        }
+    };
+
+    let executor = thread_pool.clone();
+    thread_pool.run(load_nova_shaderpack(executor, path))
+}
+
 /// Properly handles launching an async task on a executor and
-/// gives back a RemoteHandle.
+/// gives back a RemoteHandle. Returns early with a [`ShaderpackLoadingFailure::ExecutorError`]
+/// if the executor can't take the task.
 ///
 /// Will get replaced with a proper async macro
 macro_rules! shaderpack_load_invoke {
     ( into: $typ:ty, $exec:expr, $($args:expr),* ) => {
-        $exec.spawn_with_handle(load_json::<$typ, T>($($args),*)).unwrap()
+        $exec
+            .spawn_with_handle(load_json::<$typ, T>($($args),*))
+            .map_err(|sub_error| ShaderpackLoadingFailure::ExecutorError { sub_error })?
     };
 }
 
@@ -155,9 +440,46 @@ macro_rules! await_result_vector {
     }};
 }
 
-async fn load_nova_shaderpack_impl<E, T>(mut executor: E, tree: T) -> Result<ShaderpackData, ShaderpackLoadingFailure>
+/// Load a nova shaderpack out of an already opened [`FileTree`].
+///
+/// This is what [`load_nova_shaderpack`] uses once it has opened the path it was given. Calling it directly allows
+/// loading shaderpacks from sources Nova doesn't ship a backend for, such as an asset database or an encrypted
+/// container, by implementing [`FileTree`] for them. The tree must have the layout described in
+/// [`load_nova_shaderpack`].
+///
+/// [`load_nova_shaderpack`] only accepts Nova's built in geometry tags. Host apps which register their own tags load
+/// shaderpacks that use them through here, with a [`DirectoryFileTree`] for packs on disk.
+///
+/// # Arguments
+///
+/// - `executor` - Executor to run sub-tasks on. See [`NovaExecutor`] for using executors other than the ones from
+///   `futures`.
+/// - `tree` - File tree whose root is the root of the shaderpack.
+/// - `geometry_tags` - Tags the geometry filters of the materials may use.
+pub async fn load_nova_shaderpack_from_tree<E, T>(
+    executor: E,
+    tree: T,
+    geometry_tags: &GeometryTagRegistry,
+) -> Result<ShaderpackData, ShaderpackLoadingFailure>
+where
+    E: NovaExecutor,
+    T: FileTree + Send + Sync + Clone + 'static,
+{
+    let (data, warnings) = load_nova_shaderpack_from_tree_with_warnings(executor, tree, geometry_tags).await?;
+    log_warnings(&warnings);
+    Ok(data)
+}
+
+/// Load a nova shaderpack out of an already opened [`FileTree`], along with any [`ShaderpackWarning`]s about it.
+///
+/// The same as [`load_nova_shaderpack_from_tree`], except that warnings are returned instead of logged.
+pub async fn load_nova_shaderpack_from_tree_with_warnings<E, T>(
+    mut executor: E,
+    tree: T,
+    geometry_tags: &GeometryTagRegistry,
+) -> Result<(ShaderpackData, Vec<ShaderpackWarning>), ShaderpackLoadingFailure>
 where
-    E: SpawnExt + Clone + 'static,
+    E: NovaExecutor,
     T: FileTree + Send + Sync + Clone + 'static,
 {
     // To maximize parallelism in an highly async function, you need to dispatch new tasks as soon as you can,
@@ -170,12 +492,14 @@ where
     // Job Creation //
     // //////////// //
 
-    // Dispatch the job to load the "passes.json" file
+    // Dispatch the job to load the "passes.json" file. It is small and describes the whole pack, so it
+    // gets to skip ahead of the bulk of the file reads.
     let passes_fut = shaderpack_load_invoke!(
         into: Vec<RenderPassCreationInfo>,
         executor,
         tree.clone(),
-        "passes.json".into()
+        "passes.json".into(),
+        Priority::High
     );
 
     // Dispatch the job to load the "resources.json" file
@@ -183,9 +507,36 @@ where
         into: ShaderpackResourceData,
         executor,
         tree.clone(),
-        "resources.json".into()
+        "resources.json".into(),
+        Priority::High
     );
 
+    // The pack info tells us whether the pack can be loaded at all, so it skips ahead as well.
+    let info_fut = if tree.exists(Path::new(PACK_INFO_FILE)) {
+        Some(shaderpack_load_invoke!(
+            into: PackInfo,
+            executor,
+            tree.clone(),
+            PACK_INFO_FILE.into(),
+            Priority::High
+        ))
+    } else {
+        None
+    };
+
+    // The pipeline defaults are needed before any pipeline can be finished, so they also skip ahead.
+    let defaults_fut = if tree.exists(Path::new(PIPELINE_DEFAULTS_FILE)) {
+        Some(shaderpack_load_invoke!(
+            into: JsonObject,
+            executor,
+            tree.clone(),
+            PIPELINE_DEFAULTS_FILE.into(),
+            Priority::High
+        ))
+    } else {
+        None
+    };
+
     // While those operations are going, get a list of files in the materials folder. Because
     // of how the loading system work, the file tree is already populated, so this is a fully
     // synchronous memory operation.
@@ -202,12 +553,20 @@ where
         // Match on the extension
         match ext {
             Some("mat") => {
-                let fut = shaderpack_load_invoke!(into: MaterialData, executor, tree.clone(), full_path);
+                let fut =
+                    shaderpack_load_invoke!(into: MaterialData, executor, tree.clone(), full_path, Priority::Normal);
                 materials_futs.push(fut)
             }
             Some("pipeline") => {
-                let fut = shaderpack_load_invoke!(into: PipelineCreationInfo, executor, tree.clone(), full_path);
-                pipelines_futs.push(fut)
+                // Pipelines can only be parsed once their parents are known, so they are loaded as plain json.
+                let fut = shaderpack_load_invoke!(
+                    into: JsonObject,
+                    executor,
+                    tree.clone(),
+                    full_path.clone(),
+                    Priority::Normal
+                );
+                pipelines_futs.push((full_path, fut))
             }
             // We give no fucks about any other files
             _ => {}
@@ -220,7 +579,18 @@ where
         .map(|path| path!("shaders" | path).into())
         .collect();
 
-    let shader_futs: Vec<_> = shaders_folder.iter().map(|p| tree.read_text(p)).collect();
+    // Only one of these gets any futures, depending on whether the shaders are compiled.
+    let compiled = shaders_are_compiled(&shaders_folder)?;
+    let source_futs: Vec<_> = if compiled {
+        Vec::new()
+    } else {
+        shaders_folder.iter().map(|p| tree.read_text(p)).collect()
+    };
+    let compiled_futs: Vec<_> = if compiled {
+        shaders_folder.iter().map(|p| tree.read(p)).collect()
+    } else {
+        Vec::new()
+    };
     // Generate a mapping from path to an index for all shaders
     // This allows us to load each file only once.
     let shader_mapping: HashMap<&PathBuf, u32> =
@@ -230,32 +600,53 @@ where
     // Job Resolution //
     // ////////////// //
 
+    // Bail out early if the pack is too new for us
+    let info = match info_fut {
+        Some(fut) => fut.await?,
+        None => PackInfo::default(),
+    };
+    check_schema_version(info.schema_version)?;
+
     // Pull all materials files first as we can do something with them
     let mut materials = await_result_vector!(materials_futs);
+    validate_geometry_filters(&materials, geometry_tags)?;
     // We have all the data we need to do the materials postprocess pass
     set_material_pass_material_name(&mut materials);
 
     // Pull all pipelines as we also can do stuff with them immediately
-    let mut pipelines = await_result_vector!(pipelines_futs);
+    let defaults = match defaults_fut {
+        Some(fut) => fut.await?,
+        None => JsonObject::new(),
+    };
+    let mut raw_pipelines = Vec::with_capacity(pipelines_futs.len());
+    for (path, fut) in pipelines_futs {
+        raw_pipelines.push((path, fut.await?));
+    }
+    let mut pipelines = resolve_pipelines(&defaults, raw_pipelines)?;
     pipeline_postprocess(&mut pipelines, &shader_mapping);
+    for pipeline in &pipelines {
+        pipeline.validate()?;
+    }
 
-    let shaders = ShaderSet::Sources({
-        let mut vec = Vec::with_capacity(shader_futs.len());
-
-        // Futures are async, but are the actual handles themselves are in the same order
-        // as the filenames, so can be safely zip together
-        for (fut, filename) in shader_futs.into_iter().zip(shaders_folder.into_iter()) {
+    // Futures are async, but are the actual handles themselves are in the same order
+    // as the filenames, so can be safely zip together
+    let shaders = if compiled {
+        let mut vec = Vec::with_capacity(compiled_futs.len());
+        for (fut, filename) in compiled_futs.into_iter().zip(shaders_folder.into_iter()) {
+            // Await the future and translate the error
+            let bytes = fut.await.map_err(|err| shader_read_error(err, &filename))?;
+            vec.push(CompiledShader::from_bytes(filename, &bytes)?);
+        }
+        ShaderSet::Compiled(vec)
+    } else {
+        let mut vec = Vec::with_capacity(source_futs.len());
+        for (fut, filename) in source_futs.into_iter().zip(shaders_folder.into_iter()) {
             // Await the future and translate the error
-            let source = fut.await.map_err(|err| match err {
-                LoadingError::NotFile => ShaderpackLoadingFailure::NotFile(filename.clone().into_os_string()),
-                LoadingError::FileSystemError { sub_error } => ShaderpackLoadingFailure::FileSystemError { sub_error },
-                LoadingError::PathNotFound => ShaderpackLoadingFailure::MissingFile(filename.clone().into_os_string()),
-                e => ShaderpackLoadingFailure::UnknownError { sub_error: e.into() },
-            })?;
+            let source = fut.await.map_err(|err| shader_read_error(err, &filename))?;
             vec.push(LoadedShader { filename, source });
         }
-        vec
-    });
+        ShaderSet::Sources(vec)
+    };
 
     // These weren't actually needed until right now, so there's no point in
     // awaiting their futures until they are needed.
@@ -266,13 +657,174 @@ where
     // Get the "resources.json" file
     let resources = resources_fut.await?;
 
-    Ok(ShaderpackData {
+    let mut data = ShaderpackData {
         passes,
         resources,
         materials,
         pipelines,
         shaders,
-    })
+    };
+    migrate(&mut data, info.schema_version);
+    data.infer_texture_usages();
+    let mut warnings = validate_material_bindings(&data)?;
+    warnings.extend(find_unused_textures(&data));
+
+    Ok((data, warnings))
+}
+
+/// Logs the warnings of a shaderpack for loaders which don't return them.
+fn log_warnings(warnings: &[ShaderpackWarning]) {
+    for warning in warnings {
+        warn!("Shaderpack warning: {}", warning);
+    }
+}
+
+/// Decides whether the shaders of a pack are [compiled](ShaderSet::Compiled) or [sources](ShaderSet::Sources) from
+/// their extensions. See [`load_nova_shaderpack`] for the rules.
+fn shaders_are_compiled(shaders: &HashSet<PathBuf>) -> Result<bool, ShaderpackLoadingFailure> {
+    let is_compiled = |path: &&PathBuf| path.extension() == Some(OsStr::new(COMPILED_SHADER_EXTENSION));
+    let compiled = shaders.iter().filter(is_compiled).min();
+    let source = shaders.iter().filter(|path| !is_compiled(path)).min();
+    match (compiled, source) {
+        (Some(compiled), Some(source)) => Err(ShaderpackLoadingFailure::MixedShaderForms {
+            compiled: compiled.clone(),
+            source: source.clone(),
+        }),
+        (compiled, _) => Ok(compiled.is_some()),
+    }
+}
+
+/// Translates the error from reading a shader file.
+fn shader_read_error(err: LoadingError, filename: &Path) -> ShaderpackLoadingFailure {
+    match err {
+        LoadingError::NotFile => ShaderpackLoadingFailure::NotFile(filename.as_os_str().to_owned()),
+        LoadingError::FileSystemError { sub_error } => ShaderpackLoadingFailure::FileSystemError { sub_error },
+        LoadingError::PathNotFound => ShaderpackLoadingFailure::MissingFile(filename.as_os_str().to_owned()),
+        LoadingError::ResourceTooLarge {
+            path: file,
+            size,
+            limit,
+        } => ShaderpackLoadingFailure::ResourceTooLarge {
+            path: file,
+            size,
+            limit,
+        },
+        e => ShaderpackLoadingFailure::UnknownError { sub_error: e.into() },
+    }
+}
+
+/// Makes sure there is a path of migrations from the given schema version to the current one.
+fn check_schema_version(version: u32) -> Result<(), ShaderpackLoadingFailure> {
+    if version == 0 || version > SHADERPACK_SCHEMA_VERSION {
+        Err(ShaderpackLoadingFailure::UnsupportedSchemaVersion {
+            found: version,
+            supported: SHADERPACK_SCHEMA_VERSION,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Upgrades a shaderpack written for the given schema version to the current one. The version must have passed
+/// [`check_schema_version`].
+fn migrate(data: &mut ShaderpackData, version: u32) {
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(data);
+    }
+}
+
+/// A json object, as found in `.pipeline` files before they are parsed.
+type JsonObject = serde_json::Map<String, serde_json::Value>;
+
+/// Applies the pack defaults and parent inheritance to the raw json of each pipeline, then parses the result.
+///
+/// See [`load_nova_shaderpack`] for the precedence of each source of fields.
+fn resolve_pipelines(
+    defaults: &JsonObject,
+    pipelines: Vec<(PathBuf, JsonObject)>,
+) -> Result<Vec<PipelineCreationInfo>, ShaderpackLoadingFailure> {
+    let indices: HashMap<&str, usize> = pipelines
+        .iter()
+        .enumerate()
+        .map(|(i, (_, pipeline))| (pipeline_name(pipeline), i))
+        .collect();
+
+    let mut resolved = vec![None; pipelines.len()];
+    let mut merged = Vec::with_capacity(pipelines.len());
+    for index in 0..pipelines.len() {
+        merged.push(resolve_pipeline(
+            index,
+            &pipelines,
+            &indices,
+            defaults,
+            &mut resolved,
+            &mut Vec::new(),
+        )?);
+    }
+
+    pipelines
+        .into_iter()
+        .zip(merged)
+        .map(|((path, _), pipeline)| {
+            let mut pipeline: PipelineCreationInfo = serde_json::from_value(serde_json::Value::Object(pipeline))
+                .map_err(|err| ShaderpackLoadingFailure::JsonError(path.into_os_string(), err))?;
+            pipeline.parent_merged = true;
+            Ok(pipeline)
+        })
+        .collect()
+}
+
+/// Resolves the json of a single pipeline for [`resolve_pipelines`], resolving its parents first.
+///
+/// `resolved` caches the pipelines which are already resolved, and `chain` holds the pipelines currently being
+/// resolved to catch inheritance cycles.
+fn resolve_pipeline(
+    index: usize,
+    pipelines: &[(PathBuf, JsonObject)],
+    indices: &HashMap<&str, usize>,
+    defaults: &JsonObject,
+    resolved: &mut [Option<JsonObject>],
+    chain: &mut Vec<usize>,
+) -> Result<JsonObject, ShaderpackLoadingFailure> {
+    if let Some(pipeline) = &resolved[index] {
+        return Ok(pipeline.clone());
+    }
+
+    let own = &pipelines[index].1;
+    let invalid = |reason: String| ShaderpackLoadingFailure::InvalidPipeline {
+        pipeline: pipeline_name(own).to_owned(),
+        reason,
+    };
+    if chain.contains(&index) {
+        return Err(invalid("pipeline inherits from itself".into()));
+    }
+
+    let mut pipeline = match own.get("parent").and_then(serde_json::Value::as_str) {
+        Some(parent) => {
+            let parent_index = *indices
+                .get(parent)
+                .ok_or_else(|| invalid(format!("parent pipeline {:?} does not exist", parent)))?;
+            chain.push(index);
+            let parent = resolve_pipeline(parent_index, pipelines, indices, defaults, resolved, chain);
+            chain.pop();
+            parent?
+        }
+        None => defaults.clone(),
+    };
+    for (key, value) in own {
+        pipeline.insert(key.clone(), value.clone());
+    }
+
+    resolved[index] = Some(pipeline.clone());
+    Ok(pipeline)
+}
+
+/// Gets the name of a pipeline from its json, or an empty string if it has none.
+fn pipeline_name(pipeline: &JsonObject) -> &str {
+    pipeline
+        .get("name")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default()
 }
 
 /// Each [`MaterialPass`] needs to have it's material name be
@@ -286,21 +838,22 @@ fn set_material_pass_material_name(materials: &mut [MaterialData]) {
     }
 }
 
-/// During loading, a ShaderSource is a path to a shader file. These have been
+/// During loading, a ShaderSource is usually a path to a shader file. These have been
 /// loaded into an array of shader sources. Using the mapping of path to index we generated before,
-/// we not replace the path with a index.
+/// we not replace the path with a index. Inline compiled shaders are already usable and are left alone.
 fn pipeline_postprocess(pipelines: &mut [PipelineCreationInfo], shader_mapping: &HashMap<&PathBuf, u32>) {
     // A helpful closure that processes a single shader. Needs to be a closure
     // because it captures the surrounding arguments.
-    let process_shader = |shader: &mut ShaderSource| {
-        if let ShaderSource::Path(name) = shader {
+    let process_shader = |shader: &mut ShaderSource| match shader {
+        ShaderSource::Path(name) => {
             *shader = match shader_mapping.get(name) {
                 Some(index) => ShaderSource::Loaded(*index),
                 None => ShaderSource::Invalid,
             }
-        } else {
-            panic!("Invalid ShaderSource state. {:?}", shader);
         }
+        // Indices are handed out by the loader, so one written in the pipeline file doesn't refer to anything
+        ShaderSource::Loaded(_) => *shader = ShaderSource::Invalid,
+        ShaderSource::Compiled(_) | ShaderSource::Invalid => {}
     };
 
     // Forwarding wrapper that unwraps an optional shader.
@@ -337,7 +890,7 @@ where
 /// Helper function that loads an json file from the file tree, then uses serde to deserialize it into
 /// R. It then properly deals with that error. The type to deserialize into is through return type deduction,
 /// so to invoke by an executor macro, you need to use superfish.
-async fn load_json<R, T>(tree: T, path: PathBuf) -> Result<R, ShaderpackLoadingFailure>
+async fn load_json<R, T>(tree: T, path: PathBuf, priority: Priority) -> Result<R, ShaderpackLoadingFailure>
 where
     R: serde::de::DeserializeOwned + Send,
     T: FileTree + Send,
@@ -345,13 +898,22 @@ where
     // Load the json file, we need the result immediately before we can proceed, so await it.
     // This isn't launched on the executor because it is not an async function itself, it's
     // a piece of async io.
-    let rp_file_result: Result<Vec<u8>, _> = tree.read(path.as_ref()).await;
+    let rp_file_result: Result<Vec<u8>, _> = tree.read_prioritized(path.as_ref(), priority).await;
 
     // Convert the errors
     let rp_file = rp_file_result.map_err(|err| match err {
         LoadingError::NotFile => ShaderpackLoadingFailure::NotFile(path.clone().into_os_string()),
         LoadingError::FileSystemError { sub_error } => ShaderpackLoadingFailure::FileSystemError { sub_error },
         LoadingError::PathNotFound => ShaderpackLoadingFailure::MissingFile(path.clone().into_os_string()),
+        LoadingError::ResourceTooLarge {
+            path: file,
+            size,
+            limit,
+        } => ShaderpackLoadingFailure::ResourceTooLarge {
+            path: file,
+            size,
+            limit,
+        },
         e => ShaderpackLoadingFailure::UnknownError { sub_error: e.into() },
     })?;
 
@@ -360,3 +922,144 @@ where
     // Map the json error
     parsed.map_err(|err| ShaderpackLoadingFailure::JsonError(path.into_os_string(), err))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use matches::matches;
+
+    fn json_object(json: &str) -> JsonObject {
+        serde_json::from_str(json).expect("Failed to parse json object")
+    }
+
+    fn pipeline(json: &str) -> (PathBuf, JsonObject) {
+        ("test.pipeline".into(), json_object(json))
+    }
+
+    #[test]
+    fn pipeline_defaults_and_inheritance() {
+        let defaults = json_object(r#"{ "srcBlendFactor": "SrcAlpha", "dstBlendFactor": "OneMinusSrcAlpha" }"#);
+        let pipelines = resolve_pipelines(
+            &defaults,
+            vec![
+                pipeline(r#"{ "name": "child", "parent": "parent", "pass": "main", "dstBlendFactor": "One" }"#),
+                pipeline(
+                    r#"{ "name": "parent", "pass": "main", "vertexFields": [], "dstBlendFactor": "Zero", "lineWidth": 2.0 }"#,
+                ),
+                pipeline(r#"{ "name": "other", "pass": "main", "vertexFields": [] }"#),
+            ],
+        )
+        .expect("Failed to resolve pipelines");
+
+        // Everything inherits the pack defaults
+        for pipeline in &pipelines {
+            assert_eq!(pipeline.src_blend_factor, BlendFactor::SrcAlpha);
+        }
+
+        // Own fields win over the parent's, which win over the defaults
+        assert_eq!(pipelines[0].dst_blend_factor, BlendFactor::One);
+        assert_eq!(pipelines[0].line_width, 2.0);
+        assert_eq!(pipelines[0].parent.as_ref().map(String::as_str), Some("parent"));
+        assert_eq!(pipelines[1].dst_blend_factor, BlendFactor::Zero);
+        assert_eq!(pipelines[2].dst_blend_factor, BlendFactor::OneMinusSrcAlpha);
+        assert_eq!(pipelines[2].line_width, 1.0);
+    }
+
+    #[test]
+    fn pipeline_inheritance_errors() {
+        let result = resolve_pipelines(
+            &JsonObject::new(),
+            vec![pipeline(
+                r#"{ "name": "a", "parent": "missing", "pass": "main", "vertexFields": [] }"#,
+            )],
+        );
+        match result {
+            Err(ShaderpackLoadingFailure::InvalidPipeline { pipeline, reason }) => {
+                assert_eq!(pipeline, "a");
+                assert!(reason.contains("missing"));
+            }
+            other => panic!("Expected invalid pipeline, got {:?}", other),
+        }
+
+        let result = resolve_pipelines(
+            &JsonObject::new(),
+            vec![
+                pipeline(r#"{ "name": "a", "parent": "b", "pass": "main", "vertexFields": [] }"#),
+                pipeline(r#"{ "name": "b", "parent": "a", "pass": "main", "vertexFields": [] }"#),
+            ],
+        );
+        assert!(matches!(result, Err(ShaderpackLoadingFailure::InvalidPipeline { .. })));
+    }
+
+    #[test]
+    fn inline_compiled_shader() {
+        let mut pipelines = resolve_pipelines(
+            &JsonObject::new(),
+            vec![pipeline(
+                r#"{
+                    "name": "a",
+                    "pass": "main",
+                    "vertexFields": [],
+                    "vertexShader": [119734787, 65536, 0, 1, 0],
+                    "fragmentShader": "shaders/a.frag",
+                    "geometryShader": 0
+                }"#,
+            )],
+        )
+        .expect("Failed to resolve pipelines");
+        let fragment = PathBuf::from("shaders/a.frag");
+        let shader_mapping = [(&fragment, 0)].iter().cloned().collect();
+
+        pipeline_postprocess(&mut pipelines, &shader_mapping);
+
+        assert_eq!(
+            pipelines[0].vertex_shader,
+            ShaderSource::Compiled(vec![CompiledShader::SPIRV_MAGIC, 0x0001_0000, 0, 1, 0])
+        );
+        assert_eq!(pipelines[0].fragment_shader, Some(ShaderSource::Loaded(0)));
+        assert_eq!(pipelines[0].geometry_shader, Some(ShaderSource::Invalid));
+    }
+
+    #[test]
+    fn schema_versions() {
+        assert!(check_schema_version(SHADERPACK_SCHEMA_VERSION).is_ok());
+        for version in &[0, SHADERPACK_SCHEMA_VERSION + 1] {
+            match check_schema_version(*version) {
+                Err(ShaderpackLoadingFailure::UnsupportedSchemaVersion { found, supported }) => {
+                    assert_eq!(found, *version);
+                    assert_eq!(supported, SHADERPACK_SCHEMA_VERSION);
+                }
+                other => panic!("Expected unsupported schema version, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn flatten_child_and_parent() {
+        let pipelines = resolve_pipelines(
+            &JsonObject::new(),
+            vec![
+                pipeline(r#"{ "name": "parent", "pass": "main", "vertexFields": [], "depthFunc": "Always" }"#),
+                pipeline(r#"{ "name": "child", "parent": "parent", "pass": "main", "polygonMode": "Line" }"#),
+            ],
+        )
+        .expect("Failed to resolve pipelines");
+        let mut data = ShaderpackData {
+            pipelines,
+            passes: Vec::new(),
+            materials: Vec::new(),
+            resources: ShaderpackResourceData {
+                textures: Vec::new(),
+                samplers: Vec::new(),
+            },
+            shaders: ShaderSet::Sources(Vec::new()),
+        };
+
+        data.flatten().expect("Failed to flatten pipelines");
+
+        let child = &data.pipelines[1];
+        assert_eq!(child.parent, None);
+        assert_eq!(child.depth_func, CompareOp::Always);
+        assert_eq!(child.polygon_mode, PolygonMode::Line);
+    }
+}
@@ -0,0 +1,1056 @@
+//! Finding out which resources the shaders of a pipeline use, without a GPU.
+
+use crate::rhi::{DescriptorType, ResourceBindingDescription, ShaderStageFlags};
+use crate::shaderpack::{
+    CompiledShader, PipelineCreationInfo, ShaderSet, ShaderSource, ShaderpackData, ShaderpackLoadingFailure,
+    ShaderpackWarning,
+};
+use failure::Fail;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Failure type for reflecting the bindings of a pipeline.
+#[derive(Fail, Debug, Clone, Eq, PartialEq)]
+pub enum ReflectionError {
+    /// The pipeline's shaders are sources, which have to be compiled to SPIR-V before they can be reflected.
+    #[fail(display = "Shaders of pipeline {:?} are not compiled", pipeline)]
+    NotCompiled {
+        /// Name of the pipeline
+        pipeline: String,
+    },
+
+    /// The pipeline refers to a shader which isn't in the shader set.
+    #[fail(display = "Pipeline {:?} uses shader {}, which doesn't exist", pipeline, index)]
+    MissingShader {
+        /// Name of the pipeline
+        pipeline: String,
+        /// Index of the missing shader
+        index: u32,
+    },
+
+    /// One of the pipeline's shaders isn't a well-formed SPIR-V module.
+    #[fail(display = "A shader of pipeline {:?} is not valid SPIR-V: {}", pipeline, reason)]
+    InvalidSpirv {
+        /// Name of the pipeline
+        pipeline: String,
+        /// What is wrong with the module
+        reason: String,
+    },
+
+    /// Two shaders of the pipeline use the same name for different resources.
+    #[fail(display = "Shaders of pipeline {:?} disagree about resource {:?}", pipeline, name)]
+    ConflictingBinding {
+        /// Name of the pipeline
+        pipeline: String,
+        /// Name of the resource
+        name: String,
+    },
+
+    /// The pipeline's descriptor sets don't count up from 0. Neither API can lay out a pipeline with a set missing in
+    /// between, so shaders have to number their sets without gaps.
+    #[fail(
+        display = "Pipeline {:?} skips descriptor set {}, descriptor sets must be numbered from 0 without gaps",
+        pipeline, set
+    )]
+    SkippedDescriptorSet {
+        /// Name of the pipeline
+        pipeline: String,
+        /// The first set which no resource uses
+        set: u32,
+    },
+}
+
+/// Failure type for reading a SPIR-V module.
+#[derive(Fail, Debug, Clone, Eq, PartialEq)]
+pub enum SpirvError {
+    /// The module doesn't start with a SPIR-V header.
+    #[fail(display = "missing SPIR-V header")]
+    MissingHeader,
+
+    /// An instruction's word count is zero or runs past the end of the module.
+    #[fail(display = "instruction at word {} has a bad length", offset)]
+    BadInstructionLength {
+        /// Position of the instruction in the module, in words
+        offset: usize,
+    },
+
+    /// A variable's type isn't a pointer type.
+    #[fail(display = "type of variable {} is not a pointer", variable)]
+    NotAPointer {
+        /// Id of the variable
+        variable: u32,
+    },
+}
+
+/// A function which a pipeline stage can start executing a module at.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SpirvEntryPoint {
+    /// Name of the function.
+    pub name: String,
+
+    /// The stage the function is written for.
+    pub stage: ShaderStageFlags,
+}
+
+/// A SPIR-V module, together with what reflecting it found.
+///
+/// Reflection walks every instruction of a module, so it's done once when the module is created. Pipeline reflection,
+/// material validation and the backends then all use the results instead of parsing the module again.
+#[derive(Debug, Clone)]
+pub struct SpirvModule {
+    words: Vec<u32>,
+    entry_points: Vec<SpirvEntryPoint>,
+    resources: Vec<(String, ResourceBindingDescription)>,
+    unsupported_resources: Vec<(String, u32)>,
+}
+
+impl SpirvModule {
+    /// Reflects a SPIR-V module.
+    ///
+    /// The module has to be well-formed enough to find its entry points and resources. The instructions are not
+    /// validated any further than that.
+    pub fn new(words: Vec<u32>) -> Result<Self, SpirvError> {
+        reflect_module(words)
+    }
+
+    /// The words of the module, ready to be passed to the graphics API.
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    /// The functions pipeline stages can start executing the module at, in the order the module declares them.
+    pub fn entry_points(&self) -> &[SpirvEntryPoint] {
+        &self.entry_points
+    }
+
+    /// Finds an entry point by name.
+    pub fn entry_point(&self, name: &str) -> Option<&SpirvEntryPoint> {
+        self.entry_points.iter().find(|entry_point| entry_point.name == name)
+    }
+
+    /// The stages of all the module's entry points.
+    pub fn stages(&self) -> ShaderStageFlags {
+        self.entry_points
+            .iter()
+            .fold(ShaderStageFlags::empty(), |stages, entry_point| {
+                stages | entry_point.stage
+            })
+    }
+
+    /// The resources the module binds through descriptors, with their names and bindings.
+    ///
+    /// Resources are named after their variable, or after their block for anonymous uniform blocks. Their stages are
+    /// the stages of all the module's entry points. Resources of types Nova can't bind are left out, see
+    /// [`unsupported_resources`](SpirvModule::unsupported_resources).
+    pub fn resources(&self) -> &[(String, ResourceBindingDescription)] {
+        &self.resources
+    }
+
+    /// Names and descriptor sets of the resources the module binds through descriptors, but which have a type that
+    /// can't be described by a [`DescriptorType`], such as separate images and samplers or storage images.
+    pub fn unsupported_resources(&self) -> &[(String, u32)] {
+        &self.unsupported_resources
+    }
+}
+
+impl PartialEq for SpirvModule {
+    /// Modules are equal if their words are. Everything else is worked out from the words.
+    fn eq(&self, other: &Self) -> bool {
+        self.words == other.words
+    }
+}
+
+/// Finds the resources the shaders of a pipeline expect, by reading their SPIR-V.
+///
+/// This needs no device, so tools can use it to check that a material binds everything a pipeline needs. Resources are
+/// named after their variable in the shader, or after their block for anonymous uniform blocks. A resource used by
+/// several shaders is only returned once, with the stages of all those shaders. Resources of types Nova can't bind yet
+/// are left out, see [`SpirvModule::unsupported_resources`].
+///
+/// The descriptor sets of the bindings are checked to be numbered from 0 without gaps, so backends can create one
+/// layout per set in order.
+///
+/// # Parameters
+///
+/// * `pipeline` - The pipeline to reflect.
+/// * `shaders` - The shaders of the shaderpack the pipeline is from. Only [compiled](ShaderSet::Compiled) shaders can
+///   be reflected. Shaders written inline in the pipeline don't need this.
+pub fn reflect_pipeline_bindings(
+    pipeline: &PipelineCreationInfo,
+    shaders: &ShaderSet,
+) -> Result<HashMap<String, ResourceBindingDescription>, ReflectionError> {
+    reflect_pipeline(pipeline, shaders).map(|(bindings, _)| bindings)
+}
+
+/// Reflects the bindings of a pipeline like [`reflect_pipeline_bindings`], also returning the sorted names of the
+/// resources which were left out because Nova can't bind them.
+fn reflect_pipeline(
+    pipeline: &PipelineCreationInfo,
+    shaders: &ShaderSet,
+) -> Result<(HashMap<String, ResourceBindingDescription>, Vec<String>), ReflectionError> {
+    let stages = [
+        (Some(&pipeline.vertex_shader), ShaderStageFlags::VERTEX),
+        (
+            pipeline.tessellation_control_shader.as_ref(),
+            ShaderStageFlags::TESSELLATION_CONTROL,
+        ),
+        (
+            pipeline.tessellation_evaluation_shader.as_ref(),
+            ShaderStageFlags::TESSELLATION_EVALUATION,
+        ),
+        (pipeline.geometry_shader.as_ref(), ShaderStageFlags::GEOMETRY),
+        (pipeline.fragment_shader.as_ref(), ShaderStageFlags::FRAGMENT),
+    ];
+
+    let mut bindings: HashMap<String, ResourceBindingDescription> = HashMap::new();
+    let mut unsupported: Vec<(String, u32)> = Vec::new();
+    for (shader, stage) in &stages {
+        let module = match shader {
+            Some(shader) => match shader_module(pipeline, shader, shaders)? {
+                Some(module) => module,
+                None => continue,
+            },
+            None => continue,
+        };
+        unsupported.extend(module.unsupported_resources().iter().cloned());
+
+        for (name, binding) in module.resources() {
+            // The module may have entry points for other stages, but only this one is used here
+            let binding = ResourceBindingDescription {
+                stages: *stage,
+                ..binding.clone()
+            };
+            match bindings.get_mut(name) {
+                Some(existing) => {
+                    if existing.set != binding.set
+                        || existing.binding != binding.binding
+                        || existing.count != binding.count
+                        || existing.descriptor_type != binding.descriptor_type
+                    {
+                        return Err(ReflectionError::ConflictingBinding {
+                            pipeline: pipeline.name.clone(),
+                            name: name.clone(),
+                        });
+                    }
+                    existing.stages |= binding.stages;
+                }
+                None => {
+                    bindings.insert(name.clone(), binding);
+                }
+            }
+        }
+    }
+
+    // Resources which are left out still take up their set, so sets after them aren't skipping one
+    let sets = bindings
+        .values()
+        .map(|binding| binding.set)
+        .chain(unsupported.iter().map(|(_, set)| *set));
+    if let Some(set) = first_skipped_set(sets) {
+        return Err(ReflectionError::SkippedDescriptorSet {
+            pipeline: pipeline.name.clone(),
+            set,
+        });
+    }
+
+    let mut unsupported: Vec<String> = unsupported.into_iter().map(|(name, _)| name).collect();
+    unsupported.sort();
+    unsupported.dedup();
+    Ok((bindings, unsupported))
+}
+
+/// Finds the lowest descriptor set which isn't used by any binding, but is below a set which is.
+fn first_skipped_set<I>(sets: I) -> Option<u32>
+where
+    I: Iterator<Item = u32>,
+{
+    let mut sets: Vec<u32> = sets.collect();
+    sets.sort();
+    sets.dedup();
+    sets.iter()
+        .zip(0..)
+        .find(|&(&set, expected)| set != expected)
+        .map(|(_, expected)| expected)
+}
+
+/// Checks that every material pass binds exactly the resources its pipeline's shaders use.
+///
+/// A resource the shaders use but the material doesn't bind fails with
+/// [`ShaderpackLoadingFailure::MissingBinding`]. Bindings the shaders don't use are harmless, so they are returned as
+/// [`ShaderpackWarning::UnusedBinding`]s. Shaders which aren't compiled yet can't be reflected, so passes whose
+/// pipeline has any are skipped.
+///
+/// Resources of types Nova can't bind yet can't be checked either. Each pipeline which uses any gets a
+/// [`ShaderpackWarning::UnsupportedResource`] for them, and materials may bind them without a warning.
+pub fn validate_material_bindings(data: &ShaderpackData) -> Result<Vec<ShaderpackWarning>, ShaderpackLoadingFailure> {
+    let mut warnings = Vec::new();
+    let mut reflected = HashMap::new();
+    for material in &data.materials {
+        for pass in &material.passes {
+            let pipeline = match data.pipelines.iter().find(|pipeline| pipeline.name == pass.pipeline) {
+                Some(pipeline) => pipeline,
+                None => continue,
+            };
+            // Several materials may use the same pipeline, which only has to be reflected and warned about once
+            if !reflected.contains_key(&pipeline.name) {
+                let (required, unsupported) = match reflect_pipeline(pipeline, &data.shaders) {
+                    Ok(resources) => resources,
+                    Err(ReflectionError::NotCompiled { .. }) => continue,
+                    Err(sub_error) => return Err(ShaderpackLoadingFailure::ReflectionFailed { sub_error }),
+                };
+                warnings.extend(unsupported.iter().map(|name| ShaderpackWarning::UnsupportedResource {
+                    pipeline: pipeline.name.clone(),
+                    name: name.clone(),
+                }));
+                reflected.insert(pipeline.name.clone(), (required, unsupported));
+            }
+            let (required, unsupported) = &reflected[&pipeline.name];
+
+            if let Some(binding) = required.keys().filter(|name| !pass.bindings.contains_key(*name)).min() {
+                return Err(ShaderpackLoadingFailure::MissingBinding {
+                    material: material.name.clone(),
+                    pass: pass.name.clone(),
+                    binding: binding.clone(),
+                });
+            }
+            let mut unused: Vec<&String> = pass
+                .bindings
+                .keys()
+                .filter(|name| !required.contains_key(*name) && !unsupported.contains(*name))
+                .collect();
+            unused.sort();
+            warnings.extend(unused.into_iter().map(|name| ShaderpackWarning::UnusedBinding {
+                material: material.name.clone(),
+                pass: pass.name.clone(),
+                binding: name.clone(),
+            }));
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Gets the module of one of a pipeline's shaders, or `None` if the shader doesn't exist.
+///
+/// Modules of the shaderpack's shaders were reflected when they were loaded. Shaders written inline in the pipeline
+/// are reflected here.
+fn shader_module<'a>(
+    pipeline: &PipelineCreationInfo,
+    shader: &'a ShaderSource,
+    shaders: &'a ShaderSet,
+) -> Result<Option<Cow<'a, SpirvModule>>, ReflectionError> {
+    match (shader, shaders) {
+        (ShaderSource::Compiled(spirv), _) => SpirvModule::new(spirv.clone())
+            .map(|module| Some(Cow::Owned(module)))
+            .map_err(|err| ReflectionError::InvalidSpirv {
+                pipeline: pipeline.name.clone(),
+                reason: err.to_string(),
+            }),
+        (ShaderSource::Loaded(index), ShaderSet::Compiled(shaders)) => shaders
+            .get(*index as usize)
+            .map(|shader| Some(Cow::Borrowed(&shader.module)))
+            .ok_or_else(|| ReflectionError::MissingShader {
+                pipeline: pipeline.name.clone(),
+                index: *index,
+            }),
+        (ShaderSource::Loaded(_), ShaderSet::Sources(_)) | (ShaderSource::Path(_), _) => {
+            Err(ReflectionError::NotCompiled {
+                pipeline: pipeline.name.clone(),
+            })
+        }
+        (ShaderSource::Invalid, _) => Ok(None),
+    }
+}
+
+// The parts of the SPIR-V specification needed to find resources
+const SPIRV_HEADER_WORDS: usize = 5;
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+/// The types a resource variable can have, as far as reflection cares.
+enum SpirvType {
+    SampledImage,
+    Struct,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Pointer { pointee: u32 },
+}
+
+/// Reflects a SPIR-V module, finding its entry points and resources.
+fn reflect_module(spirv: Vec<u32>) -> Result<SpirvModule, SpirvError> {
+    if spirv.len() < SPIRV_HEADER_WORDS || spirv[0] != CompiledShader::SPIRV_MAGIC {
+        return Err(SpirvError::MissingHeader);
+    }
+
+    let mut names = HashMap::new();
+    let mut sets = HashMap::new();
+    let mut binding_numbers = HashMap::new();
+    let mut buffer_blocks = HashMap::new();
+    let mut types = HashMap::new();
+    let mut constants = HashMap::new();
+    let mut variables = Vec::new();
+    let mut entry_points = Vec::new();
+
+    let mut offset = SPIRV_HEADER_WORDS;
+    while offset < spirv.len() {
+        let word_count = (spirv[offset] >> 16) as usize;
+        let opcode = spirv[offset] & 0xFFFF;
+        if word_count == 0 || offset + word_count > spirv.len() {
+            return Err(SpirvError::BadInstructionLength { offset });
+        }
+        let operands = &spirv[offset + 1..offset + word_count];
+        offset += word_count;
+
+        match (opcode, operands.len()) {
+            (OP_ENTRY_POINT, len) if len >= 2 => {
+                if let Some(stage) = execution_model_stage(operands[0]) {
+                    entry_points.push(SpirvEntryPoint {
+                        name: decode_string(&operands[2..]),
+                        stage,
+                    });
+                }
+            }
+            (OP_NAME, len) if len >= 1 => {
+                names.insert(operands[0], decode_string(&operands[1..]));
+            }
+            (OP_DECORATE, len) if len >= 2 => match operands[1] {
+                DECORATION_BLOCK => {
+                    buffer_blocks.insert(operands[0], DescriptorType::UniformBuffer);
+                }
+                DECORATION_BUFFER_BLOCK => {
+                    buffer_blocks.insert(operands[0], DescriptorType::StorageBuffer);
+                }
+                DECORATION_BINDING if len >= 3 => {
+                    binding_numbers.insert(operands[0], operands[2]);
+                }
+                DECORATION_DESCRIPTOR_SET if len >= 3 => {
+                    sets.insert(operands[0], operands[2]);
+                }
+                _ => {}
+            },
+            (OP_TYPE_SAMPLED_IMAGE, len) if len >= 1 => {
+                types.insert(operands[0], SpirvType::SampledImage);
+            }
+            (OP_TYPE_STRUCT, len) if len >= 1 => {
+                types.insert(operands[0], SpirvType::Struct);
+            }
+            (OP_TYPE_ARRAY, len) if len >= 3 => {
+                types.insert(
+                    operands[0],
+                    SpirvType::Array {
+                        element: operands[1],
+                        length: operands[2],
+                    },
+                );
+            }
+            (OP_TYPE_RUNTIME_ARRAY, len) if len >= 2 => {
+                types.insert(operands[0], SpirvType::RuntimeArray { element: operands[1] });
+            }
+            (OP_TYPE_POINTER, len) if len >= 3 => {
+                types.insert(operands[0], SpirvType::Pointer { pointee: operands[2] });
+            }
+            // Arrays sized by a specialization constant use its default value, like pipelines which don't specialize it
+            (OP_CONSTANT, len) | (OP_SPEC_CONSTANT, len) if len >= 3 => {
+                constants.insert(operands[1], operands[2]);
+            }
+            (OP_VARIABLE, len) if len >= 3 => {
+                variables.push((operands[1], operands[0], operands[2]));
+            }
+            _ => {}
+        }
+    }
+
+    let stages = entry_points
+        .iter()
+        .fold(ShaderStageFlags::empty(), |stages, entry_point: &SpirvEntryPoint| {
+            stages | entry_point.stage
+        });
+    let mut resources = Vec::new();
+    let mut unsupported_resources = Vec::new();
+    for (variable, pointer_type, storage_class) in variables {
+        let binding = match binding_numbers.get(&variable) {
+            Some(binding) => *binding,
+            // Not a resource, such as a vertex input or a push constant block
+            None => continue,
+        };
+        if !matches_storage_class(storage_class) {
+            continue;
+        }
+
+        let pointee = match types.get(&pointer_type) {
+            Some(SpirvType::Pointer { pointee }) => *pointee,
+            _ => return Err(SpirvError::NotAPointer { variable }),
+        };
+        let (element, count) = match types.get(&pointee) {
+            // Lengths computed from specialization constants aren't known until the pipeline is created, so they get as
+            // many descriptors as a runtime-sized array
+            Some(SpirvType::Array { element, length }) => (
+                *element,
+                ResourceBindingDescription::count_for_array(constants.get(length).cloned()),
+            ),
+            Some(SpirvType::RuntimeArray { element }) => (*element, ResourceBindingDescription::count_for_array(None)),
+            _ => (pointee, 1),
+        };
+
+        let set = sets.get(&variable).cloned().unwrap_or(0);
+
+        // Anonymous uniform blocks only have a name on their type
+        let name = names
+            .get(&variable)
+            .filter(|name: &&String| !name.is_empty())
+            .or_else(|| names.get(&element))
+            .cloned()
+            .unwrap_or_default();
+        let descriptor_type = match (types.get(&element), storage_class) {
+            (Some(SpirvType::SampledImage), STORAGE_CLASS_UNIFORM_CONSTANT) => DescriptorType::CombinedImageSampler,
+            (Some(SpirvType::Struct), STORAGE_CLASS_UNIFORM) => buffer_blocks
+                .get(&element)
+                .cloned()
+                .unwrap_or(DescriptorType::UniformBuffer),
+            (Some(SpirvType::Struct), STORAGE_CLASS_STORAGE_BUFFER) => DescriptorType::StorageBuffer,
+            _ => {
+                unsupported_resources.push((name, set));
+                continue;
+            }
+        };
+
+        resources.push((
+            name,
+            ResourceBindingDescription {
+                set,
+                binding,
+                count,
+                descriptor_type,
+                stages,
+            },
+        ));
+    }
+
+    Ok(SpirvModule {
+        words: spirv,
+        entry_points,
+        resources,
+        unsupported_resources,
+    })
+}
+
+/// Gets the stage of an entry point with the given execution model, or `None` for execution models Nova doesn't use.
+fn execution_model_stage(execution_model: u32) -> Option<ShaderStageFlags> {
+    match execution_model {
+        0 => Some(ShaderStageFlags::VERTEX),
+        1 => Some(ShaderStageFlags::TESSELLATION_CONTROL),
+        2 => Some(ShaderStageFlags::TESSELLATION_EVALUATION),
+        3 => Some(ShaderStageFlags::GEOMETRY),
+        4 => Some(ShaderStageFlags::FRAGMENT),
+        5 => Some(ShaderStageFlags::COMPUTE),
+        5267 => Some(ShaderStageFlags::TASK),
+        5268 => Some(ShaderStageFlags::MESH),
+        5313 => Some(ShaderStageFlags::RAYGEN),
+        5314 => Some(ShaderStageFlags::INTERSECTION),
+        5315 => Some(ShaderStageFlags::ANY_HIT),
+        5316 => Some(ShaderStageFlags::CLOSEST_HIT),
+        5317 => Some(ShaderStageFlags::MISS),
+        _ => None,
+    }
+}
+
+/// Whether variables of the given storage class are bound through descriptors.
+fn matches_storage_class(storage_class: u32) -> bool {
+    storage_class == STORAGE_CLASS_UNIFORM_CONSTANT
+        || storage_class == STORAGE_CLASS_UNIFORM
+        || storage_class == STORAGE_CLASS_STORAGE_BUFFER
+}
+
+/// Decodes a null terminated SPIR-V string literal.
+fn decode_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rhi::{BackendDescriptor, BackendDescriptorType, DescriptorBindingModel};
+    use crate::shaderpack::{LoadedShader, MaterialData, MaterialPass, ShaderpackResourceData};
+    use matches::matches;
+
+    const OP_TYPE_IMAGE: u32 = 25;
+    const OP_TYPE_SAMPLER: u32 = 26;
+    const OP_SPEC_CONSTANT_OP: u32 = 52;
+
+    /// Assembles a SPIR-V module from `(opcode, operands)` pairs.
+    fn assemble(instructions: &[(u32, Vec<u32>)]) -> Vec<u32> {
+        let mut spirv = vec![CompiledShader::SPIRV_MAGIC, 0x0001_0000, 0, 100, 0];
+        for (opcode, operands) in instructions {
+            spirv.push(((operands.len() as u32 + 1) << 16) | opcode);
+            spirv.extend(operands);
+        }
+        spirv
+    }
+
+    /// Encodes a SPIR-V string literal, preceded by `id`.
+    fn named(id: u32, name: &str) -> Vec<u32> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+        let mut operands = vec![id];
+        operands.extend(
+            bytes
+                .chunks(4)
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])),
+        );
+        operands
+    }
+
+    /// Instructions declaring an anonymous `Camera` uniform block at set 0 and the given binding.
+    fn camera_block(binding: u32) -> Vec<(u32, Vec<u32>)> {
+        vec![
+            (OP_NAME, named(10, "Camera")),
+            (OP_NAME, named(12, "")),
+            (OP_DECORATE, vec![10, DECORATION_BLOCK]),
+            (OP_DECORATE, vec![12, DECORATION_DESCRIPTOR_SET, 0]),
+            (OP_DECORATE, vec![12, DECORATION_BINDING, binding]),
+            (OP_TYPE_STRUCT, vec![10, 1]),
+            (OP_TYPE_POINTER, vec![11, STORAGE_CLASS_UNIFORM, 10]),
+            (OP_VARIABLE, vec![11, 12, STORAGE_CLASS_UNIFORM]),
+        ]
+    }
+
+    /// A fragment shader with the camera block and an array of four `textures` at set 1, binding 2.
+    fn fragment_shader() -> Vec<u32> {
+        fragment_shader_with_textures_in_set(1)
+    }
+
+    /// A fragment shader with the camera block and an array of four `textures` at the given set, binding 2.
+    fn fragment_shader_with_textures_in_set(set: u32) -> Vec<u32> {
+        let mut instructions = camera_block(0);
+        instructions.extend(vec![
+            (OP_NAME, named(24, "textures")),
+            (OP_DECORATE, vec![24, DECORATION_DESCRIPTOR_SET, set]),
+            (OP_DECORATE, vec![24, DECORATION_BINDING, 2]),
+            (OP_TYPE_SAMPLED_IMAGE, vec![20, 2]),
+            (OP_CONSTANT, vec![3, 21, 4]),
+            (OP_TYPE_ARRAY, vec![22, 20, 21]),
+            (OP_TYPE_POINTER, vec![23, STORAGE_CLASS_UNIFORM_CONSTANT, 22]),
+            (OP_VARIABLE, vec![23, 24, STORAGE_CLASS_UNIFORM_CONSTANT]),
+            // The output color has a location, not a binding
+            (OP_DECORATE, vec![26, 30, 0]),
+            (OP_TYPE_POINTER, vec![25, 3, 1]),
+            (OP_VARIABLE, vec![25, 26, 3]),
+        ]);
+        assemble(&instructions)
+    }
+
+    fn pipeline(vertex: ShaderSource, fragment: ShaderSource) -> PipelineCreationInfo {
+        let mut pipeline: PipelineCreationInfo =
+            serde_json::from_str(r#"{ "name": "test", "pass": "main", "vertexFields": [] }"#).unwrap();
+        pipeline.vertex_shader = vertex;
+        pipeline.fragment_shader = Some(fragment);
+        pipeline
+    }
+
+    #[test]
+    fn uniform_block_and_sampler() {
+        let shaders = ShaderSet::Compiled(vec![CompiledShader {
+            filename: "shaders/test.vert.spv".into(),
+            module: SpirvModule::new(assemble(&camera_block(0))).unwrap(),
+        }]);
+        let pipeline = pipeline(ShaderSource::Loaded(0), ShaderSource::Compiled(fragment_shader()));
+
+        let bindings = reflect_pipeline_bindings(&pipeline, &shaders).expect("Failed to reflect pipeline");
+        assert_eq!(bindings.len(), 2);
+
+        let camera = &bindings["Camera"];
+        assert_eq!((camera.set, camera.binding, camera.count), (0, 0, 1));
+        assert_eq!(camera.descriptor_type, DescriptorType::UniformBuffer);
+        assert_eq!(camera.stages, ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT);
+
+        let textures = &bindings["textures"];
+        assert_eq!((textures.set, textures.binding, textures.count), (1, 2, 4));
+        assert_eq!(textures.descriptor_type, DescriptorType::CombinedImageSampler);
+        assert_eq!(textures.stages, ShaderStageFlags::FRAGMENT);
+    }
+
+    #[test]
+    fn combined_sampler_descriptors_per_backend() {
+        let shaders = ShaderSet::Compiled(Vec::new());
+        let pipeline = pipeline(
+            ShaderSource::Compiled(assemble(&camera_block(0))),
+            ShaderSource::Compiled(fragment_shader()),
+        );
+        let bindings = reflect_pipeline_bindings(&pipeline, &shaders).expect("Failed to reflect pipeline");
+        let descriptor = |binding, count, descriptor_type| BackendDescriptor {
+            set: 1,
+            binding,
+            count,
+            descriptor_type,
+        };
+
+        // Vulkan binds the textures through one descriptor
+        let textures = &bindings["textures"];
+        assert_eq!(
+            textures.backend_descriptors(DescriptorBindingModel::CombinedSamplers),
+            vec![descriptor(2, 4, BackendDescriptorType::CombinedImageSampler)]
+        );
+
+        // Direct3D 12 needs an SRV and a sampler, both at the binding the shader declared
+        assert_eq!(
+            textures.backend_descriptors(DescriptorBindingModel::SeparateSamplers),
+            vec![
+                descriptor(2, 4, BackendDescriptorType::SampledImage),
+                descriptor(2, 4, BackendDescriptorType::Sampler),
+            ]
+        );
+
+        // Buffers are the same under either model
+        let camera = &bindings["Camera"];
+        for model in &[
+            DescriptorBindingModel::CombinedSamplers,
+            DescriptorBindingModel::SeparateSamplers,
+        ] {
+            assert_eq!(
+                camera.backend_descriptors(*model),
+                vec![BackendDescriptor {
+                    set: 0,
+                    binding: 0,
+                    count: 1,
+                    descriptor_type: BackendDescriptorType::UniformBuffer,
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn reflection_errors() {
+        let sources = ShaderSet::Sources(vec![LoadedShader {
+            filename: "shaders/test.vert".into(),
+            source: String::new(),
+        }]);
+        let loaded = pipeline(ShaderSource::Loaded(0), ShaderSource::Invalid);
+        assert_eq!(
+            reflect_pipeline_bindings(&loaded, &sources).err(),
+            Some(ReflectionError::NotCompiled {
+                pipeline: String::from("test")
+            })
+        );
+        assert_eq!(
+            reflect_pipeline_bindings(&loaded, &ShaderSet::Compiled(Vec::new())).err(),
+            Some(ReflectionError::MissingShader {
+                pipeline: String::from("test"),
+                index: 0
+            })
+        );
+
+        let garbage = pipeline(ShaderSource::Compiled(vec![1, 2, 3]), ShaderSource::Invalid);
+        assert!(matches!(
+            reflect_pipeline_bindings(&garbage, &sources),
+            Err(ReflectionError::InvalidSpirv { .. })
+        ));
+
+        // The camera is at a different binding in each shader
+        let moved = pipeline(
+            ShaderSource::Compiled(assemble(&camera_block(5))),
+            ShaderSource::Compiled(fragment_shader()),
+        );
+        assert_eq!(
+            reflect_pipeline_bindings(&moved, &sources).err(),
+            Some(ReflectionError::ConflictingBinding {
+                pipeline: String::from("test"),
+                name: String::from("Camera")
+            })
+        );
+
+        // The textures are in set 2, but nothing is in set 1
+        let skipped = pipeline(
+            ShaderSource::Compiled(assemble(&camera_block(0))),
+            ShaderSource::Compiled(fragment_shader_with_textures_in_set(2)),
+        );
+        assert_eq!(
+            reflect_pipeline_bindings(&skipped, &sources).err(),
+            Some(ReflectionError::SkippedDescriptorSet {
+                pipeline: String::from("test"),
+                set: 1
+            })
+        );
+    }
+
+    /// Projects bindings onto something comparable, sorted by name.
+    fn binding_summary<'a>(
+        bindings: impl Iterator<Item = (&'a String, &'a ResourceBindingDescription)>,
+    ) -> Vec<(String, u32, u32, u32, DescriptorType, ShaderStageFlags)> {
+        let mut summary: Vec<_> = bindings
+            .map(|(name, binding)| {
+                (
+                    name.clone(),
+                    binding.set,
+                    binding.binding,
+                    binding.count,
+                    binding.descriptor_type.clone(),
+                    binding.stages,
+                )
+            })
+            .collect();
+        summary.sort_by(|a, b| a.0.cmp(&b.0));
+        summary
+    }
+
+    #[test]
+    fn module_reflection() {
+        let mut entry_point = vec![4];
+        entry_point.extend(named(1, "main"));
+        let mut instructions = vec![(OP_ENTRY_POINT, entry_point)];
+        instructions.extend(camera_block(0));
+        let words = assemble(&instructions);
+
+        let module = SpirvModule::new(words.clone()).unwrap();
+        assert_eq!(
+            module.entry_points(),
+            &[SpirvEntryPoint {
+                name: String::from("main"),
+                stage: ShaderStageFlags::FRAGMENT,
+            }]
+        );
+        assert!(module.entry_point("main").is_some());
+        assert!(module.entry_point("vertex_main").is_none());
+        assert_eq!(module.stages(), ShaderStageFlags::FRAGMENT);
+        assert_eq!(module.words(), words.as_slice());
+
+        // The module reflected when loading gives the same bindings as reflecting the words again
+        let fresh = SpirvModule::new(words.clone()).unwrap();
+        assert_eq!(module, fresh);
+        assert_eq!(
+            binding_summary(module.resources().iter().map(|(name, binding)| (name, binding))),
+            binding_summary(fresh.resources().iter().map(|(name, binding)| (name, binding)))
+        );
+        assert_eq!(module.resources()[0].1.stages, ShaderStageFlags::FRAGMENT);
+
+        let shaders = ShaderSet::Compiled(vec![CompiledShader {
+            filename: "shaders/test.frag.spv".into(),
+            module,
+        }]);
+        let cached = reflect_pipeline_bindings(&pipeline(ShaderSource::Invalid, ShaderSource::Loaded(0)), &shaders);
+        let inline = reflect_pipeline_bindings(
+            &pipeline(ShaderSource::Invalid, ShaderSource::Compiled(words)),
+            &shaders,
+        );
+        assert_eq!(
+            binding_summary(cached.unwrap().iter()),
+            binding_summary(inline.unwrap().iter())
+        );
+    }
+
+    #[test]
+    fn unsupported_resources() {
+        // A uniform block in the storage class of images and samplers
+        let words = assemble(&[
+            (OP_NAME, named(12, "weird")),
+            (OP_DECORATE, vec![12, DECORATION_BINDING, 0]),
+            (OP_TYPE_STRUCT, vec![10, 1]),
+            (OP_TYPE_POINTER, vec![11, STORAGE_CLASS_UNIFORM_CONSTANT, 10]),
+            (OP_VARIABLE, vec![11, 12, STORAGE_CLASS_UNIFORM_CONSTANT]),
+        ]);
+        let module = SpirvModule::new(words.clone()).unwrap();
+        assert!(module.resources().is_empty());
+        assert_eq!(module.unsupported_resources(), &[(String::from("weird"), 0)]);
+
+        // They are left out of the pipeline's bindings rather than failing it
+        let inline = pipeline(ShaderSource::Compiled(words), ShaderSource::Invalid);
+        assert_eq!(
+            reflect_pipeline_bindings(&inline, &ShaderSet::Compiled(Vec::new())).unwrap(),
+            HashMap::new()
+        );
+
+        assert_eq!(SpirvModule::new(vec![1, 2, 3]).err(), Some(SpirvError::MissingHeader));
+    }
+
+    #[test]
+    fn specialized_array_lengths() {
+        let array_of_textures = |name: &str, variable: u32, length: u32| {
+            vec![
+                (OP_NAME, named(variable, name)),
+                (OP_DECORATE, vec![variable, DECORATION_BINDING, variable]),
+                (OP_TYPE_ARRAY, vec![variable + 1, 20, length]),
+                (
+                    OP_TYPE_POINTER,
+                    vec![variable + 2, STORAGE_CLASS_UNIFORM_CONSTANT, variable + 1],
+                ),
+                (
+                    OP_VARIABLE,
+                    vec![variable + 2, variable, STORAGE_CLASS_UNIFORM_CONSTANT],
+                ),
+            ]
+        };
+        let mut instructions = vec![
+            (OP_TYPE_SAMPLED_IMAGE, vec![20, 2]),
+            (OP_SPEC_CONSTANT, vec![3, 21, 8]),
+            // Its value depends on the specialization
+            (OP_SPEC_CONSTANT_OP, vec![3, 22, 128, 21, 21]),
+        ];
+        instructions.extend(array_of_textures("default_length", 30, 21));
+        instructions.extend(array_of_textures("computed_length", 40, 22));
+
+        let module = SpirvModule::new(assemble(&instructions)).unwrap();
+        let count = |name: &str| {
+            module
+                .resources()
+                .iter()
+                .find(|(resource, _)| resource == name)
+                .map(|(_, binding)| binding.count)
+        };
+        assert_eq!(count("default_length"), Some(8));
+        assert_eq!(
+            count("computed_length"),
+            Some(ResourceBindingDescription::MAX_RUNTIME_ARRAY_COUNT)
+        );
+    }
+
+    /// A fragment shader with the camera block, a separate `albedo` texture and `albedo_sampler` in set 1, and the
+    /// `textures` array in set 2.
+    fn separate_sampler_shader() -> Vec<u32> {
+        let mut instructions = camera_block(0);
+        instructions.extend(vec![
+            (OP_NAME, named(33, "albedo")),
+            (OP_DECORATE, vec![33, DECORATION_DESCRIPTOR_SET, 1]),
+            (OP_DECORATE, vec![33, DECORATION_BINDING, 0]),
+            (OP_TYPE_IMAGE, vec![30, 2, 1, 0, 0, 0, 1, 0]),
+            (OP_TYPE_POINTER, vec![31, STORAGE_CLASS_UNIFORM_CONSTANT, 30]),
+            (OP_VARIABLE, vec![31, 33, STORAGE_CLASS_UNIFORM_CONSTANT]),
+            (OP_NAME, named(36, "albedo_sampler")),
+            (OP_DECORATE, vec![36, DECORATION_DESCRIPTOR_SET, 1]),
+            (OP_DECORATE, vec![36, DECORATION_BINDING, 1]),
+            (OP_TYPE_SAMPLER, vec![34]),
+            (OP_TYPE_POINTER, vec![35, STORAGE_CLASS_UNIFORM_CONSTANT, 34]),
+            (OP_VARIABLE, vec![35, 36, STORAGE_CLASS_UNIFORM_CONSTANT]),
+            (OP_NAME, named(24, "textures")),
+            (OP_DECORATE, vec![24, DECORATION_DESCRIPTOR_SET, 2]),
+            (OP_DECORATE, vec![24, DECORATION_BINDING, 0]),
+            (OP_TYPE_SAMPLED_IMAGE, vec![20, 30]),
+            (OP_CONSTANT, vec![3, 21, 4]),
+            (OP_TYPE_ARRAY, vec![22, 20, 21]),
+            (OP_TYPE_POINTER, vec![23, STORAGE_CLASS_UNIFORM_CONSTANT, 22]),
+            (OP_VARIABLE, vec![23, 24, STORAGE_CLASS_UNIFORM_CONSTANT]),
+        ]);
+        assemble(&instructions)
+    }
+
+    #[test]
+    fn separate_sampler() {
+        let module = SpirvModule::new(separate_sampler_shader()).unwrap();
+        assert_eq!(
+            module.unsupported_resources(),
+            &[(String::from("albedo"), 1), (String::from("albedo_sampler"), 1)]
+        );
+
+        // Set 1 only holds the separate texture and sampler, but isn't skipped
+        let pipeline = pipeline(ShaderSource::Invalid, ShaderSource::Compiled(separate_sampler_shader()));
+        let bindings = reflect_pipeline_bindings(&pipeline, &ShaderSet::Compiled(Vec::new())).unwrap();
+        let mut names: Vec<&String> = bindings.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["Camera", "textures"]);
+
+        let shaders = ShaderSet::Compiled(vec![CompiledShader {
+            filename: "shaders/test.frag.spv".into(),
+            module,
+        }]);
+        let mut data = material_data(shaders, &["Camera", "textures", "albedo"]);
+        data.materials.push(data.materials[0].clone());
+        let unsupported = |name: &str| ShaderpackWarning::UnsupportedResource {
+            pipeline: String::from("test"),
+            name: String::from(name),
+        };
+        // Once for the pipeline, however many materials use it, and binding the texture isn't an unused binding
+        assert_eq!(
+            validate_material_bindings(&data).unwrap(),
+            vec![unsupported("albedo"), unsupported("albedo_sampler")]
+        );
+    }
+
+    fn material_data(shaders: ShaderSet, bindings: &[&str]) -> ShaderpackData {
+        ShaderpackData {
+            pipelines: vec![pipeline(ShaderSource::Invalid, ShaderSource::Loaded(0))],
+            passes: Vec::new(),
+            materials: vec![MaterialData {
+                name: String::from("stone"),
+                passes: vec![MaterialPass {
+                    name: String::from("main"),
+                    material_name: String::from("stone"),
+                    pipeline: String::from("test"),
+                    bindings: bindings
+                        .iter()
+                        .map(|name| (String::from(*name), String::from("SomeResource")))
+                        .collect(),
+                }],
+                geometry_filter: String::new(),
+            }],
+            resources: ShaderpackResourceData {
+                textures: Vec::new(),
+                samplers: Vec::new(),
+            },
+            shaders,
+        }
+    }
+
+    #[test]
+    fn material_bindings() {
+        let compiled = || {
+            ShaderSet::Compiled(vec![CompiledShader {
+                filename: "shaders/test.frag.spv".into(),
+                module: SpirvModule::new(fragment_shader()).unwrap(),
+            }])
+        };
+
+        let data = material_data(compiled(), &["Camera"]);
+        match validate_material_bindings(&data) {
+            Err(ShaderpackLoadingFailure::MissingBinding {
+                material,
+                pass,
+                binding,
+            }) => {
+                assert_eq!(material, "stone");
+                assert_eq!(pass, "main");
+                assert_eq!(binding, "textures");
+            }
+            other => panic!("Expected missing binding, got {:?}", other),
+        }
+
+        // Extra bindings are only warned about
+        let data = material_data(compiled(), &["Camera", "textures", "unused"]);
+        assert_eq!(
+            validate_material_bindings(&data).unwrap(),
+            vec![ShaderpackWarning::UnusedBinding {
+                material: String::from("stone"),
+                pass: String::from("main"),
+                binding: String::from("unused"),
+            }]
+        );
+
+        // Uncompiled shaders can't be checked yet
+        let sources = ShaderSet::Sources(vec![LoadedShader {
+            filename: "shaders/test.frag".into(),
+            source: String::new(),
+        }]);
+        assert_eq!(
+            validate_material_bindings(&material_data(sources, &[])).unwrap(),
+            Vec::new()
+        );
+    }
+}
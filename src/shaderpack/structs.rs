@@ -1,4 +1,8 @@
+use crate::rhi::ImageUsage;
+use crate::shaderpack::{ShaderpackLoadingFailure, SpirvModule};
 use cgmath::Vector2;
+use log::warn;
+use matches::matches;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -26,6 +30,232 @@ pub struct ShaderpackData {
     pub shaders: ShaderSet,
 }
 
+impl ShaderpackData {
+    /// Merges another shaderpack on top of this one.
+    ///
+    /// Later packs win: any pipeline, pass, material, texture, or sampler in `other` replaces the entry in `self` with
+    /// the same name, keeping the position of the entry it replaced. Entries with new names are appended. Shaders are
+    /// matched by filename in the same way, and the shader indices in `other`'s pipelines are remapped to match.
+    ///
+    /// Fails with [`ShaderpackLoadingFailure::MixedShaderForms`] if one shaderpack has its shaders in source form and
+    /// the other has them compiled, leaving `self` unchanged. A shaderpack without any shaders can be merged with
+    /// either.
+    pub fn merge(&mut self, other: Self) -> Result<(), ShaderpackLoadingFailure> {
+        if let Some((compiled, source)) = mixed_shader_forms(&self.shaders, &other.shaders) {
+            return Err(ShaderpackLoadingFailure::MixedShaderForms { compiled, source });
+        }
+
+        let shader_mapping = match (&mut self.shaders, other.shaders) {
+            (ShaderSet::Sources(ours), ShaderSet::Sources(theirs)) => merge_shaders(ours, theirs, |s| &s.filename),
+            (ShaderSet::Compiled(ours), ShaderSet::Compiled(theirs)) => merge_shaders(ours, theirs, |s| &s.filename),
+            // One of the sets is empty, so the merged pack's shaders are in the form of the other
+            (ours, theirs) => {
+                let count = match &theirs {
+                    ShaderSet::Sources(shaders) => shaders.len(),
+                    ShaderSet::Compiled(shaders) => shaders.len(),
+                };
+                if count > 0 {
+                    *ours = theirs;
+                }
+                (0..count as u32).collect()
+            }
+        };
+
+        let mut pipelines = other.pipelines;
+        for pipeline in &mut pipelines {
+            pipeline.remap_shaders(&shader_mapping);
+        }
+
+        merge_by_name(&mut self.pipelines, pipelines, |p| &p.name);
+        merge_by_name(&mut self.passes, other.passes, |p| &p.name);
+        merge_by_name(&mut self.materials, other.materials, |m| &m.name);
+        merge_by_name(&mut self.resources.textures, other.resources.textures, |t| &t.name);
+        merge_by_name(&mut self.resources.samplers, other.resources.samplers, |s| &s.name);
+        Ok(())
+    }
+
+    /// Makes every pipeline self-contained, so it no longer refers to any other pipeline.
+    ///
+    /// Parents and pack defaults are already merged into each pipeline while loading, so this only has to take care of
+    /// fallbacks: a pipeline whose vertex shader couldn't be found takes its shaders from its fallback pipeline,
+    /// following the chain of fallbacks until one has a vertex shader. Only the shaders come from the fallback, the
+    /// pipeline keeps its own states. Afterwards the `parent` and `fallback` of every pipeline are cleared.
+    ///
+    /// Fails with [`ShaderpackLoadingFailure::InvalidPipeline`] if a pipeline has a parent which wasn't
+    /// [merged](PipelineCreationInfo::parent_merged) into it, because clearing the parent would lose what the pipeline
+    /// inherits. Nothing is changed in that case.
+    pub fn flatten(&mut self) -> Result<(), ShaderpackLoadingFailure> {
+        let unmerged = self
+            .pipelines
+            .iter()
+            .filter(|pipeline| !pipeline.parent_merged)
+            .find_map(|pipeline| pipeline.parent.as_ref().map(|parent| (pipeline, parent)));
+        if let Some((pipeline, parent)) = unmerged {
+            return Err(ShaderpackLoadingFailure::InvalidPipeline {
+                pipeline: pipeline.name.clone(),
+                reason: format!("parent pipeline {:?} was never merged into it", parent),
+            });
+        }
+
+        let fallbacks: Vec<Option<usize>> = (0..self.pipelines.len())
+            .map(|index| self.find_fallback(index))
+            .collect();
+        for (index, fallback) in fallbacks.into_iter().enumerate() {
+            if let Some(fallback) = fallback {
+                let fallback = self.pipelines[fallback].clone();
+                self.pipelines[index].use_shaders_of(&fallback);
+            }
+        }
+
+        for pipeline in &mut self.pipelines {
+            pipeline.parent = None;
+            pipeline.fallback = None;
+        }
+        Ok(())
+    }
+
+    /// Finds the index of the pipeline whose shaders the pipeline at `index` should use instead of its own, if any.
+    fn find_fallback(&self, index: usize) -> Option<usize> {
+        let mut current = index;
+        // A chain can't be longer than the number of pipelines without going in circles.
+        for _ in 0..self.pipelines.len() {
+            let pipeline = &self.pipelines[current];
+            if pipeline.vertex_shader != ShaderSource::Invalid {
+                return if current == index { None } else { Some(current) };
+            }
+
+            let fallback = pipeline.fallback.as_ref()?;
+            current = self.pipelines.iter().position(|p| &p.name == fallback)?;
+        }
+        None
+    }
+
+    /// Sets the [`usage`](TextureCreateInfo::usage) of every texture from how the passes and materials use it.
+    ///
+    /// Textures a pass renders to are color attachments, depth textures of passes are depth attachments, and textures
+    /// a pass reads or a material binds are sampled. Nothing in a shaderpack asks for storage images yet, so that usage
+    /// is never inferred.
+    pub fn infer_texture_usages(&mut self) {
+        let mut usages: HashMap<&str, ImageUsage> = HashMap::new();
+        for pass in &self.passes {
+            for output in &pass.texture_outputs {
+                *usages.entry(output.name.as_str()).or_insert_with(ImageUsage::empty) |= ImageUsage::COLOR_ATTACHMENT;
+            }
+            for depth in &pass.depth_texture {
+                *usages.entry(depth.name.as_str()).or_insert_with(ImageUsage::empty) |= ImageUsage::DEPTH_ATTACHMENT;
+            }
+            for input in &pass.texture_inputs {
+                *usages.entry(input.as_str()).or_insert_with(ImageUsage::empty) |= ImageUsage::SAMPLED;
+            }
+        }
+        let bound = self
+            .materials
+            .iter()
+            .flat_map(|material| material.passes.iter())
+            .flat_map(|pass| pass.bindings.values());
+        for resource in bound {
+            *usages.entry(resource.as_str()).or_insert_with(ImageUsage::empty) |= ImageUsage::SAMPLED;
+        }
+
+        for texture in &mut self.resources.textures {
+            texture.usage = usages
+                .get(texture.name.as_str())
+                .cloned()
+                .unwrap_or_else(ImageUsage::empty);
+        }
+    }
+
+    /// Gets every pass that writes to the given texture, in submission order.
+    ///
+    /// Textures provided by Nova, such as the virtual texture atlases, aren't written by any pass and have no
+    /// producers. The [backbuffer](BACKBUFFER_NAME) is produced by whichever pass outputs to it.
+    pub fn producers_of(&self, texture: &str) -> Vec<&RenderPassCreationInfo> {
+        self.passes
+            .iter()
+            .filter(|pass| {
+                pass.texture_outputs
+                    .iter()
+                    .chain(pass.depth_texture.iter())
+                    .any(|output| output.name == texture)
+            })
+            .collect()
+    }
+
+    /// Gets every pass that reads from the given texture, in submission order.
+    ///
+    /// The [backbuffer](BACKBUFFER_NAME) is only presented to the screen, so it has no consumers.
+    pub fn consumers_of(&self, texture: &str) -> Vec<&RenderPassCreationInfo> {
+        self.passes
+            .iter()
+            .filter(|pass| pass.texture_inputs.iter().any(|input| input == texture))
+            .collect()
+    }
+}
+
+/// Moves every item in `theirs` into `ours`, replacing items with the same name.
+fn merge_by_name<T>(ours: &mut Vec<T>, theirs: Vec<T>, name: fn(&T) -> &str) {
+    for item in theirs {
+        match ours.iter().position(|existing| name(existing) == name(&item)) {
+            Some(idx) => ours[idx] = item,
+            None => ours.push(item),
+        }
+    }
+}
+
+/// Finds a compiled shader and a shader source to report, if one set has shaders in a different form than the other.
+fn mixed_shader_forms(a: &ShaderSet, b: &ShaderSet) -> Option<(PathBuf, PathBuf)> {
+    let (compiled, sources) = match (a, b) {
+        (ShaderSet::Compiled(compiled), ShaderSet::Sources(sources))
+        | (ShaderSet::Sources(sources), ShaderSet::Compiled(compiled)) => (compiled, sources),
+        _ => return None,
+    };
+    Some((compiled.first()?.filename.clone(), sources.first()?.filename.clone()))
+}
+
+/// Moves every shader in `theirs` into `ours`, replacing shaders with the same filename.
+///
+/// Returns the new index of each shader in `theirs`.
+fn merge_shaders<T>(ours: &mut Vec<T>, theirs: Vec<T>, filename: fn(&T) -> &PathBuf) -> Vec<u32> {
+    theirs
+        .into_iter()
+        .map(
+            |shader| match ours.iter().position(|existing| filename(existing) == filename(&shader)) {
+                Some(idx) => {
+                    ours[idx] = shader;
+                    idx as u32
+                }
+                None => {
+                    ours.push(shader);
+                    (ours.len() - 1) as u32
+                }
+            },
+        )
+        .collect()
+}
+
+/// Contents of the optional [`PACK_INFO_FILE`](crate::shaderpack::PACK_INFO_FILE) in the root of a shaderpack.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackInfo {
+    /// Version of the shaderpack format the pack was written for.
+    #[serde(default = "PackInfo::default_schema_version")]
+    pub schema_version: u32,
+}
+
+impl PackInfo {
+    const fn default_schema_version() -> u32 {
+        1
+    }
+}
+
+impl Default for PackInfo {
+    fn default() -> Self {
+        Self {
+            schema_version: Self::default_schema_version(),
+        }
+    }
+}
+
 /// Information needed to create a pipeline
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -37,6 +267,13 @@ pub struct PipelineCreationInfo {
     #[serde(default)]
     pub parent: Option<String>,
 
+    /// Whether the fields of the [`parent`](PipelineCreationInfo::parent) pipeline have been merged into this one.
+    ///
+    /// The loaders merge parents while loading and set this. Pipelines deserialized any other way still need their
+    /// parent merged, which only works on their json.
+    #[serde(skip)]
+    pub parent_merged: bool,
+
     /// The name of the pass that this pipeline belongs to.
     pub pass: String,
 
@@ -71,6 +308,13 @@ pub struct PipelineCreationInfo {
     #[serde(default = "PipelineCreationInfo::default_slope_scaled_depth_bias")]
     pub slope_scaled_depth_bias: f32,
 
+    /// Clamp fragment depth to the depth range instead of clipping primitives against the near and far planes.
+    ///
+    /// Useful for shadow maps, where geometry in front of the near plane still needs to cast shadows. Ignored if
+    /// the device doesn't support depth clamping.
+    #[serde(default)]
+    pub depth_clamp: bool,
+
     /// The reference value to use for the stencil test.
     #[serde(default = "PipelineCreationInfo::default_stencil_ref")]
     pub stencil_ref: u32,
@@ -83,14 +327,23 @@ pub struct PipelineCreationInfo {
     #[serde(default = "PipelineCreationInfo::default_stencil_write_mask")]
     pub stencil_write_mask: u32,
 
-    /// How to handle MSAA for this state.
-    #[serde(default = "PipelineCreationInfo::default_msaa_support")]
-    pub msaa_support: MSAASupport,
+    /// How many samples to take per pixel for multisample antialiasing.
+    #[serde(alias = "msaaSupport")]
+    #[serde(default = "PipelineCreationInfo::default_sample_count")]
+    pub sample_count: SampleCount,
 
     /// Decides how the vertices are rendered.
     #[serde(default = "PipelineCreationInfo::default_primitive_mode")]
     pub primitive_mode: PrimitiveTopology,
 
+    /// How the rasterizer fills in polygons.
+    #[serde(default = "PipelineCreationInfo::default_polygon_mode")]
+    pub polygon_mode: PolygonMode,
+
+    /// Width, in pixels, of rasterized lines.
+    #[serde(default = "PipelineCreationInfo::default_line_width")]
+    pub line_width: f32,
+
     /// Where to get the blending factor for the source.
     #[serde(default = "PipelineCreationInfo::default_src_blend_factor")]
     pub src_blend_factor: BlendFactor,
@@ -136,6 +389,26 @@ pub struct PipelineCreationInfo {
     /// Fragment shader to use.
     #[serde(default)]
     pub fragment_shader: Option<ShaderSource>,
+
+    /// Values of the shaders' specialization constants, by constant ID.
+    ///
+    /// Constants which aren't given a value here keep the default value from the shader.
+    #[serde(default)]
+    pub specialization_constants: HashMap<u32, SpecializationValue>,
+
+    /// Names of the entry points of each of the shaders.
+    ///
+    /// Each stage defaults to [`DEFAULT_ENTRY_POINT`](ShaderEntryPoints::DEFAULT_ENTRY_POINT), so this only needs to
+    /// be set for modules which use different names, such as a SPIR-V module containing several stages.
+    #[serde(default)]
+    pub entry_points: ShaderEntryPoints,
+
+    /// Number of control points in each patch fed to the tessellation shaders.
+    ///
+    /// Required if the pipeline has a tessellation control or tessellation evaluation shader. When present, the
+    /// vertices are interpreted as a list of patches instead of [`primitive_mode`](#structfield.primitive_mode).
+    #[serde(default)]
+    pub patch_control_points: Option<u32>,
 }
 
 impl PipelineCreationInfo {
@@ -154,12 +427,18 @@ impl PipelineCreationInfo {
     const fn default_stencil_write_mask() -> u32 {
         0
     }
-    const fn default_msaa_support() -> MSAASupport {
-        MSAASupport::None
+    const fn default_sample_count() -> SampleCount {
+        SampleCount::X1
     }
     const fn default_primitive_mode() -> PrimitiveTopology {
         PrimitiveTopology::Triangles
     }
+    const fn default_polygon_mode() -> PolygonMode {
+        PolygonMode::Fill
+    }
+    const fn default_line_width() -> f32 {
+        1.0
+    }
     const fn default_src_blend_factor() -> BlendFactor {
         BlendFactor::One
     }
@@ -182,13 +461,115 @@ impl PipelineCreationInfo {
         ShaderSource::Invalid
     }
 
-    /// Merge a shaderpack with a "parent" shaderpack. Unimplemented.
+    /// Packs the specialization constants into the layout the graphics APIs expect.
     ///
-    /// # Parameters
+    /// Constants are laid out in order of their IDs, so the same constants always produce the same data.
+    pub fn specialization_info(&self) -> SpecializationInfo {
+        let mut ids: Vec<u32> = self.specialization_constants.keys().copied().collect();
+        ids.sort_unstable();
+
+        let mut info = SpecializationInfo::default();
+        for constant_id in ids {
+            let bytes = self.specialization_constants[&constant_id].to_bytes();
+            info.map_entries.push(SpecializationMapEntry {
+                constant_id,
+                offset: info.data.len() as u32,
+                size: bytes.len(),
+            });
+            info.data.extend_from_slice(&bytes);
+        }
+        info
+    }
+
+    /// Gets the indices into [`ShaderpackData::shaders`] of every loaded shader this pipeline uses.
+    pub fn loaded_shaders(&self) -> Vec<u32> {
+        std::iter::once(&self.vertex_shader)
+            .chain(self.tessellation_control_shader.iter())
+            .chain(self.tessellation_evaluation_shader.iter())
+            .chain(self.geometry_shader.iter())
+            .chain(self.fragment_shader.iter())
+            .filter_map(|shader| match shader {
+                ShaderSource::Loaded(index) => Some(*index),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns true if this pipeline has a tessellation control or tessellation evaluation shader.
+    pub fn has_tessellation_shaders(&self) -> bool {
+        self.tessellation_control_shader.is_some() || self.tessellation_evaluation_shader.is_some()
+    }
+
+    /// Returns true if the rasterizer should apply a depth bias, which is the case when either bias value is non-zero.
+    pub fn depth_bias_enabled(&self) -> bool {
+        self.depth_bias != 0.0 || self.slope_scaled_depth_bias != 0.0
+    }
+
+    /// Returns true if this pipeline needs the device to support rasterizing polygons as lines or points.
+    pub fn requires_non_solid_fill(&self) -> bool {
+        self.polygon_mode != PolygonMode::Fill
+    }
+
+    /// Returns true if this pipeline needs the device to support lines wider than one pixel.
+    pub fn requires_wide_lines(&self) -> bool {
+        self.line_width != 1.0
+    }
+
+    /// Checks that the combination of states in this pipeline makes sense.
     ///
-    /// - `_other` - Shaderpack to merge with.
-    pub fn merge_with_parent(&mut self, _other: &Self) -> Self {
-        unimplemented!()
+    /// Tessellated pipelines must specify [`patch_control_points`](#structfield.patch_control_points), and it must
+    /// be non-zero. The line width must be positive.
+    pub fn validate(&self) -> Result<(), ShaderpackLoadingFailure> {
+        if self.line_width.is_nan() || self.line_width <= 0.0 {
+            return Err(ShaderpackLoadingFailure::InvalidPipeline {
+                pipeline: self.name.clone(),
+                reason: format!("lineWidth must be positive, found {}", self.line_width),
+            });
+        }
+
+        match (self.has_tessellation_shaders(), self.patch_control_points) {
+            (true, None) => Err(ShaderpackLoadingFailure::InvalidPipeline {
+                pipeline: self.name.clone(),
+                reason: "pipeline has tessellation shaders but no patchControlPoints".into(),
+            }),
+            (true, Some(0)) => Err(ShaderpackLoadingFailure::InvalidPipeline {
+                pipeline: self.name.clone(),
+                reason: "patchControlPoints must be greater than zero".into(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Changes every loaded shader index according to `mapping`, where the old index is used to index the mapping.
+    fn remap_shaders(&mut self, mapping: &[u32]) {
+        let remap = |shader: &mut ShaderSource| {
+            if let ShaderSource::Loaded(idx) = shader {
+                *idx = mapping[*idx as usize];
+            }
+        };
+
+        let remap_option = |shader_option: &mut Option<ShaderSource>| {
+            if let Some(shader) = shader_option {
+                remap(shader)
+            }
+        };
+
+        remap(&mut self.vertex_shader);
+        remap_option(&mut self.tessellation_control_shader);
+        remap_option(&mut self.tessellation_evaluation_shader);
+        remap_option(&mut self.geometry_shader);
+        remap_option(&mut self.fragment_shader);
+    }
+
+    /// Replaces all of this pipeline's shaders with the ones from `other`.
+    fn use_shaders_of(&mut self, other: &Self) {
+        self.vertex_shader = other.vertex_shader.clone();
+        self.tessellation_control_shader = other.tessellation_control_shader.clone();
+        self.tessellation_evaluation_shader = other.tessellation_evaluation_shader.clone();
+        self.geometry_shader = other.geometry_shader.clone();
+        self.fragment_shader = other.fragment_shader.clone();
+        self.entry_points = other.entry_points.clone();
+        self.patch_control_points = other.patch_control_points;
     }
 }
 
@@ -216,7 +597,10 @@ pub struct RenderPassCreationInfo {
     #[serde(default = "RenderPassCreationInfo::default_name")]
     pub name: String,
 
-    /// The materials that MUST execute before this one.
+    /// The passes that MUST execute before this one.
+    ///
+    /// Dependencies implied by the textures and buffers this pass reads can be added with
+    /// [`infer_dependencies`](crate::renderer::infer_dependencies).
     #[serde(default)]
     pub dependencies: Vec<String>,
 
@@ -241,6 +625,11 @@ pub struct RenderPassCreationInfo {
     pub output_buffers: Vec<String>,
 }
 
+/// Name of the texture that is presented to the screen.
+///
+/// It is provided by Nova, so it is never declared in `resources.json`.
+pub const BACKBUFFER_NAME: &str = "Backbuffer";
+
 impl RenderPassCreationInfo {
     fn default_name() -> String {
         String::from("<NAME_MISSING>")
@@ -299,8 +688,160 @@ pub struct LoadedShader {
 pub struct CompiledShader {
     /// Filename for the source file of the shader. Relative to shaderpack root.
     pub filename: PathBuf,
-    /// Compiled SPIR-V of the shader.
-    pub compiled: Vec<u32>,
+    /// Compiled SPIR-V of the shader, already reflected.
+    pub module: SpirvModule,
+}
+
+impl CompiledShader {
+    /// The magic number every SPIR-V module starts with.
+    pub const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+    /// Amount of words in the SPIR-V module header.
+    const SPIRV_HEADER_WORDS: usize = 5;
+
+    /// Creates a compiled shader from the little endian bytes of a SPIR-V module, validating it on the way.
+    pub fn from_bytes(filename: PathBuf, bytes: &[u8]) -> Result<Self, ShaderpackLoadingFailure> {
+        if bytes.len() % 4 != 0 {
+            return Err(ShaderpackLoadingFailure::InvalidSpirv {
+                filename,
+                reason: format!("size of {} bytes is not a whole number of words", bytes.len()),
+            });
+        }
+
+        let words: Vec<u32> = bytes
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        if let Err(reason) = Self::check_header(&words) {
+            return Err(ShaderpackLoadingFailure::InvalidSpirv { filename, reason });
+        }
+
+        match SpirvModule::new(words) {
+            Ok(module) => Ok(Self { filename, module }),
+            Err(err) => Err(ShaderpackLoadingFailure::InvalidSpirv {
+                filename,
+                reason: err.to_string(),
+            }),
+        }
+    }
+
+    /// Checks that the words start with a little endian SPIR-V header, explaining what's wrong if they don't.
+    fn check_header(words: &[u32]) -> Result<(), String> {
+        if words.len() < Self::SPIRV_HEADER_WORDS {
+            return Err(format!(
+                "module is {} words long, but the header alone is {} words",
+                words.len(),
+                Self::SPIRV_HEADER_WORDS
+            ));
+        }
+
+        match words[0] {
+            Self::SPIRV_MAGIC => Ok(()),
+            magic if magic.swap_bytes() == Self::SPIRV_MAGIC => {
+                Err(String::from("module is big endian, but must be little endian"))
+            }
+            magic => Err(format!("wrong magic number {:#010x}", magic)),
+        }
+    }
+}
+
+/// Value of a single specialization constant.
+///
+/// In JSON this is just the value itself. Whole numbers are unsigned if they can be, and signed otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum SpecializationValue {
+    /// A boolean constant.
+    Bool(bool),
+    /// A 32-bit unsigned integer constant.
+    UInt(u32),
+    /// A 32-bit signed integer constant.
+    Int(i32),
+    /// A 32-bit floating point constant.
+    Float(f32),
+}
+
+impl SpecializationValue {
+    /// Gets the little endian bytes of the constant as a shader sees them. Booleans are 32 bits wide.
+    pub fn to_bytes(self) -> [u8; 4] {
+        match self {
+            Self::Bool(value) => u32::from(value).to_le_bytes(),
+            Self::UInt(value) => value.to_le_bytes(),
+            Self::Int(value) => value.to_le_bytes(),
+            Self::Float(value) => value.to_bits().to_le_bytes(),
+        }
+    }
+}
+
+/// Specialization constants of a pipeline, packed into one block of data.
+///
+/// This is laid out the same way as Vulkan's `VkSpecializationInfo`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpecializationInfo {
+    /// Where each constant is in [`data`](#structfield.data).
+    pub map_entries: Vec<SpecializationMapEntry>,
+
+    /// Values of all the constants.
+    pub data: Vec<u8>,
+}
+
+/// Location of a single specialization constant in [`SpecializationInfo::data`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpecializationMapEntry {
+    /// ID of the constant in the shader.
+    pub constant_id: u32,
+
+    /// Offset of the constant's value, in bytes.
+    pub offset: u32,
+
+    /// Size of the constant's value, in bytes.
+    pub size: usize,
+}
+
+/// Names of the functions each stage of a pipeline starts executing at.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShaderEntryPoints {
+    /// Entry point of the vertex shader.
+    #[serde(default = "ShaderEntryPoints::default_entry_point")]
+    pub vertex: String,
+
+    /// Entry point of the geometry shader.
+    #[serde(default = "ShaderEntryPoints::default_entry_point")]
+    pub geometry: String,
+
+    /// Entry point of the tessellation control shader.
+    #[serde(default = "ShaderEntryPoints::default_entry_point")]
+    pub tessellation_control: String,
+
+    /// Entry point of the tessellation evaluation shader.
+    #[serde(default = "ShaderEntryPoints::default_entry_point")]
+    pub tessellation_evaluation: String,
+
+    /// Entry point of the fragment shader.
+    #[serde(default = "ShaderEntryPoints::default_entry_point")]
+    pub fragment: String,
+}
+
+impl ShaderEntryPoints {
+    /// Entry point used for any stage that doesn't specify one.
+    pub const DEFAULT_ENTRY_POINT: &'static str = "main";
+
+    fn default_entry_point() -> String {
+        String::from(Self::DEFAULT_ENTRY_POINT)
+    }
+}
+
+impl Default for ShaderEntryPoints {
+    fn default() -> Self {
+        Self {
+            vertex: Self::default_entry_point(),
+            geometry: Self::default_entry_point(),
+            tessellation_control: Self::default_entry_point(),
+            tessellation_evaluation: Self::default_entry_point(),
+            fragment: Self::default_entry_point(),
+        }
+    }
 }
 
 /// Connects a [`VertexField`] with a semantic name.
@@ -373,6 +914,8 @@ pub enum ShaderSource {
     Path(PathBuf),
     /// Loaded shader with index into the shader vector. [`ShaderpackData::shaders`]
     Loaded(u32),
+    /// SPIR-V words of a shader compiled ahead of time, written inline in the pipeline file.
+    Compiled(Vec<u32>),
     /// Shader does not exist
     Invalid,
 }
@@ -390,11 +933,16 @@ pub struct TextureAttachmentInfo {
 
     /// Whether to clear the texture.
     ///
+    /// Unless [`clear_value`](#structfield.clear_value) says otherwise:
     /// If the texture is a depth buffer, it gets cleared to 1.
     /// If the texture is a stencil buffer, it gets cleared to 0xFFFFFFFF.
     /// If the texture is a color buffer, it gets cleared to (0, 0, 0, 0).
     #[serde(default = "TextureAttachmentInfo::default_clear")]
     pub clear: bool,
+
+    /// The value to clear the texture to. Only used if [`clear`](#structfield.clear) is true.
+    #[serde(default)]
+    pub clear_value: Option<ClearValue>,
 }
 
 impl TextureAttachmentInfo {
@@ -404,6 +952,64 @@ impl TextureAttachmentInfo {
     const fn default_clear() -> bool {
         false
     }
+
+    /// Returns the value this attachment should be cleared to.
+    ///
+    /// If no clear value was specified, or the specified value is for the wrong kind of attachment, the default for
+    /// the kind of attachment is used.
+    ///
+    /// # Parameters
+    ///
+    /// - `depth_attachment` - If the texture is being used as a depth/stencil attachment.
+    pub fn resolved_clear_value(&self, depth_attachment: bool) -> ClearValue {
+        match &self.clear_value {
+            Some(ClearValue::Color(color)) if !depth_attachment => ClearValue::Color(*color),
+            Some(ClearValue::DepthStencil { depth, stencil }) if depth_attachment => ClearValue::DepthStencil {
+                depth: *depth,
+                stencil: *stencil,
+            },
+            _ if depth_attachment => ClearValue::DEFAULT_DEPTH_STENCIL,
+            _ => ClearValue::DEFAULT_COLOR,
+        }
+    }
+}
+
+/// Value to clear an attachment to at the beginning of a renderpass.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClearValue {
+    /// RGBA color to clear a color attachment to.
+    Color([f32; 4]),
+
+    /// Values to clear a depth/stencil attachment to.
+    #[serde(rename_all = "camelCase")]
+    DepthStencil {
+        /// Depth to clear to.
+        #[serde(default = "ClearValue::default_depth")]
+        depth: f32,
+
+        /// Stencil value to clear to.
+        #[serde(default = "ClearValue::default_stencil")]
+        stencil: u32,
+    },
+}
+
+impl ClearValue {
+    /// Color attachments are cleared to transparent black by default.
+    pub const DEFAULT_COLOR: Self = Self::Color([0.0, 0.0, 0.0, 0.0]);
+
+    /// Depth attachments are cleared to the far plane, and stencil attachments to all ones, by default.
+    pub const DEFAULT_DEPTH_STENCIL: Self = Self::DepthStencil {
+        depth: 1.0,
+        stencil: 0xFFFF_FFFF,
+    };
+
+    const fn default_depth() -> f32 {
+        1.0
+    }
+    const fn default_stencil() -> u32 {
+        0xFFFF_FFFF
+    }
 }
 
 /// The per-renderpass data for a material
@@ -473,6 +1079,13 @@ pub struct TextureCreateInfo {
     /// All members except the bindings are ignored if the texture is virtual. Everything is
     /// ignored if the texture is the BackBuffer.
     pub format: TextureFormat,
+
+    /// What the render graph uses the texture for.
+    ///
+    /// This is not read from the shaderpack, it is inferred from the passes and materials by
+    /// [`ShaderpackData::infer_texture_usages`] while loading.
+    #[serde(skip, default = "ImageUsage::empty")]
+    pub usage: ImageUsage,
 }
 
 /// Defines a sampler to use for a texture.
@@ -528,6 +1141,15 @@ pub struct TextureFormat {
     /// The height, in pixels, of the texture.
     #[serde(default = "TextureFormat::default_height")]
     pub height: f32,
+
+    /// What kind of image the texture is.
+    #[serde(default = "TextureFormat::default_texture_type")]
+    pub texture_type: TextureType,
+
+    /// The depth, in pixels, of a [3D texture](TextureType::Tex3D), or the number of layers of a
+    /// [texture array](TextureType::Tex2DArray). Ignored for other texture types.
+    #[serde(default = "TextureFormat::default_depth_or_array_layers")]
+    pub depth_or_array_layers: u32,
 }
 
 impl TextureFormat {
@@ -543,6 +1165,29 @@ impl TextureFormat {
     const fn default_height() -> f32 {
         0.0
     }
+    const fn default_texture_type() -> TextureType {
+        TextureType::Tex2D
+    }
+    const fn default_depth_or_array_layers() -> u32 {
+        1
+    }
+
+    /// Returns the number of array layers the image of this texture needs. Cubemaps have one layer per face.
+    pub fn array_layers(&self) -> u32 {
+        match self.texture_type {
+            TextureType::Tex2D | TextureType::Tex3D => 1,
+            TextureType::TexCube => 6,
+            TextureType::Tex2DArray => self.depth_or_array_layers,
+        }
+    }
+
+    /// Returns the depth, in pixels, of the image of this texture. Only 3D textures are deeper than one pixel.
+    pub fn depth(&self) -> u32 {
+        match self.texture_type {
+            TextureType::Tex3D => self.depth_or_array_layers,
+            _ => 1,
+        }
+    }
 
     /// Returns the screen size in pixels.
     ///
@@ -557,6 +1202,26 @@ impl TextureFormat {
 
         Vector2::new(width.round(), height.round())
     }
+
+    /// Returns the size in pixels, like [`get_size_in_pixels`](TextureFormat::get_size_in_pixels), but scales
+    /// screen-relative textures down to be at most `max_dimension` pixels wide and high, keeping their aspect ratio.
+    /// Absolute sizes are chosen by the shaderpack author and are never scaled.
+    ///
+    /// # Parameters
+    ///
+    /// - `screen_size` - Needed if the texture resolution is relative to the screen size
+    /// - `max_dimension` - Largest width or height a screen-relative texture may have
+    pub fn get_clamped_size_in_pixels(&self, screen_size: Vector2<f32>, max_dimension: u32) -> Vector2<f32> {
+        let size = self.get_size_in_pixels(screen_size);
+        let max_dimension = max_dimension as f32;
+        let largest = size.x.max(size.y);
+        if self.dimension_type == TextureDimensionType::Absolute || largest <= max_dimension {
+            return size;
+        }
+
+        let scale = max_dimension / largest;
+        Vector2::new((size.x * scale).round().max(1.0), (size.y * scale).round().max(1.0))
+    }
 }
 
 /// State of the fixed-function rasterizer.
@@ -593,14 +1258,61 @@ pub enum RasterizerState {
     DisableAlphaWrite,
 }
 
-/// Multisample Antialiasing mode.
-#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
-pub enum MSAASupport {
-    /// Enable MSAA.
-    MSAA,
+/// Number of samples per pixel for multisample antialiasing.
+///
+/// For compatibility with older shaderpacks, `"None"` is accepted as [`X1`](SampleCount::X1) and `"MSAA"` as
+/// [`X4`](SampleCount::X4).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize)]
+pub enum SampleCount {
+    /// One sample per pixel, which disables antialiasing.
+    #[serde(alias = "None")]
+    X1,
+
+    /// Two samples per pixel.
+    X2,
+
+    /// Four samples per pixel.
+    #[serde(alias = "MSAA")]
+    X4,
+
+    /// Eight samples per pixel.
+    X8,
+}
 
-    /// Disable antialiasing.
-    None,
+impl SampleCount {
+    /// Gets the number of samples per pixel.
+    pub fn samples(self) -> u32 {
+        match self {
+            Self::X1 => 1,
+            Self::X2 => 2,
+            Self::X4 => 4,
+            Self::X8 => 8,
+        }
+    }
+
+    /// Lowers this sample count to the highest one the device supports, logging a warning if it had to be lowered.
+    ///
+    /// [`X1`](SampleCount::X1) is always considered supported.
+    ///
+    /// # Parameters
+    ///
+    /// * `supported` - The sample counts the device supports for the attachment format.
+    pub fn clamped_to(self, supported: &[Self]) -> Self {
+        let clamped = supported
+            .iter()
+            .copied()
+            .filter(|count| *count <= self)
+            .max()
+            .unwrap_or(Self::X1);
+        if clamped != self {
+            warn!(
+                "{} samples per pixel is not supported, using {} instead",
+                self.samples(),
+                clamped.samples()
+            );
+        }
+        clamped
+    }
 }
 
 /// Primitive to interpret vertex buffer as.
@@ -613,6 +1325,19 @@ pub enum PrimitiveTopology {
     Lines,
 }
 
+/// How the rasterizer fills in polygons.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub enum PolygonMode {
+    /// Fill in the whole polygon.
+    Fill,
+
+    /// Only draw the edges of the polygon. Useful for wireframe debugging.
+    Line,
+
+    /// Only draw the vertices of the polygon.
+    Point,
+}
+
 /// How to blend the new image with the old image.
 ///
 /// See [opengl wiki](https://www.khronos.org/opengl/wiki/Blending#Blend_Equations) for more info.
@@ -777,7 +1502,7 @@ pub enum StencilOp {
 }
 
 /// Layout of pixels in memory
-#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Deserialize)]
 pub enum PixelFormat {
     /// R, G, B, and A channels, all taking up 8 bits integers each. 4 bytes.
     RGBA8,
@@ -795,6 +1520,13 @@ pub enum PixelFormat {
     DepthStencil,
 }
 
+impl PixelFormat {
+    /// Checks if the format has a depth channel.
+    pub fn is_depth(self) -> bool {
+        matches!(self, Self::Depth | Self::DepthStencil)
+    }
+}
+
 /// Filter to use when reading from texture.
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
 pub enum TextureFilter {
@@ -828,6 +1560,23 @@ pub enum TextureDimensionType {
     Absolute,
 }
 
+/// Kind of image a texture is.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Deserialize)]
+pub enum TextureType {
+    /// A single 2D image.
+    Tex2D,
+
+    /// Six square 2D images, one for each face of a cube. Sampled with a direction, such as for environment
+    /// reflections.
+    TexCube,
+
+    /// A volume of pixels.
+    Tex3D,
+
+    /// Several 2D images of the same size, sampled with a layer index.
+    Tex2DArray,
+}
+
 /// Origin location of a texture
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
 pub enum TextureLocation {
@@ -840,3 +1589,367 @@ pub enum TextureLocation {
     /// The texture is provided by Nova or by Minecraft.
     InAppPackage,
 }
+
+#[cfg(test)]
+mod test {
+    use crate::rhi::ImageUsage;
+    use crate::shaderpack::{
+        ClearValue, CompiledShader, LoadedShader, PipelineCreationInfo, RenderPassCreationInfo, SampleCount,
+        ShaderEntryPoints, ShaderSet, ShaderSource, ShaderpackData, ShaderpackLoadingFailure, ShaderpackResourceData,
+        SpecializationMapEntry, SpecializationValue, TextureAttachmentInfo, TextureFormat, TextureType,
+        BACKBUFFER_NAME,
+    };
+    use matches::matches;
+
+    fn parse_pipeline(json: &str) -> PipelineCreationInfo {
+        serde_json::from_str(json).expect("Failed to parse pipeline json")
+    }
+
+    #[test]
+    fn depth_bias_enabled() {
+        let pipeline = parse_pipeline(r#"{ "name": "a", "pass": "main", "vertexFields": [] }"#);
+        assert_eq!(pipeline.depth_bias_enabled(), false);
+        assert_eq!(pipeline.depth_clamp, false);
+
+        let pipeline = parse_pipeline(r#"{ "name": "a", "pass": "main", "vertexFields": [], "depthBias": 0.5 }"#);
+        assert_eq!(pipeline.depth_bias_enabled(), true);
+
+        let pipeline = parse_pipeline(
+            r#"{ "name": "a", "pass": "main", "vertexFields": [], "slopeScaledDepthBias": 2.0, "depthClamp": true }"#,
+        );
+        assert_eq!(pipeline.depth_bias_enabled(), true);
+        assert_eq!(pipeline.depth_clamp, true);
+    }
+
+    #[test]
+    fn attachment_clear_values() {
+        let attachment: TextureAttachmentInfo = serde_json::from_str(
+            r#"{ "name": "LitWorld", "clear": true, "clearValue": { "color": [1.0, 0.5, 0.25, 1.0] } }"#,
+        )
+        .expect("Failed to parse attachment json");
+        assert_eq!(
+            attachment.resolved_clear_value(false),
+            ClearValue::Color([1.0, 0.5, 0.25, 1.0])
+        );
+        assert_eq!(attachment.resolved_clear_value(true), ClearValue::DEFAULT_DEPTH_STENCIL);
+
+        let attachment: TextureAttachmentInfo = serde_json::from_str(
+            r#"{ "name": "DepthBuffer", "clear": true, "clearValue": { "depthStencil": { "depth": 0.0 } } }"#,
+        )
+        .expect("Failed to parse attachment json");
+        assert_eq!(
+            attachment.resolved_clear_value(true),
+            ClearValue::DepthStencil {
+                depth: 0.0,
+                stencil: 0xFFFF_FFFF
+            }
+        );
+        assert_eq!(attachment.resolved_clear_value(false), ClearValue::DEFAULT_COLOR);
+    }
+
+    fn shaderpack(pipelines: &[(&str, &str)], shaders: &[&str]) -> ShaderpackData {
+        let pipelines = pipelines
+            .iter()
+            .map(|(name, shader)| {
+                let mut pipeline = parse_pipeline(&format!(
+                    r#"{{ "name": "{}", "pass": "main", "vertexFields": [] }}"#,
+                    name
+                ));
+                let idx = shaders.iter().position(|s| s == shader).unwrap();
+                pipeline.vertex_shader = ShaderSource::Loaded(idx as u32);
+                pipeline
+            })
+            .collect();
+        let shaders = shaders
+            .iter()
+            .map(|filename| LoadedShader {
+                filename: filename.into(),
+                source: String::new(),
+            })
+            .collect();
+
+        ShaderpackData {
+            pipelines,
+            passes: Vec::new(),
+            materials: Vec::new(),
+            resources: ShaderpackResourceData {
+                textures: Vec::new(),
+                samplers: Vec::new(),
+            },
+            shaders: ShaderSet::Sources(shaders),
+        }
+    }
+
+    #[test]
+    fn merge_overrides_by_name() {
+        let mut base = shaderpack(
+            &[("terrain", "terrain.vert"), ("gui", "gui.vert")],
+            &["terrain.vert", "gui.vert"],
+        );
+        let addon = shaderpack(
+            &[("water", "water.vert"), ("terrain", "terrain_v2.vert")],
+            &["water.vert", "gui.vert", "terrain_v2.vert"],
+        );
+        base.merge(addon).expect("Failed to merge shaderpacks");
+
+        let names: Vec<_> = base.pipelines.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["terrain", "gui", "water"]);
+
+        let shaders = match &base.shaders {
+            ShaderSet::Sources(shaders) => shaders,
+            ShaderSet::Compiled(..) => panic!("Shaders should still be sources"),
+        };
+        let shader_name = |pipeline: &PipelineCreationInfo| match pipeline.vertex_shader {
+            ShaderSource::Loaded(idx) => shaders[idx as usize].filename.to_str().unwrap(),
+            _ => panic!("Shader should be loaded"),
+        };
+        assert_eq!(shaders.len(), 4);
+        assert_eq!(shader_name(&base.pipelines[0]), "terrain_v2.vert");
+        assert_eq!(shader_name(&base.pipelines[1]), "gui.vert");
+        assert_eq!(shader_name(&base.pipelines[2]), "water.vert");
+    }
+
+    #[test]
+    fn merge_mixed_shader_forms() {
+        let header = [CompiledShader::SPIRV_MAGIC, 0x0001_0000, 0, 1, 0];
+        let bytes: Vec<u8> = header.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+        let mut compiled = shaderpack(&[], &[]);
+        compiled.shaders = ShaderSet::Compiled(vec![
+            CompiledShader::from_bytes("water.vert.spv".into(), &bytes).unwrap(),
+        ]);
+
+        let mut base = shaderpack(&[("terrain", "terrain.vert")], &["terrain.vert"]);
+        match base.merge(compiled.clone()) {
+            Err(ShaderpackLoadingFailure::MixedShaderForms { compiled, source }) => {
+                assert_eq!(compiled.to_str(), Some("water.vert.spv"));
+                assert_eq!(source.to_str(), Some("terrain.vert"));
+            }
+            other => panic!("Expected mixed shader forms, got {:?}", other),
+        }
+        assert_eq!(base.pipelines.len(), 1);
+        assert!(matches!(base.shaders, ShaderSet::Sources(_)));
+
+        // A pack without shaders takes on the form of the other
+        let mut empty = shaderpack(&[], &[]);
+        empty
+            .merge(compiled)
+            .expect("Failed to merge into a pack without shaders");
+        assert!(matches!(&empty.shaders, ShaderSet::Compiled(shaders) if shaders.len() == 1));
+    }
+
+    fn default_pack_passes() -> ShaderpackData {
+        let mut data = shaderpack(&[], &[]);
+        data.passes = serde_json::from_str(include_str!(
+            "../../tests/data/shaderpacks/nova/DefaultShaderpack/passes.json"
+        ))
+        .expect("Failed to parse passes.json");
+        data
+    }
+
+    #[test]
+    fn texture_producers_and_consumers() {
+        let data = default_pack_passes();
+        let names =
+            |passes: Vec<&RenderPassCreationInfo>| passes.into_iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+
+        assert_eq!(names(data.producers_of("LitWorld")), ["Forward"]);
+        assert_eq!(names(data.consumers_of("LitWorld")), ["Final"]);
+        assert_eq!(names(data.producers_of("DepthBuffer")), ["Forward"]);
+        assert_eq!(names(data.producers_of(BACKBUFFER_NAME)), ["Final"]);
+        assert!(data.consumers_of(BACKBUFFER_NAME).is_empty());
+        assert!(data.producers_of("NovaColorVirtualTexture").is_empty());
+    }
+
+    #[test]
+    fn inferred_texture_usages() {
+        let mut data = default_pack_passes();
+        data.resources = serde_json::from_str(include_str!(
+            "../../tests/data/shaderpacks/nova/DefaultShaderpack/resources.json"
+        ))
+        .expect("Failed to parse resources.json");
+        data.resources
+            .textures
+            .push(serde_json::from_str(r#"{ "name": "Unused", "format": {} }"#).unwrap());
+
+        data.infer_texture_usages();
+
+        let usage = |name: &str| data.resources.textures.iter().find(|t| t.name == name).unwrap().usage;
+        assert_eq!(usage("LitWorld"), ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED);
+        assert_eq!(usage("DepthBuffer"), ImageUsage::DEPTH_ATTACHMENT);
+        assert_eq!(usage("Unused"), ImageUsage::empty());
+    }
+
+    #[test]
+    fn spirv_validation() {
+        let header = [CompiledShader::SPIRV_MAGIC, 0x0001_0000, 0, 1, 0];
+        let bytes: Vec<u8> = header.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+        assert!(CompiledShader::from_bytes("valid.spv".into(), &bytes).is_ok());
+
+        let result = CompiledShader::from_bytes("truncated.spv".into(), &bytes[..bytes.len() - 1]);
+        assert!(matches!(result, Err(ShaderpackLoadingFailure::InvalidSpirv { .. })));
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = 0;
+        match CompiledShader::from_bytes("wrong_magic.spv".into(), &wrong_magic) {
+            Err(ShaderpackLoadingFailure::InvalidSpirv { filename, reason }) => {
+                assert_eq!(filename.to_str(), Some("wrong_magic.spv"));
+                assert!(reason.contains("magic"));
+            }
+            other => panic!("Expected invalid SPIR-V error, got {:?}", other),
+        }
+
+        let big_endian: Vec<u8> = header.iter().flat_map(|word| word.to_be_bytes().to_vec()).collect();
+        match CompiledShader::from_bytes("big_endian.spv".into(), &big_endian) {
+            Err(ShaderpackLoadingFailure::InvalidSpirv { reason, .. }) => assert!(reason.contains("big endian")),
+            other => panic!("Expected invalid SPIR-V error, got {:?}", other),
+        }
+
+        // Instructions are checked as far as reflection needs
+        let mut bad_instruction = bytes.clone();
+        bad_instruction.extend(&[0, 0, 0, 0]);
+        let result = CompiledShader::from_bytes("bad_instruction.spv".into(), &bad_instruction);
+        assert!(matches!(result, Err(ShaderpackLoadingFailure::InvalidSpirv { .. })));
+    }
+
+    #[test]
+    fn entry_points() {
+        let pipeline = parse_pipeline(r#"{ "name": "a", "pass": "main", "vertexFields": [] }"#);
+        assert_eq!(pipeline.entry_points, ShaderEntryPoints::default());
+        assert_eq!(pipeline.entry_points.fragment, "main");
+
+        let pipeline = parse_pipeline(
+            r#"{
+                "name": "a",
+                "pass": "main",
+                "vertexFields": [],
+                "vertexShader": "shaders/terrain.spv",
+                "fragmentShader": "shaders/terrain.spv",
+                "entryPoints": { "vertex": "vs_main", "fragment": "fs_main" }
+            }"#,
+        );
+        assert_eq!(pipeline.entry_points.vertex, "vs_main");
+        assert_eq!(pipeline.entry_points.fragment, "fs_main");
+        assert_eq!(pipeline.entry_points.geometry, ShaderEntryPoints::DEFAULT_ENTRY_POINT);
+    }
+
+    #[test]
+    fn specialization_constants() {
+        let pipeline = parse_pipeline(r#"{ "name": "a", "pass": "main", "vertexFields": [] }"#);
+        assert!(pipeline.specialization_info().map_entries.is_empty());
+
+        let pipeline = parse_pipeline(
+            r#"{
+                "name": "a",
+                "pass": "main",
+                "vertexFields": [],
+                "specializationConstants": { "7": -2, "0": true, "3": 1.5, "4": 16 }
+            }"#,
+        );
+        assert_eq!(pipeline.specialization_constants[&0], SpecializationValue::Bool(true));
+        assert_eq!(pipeline.specialization_constants[&3], SpecializationValue::Float(1.5));
+        assert_eq!(pipeline.specialization_constants[&4], SpecializationValue::UInt(16));
+        assert_eq!(pipeline.specialization_constants[&7], SpecializationValue::Int(-2));
+
+        let info = pipeline.specialization_info();
+        let ids: Vec<u32> = info.map_entries.iter().map(|entry| entry.constant_id).collect();
+        assert_eq!(ids, [0, 3, 4, 7]);
+        assert_eq!(
+            info.map_entries[2],
+            SpecializationMapEntry {
+                constant_id: 4,
+                offset: 8,
+                size: 4
+            }
+        );
+        assert_eq!(info.data.len(), 16);
+        assert_eq!(info.data[0..4], [1, 0, 0, 0]);
+        assert_eq!(info.data[4..8], 1.5_f32.to_bits().to_le_bytes());
+        assert_eq!(info.data[12..16], (-2_i32).to_le_bytes());
+    }
+
+    #[test]
+    fn sample_count() {
+        let pipeline = parse_pipeline(r#"{ "name": "a", "pass": "main", "vertexFields": [] }"#);
+        assert_eq!(pipeline.sample_count, SampleCount::X1);
+
+        let pipeline = parse_pipeline(r#"{ "name": "a", "pass": "main", "vertexFields": [], "sampleCount": "X8" }"#);
+        assert_eq!(pipeline.sample_count.samples(), 8);
+
+        let pipeline = parse_pipeline(r#"{ "name": "a", "pass": "main", "vertexFields": [], "msaaSupport": "MSAA" }"#);
+        assert_eq!(pipeline.sample_count, SampleCount::X4);
+
+        let pipeline = parse_pipeline(r#"{ "name": "a", "pass": "main", "vertexFields": [], "msaaSupport": "None" }"#);
+        assert_eq!(pipeline.sample_count, SampleCount::X1);
+    }
+
+    #[test]
+    fn sample_count_clamping() {
+        let supported = [SampleCount::X1, SampleCount::X2, SampleCount::X4];
+        assert_eq!(SampleCount::X4.clamped_to(&supported), SampleCount::X4);
+        assert_eq!(SampleCount::X8.clamped_to(&supported), SampleCount::X4);
+        assert_eq!(SampleCount::X2.clamped_to(&[SampleCount::X4]), SampleCount::X1);
+        assert_eq!(SampleCount::X8.clamped_to(&[]), SampleCount::X1);
+    }
+
+    #[test]
+    fn texture_types() {
+        let format = |json: &str| -> TextureFormat { serde_json::from_str(json).expect("Failed to parse format") };
+
+        let plain = format(r#"{ "width": 1, "height": 1 }"#);
+        assert_eq!(plain.texture_type, TextureType::Tex2D);
+        assert_eq!((plain.array_layers(), plain.depth()), (1, 1));
+
+        let cube = format(r#"{ "textureType": "TexCube", "depthOrArrayLayers": 3 }"#);
+        assert_eq!((cube.array_layers(), cube.depth()), (6, 1));
+
+        let volume = format(r#"{ "textureType": "Tex3D", "depthOrArrayLayers": 32 }"#);
+        assert_eq!((volume.array_layers(), volume.depth()), (1, 32));
+
+        let array = format(r#"{ "textureType": "Tex2DArray", "depthOrArrayLayers": 4 }"#);
+        assert_eq!((array.array_layers(), array.depth()), (4, 1));
+    }
+
+    #[test]
+    fn flatten_fallbacks() {
+        let mut data = shaderpack(
+            &[("a", "a.vert"), ("b", "b.vert"), ("c", "c.vert")],
+            &["a.vert", "b.vert", "c.vert"],
+        );
+        data.pipelines[0].vertex_shader = ShaderSource::Invalid;
+        data.pipelines[0].fallback = Some("b".into());
+        data.pipelines[1].vertex_shader = ShaderSource::Invalid;
+        data.pipelines[1].fallback = Some("c".into());
+        data.pipelines[2].fallback = Some("a".into());
+        data.pipelines[2].parent = Some("b".into());
+
+        // The parent has to be merged first, which only the loaders can do
+        match data.flatten() {
+            Err(ShaderpackLoadingFailure::InvalidPipeline { pipeline, .. }) => assert_eq!(pipeline, "c"),
+            other => panic!("Expected invalid pipeline, got {:?}", other),
+        }
+        assert_eq!(data.pipelines[2].parent.as_ref().map(String::as_str), Some("b"));
+        data.pipelines[2].parent_merged = true;
+
+        data.flatten().expect("Failed to flatten pipelines");
+
+        for pipeline in &data.pipelines {
+            assert_eq!(pipeline.vertex_shader, ShaderSource::Loaded(2));
+            assert_eq!(pipeline.fallback, None);
+            assert_eq!(pipeline.parent, None);
+        }
+    }
+
+    #[test]
+    fn flatten_fallback_cycle() {
+        let mut data = shaderpack(&[("a", "a.vert"), ("b", "b.vert")], &["a.vert", "b.vert"]);
+        for (pipeline, fallback) in data.pipelines.iter_mut().zip(&["b", "a"]) {
+            pipeline.vertex_shader = ShaderSource::Invalid;
+            pipeline.fallback = Some(fallback.to_string());
+        }
+
+        data.flatten().expect("Failed to flatten pipelines");
+
+        assert_eq!(data.pipelines[0].vertex_shader, ShaderSource::Invalid);
+        assert_eq!(data.pipelines[1].fallback, None);
+    }
+}
@@ -0,0 +1,61 @@
+//! Problems with a shaderpack which don't stop it from loading.
+
+use crate::shaderpack::ShaderpackData;
+use failure::Fail;
+
+/// Something in a shaderpack which is likely a mistake, but doesn't stop the pack from working.
+///
+/// [`load_nova_shaderpack_with_warnings`](crate::shaderpack::load_nova_shaderpack_with_warnings) returns these along
+/// with the pack, so tools can show them to pack authors. The other loaders only log them.
+#[derive(Fail, Debug, Clone, Eq, PartialEq)]
+pub enum ShaderpackWarning {
+    /// A texture in `resources.json` isn't rendered to or read by any pass, nor bound by any material, so it would
+    /// only take up memory.
+    #[fail(display = "Texture {:?} is never used", texture)]
+    UnusedTexture {
+        /// Name of the texture
+        texture: String,
+    },
+
+    /// A material binds a resource the shaders of its pipeline don't use.
+    #[fail(
+        display = "Material {:?} binds {:?} in pass {:?}, but its pipeline doesn't use it",
+        material, binding, pass
+    )]
+    UnusedBinding {
+        /// Name of the material
+        material: String,
+        /// Name of the material's pass
+        pass: String,
+        /// Name of the unused binding
+        binding: String,
+    },
+
+    /// A shader uses a kind of resource Nova can't bind yet, such as a separate image or sampler, a storage image or
+    /// an input attachment. The resource is left out of the pipeline's bindings, so materials can't bind it.
+    #[fail(
+        display = "Resource {:?} of pipeline {:?} has a type Nova can't bind yet",
+        name, pipeline
+    )]
+    UnsupportedResource {
+        /// Name of the pipeline
+        pipeline: String,
+        /// Name of the resource
+        name: String,
+    },
+}
+
+/// Finds the textures of a shaderpack which nothing uses.
+///
+/// Relies on the texture usages, so [`ShaderpackData::infer_texture_usages`] must have been called first. The loaders
+/// do this.
+pub fn find_unused_textures(data: &ShaderpackData) -> Vec<ShaderpackWarning> {
+    data.resources
+        .textures
+        .iter()
+        .filter(|texture| texture.usage.is_empty())
+        .map(|texture| ShaderpackWarning::UnusedTexture {
+            texture: texture.name.clone(),
+        })
+        .collect()
+}
@@ -0,0 +1,45 @@
+//! Helpers shared by the unit tests and the integration tests.
+//!
+//! The integration tests include this file with `#[path]`, so it only uses `std`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An empty directory in the system temp directory, which is deleted along with everything in it when dropped.
+///
+/// The path is unique to the process and to each directory created in it, so tests which run at the same time, even
+/// from several `cargo test` runs, never share a directory.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a directory whose name starts with `name`, so it can be told apart when a test fails to clean up.
+    pub fn new(name: &str) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "nova-rs-{}-{}-{}",
+            name,
+            process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&path).expect("Failed to create temp directory");
+        Self { path }
+    }
+
+    /// Path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // Cleaning up is best effort, a failure must not hide the result of the test
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
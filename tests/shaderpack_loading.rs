@@ -1,9 +1,39 @@
 #![allow(clippy::cognitive_complexity)]
 #![allow(clippy::float_cmp)]
 
-use futures::executor::ThreadPoolBuilder;
+use futures::executor::{block_on, ThreadPoolBuilder};
+use futures::future::FutureObj;
+use futures::task::{Spawn, SpawnError};
+use futures::Future;
+use nova_rs::fs::dir::IgnorePatterns;
+use nova_rs::loading::{DirectoryFileTree, DirectoryOptions, FileTree, LoadingError, LoadingLimits, MemoryFileTree};
 use nova_rs::shaderpack::*;
 use path_dsl::{path, PathDSL};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use test_utils::TempDir;
+
+#[path = "../src/test_utils.rs"]
+mod test_utils;
+
+/// Copies the default shaderpack to `dest`, so tests can add files to it.
+fn copy_default_pack(dest: &Path) {
+    let default = PathBuf::from(path!("tests" | "data" | "shaderpacks" | "nova" | "DefaultShaderpack"));
+    for dir in &["materials", "shaders"] {
+        fs::create_dir_all(dest.join(dir)).unwrap();
+        for entry in fs::read_dir(default.join(dir)).unwrap() {
+            let entry = entry.unwrap();
+            fs::copy(entry.path(), dest.join(dir).join(entry.file_name())).unwrap();
+        }
+    }
+    for file in &["passes.json", "resources.json"] {
+        fs::copy(default.join(file), dest.join(file)).unwrap();
+    }
+}
 
 /// Utility function that extracts the option, expecting it to be Some
 fn check_shader_option(shaders: &[LoadedShader], shader: &Option<ShaderSource>, filename: PathDSL) {
@@ -238,6 +268,7 @@ fn default_nova_shaderpack() -> Result<(), ShaderpackLoadingFailure> {
         assert_eq!(pipeline.states.contains(&RasterizerState::DisableAlphaWrite), true);
         assert_eq!(pipeline.states.contains(&RasterizerState::DisableDepthWrite), true);
         assert_eq!(pipeline.states.contains(&RasterizerState::DisableDepthTest), true);
+        assert_eq!(pipeline.sample_count, SampleCount::X1);
 
         check_shader(
             shader_list,
@@ -351,3 +382,479 @@ fn default_nova_shaderpack() -> Result<(), ShaderpackLoadingFailure> {
 
     Ok(())
 }
+
+#[test]
+fn default_nova_shaderpack_blocking() -> Result<(), ShaderpackLoadingFailure> {
+    let path = path!("tests" | "data" | "shaderpacks" | "nova" | "DefaultShaderpack");
+
+    // Load twice to make sure the cached thread pool can be reused.
+    let first = load_nova_shaderpack_blocking(path.clone().into())?;
+    let second = load_nova_shaderpack_blocking(path.into())?;
+
+    assert_eq!(first.passes.len(), 2);
+    assert_eq!(first.pipelines.len(), second.pipelines.len());
+    assert_eq!(first.materials.len(), second.materials.len());
+
+    Ok(())
+}
+
+/// Executor which refuses every task, as if it was shutting down.
+#[derive(Clone)]
+struct ShutdownExecutor;
+
+impl Spawn for ShutdownExecutor {
+    fn spawn_obj(&mut self, _future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+        Err(SpawnError::shutdown())
+    }
+}
+
+#[test]
+fn failing_executor() {
+    let result = block_on(load_nova_shaderpack(
+        ShutdownExecutor,
+        path!("tests" | "data" | "shaderpacks" | "nova" | "DefaultShaderpack").into(),
+    ));
+
+    match result {
+        Err(ShaderpackLoadingFailure::ExecutorError { sub_error }) => assert!(sub_error.is_shutdown()),
+        other => panic!("Expected executor error, got {:?}", other),
+    }
+}
+
+fn assert_send<T: Send>(_: T) {}
+
+#[test]
+fn loading_futures_are_send() {
+    // Only has to compile, the futures are never polled
+    let path: PathBuf = path!("tests" | "data" | "shaderpacks" | "nova" | "DefaultShaderpack").into();
+    assert_send(load_nova_shaderpack(ShutdownExecutor, path.clone()));
+    assert_send(load_nova_shaderpack_with_warnings(ShutdownExecutor, path));
+}
+
+#[test]
+fn unsupported_archive_extension() {
+    let temp = TempDir::new("archive-extension");
+    let file = temp.path().join("pack.rar");
+    fs::write(&file, "").unwrap();
+    assert!(!nova_rs::loading::supported_archive_extensions().contains(&"rar"));
+
+    match load_nova_shaderpack_blocking(file) {
+        Err(ShaderpackLoadingFailure::UnsupportedExtension(ext)) => assert_eq!(ext, "rar"),
+        other => panic!("Expected unsupported extension error, got {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_paths() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp = TempDir::new("non-utf8");
+    let root = temp.path();
+
+    // A single file shaderpack with an extension that isn't UTF-8 is reported, not treated as having no extension.
+    let file = root.join(OsStr::from_bytes(b"pack.\xFF"));
+    fs::write(&file, "").unwrap();
+    match load_nova_shaderpack_blocking(file.clone()) {
+        Err(ShaderpackLoadingFailure::NonUtf8Path(path)) => assert_eq!(path, file.into_os_string()),
+        other => panic!("Expected non UTF-8 path error, got {:?}", other),
+    }
+
+    // Files with names that aren't UTF-8 inside a shaderpack don't stop it from loading.
+    let pack = root.join("pack");
+    copy_default_pack(&pack);
+    fs::write(pack.join("shaders").join(OsStr::from_bytes(b"\xFF.frag")), "").unwrap();
+    fs::write(pack.join("materials").join(OsStr::from_bytes(b"\xFF.txt")), "").unwrap();
+
+    let parsed = load_nova_shaderpack_blocking(pack).expect("Failed to load shaderpack with non UTF-8 names");
+    assert_eq!(parsed.passes.len(), 2);
+}
+
+#[test]
+fn pipeline_defaults() {
+    let temp = TempDir::new("pipeline-defaults");
+    let pack = temp.path().to_path_buf();
+    copy_default_pack(&pack);
+    fs::write(
+        pack.join(PIPELINE_DEFAULTS_FILE),
+        r#"{ "srcBlendFactor": "SrcAlpha", "dstBlendFactor": "OneMinusSrcAlpha", "depthFunc": "Always" }"#,
+    )
+    .unwrap();
+    fs::write(
+        pack.join("materials").join("gui_overlay.pipeline"),
+        r#"{ "name": "gui_overlay", "parent": "gui", "dstBlendFactor": "One" }"#,
+    )
+    .unwrap();
+
+    let parsed = load_nova_shaderpack_blocking(pack).expect("Failed to load shaderpack with pipeline defaults");
+    assert_eq!(parsed.pipelines.len(), 4);
+    for pipeline in &parsed.pipelines {
+        assert_eq!(pipeline.src_blend_factor, BlendFactor::SrcAlpha);
+    }
+
+    let pipeline = |name: &str| parsed.pipelines.iter().find(|p| p.name == name).unwrap();
+    assert_eq!(pipeline("Final").dst_blend_factor, BlendFactor::OneMinusSrcAlpha);
+    assert_eq!(pipeline("Final").depth_func, CompareOp::Always);
+    assert_eq!(pipeline("gui").depth_func, CompareOp::LessEqual);
+
+    // The child inherits everything from its parent, except what it overrides.
+    let overlay = pipeline("gui_overlay");
+    let gui = pipeline("gui");
+    assert_eq!(overlay.dst_blend_factor, BlendFactor::One);
+    assert_eq!(overlay.depth_func, CompareOp::LessEqual);
+    assert_eq!(overlay.pass, "Forward");
+    assert_eq!(overlay.vertex_fields.len(), gui.vertex_fields.len());
+    assert_eq!(overlay.vertex_shader, gui.vertex_shader);
+}
+
+/// Custom backend which reads from a directory and counts how many files were read through it.
+#[derive(Clone)]
+struct CountingFileTree {
+    inner: DirectoryFileTree,
+    reads: Arc<AtomicUsize>,
+}
+
+impl FileTree for CountingFileTree {
+    fn from_path(path: &Path) -> Self::FromPathResult {
+        let inner = DirectoryFileTree::from_path(path);
+        Box::pin(async move {
+            Ok(Self {
+                inner: inner.await?,
+                reads: Arc::default(),
+            })
+        })
+    }
+    type FromPathResult = Pin<Box<dyn Future<Output = Result<Self, LoadingError>> + Send>>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn is_file(&self, path: &Path) -> Result<bool, LoadingError> {
+        self.inner.is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> Result<bool, LoadingError> {
+        self.inner.is_dir(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<HashSet<PathBuf>, LoadingError> {
+        self.inner.read_dir(path)
+    }
+
+    fn read(&self, path: &Path) -> Self::ReadResult {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.inner.read(path)
+    }
+    type ReadResult = <DirectoryFileTree as FileTree>::ReadResult;
+
+    fn read_u32(&self, path: &Path) -> Self::ReadU32Result {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.inner.read_u32(path)
+    }
+    type ReadU32Result = <DirectoryFileTree as FileTree>::ReadU32Result;
+
+    fn read_text(&self, path: &Path) -> Self::ReadTextResult {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.inner.read_text(path)
+    }
+    type ReadTextResult = <DirectoryFileTree as FileTree>::ReadTextResult;
+}
+
+#[test]
+fn custom_file_tree() {
+    let mut threadpool = ThreadPoolBuilder::new()
+        .name_prefix("custom_file_tree")
+        .create()
+        .unwrap();
+    let executor = threadpool.clone();
+
+    let tree = threadpool
+        .run(CountingFileTree::from_path(&PathBuf::from(path!(
+            "tests" | "data" | "shaderpacks" | "nova" | "DefaultShaderpack"
+        ))))
+        .expect("Failed to open shaderpack directory");
+    let reads = Arc::clone(&tree.reads);
+
+    let parsed = threadpool
+        .run(load_nova_shaderpack_from_tree(
+            executor,
+            tree,
+            &GeometryTagRegistry::default(),
+        ))
+        .expect("Failed to load shaderpack from custom file tree");
+    assert_eq!(parsed.passes.len(), 2);
+    assert!(reads.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn in_memory_shaderpack() {
+    let mut threadpool = ThreadPoolBuilder::new()
+        .name_prefix("in_memory_shaderpack")
+        .create()
+        .unwrap();
+    let executor = threadpool.clone();
+
+    let tree = MemoryFileTree::from_directory(&PathBuf::from(path!(
+        "tests" | "data" | "shaderpacks" | "nova" | "DefaultShaderpack"
+    )))
+    .expect("Failed to read shaderpack into memory");
+
+    let parsed = threadpool
+        .run(load_nova_shaderpack_from_tree(
+            executor,
+            tree,
+            &GeometryTagRegistry::default(),
+        ))
+        .expect("Failed to load shaderpack from memory");
+    assert_eq!(parsed.passes.len(), 2);
+    assert!(!parsed.pipelines.is_empty());
+}
+
+#[test]
+fn unsupported_schema_version() {
+    let temp = TempDir::new("unsupported-schema-version");
+    let pack = temp.path().to_path_buf();
+    copy_default_pack(&pack);
+    fs::write(
+        pack.join(PACK_INFO_FILE),
+        format!(r#"{{ "schemaVersion": {} }}"#, SHADERPACK_SCHEMA_VERSION + 1),
+    )
+    .unwrap();
+
+    match load_nova_shaderpack_blocking(pack.clone()) {
+        Err(ShaderpackLoadingFailure::UnsupportedSchemaVersion { found, supported }) => {
+            assert_eq!(found, SHADERPACK_SCHEMA_VERSION + 1);
+            assert_eq!(supported, SHADERPACK_SCHEMA_VERSION);
+        }
+        other => panic!("Expected unsupported schema version, got {:?}", other.map(|_| ())),
+    }
+
+    // The current version loads normally
+    fs::write(pack.join(PACK_INFO_FILE), r#"{ "schemaVersion": 1 }"#).unwrap();
+    let parsed = load_nova_shaderpack_blocking(pack).expect("Failed to load shaderpack with current schema version");
+    assert_eq!(parsed.passes.len(), 2);
+}
+
+#[test]
+fn compiled_shaders() {
+    let temp = TempDir::new("compiled-shaders");
+    let pack = temp.path().to_path_buf();
+    copy_default_pack(&pack);
+    fs::remove_dir_all(pack.join("shaders")).unwrap();
+    fs::create_dir(pack.join("shaders")).unwrap();
+
+    let spirv: Vec<u8> = [CompiledShader::SPIRV_MAGIC, 0x0001_0000, 0, 1, 0]
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .collect();
+    fs::write(pack.join("shaders").join("textured_unlit.spv"), &spirv).unwrap();
+    fs::write(pack.join("shaders").join("image_passthrough.spv"), &spirv).unwrap();
+    fs::write(
+        pack.join("materials").join("final.pipeline"),
+        r#"{
+            "name": "Final",
+            "pass": "Final",
+            "vertexShader": "shaders/textured_unlit.spv",
+            "fragmentShader": "shaders/image_passthrough.spv",
+            "vertexFields": []
+        }"#,
+    )
+    .unwrap();
+
+    let parsed = load_nova_shaderpack_blocking(pack.clone()).expect("Failed to load shaderpack with compiled shaders");
+    let shaders = match &parsed.shaders {
+        ShaderSet::Compiled(shaders) => shaders,
+        _ => panic!("Shader set isn't `Compiled`"),
+    };
+    assert_eq!(shaders.len(), 2);
+
+    let pipeline = parsed.pipelines.iter().find(|p| p.name == "Final").unwrap();
+    match pipeline.vertex_shader {
+        ShaderSource::Loaded(idx) => {
+            let shader = &shaders[idx as usize];
+            assert_eq!(shader.filename, PathBuf::from(path!("shaders" | "textured_unlit.spv")));
+            assert_eq!(shader.module.words()[0], CompiledShader::SPIRV_MAGIC);
+        }
+        _ => panic!("ShaderSource not loaded."),
+    }
+
+    // Sources and compiled shaders can't be mixed
+    fs::write(pack.join("shaders").join("gui.vert"), "").unwrap();
+    match load_nova_shaderpack_blocking(pack) {
+        Err(ShaderpackLoadingFailure::MixedShaderForms { compiled, source }) => {
+            assert_eq!(compiled, PathBuf::from(path!("shaders" | "image_passthrough.spv")));
+            assert_eq!(source, PathBuf::from(path!("shaders" | "gui.vert")));
+        }
+        other => panic!("Expected mixed shader forms, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn directory_size_limits() {
+    let temp = TempDir::new("size-limits");
+    let pack = temp.path();
+    // Compresses to almost nothing, so an archive of it would be tiny
+    fs::write(pack.join("zeros.bin"), vec![0_u8; 64 * 1024]).unwrap();
+    fs::write(pack.join("a.json"), vec![b' '; 600]).unwrap();
+    fs::write(pack.join("b.json"), vec![b' '; 600]).unwrap();
+
+    let options = DirectoryOptions {
+        limits: LoadingLimits {
+            max_file_size: 1024,
+            max_total_size: 1000,
+        },
+        ..DirectoryOptions::default()
+    };
+    let limits = options.limits;
+    let tree = block_on(DirectoryFileTree::from_path_with_options(&pack, options)).expect("Failed to open directory");
+
+    match block_on(tree.read(Path::new("zeros.bin"))) {
+        Err(LoadingError::ResourceTooLarge { size, limit: 1024, .. }) => assert_eq!(size, 64 * 1024),
+        other => panic!("Read of too large file didn't fail: {:?}", other.map(|data| data.len())),
+    }
+    assert_eq!(block_on(tree.read(Path::new("a.json"))).unwrap().len(), 600);
+    match block_on(tree.read_text(Path::new("b.json"))) {
+        Err(LoadingError::ResourceTooLarge {
+            size: 1200,
+            limit: 1000,
+            ..
+        }) => {}
+        other => panic!(
+            "Read past the total limit didn't fail: {:?}",
+            other.map(|text| text.len())
+        ),
+    }
+
+    // The failed read wasn't counted, and reading the same file again doesn't count it twice
+    for _ in 0..5 {
+        assert_eq!(block_on(tree.read(Path::new("a.json"))).unwrap().len(), 600);
+    }
+
+    // Reading a whole directory into memory is limited the same way
+    fs::remove_file(pack.join("zeros.bin")).unwrap();
+    match MemoryFileTree::from_directory_with_limits(&pack, limits) {
+        Err(LoadingError::ResourceTooLarge {
+            size: 1200,
+            limit: 1000,
+            ..
+        }) => {}
+        other => panic!("Reading past the total limit didn't fail: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn hidden_and_temp_files_are_ignored() {
+    let temp = TempDir::new("ignored-files");
+    let pack = temp.path().to_path_buf();
+    copy_default_pack(&pack);
+    fs::create_dir_all(pack.join(".git").join("objects")).unwrap();
+    fs::write(pack.join(".git").join("HEAD"), "ref: refs/heads/master").unwrap();
+    fs::write(pack.join("shaders").join(".gui.frag.swp"), vec![0_u8; 16]).unwrap();
+    fs::write(pack.join("materials").join("gui.mat~"), "{").unwrap();
+
+    let tree = block_on(DirectoryFileTree::from_path(&pack)).expect("Failed to open shaderpack directory");
+    assert!(!tree.exists(Path::new(".git")));
+    assert!(!tree.exists(Path::new("shaders/.gui.frag.swp")));
+    assert!(!tree.exists(Path::new("materials/gui.mat~")));
+    assert!(tree.exists(Path::new("passes.json")));
+    let parsed = block_on(load_nova_shaderpack_from_tree(
+        ThreadPoolBuilder::new().create().unwrap(),
+        tree,
+        &GeometryTagRegistry::default(),
+    ))
+    .expect("Failed to load shaderpack with ignored files");
+    assert_eq!(parsed.passes.len(), 2);
+
+    // Without the default patterns, everything shows up
+    let options = DirectoryOptions {
+        ignore: IgnorePatterns::none(),
+        ..DirectoryOptions::default()
+    };
+    let tree = block_on(DirectoryFileTree::from_path_with_options(&pack, options)).expect("Failed to open directory");
+    assert!(tree.exists(Path::new(".git/HEAD")));
+    assert!(tree.exists(Path::new("shaders/.gui.frag.swp")));
+
+    // Reading the pack into memory leaves out the same files
+    let tree = MemoryFileTree::from_directory(&pack).expect("Failed to read shaderpack into memory");
+    assert!(!tree.exists(Path::new(".git")));
+    assert!(!tree.exists(Path::new("shaders/.gui.frag.swp")));
+    assert!(!tree.exists(Path::new("materials/gui.mat~")));
+    assert!(tree.exists(Path::new("passes.json")));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinked_files_are_refused() {
+    use std::os::unix::fs::symlink;
+
+    let temp = TempDir::new("symlinked-passes");
+    let root = temp.path();
+    let pack = root.join("pack");
+    copy_default_pack(&pack);
+    fs::rename(pack.join("passes.json"), root.join("passes.json")).unwrap();
+    symlink(root.join("passes.json"), pack.join("passes.json")).unwrap();
+
+    let tree = block_on(DirectoryFileTree::from_path(&pack)).expect("Failed to open shaderpack directory");
+    match block_on(tree.read(Path::new("passes.json"))) {
+        Err(LoadingError::PathNotFound) => {}
+        other => panic!("Read through a symlink didn't fail: {:?}", other.map(|data| data.len())),
+    }
+    match block_on(tree.read_text(Path::new("../passes.json"))) {
+        Err(LoadingError::PathNotFound) => {}
+        other => panic!("Read outside of the pack didn't fail: {:?}", other),
+    }
+
+    match block_on(load_nova_shaderpack(ThreadPoolBuilder::new().create().unwrap(), pack)) {
+        Err(ShaderpackLoadingFailure::MissingFile(file)) => assert_eq!(file, "passes.json"),
+        other => panic!(
+            "Shaderpack with a symlinked passes.json loaded: {:?}",
+            other.map(|_| ())
+        ),
+    }
+}
+
+#[test]
+fn unused_texture_warning() {
+    let temp = TempDir::new("unused-texture");
+    let pack = temp.path().to_path_buf();
+    copy_default_pack(&pack);
+
+    let (_, warnings) = block_on(load_nova_shaderpack_with_warnings(
+        ThreadPoolBuilder::new().create().unwrap(),
+        pack.clone(),
+    ))
+    .expect("Failed to load default shaderpack");
+    assert_eq!(warnings, Vec::new());
+
+    let resources = fs::read_to_string(pack.join("resources.json")).unwrap();
+    let resources = resources.replacen(
+        r#""textures": ["#,
+        r#""textures": [
+        {
+            "name": "Forgotten",
+            "format": {
+                "pixelFormat": "RGBA8",
+                "dimensionType": "ScreenRelative",
+                "width": 1,
+                "height": 1
+            }
+        },"#,
+        1,
+    );
+    fs::write(pack.join("resources.json"), resources).unwrap();
+
+    let (parsed, warnings) = block_on(load_nova_shaderpack_with_warnings(
+        ThreadPoolBuilder::new().create().unwrap(),
+        pack,
+    ))
+    .expect("Failed to load shaderpack with an unused texture");
+    assert_eq!(parsed.resources.textures.len(), 3);
+    assert_eq!(
+        warnings,
+        vec![ShaderpackWarning::UnusedTexture {
+            texture: String::from("Forgotten")
+        }]
+    );
+}